@@ -0,0 +1,43 @@
+//! Synthetic PNGs shared by the benchmarks.
+
+// Each benchmark uses only some of these.
+#![allow(dead_code)]
+
+use congenial_chainsaw::ihdr::{ColorType, Ihdr};
+use congenial_chainsaw::{Chunk, ChunkType, Png};
+
+/// Bytes of image data in each IDAT chunk, a common encoder buffer size.
+pub const IDAT_SIZE: usize = 32 * 1024;
+
+/// A PNG whose image data is split over `count` IDAT chunks. The data isn't
+/// a valid image, so this is only for benchmarks that work on chunks.
+pub fn multi_idat(count: usize) -> Png {
+    let mut chunks = vec![Chunk::new(ChunkType::IHDR, vec![0; 13])];
+    for i in 0..count {
+        // Vary the data so no two chunks are identical.
+        let data = (0..IDAT_SIZE).map(|j| (i + j) as u8).collect();
+        chunks.push(Chunk::new(ChunkType::IDAT, data));
+    }
+    chunks.push(Chunk::new(ChunkType::IEND, Vec::new()));
+    Png::from_chunks(chunks)
+}
+
+/// A decodable `size`×`size` 8-bit RGB image with a noisy gradient, cycling
+/// through every filter type.
+pub fn image(size: u32) -> Png {
+    let ihdr = Ihdr::new(size, size, 8, ColorType::Rgb).unwrap();
+    let stride = size as usize * 3;
+    let filtered: Vec<u8> = (0..size as usize)
+        .flat_map(|row| {
+            let filter = (row % 5) as u8;
+            std::iter::once(filter).chain((0..stride).map(move |i| (row * 7 + i * 13) as u8))
+        })
+        .collect();
+    let mut png = Png::from_chunks(vec![
+        ihdr.to_chunk(),
+        Chunk::new(ChunkType::IDAT, Vec::new()),
+        Chunk::new(ChunkType::IEND, Vec::new()),
+    ]);
+    png.set_image_data(&filtered).unwrap();
+    png
+}
@@ -0,0 +1,22 @@
+//! Checking the CRC of a single chunk, the cost every parse and encode pays
+//! once per chunk.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use congenial_chainsaw::{Chunk, ChunkType};
+
+fn crc(c: &mut Criterion) {
+    let mut group = c.benchmark_group("crc");
+    for size in [1 << 10, 64 << 10, 1 << 20, 16 << 20] {
+        let data = (0..size).map(|i| i as u8).collect();
+        let chunk = Chunk::new(ChunkType::IDAT, data);
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &chunk, |b, chunk| {
+            b.iter(|| assert!(chunk.has_valid_crc()))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, crc);
+criterion_main!(benches);
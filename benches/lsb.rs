@@ -0,0 +1,31 @@
+//! Embedding a payload in the pixels, which decodes, rewrites and re-encodes
+//! the whole image.
+
+mod common;
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+
+use congenial_chainsaw::lsb::{self, LsbOptions};
+
+use common::image;
+
+fn embed(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lsb_embed");
+    group.sample_size(20);
+    for size in [64, 256, 1024] {
+        let png = image(size);
+        // A quarter of what the image holds.
+        let payload = vec![0x5a; lsb::capacity(&png.ihdr().unwrap()) / 4];
+        group.bench_with_input(BenchmarkId::from_parameter(size), &png, |b, png| {
+            b.iter_batched(
+                || png.clone(),
+                |mut png| lsb::embed(&mut png, &payload, &LsbOptions::default()).unwrap(),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, embed);
+criterion_main!(benches);
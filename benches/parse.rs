@@ -0,0 +1,40 @@
+//! Parsing and serializing whole files: owned chunks, borrowed chunks, and a
+//! parse followed by writing the file back out.
+
+mod common;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use congenial_chainsaw::{ParseOptions, Png, Result};
+
+use common::multi_idat;
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for count in [16, 256, 2048] {
+        let bytes = multi_idat(count).as_bytes();
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+        group.bench_with_input(BenchmarkId::new("owned", count), &bytes, |b, bytes| {
+            b.iter(|| Png::from_bytes_with(bytes, &ParseOptions::default()).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("borrowed", count), &bytes, |b, bytes| {
+            b.iter(|| {
+                Png::chunk_refs(bytes, &ParseOptions::default())
+                    .unwrap()
+                    .collect::<Result<Vec<_>>>()
+                    .unwrap()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("round_trip", count), &bytes, |b, bytes| {
+            b.iter(|| {
+                Png::from_bytes_with(bytes, &ParseOptions::default())
+                    .unwrap()
+                    .as_bytes()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
@@ -0,0 +1,30 @@
+//! Compares `Png::validate`, parallel with the default features, against
+//! checking each chunk's CRC in turn, on files split into many IDAT chunks.
+//!
+//!     cargo bench --bench validate
+
+mod common;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use congenial_chainsaw::Chunk;
+
+use common::{multi_idat, IDAT_SIZE};
+
+fn validate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("validate");
+    for count in [16, 256, 2048] {
+        let png = multi_idat(count);
+        group.throughput(Throughput::Bytes((count * IDAT_SIZE) as u64));
+        group.bench_with_input(BenchmarkId::new("validate", count), &png, |b, png| {
+            b.iter(|| png.validate().unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("sequential", count), &png, |b, png| {
+            b.iter(|| assert!(png.chunks().iter().all(Chunk::has_valid_crc)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, validate);
+criterion_main!(benches);
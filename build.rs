@@ -0,0 +1,6 @@
+fn main() {
+    // Only the grpc feature needs the generated service code, so only it
+    // needs protoc installed.
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/pngme.proto").expect("proto/pngme.proto should compile");
+}
@@ -0,0 +1,21 @@
+//! Feeds arbitrary bytes to every parser that accepts untrusted input.
+//! Errors are expected; a panic, or an allocation much larger than the input,
+//! is a bug.
+//!
+//!     cargo +nightly fuzz run parse -- -rss_limit_mb=256
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use congenial_chainsaw::{Chunk, ParseOptions, Png};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Chunk::try_from(data);
+    let _ = Png::try_from(data);
+    let _ = Png::from_bytes_with(data, &ParseOptions::permissive());
+    let _ = Png::from_reader(data);
+    if let Ok(chunks) = Png::chunk_refs(data, &ParseOptions::permissive()) {
+        let _ = chunks.count();
+    }
+});
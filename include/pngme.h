@@ -0,0 +1,67 @@
+#ifndef PNGME_H
+#define PNGME_H
+
+/* Generated by cbindgen from src/capi.rs. Do not edit by hand. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+// The call succeeded.
+#define PNGME_OK 0
+
+// The call failed; see `pngme_last_error`.
+#define PNGME_ERROR -1
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Hides the NUL-terminated `message` in the PNG of `png_len` bytes at `png`,
+// in chunks of type `chunk_type`, and hands the new file back through `out`
+// and `out_len`.
+//
+// # Safety
+//
+// `png` must point to `png_len` readable bytes, `chunk_type` and `message`
+// must be NUL-terminated strings, and `out` and `out_len` must be valid for
+// writes.
+int pngme_encode(const uint8_t *png,
+                 size_t png_len,
+                 const char *chunk_type,
+                 const char *message,
+                 uint8_t **out,
+                 size_t *out_len);
+
+// Reveals the message hidden in the PNG of `png_len` bytes at `png`, in
+// chunks of type `chunk_type`, and hands it back through `out` and
+// `out_len`. The message is not NUL-terminated and may contain NUL bytes.
+//
+// # Safety
+//
+// `png` must point to `png_len` readable bytes, `chunk_type` must be a
+// NUL-terminated string, and `out` and `out_len` must be valid for writes.
+int pngme_decode(const uint8_t *png,
+                 size_t png_len,
+                 const char *chunk_type,
+                 uint8_t **out,
+                 size_t *out_len);
+
+// Releases a buffer returned by `pngme_encode` or `pngme_decode`.
+// Passing a null pointer does nothing.
+//
+// # Safety
+//
+// `buf` and `len` must be exactly as returned, and each buffer may be freed
+// only once.
+void pngme_free(uint8_t *buf, size_t len);
+
+// Describes the last error raised on the calling thread, or returns null if
+// there has been none. The string stays valid until the next failing call on
+// the same thread.
+const char *pngme_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif /* PNGME_H */
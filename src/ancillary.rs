@@ -0,0 +1,591 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use thiserror::Error;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::{PngMeError, Result};
+
+/// Ancillary chunk types that change how the image is drawn: transparency,
+/// color space and animation. Scrubbing keeps these by default.
+pub const RENDERING_CHUNK_TYPES: [ChunkType; 8] = [
+    ChunkType::TRNS,
+    ChunkType::GAMA,
+    ChunkType::CHRM,
+    ChunkType::SRGB,
+    ChunkType::CICP,
+    ChunkType::ACTL,
+    ChunkType::FCTL,
+    ChunkType::FDAT,
+];
+
+/// An ancillary chunk with a fixed binary layout that can be parsed from and
+/// serialized to its data field.
+pub trait AncillaryChunk: Sized {
+    const CHUNK_TYPE: [u8; 4];
+
+    fn parse(data: &[u8]) -> Result<Self>;
+
+    fn encode(&self) -> Vec<u8>;
+
+    fn to_chunk(&self) -> Chunk {
+        let chunk_type =
+            ChunkType::try_from(Self::CHUNK_TYPE).expect("ancillary chunk types are valid");
+        Chunk::new(chunk_type, self.encode())
+    }
+}
+
+impl Png {
+    /// Parses the first chunk of type `T`, if there is one.
+    pub fn ancillary<T: AncillaryChunk>(&self) -> Result<Option<T>> {
+        self.chunks()
+            .iter()
+            .find(|chunk| chunk.chunk_type().bytes() == T::CHUNK_TYPE)
+            .map(|chunk| T::parse(chunk.data()))
+            .transpose()
+    }
+
+    /// Replaces every chunk of type `T` with `value`. New chunks go straight
+    /// after IHDR, which keeps them ahead of PLTE and IDAT as required.
+    pub fn set_ancillary<T: AncillaryChunk>(&mut self, value: &T) {
+        let is_type = |chunk: &Chunk| chunk.chunk_type().bytes() == T::CHUNK_TYPE;
+        let position = match self.chunks().iter().position(is_type) {
+            Some(position) => position,
+            None => self
+                .chunks()
+                .iter()
+                .position(|chunk| *chunk.chunk_type() == ChunkType::IHDR)
+                .map_or(0, |ihdr| ihdr + 1),
+        };
+        self.retain_chunks(|chunk| !is_type(chunk));
+        self.insert_chunk(position, value.to_chunk());
+    }
+
+    /// Removes every chunk of type `T`, returning how many were removed.
+    pub fn remove_ancillary<T: AncillaryChunk>(&mut self) -> usize {
+        let before = self.chunks().len();
+        self.retain_chunks(|chunk| chunk.chunk_type().bytes() != T::CHUNK_TYPE);
+        before - self.chunks().len()
+    }
+
+    /// Marks the image as sRGB, also writing the gAMA and cHRM values the
+    /// specification recommends for decoders that do not understand sRGB.
+    /// Any ICC profile is dropped, since it would take precedence.
+    pub fn set_srgb(&mut self, intent: RenderingIntent) {
+        self.retain_chunks(|chunk| *chunk.chunk_type() != ChunkType::ICCP);
+        self.set_ancillary(&Chromaticities::SRGB);
+        self.set_ancillary(&Gamma::SRGB);
+        self.set_ancillary(&Srgb { intent });
+    }
+
+    /// Sets the tIME chunk to the current time.
+    pub fn touch(&mut self) {
+        self.set_ancillary(&LastModified::now());
+    }
+
+    /// Removes every ancillary chunk whose type isn't in `keep`, such as
+    /// text, EXIF, timestamps, ICC profiles and private chunks, returning
+    /// the types removed in file order. Critical chunks are always kept, so
+    /// the image still decodes; pass [`RENDERING_CHUNK_TYPES`] to keep it
+    /// looking the same too.
+    pub fn strip_ancillary(&mut self, keep: &[ChunkType]) -> Vec<ChunkType> {
+        let mut removed = Vec::new();
+        self.retain_chunks(|chunk| {
+            let chunk_type = chunk.chunk_type();
+            let kept = chunk_type.is_critical() || keep.contains(chunk_type);
+            if !kept {
+                removed.push(*chunk_type);
+            }
+            kept
+        });
+        removed
+    }
+}
+
+/// Checks that `data` is exactly `N` bytes long.
+pub(crate) fn fixed<const N: usize>(chunk_type: [u8; 4], data: &[u8]) -> Result<&[u8; N]> {
+    data.try_into().map_err(|_| {
+        AncillaryError::InvalidLength {
+            chunk_type,
+            expected: N,
+            found: data.len(),
+        }
+        .into()
+    })
+}
+
+pub(crate) fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes(bytes.try_into().expect("callers pass 4 bytes"))
+}
+
+/// The unit of a [`Phys`] pixel density.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhysUnit {
+    /// Only the aspect ratio is known.
+    Unknown,
+    Meter,
+}
+
+/// `pHYs`: the intended pixel size or aspect ratio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Phys {
+    pub pixels_per_unit_x: u32,
+    pub pixels_per_unit_y: u32,
+    pub unit: PhysUnit,
+}
+
+impl Phys {
+    const METERS_PER_INCH: f64 = 0.0254;
+
+    /// A density given in dots per inch, rounded to whole pixels per meter.
+    pub fn from_dpi(dpi_x: f64, dpi_y: f64) -> Phys {
+        Phys {
+            pixels_per_unit_x: (dpi_x / Self::METERS_PER_INCH).round() as u32,
+            pixels_per_unit_y: (dpi_y / Self::METERS_PER_INCH).round() as u32,
+            unit: PhysUnit::Meter,
+        }
+    }
+
+    /// The density in dots per inch, if the unit is known.
+    pub fn dpi(&self) -> Option<(f64, f64)> {
+        (self.unit == PhysUnit::Meter).then_some((
+            self.pixels_per_unit_x as f64 * Self::METERS_PER_INCH,
+            self.pixels_per_unit_y as f64 * Self::METERS_PER_INCH,
+        ))
+    }
+}
+
+impl AncillaryChunk for Phys {
+    const CHUNK_TYPE: [u8; 4] = *b"pHYs";
+
+    fn parse(data: &[u8]) -> Result<Phys> {
+        let data = fixed::<9>(Self::CHUNK_TYPE, data)?;
+        let unit = match data[8] {
+            0 => PhysUnit::Unknown,
+            1 => PhysUnit::Meter,
+            other => return Err(AncillaryError::invalid(Self::CHUNK_TYPE, other).into()),
+        };
+        Ok(Phys {
+            pixels_per_unit_x: be_u32(&data[0..4]),
+            pixels_per_unit_y: be_u32(&data[4..8]),
+            unit,
+        })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut data = self.pixels_per_unit_x.to_be_bytes().to_vec();
+        data.extend(self.pixels_per_unit_y.to_be_bytes());
+        data.push(match self.unit {
+            PhysUnit::Unknown => 0,
+            PhysUnit::Meter => 1,
+        });
+        data
+    }
+}
+
+/// `gAMA`: the image gamma, stored as the value times 100000.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gamma(pub u32);
+
+impl Gamma {
+    /// The gamma the specification recommends alongside sRGB, 1/2.2.
+    pub const SRGB: Gamma = Gamma(45455);
+
+    pub fn from_value(gamma: f64) -> Gamma {
+        Gamma((gamma * 100_000.0).round() as u32)
+    }
+
+    pub fn value(&self) -> f64 {
+        self.0 as f64 / 100_000.0
+    }
+}
+
+impl AncillaryChunk for Gamma {
+    const CHUNK_TYPE: [u8; 4] = *b"gAMA";
+
+    fn parse(data: &[u8]) -> Result<Gamma> {
+        let data = fixed::<4>(Self::CHUNK_TYPE, data)?;
+        Ok(Gamma(be_u32(data)))
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        self.0.to_be_bytes().to_vec()
+    }
+}
+
+/// `cHRM`: CIE 1931 x,y chromaticities of the white point and primaries,
+/// each stored as the value times 100000.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chromaticities {
+    pub white: (u32, u32),
+    pub red: (u32, u32),
+    pub green: (u32, u32),
+    pub blue: (u32, u32),
+}
+
+impl Chromaticities {
+    /// The sRGB primaries with a D65 white point.
+    pub const SRGB: Chromaticities = Chromaticities {
+        white: (31270, 32900),
+        red: (64000, 33000),
+        green: (30000, 60000),
+        blue: (15000, 6000),
+    };
+}
+
+impl AncillaryChunk for Chromaticities {
+    const CHUNK_TYPE: [u8; 4] = *b"cHRM";
+
+    fn parse(data: &[u8]) -> Result<Chromaticities> {
+        let data = fixed::<32>(Self::CHUNK_TYPE, data)?;
+        let point = |i: usize| {
+            (
+                be_u32(&data[i * 8..i * 8 + 4]),
+                be_u32(&data[i * 8 + 4..i * 8 + 8]),
+            )
+        };
+        Ok(Chromaticities {
+            white: point(0),
+            red: point(1),
+            green: point(2),
+            blue: point(3),
+        })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        [self.white, self.red, self.green, self.blue]
+            .iter()
+            .flat_map(|&(x, y)| x.to_be_bytes().into_iter().chain(y.to_be_bytes()))
+            .collect()
+    }
+}
+
+/// How a color-managed decoder should map sRGB colors it cannot reproduce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderingIntent {
+    Perceptual,
+    RelativeColorimetric,
+    Saturation,
+    AbsoluteColorimetric,
+}
+
+/// `sRGB`: the image uses the sRGB color space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Srgb {
+    pub intent: RenderingIntent,
+}
+
+impl AncillaryChunk for Srgb {
+    const CHUNK_TYPE: [u8; 4] = *b"sRGB";
+
+    fn parse(data: &[u8]) -> Result<Srgb> {
+        let data = fixed::<1>(Self::CHUNK_TYPE, data)?;
+        let intent = match data[0] {
+            0 => RenderingIntent::Perceptual,
+            1 => RenderingIntent::RelativeColorimetric,
+            2 => RenderingIntent::Saturation,
+            3 => RenderingIntent::AbsoluteColorimetric,
+            other => return Err(AncillaryError::invalid(Self::CHUNK_TYPE, other).into()),
+        };
+        Ok(Srgb { intent })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        vec![self.intent as u8]
+    }
+}
+
+/// `tIME`: when the image was last modified, in UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LastModified {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    /// Up to 60, to allow for leap seconds.
+    pub second: u8,
+}
+
+impl LastModified {
+    const SECONDS_PER_DAY: i64 = 86_400;
+
+    pub fn now() -> LastModified {
+        LastModified::from_system_time(SystemTime::now())
+    }
+
+    /// Converts `time` to a UTC calendar date, dropping fractional seconds.
+    pub fn from_system_time(time: SystemTime) -> LastModified {
+        let seconds = match time.duration_since(UNIX_EPOCH) {
+            Ok(after) => after.as_secs() as i64,
+            Err(before) => -(before.duration().as_secs_f64().ceil() as i64),
+        };
+        let (days, of_day) = (
+            seconds.div_euclid(Self::SECONDS_PER_DAY),
+            seconds.rem_euclid(Self::SECONDS_PER_DAY),
+        );
+        let (year, month, day) = civil_from_days(days);
+        LastModified {
+            year: year as u16,
+            month,
+            day,
+            hour: (of_day / 3600) as u8,
+            minute: (of_day / 60 % 60) as u8,
+            second: (of_day % 60) as u8,
+        }
+    }
+
+    pub fn to_system_time(self) -> SystemTime {
+        let days = days_from_civil(self.year.into(), self.month, self.day);
+        let seconds = days * Self::SECONDS_PER_DAY
+            + self.hour as i64 * 3600
+            + self.minute as i64 * 60
+            + self.second as i64;
+        match u64::try_from(seconds) {
+            Ok(seconds) => UNIX_EPOCH + Duration::from_secs(seconds),
+            Err(_) => UNIX_EPOCH - Duration::from_secs(seconds.unsigned_abs()),
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        let fields = [
+            (self.month, 1..=12),
+            (self.day, 1..=31),
+            (self.hour, 0..=23),
+            (self.minute, 0..=59),
+            (self.second, 0..=60),
+        ];
+        match fields
+            .into_iter()
+            .find(|(value, range)| !range.contains(value))
+        {
+            Some((value, _)) => Err(AncillaryError::invalid(Self::CHUNK_TYPE, value).into()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Converts days since 1970-01-01 to a proleptic Gregorian date, using
+/// Howard Hinnant's `civil_from_days` algorithm.
+fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u8;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u8;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// The inverse of [`civil_from_days`].
+fn days_from_civil(year: i64, month: u8, day: u8) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = i64::from(month);
+    let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * shifted_month + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+impl AncillaryChunk for LastModified {
+    const CHUNK_TYPE: [u8; 4] = *b"tIME";
+
+    fn parse(data: &[u8]) -> Result<LastModified> {
+        let data = fixed::<7>(Self::CHUNK_TYPE, data)?;
+        let time = LastModified {
+            year: u16::from_be_bytes([data[0], data[1]]),
+            month: data[2],
+            day: data[3],
+            hour: data[4],
+            minute: data[5],
+            second: data[6],
+        };
+        time.validate()?;
+        Ok(time)
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut data = self.year.to_be_bytes().to_vec();
+        data.extend([self.month, self.day, self.hour, self.minute, self.second]);
+        data
+    }
+}
+
+impl FromStr for LastModified {
+    type Err = PngMeError;
+
+    /// Parses an RFC 3339 UTC timestamp such as `2024-05-01T12:30:00Z`. The
+    /// trailing `Z` is optional and a space may replace the `T`.
+    fn from_str(s: &str) -> Result<LastModified> {
+        let invalid = || AncillaryError::MalformedTimestamp(s.to_string());
+        let trimmed = s.strip_suffix('Z').unwrap_or(s);
+        let (date, time) = trimmed.split_once(['T', ' ']).ok_or_else(invalid)?;
+        let date: Vec<&str> = date.split('-').collect();
+        let time: Vec<&str> = time.split(':').collect();
+        let ([year, month, day], [hour, minute, second]) = (date.as_slice(), time.as_slice())
+        else {
+            return Err(invalid().into());
+        };
+        let field = |value: &str| value.parse::<u8>().map_err(|_| invalid());
+        let time = LastModified {
+            year: year.parse().map_err(|_| invalid())?,
+            month: field(month)?,
+            day: field(day)?,
+            hour: field(hour)?,
+            minute: field(minute)?,
+            second: field(second)?,
+        };
+        time.validate().map_err(|_| invalid())?;
+        Ok(time)
+    }
+}
+
+impl fmt::Display for LastModified {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// Errors raised while parsing an [`AncillaryChunk`].
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum AncillaryError {
+    /// The data field is not the size the chunk type requires.
+    #[error(
+        "{} chunk must be {expected} bytes long, got {found}",
+        String::from_utf8_lossy(.chunk_type)
+    )]
+    InvalidLength {
+        chunk_type: [u8; 4],
+        expected: usize,
+        found: usize,
+    },
+    /// An enumerated field holds a value the specification does not define.
+    #[error("{} chunk has invalid value {value}", String::from_utf8_lossy(.chunk_type))]
+    InvalidValue { chunk_type: [u8; 4], value: u8 },
+    /// A timestamp is not in `YYYY-MM-DDTHH:MM:SSZ` form.
+    #[error("invalid timestamp {0:?}, expected YYYY-MM-DDTHH:MM:SSZ")]
+    MalformedTimestamp(String),
+}
+
+impl AncillaryError {
+    fn invalid(chunk_type: [u8; 4], value: u8) -> AncillaryError {
+        AncillaryError::InvalidValue { chunk_type, value }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::{ColorType, Ihdr};
+
+    fn round_trip<T: AncillaryChunk + PartialEq + fmt::Debug>(value: T) {
+        assert_eq!(T::parse(&value.encode()).unwrap(), value);
+    }
+
+    #[test]
+    fn test_round_trips() {
+        round_trip(Phys::from_dpi(300.0, 300.0));
+        round_trip(Gamma::SRGB);
+        round_trip(Chromaticities::SRGB);
+        round_trip(Srgb {
+            intent: RenderingIntent::Saturation,
+        });
+    }
+
+    #[test]
+    fn test_phys_dpi() {
+        let phys = Phys::from_dpi(72.0, 72.0);
+        assert_eq!(phys.pixels_per_unit_x, 2835);
+        let (dpi_x, _) = phys.dpi().unwrap();
+        assert!((dpi_x - 72.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_invalid_data() {
+        assert!(Gamma::parse(&[0, 0, 1]).is_err());
+        assert!(Srgb::parse(&[4]).is_err());
+        assert!(Phys::parse(&[0, 0, 0, 1, 0, 0, 0, 1, 2]).is_err());
+    }
+
+    #[test]
+    fn test_last_modified_system_time() {
+        let time = LastModified::from_system_time(UNIX_EPOCH + Duration::from_secs(951_827_696));
+        assert_eq!(time.to_string(), "2000-02-29T12:34:56Z");
+        assert_eq!(
+            time.to_system_time(),
+            UNIX_EPOCH + Duration::from_secs(951_827_696)
+        );
+        round_trip(time);
+
+        let before_epoch = LastModified::from_system_time(UNIX_EPOCH - Duration::from_secs(1));
+        assert_eq!(before_epoch.to_string(), "1969-12-31T23:59:59Z");
+    }
+
+    #[test]
+    fn test_last_modified_from_str() {
+        let time: LastModified = "2024-05-01 08:09:10".parse().unwrap();
+        assert_eq!(time.to_string(), "2024-05-01T08:09:10Z");
+        assert!("2024-13-01T00:00:00Z".parse::<LastModified>().is_err());
+        assert!("yesterday".parse::<LastModified>().is_err());
+        assert!(LastModified::parse(&[7, 232, 1, 1, 24, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_set_srgb_after_ihdr() {
+        let ihdr = Ihdr::new(1, 1, 8, ColorType::Rgb).unwrap();
+        let iend = Chunk::new(ChunkType::try_from(*b"IEND").unwrap(), Vec::new());
+        let mut png = Png::from_chunks(vec![ihdr.to_chunk(), iend]);
+        png.set_ancillary(&Gamma::from_value(1.0));
+        png.set_srgb(RenderingIntent::Perceptual);
+
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "sRGB", "cHRM", "gAMA", "IEND"]);
+        assert_eq!(png.ancillary::<Gamma>().unwrap(), Some(Gamma::SRGB));
+        assert_eq!(png.remove_ancillary::<Srgb>(), 1);
+        assert_eq!(png.ancillary::<Srgb>().unwrap(), None);
+    }
+
+    #[test]
+    fn test_strip_ancillary() {
+        let chunk = |chunk_type: &[u8; 4]| {
+            Chunk::new(ChunkType::try_from(*chunk_type).unwrap(), vec![0; 4])
+        };
+        let mut png = Png::from_chunks(
+            [
+                b"IHDR", b"iCCP", b"tRNS", b"tEXt", b"IDAT", b"ruSt", b"tIME", b"IEND",
+            ]
+            .into_iter()
+            .map(chunk)
+            .collect(),
+        );
+        let removed = png.strip_ancillary(&RENDERING_CHUNK_TYPES);
+        assert_eq!(
+            removed.iter().map(ChunkType::to_string).collect::<Vec<_>>(),
+            ["iCCP", "tEXt", "ruSt", "tIME"]
+        );
+        assert_eq!(png.chunks().len(), 4);
+    }
+}
@@ -0,0 +1,378 @@
+use thiserror::Error;
+
+use crate::ancillary::{be_u32, fixed, AncillaryChunk, AncillaryError};
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::Result;
+
+/// Chunk types whose data starts with an APNG sequence number.
+const SEQUENCED: [[u8; 4]; 2] = [*b"fcTL", *b"fdAT"];
+
+/// Returns true for the chunk types that make up an animation. Payloads must
+/// never be stored under these, or decoders would treat them as frames.
+pub fn is_animation_chunk_type(chunk_type: &ChunkType) -> bool {
+    matches!(&chunk_type.bytes(), b"acTL" | b"fcTL" | b"fdAT")
+}
+
+/// `acTL`: marks a PNG as animated, giving its frame and loop counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnimationControl {
+    pub num_frames: u32,
+    /// Times to play the animation; 0 loops forever.
+    pub num_plays: u32,
+}
+
+impl AncillaryChunk for AnimationControl {
+    const CHUNK_TYPE: [u8; 4] = *b"acTL";
+
+    fn parse(data: &[u8]) -> Result<AnimationControl> {
+        let data = fixed::<8>(Self::CHUNK_TYPE, data)?;
+        Ok(AnimationControl {
+            num_frames: be_u32(&data[0..4]),
+            num_plays: be_u32(&data[4..8]),
+        })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut data = self.num_frames.to_be_bytes().to_vec();
+        data.extend(self.num_plays.to_be_bytes());
+        data
+    }
+}
+
+/// What to do with a frame's region before rendering the next frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisposeOp {
+    None,
+    Background,
+    Previous,
+}
+
+/// How a frame is combined with the output buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendOp {
+    Source,
+    Over,
+}
+
+/// `fcTL`: the position, size and timing of one frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameControl {
+    pub sequence_number: u32,
+    pub width: u32,
+    pub height: u32,
+    pub x_offset: u32,
+    pub y_offset: u32,
+    /// The frame delay is `delay_num / delay_den` seconds; a denominator of
+    /// 0 means 1/100ths of a second.
+    pub delay_num: u16,
+    pub delay_den: u16,
+    pub dispose_op: DisposeOp,
+    pub blend_op: BlendOp,
+}
+
+impl FrameControl {
+    const CHUNK_TYPE: [u8; 4] = *b"fcTL";
+
+    pub fn parse(data: &[u8]) -> Result<FrameControl> {
+        let data = fixed::<26>(Self::CHUNK_TYPE, data)?;
+        let invalid = |value| AncillaryError::InvalidValue {
+            chunk_type: Self::CHUNK_TYPE,
+            value,
+        };
+        let dispose_op = match data[24] {
+            0 => DisposeOp::None,
+            1 => DisposeOp::Background,
+            2 => DisposeOp::Previous,
+            other => return Err(invalid(other).into()),
+        };
+        let blend_op = match data[25] {
+            0 => BlendOp::Source,
+            1 => BlendOp::Over,
+            other => return Err(invalid(other).into()),
+        };
+        Ok(FrameControl {
+            sequence_number: be_u32(&data[0..4]),
+            width: be_u32(&data[4..8]),
+            height: be_u32(&data[8..12]),
+            x_offset: be_u32(&data[12..16]),
+            y_offset: be_u32(&data[16..20]),
+            delay_num: u16::from_be_bytes([data[20], data[21]]),
+            delay_den: u16::from_be_bytes([data[22], data[23]]),
+            dispose_op,
+            blend_op,
+        })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(26);
+        for field in [
+            self.sequence_number,
+            self.width,
+            self.height,
+            self.x_offset,
+            self.y_offset,
+        ] {
+            data.extend(field.to_be_bytes());
+        }
+        data.extend(self.delay_num.to_be_bytes());
+        data.extend(self.delay_den.to_be_bytes());
+        data.push(self.dispose_op as u8);
+        data.push(self.blend_op as u8);
+        data
+    }
+
+    /// The frame delay in seconds.
+    pub fn delay(&self) -> f64 {
+        let den = if self.delay_den == 0 {
+            100
+        } else {
+            self.delay_den
+        };
+        f64::from(self.delay_num) / f64::from(den)
+    }
+}
+
+/// One animation frame: its control chunk and compressed image data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame<'a> {
+    pub control: FrameControl,
+    /// The frame's zlib stream, split across IDAT or fdAT chunks, with fdAT
+    /// sequence numbers removed.
+    pub data: Vec<&'a [u8]>,
+    /// True if the frame is stored in IDAT, making it the static image too.
+    pub is_default_image: bool,
+}
+
+impl Png {
+    pub fn is_animated(&self) -> bool {
+        self.chunk_by_type("acTL").is_some()
+    }
+
+    /// Parses the acTL chunk, if the PNG is animated.
+    pub fn animation_control(&self) -> Result<Option<AnimationControl>> {
+        self.ancillary()
+    }
+
+    /// Lists the animation frames in file order. Returns an empty list for
+    /// PNGs that are not animated.
+    pub fn frames(&self) -> Result<Vec<Frame<'_>>> {
+        let mut frames: Vec<Frame> = Vec::new();
+        if !self.is_animated() {
+            return Ok(frames);
+        }
+        // IDAT only belongs to a frame if an fcTL comes straight before it.
+        let mut previous_was_fctl = false;
+        for chunk in self.chunks() {
+            match &chunk.chunk_type().bytes() {
+                b"fcTL" => frames.push(Frame {
+                    control: FrameControl::parse(chunk.data())?,
+                    data: Vec::new(),
+                    is_default_image: false,
+                }),
+                b"IDAT" => {
+                    if let Some(frame) = frames.last_mut() {
+                        if previous_was_fctl || frame.is_default_image {
+                            frame.is_default_image = true;
+                            frame.data.push(chunk.data());
+                        }
+                    }
+                }
+                b"fdAT" => {
+                    let frame = frames.last_mut().ok_or(AnimationError::DataBeforeControl)?;
+                    let data = chunk.data().get(4..).ok_or(AnimationError::Truncated)?;
+                    frame.data.push(data);
+                }
+                _ => {}
+            }
+            previous_was_fctl = *chunk.chunk_type() == ChunkType::FCTL;
+        }
+        Ok(frames)
+    }
+
+    /// Checks that fcTL and fdAT sequence numbers count up from 0 without
+    /// gaps and that acTL declares the number of frames actually present.
+    pub fn validate_animation(&self) -> Result<()> {
+        let control = self
+            .animation_control()?
+            .ok_or(AnimationError::NotAnimated)?;
+        for (expected, chunk) in (0u32..).zip(self.sequenced_chunks()) {
+            let found = sequence_number(chunk)?;
+            if found != expected {
+                return Err(AnimationError::SequenceGap { expected, found }.into());
+            }
+        }
+        let frames = self.frames()?.len();
+        if frames != control.num_frames as usize {
+            return Err(AnimationError::FrameCountMismatch {
+                declared: control.num_frames,
+                found: frames,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Rewrites fcTL and fdAT sequence numbers to count up from 0 in file
+    /// order, and acTL to match the number of frames, repairing animations
+    /// after chunks were added or removed.
+    pub fn renumber_animation(&mut self) -> Result<()> {
+        let mut next = 0u32;
+        for chunk in self.chunks_mut() {
+            if !SEQUENCED.contains(&chunk.chunk_type().bytes()) {
+                continue;
+            }
+            let mut data = chunk.data().to_vec();
+            let number = data.get_mut(..4).ok_or(AnimationError::Truncated)?;
+            number.copy_from_slice(&next.to_be_bytes());
+            *chunk = Chunk::new(*chunk.chunk_type(), data);
+            next += 1;
+        }
+        if let Some(mut control) = self.animation_control()? {
+            control.num_frames = self.frames()?.len() as u32;
+            self.set_ancillary(&control);
+        }
+        Ok(())
+    }
+
+    /// Moves `position` forward past any frame it would split, so a chunk
+    /// inserted there never lands between an fcTL and its frame data.
+    pub fn safe_insert_position(&self, position: usize) -> usize {
+        let chunks = self.chunks();
+        let mut position = position.min(chunks.len());
+        let in_frame = |i: usize| matches!(&chunks[i].chunk_type().bytes(), b"fdAT" | b"IDAT");
+        let follows_frame = |i: usize| {
+            matches!(
+                &chunks[i - 1].chunk_type().bytes(),
+                b"fcTL" | b"fdAT" | b"IDAT"
+            )
+        };
+        while position > 0
+            && position < chunks.len()
+            && in_frame(position)
+            && follows_frame(position)
+        {
+            position += 1;
+        }
+        position
+    }
+
+    fn sequenced_chunks(&self) -> impl Iterator<Item = &Chunk> {
+        self.chunks()
+            .iter()
+            .filter(|chunk| SEQUENCED.contains(&chunk.chunk_type().bytes()))
+    }
+}
+
+fn sequence_number(chunk: &Chunk) -> Result<u32> {
+    let bytes = chunk.data().get(..4).ok_or(AnimationError::Truncated)?;
+    Ok(be_u32(bytes))
+}
+
+/// Errors raised while reading or checking an animation.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum AnimationError {
+    /// The PNG has no acTL chunk.
+    #[error("PNG is not animated")]
+    NotAnimated,
+    /// An fdAT chunk appears before any fcTL.
+    #[error("fdAT chunk appears before any fcTL")]
+    DataBeforeControl,
+    /// A sequenced chunk is too short to hold its sequence number.
+    #[error("animation chunk is too short")]
+    Truncated,
+    /// Sequence numbers must count up from 0 without gaps.
+    #[error("expected sequence number {expected}, found {found}")]
+    SequenceGap { expected: u32, found: u32 },
+    /// acTL declares a different number of frames than there are fcTL chunks.
+    #[error("acTL declares {declared} frames but {found} were found")]
+    FrameCountMismatch { declared: u32, found: usize },
+    /// Payloads cannot be stored under an animation chunk type.
+    #[error("{0} is an APNG chunk type and cannot hold a payload")]
+    ReservedChunkType(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    fn chunk(chunk_type: &[u8; 4], data: Vec<u8>) -> Chunk {
+        Chunk::new(ChunkType::try_from(*chunk_type).unwrap(), data)
+    }
+
+    fn fctl(sequence_number: u32) -> Chunk {
+        let control = FrameControl {
+            sequence_number,
+            width: 1,
+            height: 1,
+            x_offset: 0,
+            y_offset: 0,
+            delay_num: 1,
+            delay_den: 10,
+            dispose_op: DisposeOp::None,
+            blend_op: BlendOp::Source,
+        };
+        chunk(b"fcTL", control.encode())
+    }
+
+    fn fdat(sequence_number: u32, data: &[u8]) -> Chunk {
+        let mut bytes = sequence_number.to_be_bytes().to_vec();
+        bytes.extend(data);
+        chunk(b"fdAT", bytes)
+    }
+
+    /// Two frames, the first of which is also the default image.
+    fn animated_png() -> Png {
+        let actl = AnimationControl {
+            num_frames: 2,
+            num_plays: 0,
+        };
+        Png::from_chunks(vec![
+            chunk(b"IHDR", vec![0; 13]),
+            actl.to_chunk(),
+            fctl(0),
+            chunk(b"IDAT", b"first".to_vec()),
+            fctl(1),
+            fdat(2, b"second"),
+            chunk(b"IEND", Vec::new()),
+        ])
+    }
+
+    #[test]
+    fn test_frames() {
+        let png = animated_png();
+        let frames = png.frames().unwrap();
+        assert_eq!(frames.len(), 2);
+        assert!(frames[0].is_default_image);
+        assert_eq!(frames[0].data, [b"first"]);
+        assert_eq!(frames[1].data, [b"second"]);
+        assert!((frames[1].control.delay() - 0.1).abs() < 1e-9);
+        png.validate_animation().unwrap();
+    }
+
+    #[test]
+    fn test_renumber_after_removing_a_frame() {
+        let mut png = animated_png();
+        png.retain_chunks(|chunk| chunk.chunk_type().bytes() != *b"IDAT");
+        png.retain_chunks(|chunk| {
+            chunk.chunk_type().bytes() != *b"fcTL" || sequence_number(chunk).unwrap() != 0
+        });
+        assert!(png.validate_animation().is_err());
+
+        png.renumber_animation().unwrap();
+        png.validate_animation().unwrap();
+        assert_eq!(png.animation_control().unwrap().unwrap().num_frames, 1);
+    }
+
+    #[test]
+    fn test_safe_insert_position() {
+        let png = animated_png();
+        assert_eq!(png.safe_insert_position(2), 2);
+        assert_eq!(png.safe_insert_position(3), 4);
+        assert_eq!(png.safe_insert_position(5), 6);
+        assert_eq!(png.safe_insert_position(100), 7);
+    }
+}
@@ -0,0 +1,893 @@
+use std::num::NonZeroU64;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use clap::{Args, Parser, Subcommand};
+use clap_complete::Shell;
+
+use congenial_chainsaw::compression::Compression;
+use congenial_chainsaw::config::Config;
+use congenial_chainsaw::crypto::CipherSuite;
+use congenial_chainsaw::payload::Method;
+use congenial_chainsaw::payload_source::{PayloadEncoding, PayloadSource};
+use congenial_chainsaw::server::ServerOptions;
+use congenial_chainsaw::stdio;
+use congenial_chainsaw::summary::OutputFormat;
+
+/// Hide secret messages inside PNG files.
+#[derive(Debug, Parser)]
+#[command(
+    name = "pngme",
+    version,
+    about,
+    after_help = "Use - as a PNG path to read standard input and write standard output.
+
+Exit codes: 0 success, 1 other failure, 2 not found, 3 CRC mismatch, 4 wrong \
+or missing password, key or signature, 5 invalid PNG, 6 I/O error, 64 invalid \
+command line or config."
+)]
+pub struct Cli {
+    /// Read default options from this file instead of
+    /// ~/.config/chainsaw/config.toml.
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+    /// Don't draw progress bars. They are also left out when standard output
+    /// isn't a terminal.
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+    /// Log what is being read and written to standard error: -v for each
+    /// step, -vv for each chunk as well.
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+    /// Keep each file a command replaces, with this suffix appended to its
+    /// name, such as .bak.
+    #[arg(long, global = true, value_name = "SUFFIX")]
+    pub backup: Option<String>,
+    /// Print errors as text, or as JSON objects for scripts.
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t,
+        value_name = "FORMAT"
+    )]
+    pub error_format: OutputFormat,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Hide a message in a new chunk of the given type.
+    Encode(EncodeArgs),
+    /// Embed a file, along with its name, size, modification time and MIME type.
+    EncodeFile(EncodeFileArgs),
+    /// Print the message stored in the first chunk of the given type.
+    Decode(DecodeArgs),
+    /// Restore a file embedded with encode-file under its original name.
+    ExtractFile(ExtractFileArgs),
+    /// Remove the first, all or the Nth chunk of the given type, or a labeled payload.
+    Remove(RemoveArgs),
+    /// List the labeled payloads recorded in the file's index.
+    ListPayloads(ListArgs),
+    /// Print every chunk in the file.
+    Print(ListArgs),
+    /// Show a chunk's data as hex and ASCII, or write it out raw.
+    Dump(DumpArgs),
+    /// List the chunks added, removed or modified between two files.
+    Diff(DiffArgs),
+    /// Measure PSNR and SSIM between two images, to see how visible embedding is.
+    Compare(CompareArgs),
+    /// Report how many payload bytes each embedding method can hide.
+    Capacity(PrintArgs),
+    /// Run chi-square and RS steganalysis to see how detectable LSB embedding is.
+    Analyze(PrintArgs),
+    /// Watch a directory and embed into, or extract from, each new PNG file.
+    Watch(WatchArgs),
+    /// Flag unusual chunks and trailing data that could be hiding a payload.
+    Scan(ScanArgs),
+    /// Count chunks by type and find the largest, to see what bloats a set of
+    /// files.
+    Stats(ScanArgs),
+    /// Check the chunk order against the PNG specification.
+    Lint(PrintArgs),
+    /// Fix bad CRCs, truncated chunks and a missing IEND.
+    Repair(RepairArgs),
+    /// Strip text, EXIF, timestamps, ICC profiles and private chunks, keeping
+    /// only what's needed to draw the image.
+    Scrub(ScrubArgs),
+    /// Merge IDAT chunks, drop duplicate chunks and optionally recompress the
+    /// image data, reporting the bytes saved.
+    Optimize(OptimizeArgs),
+    /// Generate an X25519 identity for public-key encryption, or an Ed25519 signing key.
+    Keygen(KeygenArgs),
+    /// Sign the first chunk of the given type with an Ed25519 key.
+    Sign(SignArgs),
+    /// Verify the signature on the first chunk of the given type and print its
+    /// message, or with no chunk type check every payload's checksum.
+    Verify(VerifyArgs),
+    /// Read and edit tEXt, zTXt and iTXt metadata.
+    Meta(MetaArgs),
+    /// Extract or embed an ICC color profile.
+    Icc(IccArgs),
+    /// Explain what the case of each letter of a chunk type means.
+    Explain(ExplainArgs),
+    /// Print a random chunk type that is safe to hide payloads in.
+    SuggestType,
+    /// Answer encode, decode and scan requests over HTTP, posted as
+    /// multipart forms to /encode, /decode and /scan, or over gRPC with --grpc.
+    Serve(ServeArgs),
+    /// Print a tab-completion script for the given shell.
+    Completions(CompletionsArgs),
+}
+
+/// Chunk type `encode-file` and `extract-file` use unless told otherwise.
+const FILE_CHUNK_TYPE: &str = "fiLe";
+
+impl Command {
+    /// Fills in options left off the command line from `config`.
+    pub fn apply_config(&mut self, config: &Config) {
+        let chunk_type = |chunk_type: &mut Option<String>| {
+            if chunk_type.is_none() {
+                *chunk_type = config.chunk_type.map(|chunk_type| chunk_type.to_string());
+            }
+        };
+        match self {
+            Command::Encode(args) => args.options.apply_config(config),
+            Command::EncodeFile(args) => {
+                chunk_type(&mut args.chunk_type);
+                args.options.apply_config(config);
+            }
+            Command::Decode(args) => args.options.apply_config(config),
+            Command::ExtractFile(args) => {
+                chunk_type(&mut args.chunk_type);
+                args.options.apply_config(config);
+            }
+            Command::Watch(args) => match &mut args.command {
+                WatchCommand::Encode(args) => args.options.apply_config(config),
+                WatchCommand::Decode(args) => args.options.apply_config(config),
+            },
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct EncodeArgs {
+    /// A PNG file, a directory of them, a glob pattern such as 'photos/*.png', -,
+    /// or, with the http and object-store features, an http:// or https:// URL
+    /// or an s3://, gs:// or azure:// URI, ending in / for every PNG under it.
+    pub file_path: PathBuf,
+    #[command(flatten)]
+    pub output: OutputArgs,
+    pub chunk_type: String,
+    #[command(flatten)]
+    pub message: MessageArgs,
+    /// Split the message into this many shares, one for each PNG in the
+    /// directory or glob pattern, so any --threshold of them rebuild it.
+    #[arg(long, requires = "threshold")]
+    pub shares: Option<u8>,
+    /// How many of the --shares are needed to rebuild the message.
+    #[arg(long, requires = "shares")]
+    pub threshold: Option<u8>,
+    #[command(flatten)]
+    pub options: EncodeOptions,
+}
+
+/// The message encode embeds and how it is given.
+#[derive(Debug, Args)]
+pub struct MessageArgs {
+    /// The message, as text unless --hex or --base64 is given.
+    #[arg(required_unless_present_any = ["input_file", "from_clipboard", "from_qr"])]
+    pub message: Option<String>,
+    /// Embed the contents of this file, or of standard input for -, as the
+    /// message.
+    #[arg(long, conflicts_with_all = ["message", "hex", "base64", "from_clipboard"])]
+    pub input_file: Option<PathBuf>,
+    /// Embed the text on the clipboard, read as --hex or --base64 if given.
+    #[arg(long, conflicts_with = "message")]
+    pub from_clipboard: bool,
+    /// Embed the data of the QR code in this PNG image, such as a photo of
+    /// another screen.
+    #[arg(long, conflicts_with_all = ["message", "input_file", "hex", "base64", "from_clipboard"])]
+    pub from_qr: Option<PathBuf>,
+    /// The message is bytes written as hex.
+    #[arg(long, conflicts_with = "base64")]
+    pub hex: bool,
+    /// The message is bytes written as base64.
+    #[arg(long)]
+    pub base64: bool,
+}
+
+impl MessageArgs {
+    pub fn source(&self) -> PayloadSource {
+        let encoding = encoding(self.hex, self.base64);
+        match (&self.input_file, &self.message) {
+            (Some(path), _) => PayloadSource::File(path.clone()),
+            (None, _) if self.from_qr.is_some() => {
+                PayloadSource::Qr(self.from_qr.clone().unwrap_or_default())
+            }
+            (None, _) if self.from_clipboard => PayloadSource::Clipboard(encoding),
+            (None, message) => PayloadSource::Inline {
+                text: message.clone().unwrap_or_default(),
+                encoding,
+            },
+        }
+    }
+}
+
+/// Where and how decode writes the message.
+#[derive(Debug, Args)]
+pub struct MessageOutputArgs {
+    /// Write the message to this file, or to - for standard output, as raw
+    /// bytes. Given several inputs, a directory to write one .bin file per
+    /// PNG into.
+    #[arg(long, conflicts_with_all = ["hex", "base64", "lossy", "binary", "to_clipboard"])]
+    pub output_file: Option<PathBuf>,
+    /// Print the message as hex.
+    #[arg(long, conflicts_with_all = ["base64", "lossy", "binary"])]
+    pub hex: bool,
+    /// Print the message as base64.
+    #[arg(long, conflicts_with_all = ["lossy", "binary"])]
+    pub base64: bool,
+    /// Print a message that isn't UTF-8 anyway, with each invalid sequence
+    /// replaced by U+FFFD.
+    #[arg(long, conflicts_with = "binary")]
+    pub lossy: bool,
+    /// Write the message's raw bytes to standard output, like
+    /// --output-file -.
+    #[arg(long, conflicts_with = "to_clipboard")]
+    pub binary: bool,
+    /// Copy the message to the clipboard instead of printing it, as text
+    /// unless --hex, --base64 or --lossy is given.
+    #[arg(long)]
+    pub to_clipboard: bool,
+    /// Show the message as a QR code in the terminal, for scanning on a
+    /// device that isn't connected.
+    #[arg(
+        long,
+        conflicts_with_all = ["output_file", "hex", "base64", "lossy", "binary", "to_clipboard"]
+    )]
+    pub qr: bool,
+}
+
+impl MessageOutputArgs {
+    pub fn destination(&self) -> Destination<'_> {
+        let encoding = match self.lossy {
+            true => PayloadEncoding::Lossy,
+            false => encoding(self.hex, self.base64),
+        };
+        match (self.binary, &self.output_file) {
+            (true, _) => Destination::File(Path::new(stdio::STDIO_PATH)),
+            (false, Some(path)) => Destination::File(path),
+            _ if self.to_clipboard => Destination::Clipboard(encoding),
+            _ if self.qr => Destination::Qr,
+            _ => Destination::Print(encoding),
+        }
+    }
+}
+
+/// Where decode puts a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Destination<'a> {
+    /// Printed to standard output as text.
+    Print(PayloadEncoding),
+    /// Written to a file as raw bytes, or for several PNGs to a directory
+    /// of files.
+    File(&'a Path),
+    /// Copied to the clipboard as text.
+    Clipboard(PayloadEncoding),
+    /// Shown as a QR code in the terminal.
+    Qr,
+}
+
+fn encoding(hex: bool, base64: bool) -> PayloadEncoding {
+    match (hex, base64) {
+        (true, _) => PayloadEncoding::Hex,
+        (_, true) => PayloadEncoding::Base64,
+        _ => PayloadEncoding::Text,
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct EncodeFileArgs {
+    pub file_path: PathBuf,
+    #[command(flatten)]
+    pub output: OutputArgs,
+    /// File to embed.
+    pub input: PathBuf,
+    /// Chunk type to embed the file in [default: fiLe].
+    #[arg(long)]
+    pub chunk_type: Option<String>,
+    #[command(flatten)]
+    pub options: EncodeOptions,
+}
+
+/// Where a command that changes a PNG writes the result.
+#[derive(Debug, Args)]
+pub struct OutputArgs {
+    /// Write the result here, or to - for standard output, leaving the input
+    /// untouched. Given several inputs, a directory to write them into.
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+impl OutputArgs {
+    /// The path to write to: the output if one was given, else `input`.
+    pub fn path<'a>(&'a self, input: &'a Path) -> &'a Path {
+        self.output.as_deref().unwrap_or(input)
+    }
+
+    /// Whether the result has to be written even if nothing changed, because
+    /// it doesn't go back over `input`.
+    pub fn must_write(&self, input: &Path) -> bool {
+        let path = self.path(input);
+        path != input || stdio::is_stdio(path)
+    }
+}
+
+/// How a payload is compressed and protected before embedding.
+#[derive(Debug, Args)]
+pub struct EncodeOptions {
+    /// Where to hide the payload [default: chunk].
+    #[arg(long, value_enum)]
+    pub method: Option<Method>,
+    /// Store the payload under this label so several can share one chunk type.
+    #[arg(long)]
+    pub label: Option<String>,
+    /// Where to insert the payload chunks: before-idat, after-idat,
+    /// before-iend or index:N [default: before-iend].
+    #[arg(long)]
+    pub position: Option<String>,
+    /// Encrypt the message with a key derived from this password, or with no
+    /// value, one typed at a prompt. Without --recipient or --auth-key,
+    /// $CHAINSAW_PASSWORD is used if this isn't given, which keeps the
+    /// password out of the process list.
+    #[arg(long, num_args = 0..=1, default_missing_value = "")]
+    pub password: Option<String>,
+    /// Encrypt with the password stored in the OS keyring for this account
+    /// under the "chainsaw" service.
+    #[arg(long, value_name = "NAME", conflicts_with = "password")]
+    pub use_keyring: Option<String>,
+    /// Encrypt the message to this hex-encoded X25519 public key.
+    #[arg(long, conflicts_with_all = ["password", "use_keyring"])]
+    pub recipient: Option<String>,
+    /// Store the message in the clear with an HMAC-SHA256 tag keyed by an
+    /// Argon2id hash of this passphrase.
+    #[arg(long, conflicts_with_all = ["password", "use_keyring", "recipient"])]
+    pub auth_key: Option<String>,
+    /// AEAD used when encrypting [default: aes256gcm].
+    #[arg(long, value_enum)]
+    pub cipher: Option<CipherSuite>,
+    /// Compress the message before encrypting or embedding it [default: none].
+    #[arg(long, value_enum)]
+    pub compress: Option<Compression>,
+    /// With --method lsb, scatter the payload over the image in an order
+    /// derived from this passphrase.
+    #[arg(long)]
+    pub lsb_key: Option<String>,
+    /// With --method lsb, let busy areas of the image hold up to 3 bits per
+    /// sample while the PSNR stays at or above this many dB, such as 40.
+    #[arg(long)]
+    pub quality: Option<f64>,
+    /// Fail instead of prompting for a password, for scripts and CI.
+    #[arg(long)]
+    pub no_prompt: bool,
+    /// Store a SHA-256 of the payload so `verify` can check it survived intact.
+    #[arg(long)]
+    pub checksum: bool,
+    /// Set the tIME chunk to now. By default the timestamp is preserved.
+    #[arg(long)]
+    pub touch: bool,
+    /// Derive the salt, nonce and ephemeral key from the inputs instead of
+    /// drawing them at random, so the same message, key and image always
+    /// produce the same bytes. Reveals when two images hold the same message.
+    #[arg(long, conflicts_with = "touch")]
+    pub deterministic: bool,
+    /// Encrypt the payload next to a slot of random bytes that could hold a
+    /// second message, so the password can be given up without revealing
+    /// whether there is one. Needs a password.
+    #[arg(long, conflicts_with_all = ["recipient", "auth_key", "deterministic", "cipher"])]
+    pub deniable: bool,
+    /// Hide this message in that slot, readable with the hidden password
+    /// instead of the password of the decoy payload. Implies --deniable.
+    /// Pick a decoy at least as long, since both are padded to one size.
+    #[arg(
+        long,
+        conflicts_with_all = ["recipient", "auth_key", "deterministic", "cipher"]
+    )]
+    pub hidden: Option<String>,
+    /// The password of the --hidden message, or with no value, one typed at
+    /// a prompt. If this isn't given, $CHAINSAW_HIDDEN_PASSWORD is used, and
+    /// failing that it is asked for.
+    #[arg(long, requires = "hidden", num_args = 0..=1, default_missing_value = "")]
+    pub hidden_password: Option<String>,
+    /// Use the password stored in the OS keyring for this account as the
+    /// --hidden password.
+    #[arg(
+        long,
+        value_name = "NAME",
+        requires = "hidden",
+        conflicts_with = "hidden_password"
+    )]
+    pub hidden_keyring: Option<String>,
+    /// Refuse to decode the payload after this UTC time, such as
+    /// 2024-05-01T12:30:00Z. Only enforced against someone without the key
+    /// when the payload is encrypted or authenticated.
+    #[arg(long, value_name = "TIME")]
+    pub expires: Option<String>,
+    /// Refuse to decode the payload once it has been read this many times.
+    /// Each decode writes the lowered count back into the PNG.
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(1..i64::from(u32::MAX))
+    )]
+    pub max_reads: Option<u32>,
+}
+
+impl EncodeFileArgs {
+    pub fn chunk_type(&self) -> &str {
+        self.chunk_type.as_deref().unwrap_or(FILE_CHUNK_TYPE)
+    }
+}
+
+impl EncodeOptions {
+    fn apply_config(&mut self, config: &Config) {
+        self.method = self.method.or(config.method);
+        if self.position.is_none() {
+            self.position = config.position.map(|position| position.to_string());
+        }
+        self.cipher = self.cipher.or(config.cipher);
+        self.compress = self.compress.or(config.compression);
+        self.checksum |= config.checksum.unwrap_or_default();
+    }
+
+    /// Whether the payload is encrypted with a slot for a hidden message.
+    pub fn deniable(&self) -> bool {
+        self.deniable || self.hidden.is_some()
+    }
+
+    pub fn method(&self) -> Method {
+        self.method.unwrap_or_default()
+    }
+
+    pub fn position(&self) -> &str {
+        self.position.as_deref().unwrap_or("before-iend")
+    }
+
+    pub fn cipher(&self) -> CipherSuite {
+        self.cipher.unwrap_or_default()
+    }
+
+    pub fn compress(&self) -> Compression {
+        self.compress.unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct DecodeArgs {
+    /// A PNG file, a directory of them, a glob pattern such as 'photos/*.png', -,
+    /// or, with the http and object-store features, an http:// or https:// URL
+    /// or an s3://, gs:// or azure:// URI, ending in / for every PNG under it.
+    pub file_path: PathBuf,
+    pub chunk_type: String,
+    #[command(flatten)]
+    pub output: MessageOutputArgs,
+    /// Rebuild a message split with encode --shares from the shares in the
+    /// PNGs of the directory or glob pattern.
+    #[arg(long)]
+    pub combine: bool,
+    #[command(flatten)]
+    pub options: DecodeOptions,
+}
+
+#[derive(Debug, Args)]
+pub struct ExtractFileArgs {
+    pub file_path: PathBuf,
+    /// Chunk type the file was embedded in [default: fiLe].
+    #[arg(long)]
+    pub chunk_type: Option<String>,
+    /// Directory to restore the file into. A file already there under the
+    /// same name is left alone and the extraction fails.
+    #[arg(short, long, default_value = ".")]
+    pub output_dir: PathBuf,
+    #[command(flatten)]
+    pub options: DecodeOptions,
+}
+
+/// How to verify or decrypt a payload after extracting it.
+#[derive(Debug, Args)]
+pub struct DecodeOptions {
+    /// Where the payload was hidden [default: chunk].
+    #[arg(long, value_enum)]
+    pub method: Option<Method>,
+    /// With --method lsb, the passphrase the payload was scattered with.
+    #[arg(long)]
+    pub lsb_key: Option<String>,
+    /// With --method lsb, the --quality the payload was embedded with.
+    #[arg(long)]
+    pub quality: Option<f64>,
+    /// Read the payload stored under this label.
+    #[arg(long)]
+    pub label: Option<String>,
+    /// Decrypt the message with this password, or with no value, one typed
+    /// at a prompt. The cipher is detected automatically. If an encrypted
+    /// payload is found without it, $CHAINSAW_PASSWORD is used, and failing
+    /// that the password is asked for.
+    #[arg(long, num_args = 0..=1, default_missing_value = "")]
+    pub password: Option<String>,
+    /// Decrypt with the password stored in the OS keyring for this account
+    /// under the "chainsaw" service.
+    #[arg(long, value_name = "NAME", conflicts_with = "password")]
+    pub use_keyring: Option<String>,
+    /// Decrypt the message with the identity stored in this file.
+    #[arg(long, conflicts_with_all = ["password", "use_keyring"])]
+    pub identity: Option<PathBuf>,
+    /// Verify the message's HMAC-SHA256 tag with this passphrase.
+    #[arg(long, conflicts_with_all = ["password", "use_keyring", "identity"])]
+    pub auth_key: Option<String>,
+    /// Fail instead of prompting for a password, for scripts and CI.
+    #[arg(long)]
+    pub no_prompt: bool,
+    /// Remove a payload from the PNG when it is found expired, or when its
+    /// last allowed read is used up.
+    #[arg(long)]
+    pub scrub_expired: bool,
+}
+
+impl ExtractFileArgs {
+    pub fn chunk_type(&self) -> &str {
+        self.chunk_type.as_deref().unwrap_or(FILE_CHUNK_TYPE)
+    }
+}
+
+impl DecodeOptions {
+    fn apply_config(&mut self, config: &Config) {
+        self.method = self.method.or(config.method);
+    }
+
+    pub fn method(&self) -> Method {
+        self.method.unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct RemoveArgs {
+    pub file_path: PathBuf,
+    #[command(flatten)]
+    pub output: OutputArgs,
+    pub chunk_type: String,
+    /// Remove every chunk of the payload stored under this label.
+    #[arg(long)]
+    pub label: Option<String>,
+    /// Remove every chunk of the given type instead of only the first.
+    #[arg(long, conflicts_with = "label")]
+    pub all: bool,
+    /// Remove the chunk of the given type at this index, counting from 0.
+    #[arg(long, conflicts_with_all = ["label", "all"])]
+    pub index: Option<usize>,
+    /// Keep unknown unsafe-to-copy chunks even when removing a critical chunk.
+    #[arg(long)]
+    pub keep_unsafe_to_copy: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ScrubArgs {
+    pub file_path: PathBuf,
+    #[command(flatten)]
+    pub output: OutputArgs,
+    /// Also keep chunks of this type. May be repeated.
+    #[arg(long)]
+    pub keep: Vec<String>,
+    /// Report what would be removed without changing the file.
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct OptimizeArgs {
+    pub file_path: PathBuf,
+    #[command(flatten)]
+    pub output: OutputArgs,
+    /// Recompress the image data at this zlib level, from 0 to 9.
+    #[arg(long)]
+    pub level: Option<u32>,
+    /// Recompress the image data with this many iterations of Zopfli, such
+    /// as 15. Much slower than --level 9 but smaller.
+    #[arg(long, conflicts_with = "level")]
+    pub zopfli: Option<NonZeroU64>,
+    /// Report the savings without changing the file.
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct RepairArgs {
+    pub file_path: PathBuf,
+    #[command(flatten)]
+    pub output: OutputArgs,
+    /// Report what would be fixed without changing the file.
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Fail on CRC mismatches instead of recomputing them.
+    #[arg(long)]
+    pub keep_crcs: bool,
+    /// Fail on a truncated chunk instead of dropping it.
+    #[arg(long)]
+    pub keep_truncated: bool,
+    /// Don't append an IEND chunk if one is missing.
+    #[arg(long)]
+    pub no_iend: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct WatchArgs {
+    /// Directory to watch, along with its subdirectories.
+    pub dir: PathBuf,
+    #[command(subcommand)]
+    pub command: WatchCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum WatchCommand {
+    /// Hide the same message in each new file.
+    Encode(WatchEncodeArgs),
+    /// Print the message hidden in each new file.
+    Decode(WatchDecodeArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct WatchEncodeArgs {
+    pub chunk_type: String,
+    pub message: String,
+    #[command(flatten)]
+    pub options: EncodeOptions,
+}
+
+#[derive(Debug, Args)]
+pub struct WatchDecodeArgs {
+    pub chunk_type: String,
+    #[command(flatten)]
+    pub options: DecodeOptions,
+}
+
+#[derive(Debug, Args)]
+pub struct ScanArgs {
+    /// A PNG file, a directory to search for PNG files recursively, a glob
+    /// pattern such as 'photos/**/*.png', or with the object-store feature an
+    /// s3://, gs:// or azure:// URI ending in / for every PNG under it.
+    pub path: PathBuf,
+    #[arg(long, value_enum, default_value_t)]
+    pub format: OutputFormat,
+}
+
+#[derive(Debug, Args)]
+pub struct ListArgs {
+    pub file_path: PathBuf,
+    #[arg(long, value_enum, default_value_t)]
+    pub format: OutputFormat,
+}
+
+#[derive(Debug, Args)]
+pub struct DumpArgs {
+    pub file_path: PathBuf,
+    pub chunk_type: String,
+    /// Which chunk of the given type to dump, counting from 0.
+    #[arg(long, default_value_t = 0)]
+    pub index: usize,
+    /// Write the chunk's data to standard output unchanged.
+    #[arg(long)]
+    pub raw: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct DiffArgs {
+    pub old_path: PathBuf,
+    pub new_path: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct CompareArgs {
+    pub original_path: PathBuf,
+    pub modified_path: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct ExplainArgs {
+    /// A four-letter chunk type, such as ruSt.
+    pub chunk_type: String,
+}
+
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    /// Address to listen on.
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    pub listen: String,
+    /// Largest upload accepted, in bytes.
+    #[arg(long, default_value_t = ServerOptions::default().max_body)]
+    pub max_body: u64,
+    /// Seconds a client may take to send a whole request, or stall while
+    /// reading the reply, before it is given up on.
+    #[arg(
+        long,
+        default_value_t = ServerOptions::default().timeout.as_secs(),
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub timeout: u64,
+    /// Requests handled at once.
+    #[arg(
+        long,
+        default_value_t = ServerOptions::default().workers,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub workers: usize,
+    /// Speak gRPC, as defined in proto/pngme.proto, instead of HTTP forms.
+    #[arg(long)]
+    pub grpc: bool,
+}
+
+impl ServeArgs {
+    pub fn options(&self) -> ServerOptions {
+        ServerOptions {
+            max_body: self.max_body,
+            timeout: Duration::from_secs(self.timeout),
+            workers: self.workers,
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct CompletionsArgs {
+    #[arg(value_enum)]
+    pub shell: Shell,
+}
+
+#[derive(Debug, Args)]
+pub struct PrintArgs {
+    pub file_path: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct KeygenArgs {
+    /// Write the key to this new file, readable only by its owner, instead of
+    /// stdout. An existing file is never replaced.
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    /// Generate an Ed25519 signing key instead of an X25519 identity.
+    #[arg(long)]
+    pub signing: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct SignArgs {
+    pub file_path: PathBuf,
+    #[command(flatten)]
+    pub output: OutputArgs,
+    pub chunk_type: String,
+    /// Key file written by `keygen --signing`.
+    #[arg(long)]
+    pub key: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct VerifyArgs {
+    pub file_path: PathBuf,
+    /// Chunk type holding a signed message.
+    #[arg(requires = "public_key")]
+    pub chunk_type: Option<String>,
+    /// Hex-encoded Ed25519 public key of the expected signer.
+    #[arg(long, requires = "chunk_type")]
+    pub public_key: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct MetaArgs {
+    #[command(subcommand)]
+    pub command: MetaCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum MetaCommand {
+    /// Store a keyword/value pair, replacing any existing value.
+    Set(MetaSetArgs),
+    /// Print the value of one keyword, or every keyword if none is given.
+    Get(MetaGetArgs),
+    /// Delete every text chunk with the given keyword.
+    Del(MetaDelArgs),
+    /// Print or update the tIME last-modified timestamp.
+    Time(MetaTimeArgs),
+    /// Print the common tags of the eXIf chunk, or strip or replace it.
+    Exif(MetaExifArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct MetaSetArgs {
+    pub file_path: PathBuf,
+    #[command(flatten)]
+    pub output: OutputArgs,
+    /// 1 to 79 printable Latin-1 characters, such as "Author" or "Title".
+    pub keyword: String,
+    pub value: String,
+    /// Store as iTXt with this language tag, such as "en" or "fr-CA".
+    #[arg(long)]
+    pub language: Option<String>,
+    /// Store as iTXt with the keyword translated into the language.
+    #[arg(long)]
+    pub translated_keyword: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct MetaGetArgs {
+    pub file_path: PathBuf,
+    pub keyword: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct MetaDelArgs {
+    pub file_path: PathBuf,
+    #[command(flatten)]
+    pub output: OutputArgs,
+    pub keyword: String,
+}
+
+#[derive(Debug, Args)]
+pub struct MetaTimeArgs {
+    pub file_path: PathBuf,
+    #[command(flatten)]
+    pub output: OutputArgs,
+    /// Set the timestamp to now.
+    #[arg(long)]
+    pub touch: bool,
+    /// Set the timestamp to this UTC time, such as 2024-05-01T12:30:00Z.
+    #[arg(long, conflicts_with = "touch")]
+    pub set: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct MetaExifArgs {
+    pub file_path: PathBuf,
+    #[command(flatten)]
+    pub output: OutputArgs,
+    /// Remove the EXIF metadata.
+    #[arg(long)]
+    pub strip: bool,
+    /// Replace the EXIF metadata with the raw TIFF data in this file.
+    #[arg(long, conflicts_with = "strip")]
+    pub replace: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct IccArgs {
+    #[command(subcommand)]
+    pub command: IccCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum IccCommand {
+    /// Write the embedded ICC profile to a file.
+    Extract(IccExtractArgs),
+    /// Embed an ICC profile, replacing any existing profile or sRGB chunk.
+    Embed(IccEmbedArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct IccExtractArgs {
+    pub file_path: PathBuf,
+    /// File to write the profile to, or - for standard output. Defaults to the
+    /// profile name with an .icc extension.
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct IccEmbedArgs {
+    pub file_path: PathBuf,
+    #[command(flatten)]
+    pub output: OutputArgs,
+    /// ICC profile to embed.
+    pub profile: PathBuf,
+    /// Profile name. Defaults to the profile's file name without its extension.
+    #[arg(long)]
+    pub name: Option<String>,
+}
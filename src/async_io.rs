@@ -0,0 +1,160 @@
+//! Reading and writing PNGs without blocking a tokio runtime, built with the
+//! `tokio` feature.
+
+use std::io;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::{ParseOptions, Png, PngError};
+use crate::{PngMeError, Result};
+
+/// Reads chunks one at a time from any [`AsyncRead`] source, checking them
+/// as [`PngReader`](crate::reader::PngReader) does.
+#[derive(Debug)]
+pub struct AsyncPngReader<R> {
+    inner: R,
+    options: ParseOptions,
+    offset: u64,
+    seen_iend: bool,
+}
+
+impl<R: AsyncRead + Unpin> AsyncPngReader<R> {
+    /// Wraps `inner`, consuming and validating the PNG signature.
+    pub async fn new(inner: R) -> Result<Self> {
+        AsyncPngReader::with_options(inner, ParseOptions::default()).await
+    }
+
+    /// Like [`AsyncPngReader::new`], checking chunks as `options` require.
+    pub async fn with_options(mut inner: R, options: ParseOptions) -> Result<Self> {
+        let mut signature = [0; 8];
+        read_exact(&mut inner, &mut signature)
+            .await
+            .map_err(|_| PngMeError::InvalidSignature)?;
+        if signature != Png::STANDARD_HEADER {
+            return Err(PngMeError::InvalidSignature);
+        }
+        Ok(AsyncPngReader {
+            inner,
+            options,
+            offset: Png::STANDARD_HEADER.len() as u64,
+            seen_iend: false,
+        })
+    }
+
+    /// Reads the next chunk, or returns `None` at a clean end of input, or
+    /// after IEND if trailing garbage is allowed.
+    pub async fn next_chunk(&mut self) -> Result<Option<Chunk>> {
+        if self.seen_iend && self.options.allow_trailing_garbage {
+            return Ok(None);
+        }
+        let mut header = [0; 8];
+        match self.inner.read(&mut header[..1]).await? {
+            0 => return Ok(None),
+            _ => read_exact(&mut self.inner, &mut header[1..]).await?,
+        }
+
+        let length = u32::from_be_bytes(header[..4].try_into()?);
+        self.options.check_length(length)?;
+        self.options.check_total(self.offset, length)?;
+        let chunk_type = self.options.chunk_type(header[4..].try_into()?)?;
+        self.options.check_critical(&chunk_type, self.offset)?;
+
+        // Read through `take` so a bogus length can't trigger a huge up-front allocation.
+        let mut data = Vec::new();
+        (&mut self.inner)
+            .take(length.into())
+            .read_to_end(&mut data)
+            .await?;
+        if data.len() != length as usize {
+            return Err(PngError::Truncated.into());
+        }
+
+        let mut crc = [0; 4];
+        read_exact(&mut self.inner, &mut crc).await?;
+        self.seen_iend = chunk_type == ChunkType::IEND;
+        let chunk = Chunk::from_parts(chunk_type, data, u32::from_be_bytes(crc), &self.options)?
+            .at_offset(self.offset);
+        self.offset += chunk.total_size();
+        Ok(Some(chunk))
+    }
+
+    /// Returns the underlying reader, positioned after the last chunk read.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl Png {
+    /// Parses a PNG from an async reader, such as an upload body, one chunk
+    /// at a time.
+    pub async fn from_async_reader<R: AsyncRead + Unpin>(reader: R) -> Result<Png> {
+        Png::from_async_reader_with(reader, ParseOptions::default()).await
+    }
+
+    /// Like [`Png::from_async_reader`], checking the input as `options`
+    /// require.
+    pub async fn from_async_reader_with<R: AsyncRead + Unpin>(
+        reader: R,
+        options: ParseOptions,
+    ) -> Result<Png> {
+        let mut reader = AsyncPngReader::with_options(reader, options).await?;
+        let mut chunks = Vec::new();
+        while let Some(chunk) = reader.next_chunk().await? {
+            chunks.push(chunk);
+        }
+        Ok(Png::from_chunks(chunks))
+    }
+
+    /// Streams the signature and every chunk to `writer`, returning it once
+    /// flushed.
+    pub async fn write_async<W: AsyncWrite + Unpin>(&self, mut writer: W) -> Result<W> {
+        writer.write_all(&Png::STANDARD_HEADER).await?;
+        for chunk in self.chunks() {
+            writer.write_all(&chunk.as_bytes()).await?;
+        }
+        writer.flush().await?;
+        Ok(writer)
+    }
+}
+
+/// Like [`AsyncReadExt::read_exact`], but reports a short read as a truncated
+/// PNG.
+async fn read_exact<R: AsyncRead + Unpin>(reader: &mut R, buf: &mut [u8]) -> Result<()> {
+    match reader.read_exact(buf).await {
+        Ok(_) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Err(PngError::Truncated.into()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::minimal_png;
+
+    fn png() -> Png {
+        let mut png = minimal_png();
+        png.append_chunk(Chunk::new(
+            ChunkType::new_unchecked(*b"ruSt"),
+            b"async".to_vec(),
+        ));
+        png
+    }
+
+    #[tokio::test]
+    async fn test_round_trip() {
+        let bytes = png().write_async(Vec::new()).await.unwrap();
+        assert_eq!(bytes, png().as_bytes());
+        let parsed = Png::from_async_reader(bytes.as_slice()).await.unwrap();
+        assert_eq!(parsed.chunks(), png().chunks());
+    }
+
+    #[tokio::test]
+    async fn test_truncated() {
+        let bytes = png().as_bytes();
+        let result = Png::from_async_reader(&bytes[..bytes.len() - 2]).await;
+        assert!(matches!(result, Err(PngMeError::Png(PngError::Truncated))));
+    }
+}
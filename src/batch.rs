@@ -0,0 +1,138 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use thiserror::Error;
+
+use crate::cloud;
+use crate::http;
+use crate::Result;
+
+/// The files a path argument names: every `.png` file under a directory,
+/// every match of a glob pattern such as `photos/*.png`, or `None` for a
+/// plain path, which commands treat as a single file.
+pub fn targets(path: &Path) -> Result<Option<Vec<PathBuf>>> {
+    if cloud::is_object_url(path) {
+        let url = path.to_string_lossy();
+        return match url.ends_with('/') {
+            true => cloud::list_pngs(&url).map(Some),
+            false => Ok(None),
+        };
+    }
+    if http::is_url(path) {
+        return Ok(None);
+    }
+    let pattern = path.to_string_lossy();
+    if pattern.contains(['*', '?', '[']) {
+        let mut files = glob::glob(&pattern)?.collect::<std::result::Result<Vec<_>, _>>()?;
+        files.retain(|file| file.is_file());
+        files.sort();
+        return Ok(Some(files));
+    }
+    match path.is_dir() {
+        true => png_files(path).map(Some),
+        false => Ok(None),
+    }
+}
+
+/// Runs `op` on every file, in parallel with the `parallel` feature,
+/// returning each file's result in the order given. Errors are kept as
+/// messages so they can cross threads.
+pub fn run<T, F>(files: &[PathBuf], op: F) -> Vec<(PathBuf, std::result::Result<T, String>)>
+where
+    T: Send,
+    F: Fn(&Path) -> Result<T> + Sync,
+{
+    #[cfg(feature = "parallel")]
+    let files = files.par_iter();
+    #[cfg(not(feature = "parallel"))]
+    let files = files.iter();
+    files
+        .map(|file| (file.clone(), op(file).map_err(|err| err.to_string())))
+        .collect()
+}
+
+/// Where the result for `file`, one of the [targets](targets) of `target`,
+/// goes inside the output directory `dir`: at the same path relative to
+/// `dir` as `file` has to a `target` directory, or under its file name for
+/// the matches of a glob pattern.
+pub fn output_path(target: &Path, file: &Path, dir: &Path) -> PathBuf {
+    match file.strip_prefix(target) {
+        Ok(relative) => dir.join(relative),
+        Err(_) => dir.join(file.file_name().unwrap_or(file.as_os_str())),
+    }
+}
+
+/// Every file with a .png extension under `dir`, recursively, in sorted order.
+pub fn png_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(png_files(&path)?);
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+        {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Raised when some files in a batch could not be processed.
+#[derive(Debug, Error)]
+#[error("{failed} of {total} files failed")]
+pub struct BatchError {
+    pub failed: usize,
+    pub total: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn test_directory_targets() {
+        let dir = std::env::temp_dir().join(format!("pngme-batch-{}", std::process::id()));
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        for name in ["b.png", "a.PNG", "notes.txt", "nested/c.png"] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+
+        let files = targets(&dir).unwrap().unwrap();
+        assert_eq!(
+            files,
+            [
+                dir.join("a.PNG"),
+                dir.join("b.png"),
+                dir.join("nested/c.png")
+            ]
+        );
+        assert!(targets(&dir.join("b.png")).unwrap().is_none());
+
+        let results = run(&files, |file| match file.ends_with("b.png") {
+            true => Err(io::Error::other("refused").into()),
+            false => Ok(()),
+        });
+        assert!(results[0].1.is_ok());
+        assert_eq!(results[1].1, Err("refused".to_string()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_output_path() {
+        let out = Path::new("out");
+        assert_eq!(
+            output_path(Path::new("photos"), Path::new("photos/2024/a.png"), out),
+            Path::new("out/2024/a.png")
+        );
+        assert_eq!(
+            output_path(Path::new("photos/*.png"), Path::new("photos/b.png"), out),
+            Path::new("out/b.png")
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use std::fmt;
+
+use crate::crypto;
+use crate::envelope;
+use crate::lsb;
+use crate::payload::{Method, MAX_CHUNK_DATA};
+use crate::png::Png;
+use crate::Result;
+
+/// How a payload is protected, which decides how many bytes it grows by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protection {
+    None,
+    Password,
+    Recipient,
+    Authenticated,
+}
+
+impl Protection {
+    pub const ALL: [Protection; 4] = [
+        Protection::None,
+        Protection::Password,
+        Protection::Recipient,
+        Protection::Authenticated,
+    ];
+
+    /// Bytes this protection adds, plus the payload envelope every embedded
+    /// payload carries.
+    pub fn overhead(self) -> usize {
+        let protection = match self {
+            Protection::None => 0,
+            Protection::Password => crypto::OVERHEAD,
+            Protection::Recipient => crypto::RECIPIENT_OVERHEAD,
+            Protection::Authenticated => crypto::AUTH_OVERHEAD,
+        };
+        envelope::HEADER_LEN + protection
+    }
+}
+
+impl fmt::Display for Protection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Protection::None => "unprotected",
+            Protection::Password => "password",
+            Protection::Recipient => "recipient",
+            Protection::Authenticated => "authenticated",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// How much a PNG can hide with one embedding method.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capacity {
+    pub method: Method,
+    /// Bytes the carrier can hold before any payload framing, or `None` if
+    /// there is no practical limit.
+    pub carrier: Option<u64>,
+}
+
+impl Capacity {
+    /// The largest message that fits with the given protection, before
+    /// compression, or `None` if there is no practical limit.
+    pub fn payload_bytes(&self, protection: Protection) -> Option<u64> {
+        self.carrier
+            .map(|carrier| carrier.saturating_sub(protection.overhead() as u64))
+    }
+}
+
+impl Png {
+    /// Reports how many bytes can be hidden with `method`.
+    ///
+    /// Chunk payloads are split across as many chunks as needed, so their
+    /// size is limited only by the file system, as are trailers. LSB payloads
+    /// get one bit per color sample, less a length prefix.
+    pub fn capacity(&self, method: Method) -> Result<Capacity> {
+        let carrier = match method {
+            Method::Chunk | Method::Trailer => None,
+            Method::Lsb => Some(lsb::payload_capacity(&self.ihdr()?)? as u64),
+        };
+        Ok(Capacity { method, carrier })
+    }
+}
+
+impl fmt::Display for Capacity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.carrier {
+            None if self.method == Method::Chunk => writeln!(
+                f,
+                "{:?}: unlimited ({MAX_CHUNK_DATA} bytes per chunk, any number of chunks)",
+                self.method
+            )?,
+            None => writeln!(f, "{:?}: unlimited", self.method)?,
+            Some(carrier) => {
+                writeln!(f, "{:?}: {carrier} bytes", self.method)?;
+                for protection in Protection::ALL {
+                    let usable = self.payload_bytes(protection).unwrap_or_default();
+                    writeln!(f, "  {protection}: {usable} bytes")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::ihdr::{ColorType, Ihdr};
+
+    #[test]
+    fn test_lsb_capacity_accounts_for_overhead() {
+        let ihdr = Ihdr::new(100, 100, 8, ColorType::Rgba).unwrap();
+        let png = Png::from_chunks(vec![ihdr.to_chunk()]);
+
+        let capacity = png.capacity(Method::Lsb).unwrap();
+        // 3 color channels per pixel, one bit each, less the length prefix.
+        assert_eq!(capacity.carrier, Some(100 * 100 * 3 / 8 - 4));
+        assert_eq!(
+            capacity.payload_bytes(Protection::Password),
+            Some(3750 - 4 - envelope::HEADER_LEN as u64 - crypto::OVERHEAD as u64)
+        );
+        assert_eq!(png.capacity(Method::Chunk).unwrap().carrier, None);
+    }
+
+    #[test]
+    fn test_tiny_image_has_no_room() {
+        let ihdr = Ihdr::new(1, 1, 8, ColorType::Rgb).unwrap();
+        let png = Png::from_chunks(vec![
+            ihdr.to_chunk(),
+            Chunk::new(ChunkType::try_from(*b"IEND").unwrap(), Vec::new()),
+        ]);
+        let capacity = png.capacity(Method::Lsb).unwrap();
+        assert_eq!(capacity.payload_bytes(Protection::Recipient), Some(0));
+    }
+}
@@ -0,0 +1,181 @@
+//! C bindings, built with the `capi` feature. The matching declarations are in
+//! `include/pngme.h`, generated with
+//! `cbindgen --config cbindgen.toml --output include/pngme.h`.
+//!
+//! Every function returns [`PNGME_OK`] or [`PNGME_ERROR`]. After an error,
+//! [`pngme_last_error`] describes what went wrong on the calling thread.
+//! Buffers returned through `out`/`out_len` belong to the caller, who
+//! releases them with [`pngme_free`].
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::{ptr, slice};
+
+use crate::{message, Result};
+
+/// The call succeeded.
+pub const PNGME_OK: c_int = 0;
+/// The call failed; see `pngme_last_error`.
+pub const PNGME_ERROR: c_int = -1;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Hides the NUL-terminated `message` in the PNG of `png_len` bytes at `png`,
+/// in chunks of type `chunk_type`, and hands the new file back through `out`
+/// and `out_len`.
+///
+/// # Safety
+///
+/// `png` must point to `png_len` readable bytes, `chunk_type` and `message`
+/// must be NUL-terminated strings, and `out` and `out_len` must be valid for
+/// writes.
+#[no_mangle]
+pub unsafe extern "C" fn pngme_encode(
+    png: *const u8,
+    png_len: usize,
+    chunk_type: *const c_char,
+    message: *const c_char,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> c_int {
+    let result = (|| {
+        let chunk_type = CStr::from_ptr(chunk_type).to_str()?;
+        let message = CStr::from_ptr(message).to_bytes();
+        message::embed(input(png, png_len), chunk_type, message)
+    })();
+    finish(result, out, out_len)
+}
+
+/// Reveals the message hidden in the PNG of `png_len` bytes at `png`, in
+/// chunks of type `chunk_type`, and hands it back through `out` and
+/// `out_len`. The message is not NUL-terminated and may contain NUL bytes.
+///
+/// # Safety
+///
+/// `png` must point to `png_len` readable bytes, `chunk_type` must be a
+/// NUL-terminated string, and `out` and `out_len` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn pngme_decode(
+    png: *const u8,
+    png_len: usize,
+    chunk_type: *const c_char,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> c_int {
+    let result = (|| {
+        let chunk_type = CStr::from_ptr(chunk_type).to_str()?;
+        message::extract(input(png, png_len), chunk_type)
+    })();
+    finish(result, out, out_len)
+}
+
+/// Releases a buffer returned by `pngme_encode` or `pngme_decode`.
+/// Passing a null pointer does nothing.
+///
+/// # Safety
+///
+/// `buf` and `len` must be exactly as returned, and each buffer may be freed
+/// only once.
+#[no_mangle]
+pub unsafe extern "C" fn pngme_free(buf: *mut u8, len: usize) {
+    if !buf.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(buf, len)));
+    }
+}
+
+/// Describes the last error raised on the calling thread, or returns null if
+/// there has been none. The string stays valid until the next failing call on
+/// the same thread.
+#[no_mangle]
+pub extern "C" fn pngme_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Borrows the caller's input, which may be null when it is empty.
+unsafe fn input<'a>(png: *const u8, png_len: usize) -> &'a [u8] {
+    match png.is_null() {
+        true => &[],
+        false => slice::from_raw_parts(png, png_len),
+    }
+}
+
+/// Hands `result` to the caller as a status code, an output buffer, and
+/// possibly a new last error.
+unsafe fn finish(result: Result<Vec<u8>>, out: *mut *mut u8, out_len: *mut usize) -> c_int {
+    match result {
+        Ok(bytes) => {
+            let bytes = Box::into_raw(bytes.into_boxed_slice());
+            *out_len = bytes.len();
+            *out = bytes.cast();
+            PNGME_OK
+        }
+        Err(err) => {
+            // Error messages never contain NUL, but don't panic across the
+            // boundary if one ever does.
+            let message = CString::new(err.to_string()).unwrap_or_default();
+            LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+            *out = ptr::null_mut();
+            *out_len = 0;
+            PNGME_ERROR
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::minimal_png;
+
+    #[test]
+    fn test_round_trip() {
+        let png = minimal_png().as_bytes();
+        let (mut encoded, mut encoded_len) = (ptr::null_mut(), 0);
+        let (mut decoded, mut decoded_len) = (ptr::null_mut(), 0);
+        unsafe {
+            let status = pngme_encode(
+                png.as_ptr(),
+                png.len(),
+                c"ruSt".as_ptr(),
+                c"hello from C".as_ptr(),
+                &mut encoded,
+                &mut encoded_len,
+            );
+            assert_eq!(status, PNGME_OK);
+            let status = pngme_decode(
+                encoded,
+                encoded_len,
+                c"ruSt".as_ptr(),
+                &mut decoded,
+                &mut decoded_len,
+            );
+            assert_eq!(status, PNGME_OK);
+            assert_eq!(slice::from_raw_parts(decoded, decoded_len), b"hello from C");
+            pngme_free(encoded, encoded_len);
+            pngme_free(decoded, decoded_len);
+        }
+    }
+
+    #[test]
+    fn test_error() {
+        let (mut out, mut out_len) = (ptr::null_mut(), 0);
+        let status = unsafe {
+            pngme_decode(
+                b"not a png".as_ptr(),
+                9,
+                c"ruSt".as_ptr(),
+                &mut out,
+                &mut out_len,
+            )
+        };
+        assert_eq!(status, PNGME_ERROR);
+        assert!(out.is_null());
+        let message = unsafe { CStr::from_ptr(pngme_last_error()) };
+        assert_eq!(message.to_str().unwrap(), "input is not a PNG file");
+    }
+}
@@ -0,0 +1,603 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use thiserror::Error;
+
+use crate::chunk_type::ChunkType;
+use crate::png::ParseOptions;
+use crate::signing;
+use crate::{PngMeError, Result};
+
+/// The largest data length the PNG specification allows in one chunk,
+/// 2^31 - 1. [`ParseOptions::max_chunk_size`] can lower the limit for
+/// untrusted input.
+pub const MAX_CHUNK_LEN: u32 = i32::MAX as u32;
+
+/// Computes the PNG CRC of `bytes`, for checksums outside chunk framing.
+///
+/// PNG uses the same CRC-32 as zlib. crc32fast picks a SIMD implementation
+/// at runtime where the CPU has one.
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    crc32fast::hash(bytes)
+}
+
+/// A single PNG chunk: length, type, data and CRC.
+///
+/// See section 3.2 of the PNG specification for the on-disk layout.
+///
+/// Two chunks are equal, and hash alike, when their type, data and CRC are,
+/// wherever they were read from.
+#[derive(Clone)]
+pub struct Chunk {
+    length: u32,
+    chunk_type: ChunkType,
+    data: Vec<u8>,
+    crc: u32,
+    offset: Option<u64>,
+}
+
+impl Chunk {
+    /// Bytes taken by the length, type and CRC fields around the data.
+    pub const OVERHEAD: usize = 12;
+
+    /// Builds a chunk from its type and data, computing length and CRC.
+    ///
+    /// # Panics
+    ///
+    /// If `data` is longer than [`MAX_CHUNK_LEN`]. Use [`Chunk::try_new`]
+    /// for data of any other origin than this crate.
+    pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
+        Chunk::try_new(chunk_type, data).expect("chunk data exceeds MAX_CHUNK_LEN bytes")
+    }
+
+    /// Like [`Chunk::new`], failing with [`PngMeError::ChunkTooLarge`] if
+    /// `data` is longer than [`MAX_CHUNK_LEN`].
+    pub fn try_new(chunk_type: ChunkType, data: Vec<u8>) -> Result<Chunk> {
+        let length = u32::try_from(data.len())
+            .ok()
+            .filter(|&length| length <= MAX_CHUNK_LEN)
+            .ok_or(PngMeError::ChunkTooLarge {
+                length: u32::try_from(data.len()).unwrap_or(u32::MAX),
+                max: MAX_CHUNK_LEN,
+            })?;
+        let crc = Self::compute_crc(&chunk_type, &data);
+        Ok(Chunk {
+            length,
+            chunk_type,
+            data,
+            crc,
+            offset: None,
+        })
+    }
+
+    /// Number of bytes in the data field.
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+
+    pub fn chunk_type(&self) -> &ChunkType {
+        &self.chunk_type
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+
+    /// Byte offset of the chunk's length field in the input it was parsed
+    /// from, or `None` for a chunk built in memory.
+    pub fn offset(&self) -> Option<u64> {
+        self.offset
+    }
+
+    /// Bytes the chunk takes when serialized, framing included.
+    pub fn total_size(&self) -> u64 {
+        (Self::OVERHEAD + self.data.len()) as u64
+    }
+
+    /// Records where the chunk was found in its input.
+    pub(crate) fn at_offset(mut self, offset: u64) -> Chunk {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// Interprets the chunk data as a UTF-8 string. Fails with
+    /// [`ChunkError::InvalidUtf8`] if it isn't one.
+    pub fn data_as_string(&self) -> Result<String> {
+        String::from_utf8(self.data.clone()).map_err(|err| {
+            ChunkError::InvalidUtf8 {
+                chunk_type: self.chunk_type.to_string(),
+                offset: err.utf8_error().valid_up_to(),
+            }
+            .into()
+        })
+    }
+
+    /// Serializes the chunk as length, type, data and CRC, all integers big-endian.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        self.length
+            .to_be_bytes()
+            .iter()
+            .chain(self.chunk_type.bytes().iter())
+            .chain(self.data.iter())
+            .chain(self.crc.to_be_bytes().iter())
+            .copied()
+            .collect()
+    }
+
+    /// Builds a chunk whose data is `payload` followed by an Ed25519
+    /// signature over the chunk type and payload.
+    pub fn new_signed(chunk_type: ChunkType, payload: &[u8], key: &SigningKey) -> Chunk {
+        let data = signing::sign(key, &chunk_type, payload);
+        Chunk::new(chunk_type, data)
+    }
+
+    /// Verifies the signature written by [`Chunk::new_signed`] and returns
+    /// the payload it covers.
+    pub fn signed_payload(&self, key: &VerifyingKey) -> Result<&[u8]> {
+        signing::verify(key, &self.chunk_type, &self.data)
+    }
+
+    /// Builds a chunk from parsed fields, failing if `stored_crc` does not
+    /// match the CRC computed over type and data.
+    pub(crate) fn with_stored_crc(
+        chunk_type: ChunkType,
+        data: Vec<u8>,
+        stored_crc: u32,
+    ) -> Result<Chunk> {
+        let chunk = Chunk::try_new(chunk_type, data)?;
+        if chunk.crc != stored_crc {
+            tracing::debug!(
+                chunk_type = %chunk.chunk_type,
+                expected = chunk.crc,
+                found = stored_crc,
+                "CRC mismatch"
+            );
+            return Err(PngMeError::CrcMismatch {
+                chunk_type: chunk.chunk_type.to_string(),
+                expected: chunk.crc,
+                found: stored_crc,
+            });
+        }
+        Ok(chunk)
+    }
+
+    /// Builds a chunk from parsed fields, checking `stored_crc` only if
+    /// `options` ask for it. Otherwise a wrong CRC is kept as-is, so the chunk
+    /// serializes back to the bytes it was read from.
+    pub(crate) fn from_parts(
+        chunk_type: ChunkType,
+        data: Vec<u8>,
+        stored_crc: u32,
+        options: &ParseOptions,
+    ) -> Result<Chunk> {
+        if options.strict_crc {
+            return Chunk::with_stored_crc(chunk_type, data, stored_crc);
+        }
+        let mut chunk = Chunk::try_new(chunk_type, data)?;
+        if chunk.crc != stored_crc {
+            tracing::debug!(
+                chunk_type = %chunk.chunk_type,
+                expected = chunk.crc,
+                found = stored_crc,
+                "CRC mismatch, keeping the stored CRC"
+            );
+        }
+        chunk.crc = stored_crc;
+        Ok(chunk)
+    }
+
+    /// Returns false for a chunk parsed without CRC checks whose stored CRC
+    /// does not match its contents.
+    pub fn has_valid_crc(&self) -> bool {
+        self.crc == Self::compute_crc(&self.chunk_type, &self.data)
+    }
+
+    /// Like [`Chunk::has_valid_crc`], but reports a mismatch as
+    /// [`PngMeError::CrcMismatch`].
+    pub fn verify_crc(&self) -> Result<()> {
+        let computed = Self::compute_crc(&self.chunk_type, &self.data);
+        if self.crc != computed {
+            tracing::debug!(
+                chunk_type = %self.chunk_type,
+                expected = computed,
+                found = self.crc,
+                "CRC mismatch"
+            );
+            return Err(PngMeError::CrcMismatch {
+                chunk_type: self.chunk_type.to_string(),
+                expected: computed,
+                found: self.crc,
+            });
+        }
+        Ok(())
+    }
+
+    /// Parses exactly one serialized chunk, checking it as `options` require.
+    pub fn from_bytes_with(bytes: &[u8], options: &ParseOptions) -> Result<Chunk> {
+        ChunkRef::from_bytes_with(bytes, options).map(|chunk| chunk.to_chunk())
+    }
+
+    /// Borrows the chunk as a [`ChunkRef`].
+    pub fn as_chunk_ref(&self) -> ChunkRef<'_> {
+        ChunkRef {
+            chunk_type: self.chunk_type,
+            data: &self.data,
+            crc: self.crc,
+            offset: self.offset,
+        }
+    }
+
+    fn compute_crc(chunk_type: &ChunkType, data: &[u8]) -> u32 {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&chunk_type.bytes());
+        hasher.update(data);
+        hasher.finalize()
+    }
+}
+
+/// A chunk whose data is borrowed from the input it was parsed from, so
+/// finding one chunk in a large image doesn't copy every other chunk's data.
+///
+/// [`ChunkRef::to_chunk`] copies it into an owned [`Chunk`]. Like chunks,
+/// two refs are equal when their type, data and CRC are.
+#[derive(Debug, Clone, Copy)]
+pub struct ChunkRef<'a> {
+    chunk_type: ChunkType,
+    data: &'a [u8],
+    crc: u32,
+    offset: Option<u64>,
+}
+
+impl<'a> ChunkRef<'a> {
+    /// Parses exactly one serialized chunk without copying its data,
+    /// checking it as `options` require.
+    pub fn from_bytes_with(bytes: &'a [u8], options: &ParseOptions) -> Result<ChunkRef<'a>> {
+        if bytes.len() < Chunk::OVERHEAD {
+            return Err(ChunkError::TooShort(bytes.len()).into());
+        }
+
+        let (length, rest) = bytes.split_at(4);
+        let length = u32::from_be_bytes(length.try_into()?);
+        options.check_length(length)?;
+        let (chunk_type, rest) = rest.split_at(4);
+        let chunk_type = options.chunk_type(chunk_type.try_into()?)?;
+
+        let available = rest.len() - 4;
+        if length as usize != available {
+            return Err(ChunkError::LengthMismatch {
+                declared: length,
+                available,
+            }
+            .into());
+        }
+
+        let (data, crc) = rest.split_at(available);
+        let chunk = ChunkRef {
+            chunk_type,
+            data,
+            crc: u32::from_be_bytes(crc.try_into()?),
+            offset: None,
+        };
+        if options.strict_crc && !chunk.has_valid_crc() {
+            let expected = Chunk::compute_crc(&chunk_type, data);
+            tracing::debug!(%chunk_type, expected, found = chunk.crc, "CRC mismatch");
+            return Err(PngMeError::CrcMismatch {
+                chunk_type: chunk_type.to_string(),
+                expected,
+                found: chunk.crc,
+            });
+        }
+        Ok(chunk)
+    }
+
+    /// Number of bytes in the data field.
+    pub fn length(&self) -> u32 {
+        self.data.len() as u32
+    }
+
+    pub fn chunk_type(&self) -> &ChunkType {
+        &self.chunk_type
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+
+    /// Byte offset of the chunk's length field in its input, if known.
+    pub fn offset(&self) -> Option<u64> {
+        self.offset
+    }
+
+    /// Bytes the chunk takes when serialized, framing included.
+    pub fn total_size(&self) -> u64 {
+        (Chunk::OVERHEAD + self.data.len()) as u64
+    }
+
+    /// Returns false if the stored CRC does not match the chunk's contents.
+    pub fn has_valid_crc(&self) -> bool {
+        self.crc == Chunk::compute_crc(&self.chunk_type, self.data)
+    }
+
+    /// Records where the chunk was found in its input.
+    pub(crate) fn at_offset(mut self, offset: u64) -> ChunkRef<'a> {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// Copies the data into an owned [`Chunk`], keeping the stored CRC and
+    /// offset.
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk {
+            length: self.length(),
+            chunk_type: self.chunk_type,
+            data: self.data.to_vec(),
+            crc: self.crc,
+            offset: self.offset,
+        }
+    }
+}
+
+impl PartialEq for ChunkRef<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.chunk_type == other.chunk_type && self.crc == other.crc && self.data == other.data
+    }
+}
+
+impl Eq for ChunkRef<'_> {}
+
+impl Hash for ChunkRef<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.chunk_type.hash(state);
+        self.data.hash(state);
+        self.crc.hash(state);
+    }
+}
+
+impl From<ChunkRef<'_>> for Chunk {
+    fn from(chunk: ChunkRef<'_>) -> Chunk {
+        chunk.to_chunk()
+    }
+}
+
+impl TryFrom<&[u8]> for Chunk {
+    type Error = PngMeError;
+
+    /// Parses exactly one serialized chunk, verifying its CRC.
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        Chunk::from_bytes_with(bytes, &ParseOptions::default())
+    }
+}
+
+impl PartialEq for Chunk {
+    fn eq(&self, other: &Self) -> bool {
+        self.chunk_type == other.chunk_type && self.crc == other.crc && self.data == other.data
+    }
+}
+
+impl Eq for Chunk {}
+
+impl Hash for Chunk {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.chunk_type.hash(state);
+        self.data.hash(state);
+        self.crc.hash(state);
+    }
+}
+
+/// Shows the data as a byte count rather than every byte.
+impl fmt::Debug for Chunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Chunk")
+            .field("chunk_type", &self.chunk_type)
+            .field("data", &format_args!("{} bytes", self.data.len()))
+            .field("crc", &format_args!("{:#010x}", self.crc))
+            .field("offset", &self.offset)
+            .finish()
+    }
+}
+
+impl fmt::Display for Chunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Chunk {{")?;
+        writeln!(f, "  Length: {}", self.length())?;
+        writeln!(f, "  Type: {}", self.chunk_type())?;
+        writeln!(f, "  Data: {} bytes", self.data().len())?;
+        writeln!(f, "  Crc: {:#010x}", self.crc())?;
+        write!(f, "}}")
+    }
+}
+
+/// Errors raised while parsing a [`Chunk`] from bytes.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ChunkError {
+    /// Fewer bytes than the fixed length, type and CRC fields need.
+    #[error("chunk needs at least {} bytes, got {0}", Chunk::OVERHEAD)]
+    TooShort(usize),
+    /// The length field disagrees with the number of data bytes supplied.
+    #[error("chunk declares {declared} data bytes but {available} were supplied")]
+    LengthMismatch { declared: u32, available: usize },
+    /// The data isn't UTF-8. `offset` is that of the first byte of the
+    /// first invalid sequence, so everything before it is valid.
+    #[error("{chunk_type} chunk data isn't UTF-8: invalid byte at offset {offset}")]
+    InvalidUtf8 { chunk_type: String, offset: usize },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    use crate::chunk_type::ChunkTypeError;
+
+    fn testing_chunk() -> Chunk {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        let data = "This is where your secret message will be!"
+            .as_bytes()
+            .to_vec();
+        Chunk::new(chunk_type, data)
+    }
+
+    #[test]
+    fn test_new_chunk() {
+        let chunk = testing_chunk();
+        assert_eq!(chunk.length(), 42);
+        assert_eq!(chunk.chunk_type().to_string(), "RuSt");
+        assert_eq!(chunk.crc(), 2882656334);
+    }
+
+    #[test]
+    fn test_try_new_rejects_oversized_data() {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        // Zeroed allocations are mapped lazily, so this costs no memory.
+        let data = vec![0; MAX_CHUNK_LEN as usize + 1];
+        assert!(matches!(
+            Chunk::try_new(chunk_type, data),
+            Err(PngMeError::ChunkTooLarge {
+                length,
+                max: MAX_CHUNK_LEN
+            }) if length == MAX_CHUNK_LEN + 1
+        ));
+        assert_eq!(
+            Chunk::try_new(chunk_type, b"ok".to_vec()).unwrap(),
+            Chunk::new(chunk_type, b"ok".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_iend_crc_matches_libpng() {
+        let chunk = Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new());
+        assert_eq!(
+            chunk.as_bytes(),
+            [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]
+        );
+    }
+
+    #[test]
+    fn test_crc32_check_value() {
+        // The standard check value for CRC-32/ISO-HDLC.
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_chunk_data_as_string() {
+        let chunk = testing_chunk();
+        assert_eq!(
+            chunk.data_as_string().unwrap(),
+            "This is where your secret message will be!"
+        );
+    }
+
+    #[test]
+    fn test_invalid_utf8_data_as_string() {
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"ok\xff\xfe".to_vec());
+        assert!(matches!(
+            chunk.data_as_string(),
+            Err(PngMeError::Chunk(ChunkError::InvalidUtf8 { offset: 2, .. }))
+        ));
+    }
+
+    #[test]
+    fn test_as_bytes_layout() {
+        let chunk = testing_chunk();
+        let bytes = chunk.as_bytes();
+
+        assert_eq!(bytes.len(), 12 + 42);
+        assert_eq!(&bytes[..4], &42u32.to_be_bytes());
+        assert_eq!(&bytes[4..8], b"RuSt");
+        assert_eq!(&bytes[8..50], chunk.data());
+        assert_eq!(&bytes[50..], &2882656334u32.to_be_bytes());
+    }
+
+    #[test]
+    fn test_chunk_round_trip() {
+        let chunk = testing_chunk();
+        let parsed = Chunk::try_from(chunk.as_bytes().as_slice()).unwrap();
+
+        assert_eq!(parsed.chunk_type(), chunk.chunk_type());
+        assert_eq!(parsed.data(), chunk.data());
+        assert_eq!(parsed.crc(), chunk.crc());
+    }
+
+    #[test]
+    fn test_invalid_chunk_crc() {
+        let mut bytes = testing_chunk().as_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+
+        let err = Chunk::try_from(bytes.as_slice()).unwrap_err();
+        assert!(matches!(err, PngMeError::CrcMismatch { .. }));
+    }
+
+    #[test]
+    fn test_permissive_crc_is_preserved() {
+        let mut bytes = testing_chunk().as_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+
+        let options = ParseOptions::default().with_strict_crc(false);
+        let chunk = Chunk::from_bytes_with(&bytes, &options).unwrap();
+        assert!(!chunk.has_valid_crc());
+        assert_eq!(chunk.as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_equality_ignores_offset() {
+        let parsed = Chunk::try_from(testing_chunk().as_bytes().as_slice())
+            .unwrap()
+            .at_offset(33);
+        assert_eq!(parsed, testing_chunk());
+        assert_eq!(parsed.clone().offset(), Some(33));
+
+        let types: std::collections::HashSet<ChunkType> =
+            [*parsed.chunk_type(), ChunkType::from_str("RuSt").unwrap()].into();
+        assert_eq!(types.len(), 1);
+        assert_eq!(format!("{:?}", parsed.chunk_type()), r#"ChunkType("RuSt")"#);
+    }
+
+    #[test]
+    fn test_invalid_chunk_type_bytes() {
+        let mut bytes = testing_chunk().as_bytes();
+        bytes[6] = b'1';
+        assert!(matches!(
+            Chunk::try_from(bytes.as_slice()).unwrap_err(),
+            PngMeError::InvalidChunkType(ChunkTypeError::InvalidByte(b'1'))
+        ));
+
+        // Forensic parsing keeps the type as read, CRC and all.
+        let chunk = Chunk::from_bytes_with(&bytes, &ParseOptions::permissive()).unwrap();
+        assert_eq!(chunk.chunk_type().bytes(), *b"Ru1t");
+        assert!(!chunk.chunk_type().is_valid());
+        assert_eq!(chunk.as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_max_chunk_size() {
+        let options = ParseOptions::default().with_max_chunk_size(41);
+        let err = Chunk::from_bytes_with(&testing_chunk().as_bytes(), &options).unwrap_err();
+        assert!(matches!(
+            err,
+            PngMeError::ChunkTooLarge {
+                length: 42,
+                max: 41
+            }
+        ));
+    }
+
+    #[test]
+    fn test_truncated_chunk() {
+        let bytes = testing_chunk().as_bytes();
+        assert!(Chunk::try_from(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Chunk::try_from(&bytes[..8]).is_err());
+    }
+}
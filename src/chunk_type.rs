@@ -0,0 +1,375 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::PngMeError;
+
+/// A 4-byte PNG chunk type code.
+///
+/// Each byte is an ASCII letter, and the case of each letter encodes one
+/// property bit (see section 3.3 of the PNG specification).
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ChunkType {
+    bytes: [u8; 4],
+}
+
+impl ChunkType {
+    pub const IHDR: ChunkType = ChunkType::new_unchecked(*b"IHDR");
+    pub const PLTE: ChunkType = ChunkType::new_unchecked(*b"PLTE");
+    pub const IDAT: ChunkType = ChunkType::new_unchecked(*b"IDAT");
+    pub const IEND: ChunkType = ChunkType::new_unchecked(*b"IEND");
+    pub const CHRM: ChunkType = ChunkType::new_unchecked(*b"cHRM");
+    pub const GAMA: ChunkType = ChunkType::new_unchecked(*b"gAMA");
+    pub const ICCP: ChunkType = ChunkType::new_unchecked(*b"iCCP");
+    pub const SBIT: ChunkType = ChunkType::new_unchecked(*b"sBIT");
+    pub const SRGB: ChunkType = ChunkType::new_unchecked(*b"sRGB");
+    pub const CICP: ChunkType = ChunkType::new_unchecked(*b"cICP");
+    pub const MDCV: ChunkType = ChunkType::new_unchecked(*b"mDCV");
+    pub const CLLI: ChunkType = ChunkType::new_unchecked(*b"cLLI");
+    pub const BKGD: ChunkType = ChunkType::new_unchecked(*b"bKGD");
+    pub const HIST: ChunkType = ChunkType::new_unchecked(*b"hIST");
+    pub const TRNS: ChunkType = ChunkType::new_unchecked(*b"tRNS");
+    pub const PHYS: ChunkType = ChunkType::new_unchecked(*b"pHYs");
+    pub const SPLT: ChunkType = ChunkType::new_unchecked(*b"sPLT");
+    pub const EXIF: ChunkType = ChunkType::new_unchecked(*b"eXIf");
+    pub const TIME: ChunkType = ChunkType::new_unchecked(*b"tIME");
+    pub const TEXT: ChunkType = ChunkType::new_unchecked(*b"tEXt");
+    pub const ZTXT: ChunkType = ChunkType::new_unchecked(*b"zTXt");
+    pub const ITXT: ChunkType = ChunkType::new_unchecked(*b"iTXt");
+    pub const ACTL: ChunkType = ChunkType::new_unchecked(*b"acTL");
+    pub const FCTL: ChunkType = ChunkType::new_unchecked(*b"fcTL");
+    pub const FDAT: ChunkType = ChunkType::new_unchecked(*b"fdAT");
+
+    /// Every chunk type defined by the PNG specification, third edition,
+    /// including the APNG chunks.
+    pub const STANDARD: [ChunkType; 25] = [
+        ChunkType::IHDR,
+        ChunkType::PLTE,
+        ChunkType::IDAT,
+        ChunkType::IEND,
+        ChunkType::CHRM,
+        ChunkType::GAMA,
+        ChunkType::ICCP,
+        ChunkType::SBIT,
+        ChunkType::SRGB,
+        ChunkType::CICP,
+        ChunkType::MDCV,
+        ChunkType::CLLI,
+        ChunkType::BKGD,
+        ChunkType::HIST,
+        ChunkType::TRNS,
+        ChunkType::PHYS,
+        ChunkType::SPLT,
+        ChunkType::EXIF,
+        ChunkType::TIME,
+        ChunkType::TEXT,
+        ChunkType::ZTXT,
+        ChunkType::ITXT,
+        ChunkType::ACTL,
+        ChunkType::FCTL,
+        ChunkType::FDAT,
+    ];
+
+    /// Starts building a chunk type from a four-letter code and the
+    /// properties it should have, such as
+    /// `ChunkType::builder().code("rust").private(true).safe_to_copy(true)`.
+    pub fn builder() -> ChunkTypeBuilder {
+        ChunkTypeBuilder::default()
+    }
+
+    /// Builds a chunk type from any four bytes, letters or not. Meant for
+    /// forensic tools that must keep whatever a damaged file contains;
+    /// [`ChunkType::is_valid`] reports whether the result is usable.
+    pub const fn new_unchecked(bytes: [u8; 4]) -> ChunkType {
+        ChunkType { bytes }
+    }
+
+    /// Returns the raw bytes of this chunk type.
+    pub fn bytes(&self) -> [u8; 4] {
+        self.bytes
+    }
+
+    /// Returns true if every byte is an ASCII letter and the reserved bit is valid.
+    pub fn is_valid(&self) -> bool {
+        self.bytes.iter().all(u8::is_ascii_alphabetic) && self.is_reserved_bit_valid()
+    }
+
+    /// Returns true for the types in [`ChunkType::STANDARD`].
+    pub fn is_standard(&self) -> bool {
+        ChunkType::STANDARD.contains(self)
+    }
+
+    /// Critical chunks have an uppercase first byte.
+    pub fn is_critical(&self) -> bool {
+        self.bytes[0].is_ascii_uppercase()
+    }
+
+    /// Public chunks have an uppercase second byte.
+    pub fn is_public(&self) -> bool {
+        self.bytes[1].is_ascii_uppercase()
+    }
+
+    /// The third byte must be uppercase in this version of the specification.
+    pub fn is_reserved_bit_valid(&self) -> bool {
+        self.bytes[2].is_ascii_uppercase()
+    }
+
+    /// Safe-to-copy chunks have a lowercase fourth byte.
+    pub fn is_safe_to_copy(&self) -> bool {
+        self.bytes[3].is_ascii_lowercase()
+    }
+
+    /// Gathers the properties the case of each letter encodes, with an
+    /// explanation of each, for choosing a custom type.
+    pub fn describe(&self) -> ChunkTypeProperties {
+        ChunkTypeProperties {
+            critical: self.is_critical(),
+            public: self.is_public(),
+            reserved_bit_valid: self.is_reserved_bit_valid(),
+            safe_to_copy: self.is_safe_to_copy(),
+            standard: self.is_standard(),
+        }
+    }
+}
+
+/// The properties of a chunk type, from [`ChunkType::describe`]. Displays as
+/// a summary such as "ancillary, private, safe to copy".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkTypeProperties {
+    pub critical: bool,
+    pub public: bool,
+    pub reserved_bit_valid: bool,
+    pub safe_to_copy: bool,
+    /// Defined by the PNG specification.
+    pub standard: bool,
+}
+
+impl ChunkTypeProperties {
+    /// What each property means for a chunk of this type, one sentence per
+    /// letter.
+    pub fn explanations(&self) -> [&'static str; 4] {
+        [
+            match self.critical {
+                true => {
+                    "Critical: decoders that don't know the type must refuse to show the image."
+                }
+                false => {
+                    "Ancillary: decoders that don't know the type skip it and show the image as \
+                     usual."
+                }
+            },
+            match self.public {
+                true => {
+                    "Public: the type is defined or reserved by the PNG specification, so custom \
+                     chunks shouldn't use it."
+                }
+                false => "Private: applications may define the type without registering it.",
+            },
+            match self.reserved_bit_valid {
+                true => "Reserved bit clear, as the specification requires.",
+                false => {
+                    "Reserved bit set: the third letter must be uppercase, so decoders may reject \
+                     the type."
+                }
+            },
+            match self.safe_to_copy {
+                true => {
+                    "Safe to copy: editors that don't know the type keep it when they change the \
+                     image."
+                }
+                false => {
+                    "Unsafe to copy: editors that don't know the type drop it when they change \
+                     the image, since it may depend on the image data."
+                }
+            },
+        ]
+    }
+}
+
+impl fmt::Display for ChunkTypeProperties {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let critical = if self.critical {
+            "critical"
+        } else {
+            "ancillary"
+        };
+        let public = if self.public { "public" } else { "private" };
+        let copy = if self.safe_to_copy { "safe" } else { "unsafe" };
+        write!(f, "{critical}, {public}, {copy} to copy")?;
+        if !self.reserved_bit_valid {
+            write!(f, ", reserved bit set")?;
+        }
+        Ok(())
+    }
+}
+
+/// Builds a [`ChunkType`] by setting its properties instead of choosing the
+/// case of each letter by hand.
+///
+/// Properties that aren't set keep the case they have in the code. The
+/// reserved bit is always made valid.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChunkTypeBuilder {
+    code: Option<String>,
+    critical: Option<bool>,
+    public: Option<bool>,
+    safe_to_copy: Option<bool>,
+}
+
+impl ChunkTypeBuilder {
+    /// The four letters of the type, in any case.
+    pub fn code(mut self, code: &str) -> Self {
+        self.code = Some(code.to_string());
+        self
+    }
+
+    /// Whether decoders must understand the chunk to display the image.
+    pub fn critical(mut self, critical: bool) -> Self {
+        self.critical = Some(critical);
+        self
+    }
+
+    /// Whether the type is defined by the specification or registered,
+    /// rather than private to an application.
+    pub fn public(mut self, public: bool) -> Self {
+        self.public = Some(public);
+        self
+    }
+
+    /// Shorthand for `public(!private)`.
+    pub fn private(self, private: bool) -> Self {
+        self.public(!private)
+    }
+
+    /// Whether editors that don't understand the chunk may keep it after
+    /// changing critical chunks.
+    pub fn safe_to_copy(mut self, safe_to_copy: bool) -> Self {
+        self.safe_to_copy = Some(safe_to_copy);
+        self
+    }
+
+    pub fn build(self) -> Result<ChunkType, PngMeError> {
+        let code = self.code.ok_or(ChunkTypeError::MissingCode)?;
+        let mut bytes = ChunkType::from_str(&code)?.bytes;
+        let set_case = |byte: &mut u8, upper: bool| match upper {
+            true => byte.make_ascii_uppercase(),
+            false => byte.make_ascii_lowercase(),
+        };
+        if let Some(critical) = self.critical {
+            set_case(&mut bytes[0], critical);
+        }
+        if let Some(public) = self.public {
+            set_case(&mut bytes[1], public);
+        }
+        set_case(&mut bytes[2], true);
+        if let Some(safe_to_copy) = self.safe_to_copy {
+            set_case(&mut bytes[3], !safe_to_copy);
+        }
+        Ok(ChunkType { bytes })
+    }
+}
+
+impl TryFrom<[u8; 4]> for ChunkType {
+    type Error = PngMeError;
+
+    /// Accepts four ASCII letters. The reserved bit is not checked, so chunk
+    /// types from later versions of the specification can still be read.
+    fn try_from(bytes: [u8; 4]) -> Result<Self, Self::Error> {
+        if let Some(&byte) = bytes.iter().find(|b| !b.is_ascii_alphabetic()) {
+            return Err(ChunkTypeError::InvalidByte(byte).into());
+        }
+        Ok(Self { bytes })
+    }
+}
+
+impl FromStr for ChunkType {
+    type Err = PngMeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes: [u8; 4] = s
+            .as_bytes()
+            .try_into()
+            .map_err(|_| ChunkTypeError::InvalidLength(s.len()))?;
+        Self::try_from(bytes)
+    }
+}
+
+impl fmt::Display for ChunkType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", String::from_utf8_lossy(&self.bytes))
+    }
+}
+
+/// Shows the four-letter code, such as `ChunkType("IHDR")`, with any
+/// non-letter bytes escaped.
+impl fmt::Debug for ChunkType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ChunkType")
+            .field(&self.bytes.escape_ascii().to_string())
+            .finish()
+    }
+}
+
+/// Errors raised while building a [`ChunkType`].
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ChunkTypeError {
+    /// A chunk type must be exactly 4 bytes long.
+    #[error("chunk type must be 4 bytes long, got {0}")]
+    InvalidLength(usize),
+    /// Chunk type bytes must be ASCII letters.
+    #[error("invalid chunk type byte: {0:#04x}")]
+    InvalidByte(u8),
+    /// [`ChunkTypeBuilder::build`] was called without a code.
+    #[error("chunk type builder needs a code")]
+    MissingCode,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_sets_case_bits() {
+        let chunk_type = ChunkType::builder()
+            .code("rust")
+            .critical(true)
+            .private(true)
+            .safe_to_copy(false)
+            .build()
+            .unwrap();
+        assert_eq!(chunk_type.to_string(), "RuST");
+        assert!(chunk_type.is_valid());
+        assert!(chunk_type.is_critical());
+        assert!(!chunk_type.is_public());
+        assert!(!chunk_type.is_safe_to_copy());
+
+        let unchanged = ChunkType::builder().code("ruSt").build().unwrap();
+        assert_eq!(unchanged.to_string(), "ruSt");
+    }
+
+    #[test]
+    fn test_builder_rejects_bad_codes() {
+        assert!(ChunkType::builder().build().is_err());
+        assert!(ChunkType::builder().code("ru5t").build().is_err());
+        assert!(ChunkType::builder().code("rusty").build().is_err());
+    }
+
+    #[test]
+    fn test_describe() {
+        let properties = ChunkType::from_str("ruSt").unwrap().describe();
+        assert!(!properties.critical && !properties.public && properties.safe_to_copy);
+        assert_eq!(properties.to_string(), "ancillary, private, safe to copy");
+        assert!(properties.explanations()[0].starts_with("Ancillary"));
+
+        let properties = ChunkType::IDAT.describe();
+        assert!(properties.standard);
+        assert_eq!(properties.to_string(), "critical, public, unsafe to copy");
+        assert_eq!(
+            ChunkType::from_str("Rust").unwrap().describe().to_string(),
+            "critical, private, safe to copy, reserved bit set"
+        );
+    }
+}
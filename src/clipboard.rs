@@ -0,0 +1,59 @@
+//! Reading a message to embed from the clipboard and copying a decoded one
+//! to it, built with the `clipboard` feature, so secrets can pass through
+//! pngme without being written to disk.
+
+use thiserror::Error;
+
+use crate::Result;
+
+/// The text on the clipboard. Needs the `clipboard` feature.
+#[cfg(feature = "clipboard")]
+pub fn read() -> Result<String> {
+    let text = arboard::Clipboard::new().and_then(|mut clipboard| clipboard.get_text());
+    Ok(text.map_err(ClipboardError::Access)?)
+}
+
+/// The text on the clipboard. Needs the `clipboard` feature.
+#[cfg(not(feature = "clipboard"))]
+pub fn read() -> Result<String> {
+    Err(ClipboardError::Unsupported.into())
+}
+
+/// Puts `text` on the clipboard. On Linux the clipboard is served by the
+/// program that set it, so this waits until a clipboard manager or another
+/// program takes it over. Needs the `clipboard` feature.
+#[cfg(feature = "clipboard")]
+pub fn write(text: &str) -> Result<()> {
+    let mut clipboard = arboard::Clipboard::new().map_err(ClipboardError::Access)?;
+    #[cfg(target_os = "linux")]
+    let copied = {
+        use arboard::SetExtLinux;
+        clipboard.set().wait().text(text)
+    };
+    #[cfg(not(target_os = "linux"))]
+    let copied = clipboard.set_text(text);
+    Ok(copied.map_err(ClipboardError::Access)?)
+}
+
+/// Puts `text` on the clipboard. Needs the `clipboard` feature.
+#[cfg(not(feature = "clipboard"))]
+pub fn write(_text: &str) -> Result<()> {
+    Err(ClipboardError::Unsupported.into())
+}
+
+/// Errors raised while using the clipboard.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ClipboardError {
+    /// The clipboard couldn't be reached or holds no text.
+    #[cfg(feature = "clipboard")]
+    #[error("couldn't use the clipboard: {0}")]
+    Access(arboard::Error),
+    /// The clipboard was requested from a build without the `clipboard`
+    /// feature.
+    #[error("the clipboard needs pngme to be built with the clipboard feature")]
+    Unsupported,
+    /// Several PNGs were decoded, but the clipboard holds one message.
+    #[error("--to-clipboard takes a single PNG, not several")]
+    SeveralFiles,
+}
@@ -0,0 +1,217 @@
+//! Reading and writing PNGs in object storage, at s3://, gs:// and azure://
+//! URIs, built with the `object-store` feature. Everywhere
+//! [`stdio`](crate::stdio) takes a path, an object URI can be given instead,
+//! and batch commands take a URI ending in `/` as every PNG under that
+//! prefix.
+//!
+//! Credentials and regions come from the environment variables each store's
+//! own tools read, such as `AWS_ACCESS_KEY_ID` and `AWS_REGION`.
+
+use std::path::Path;
+
+use thiserror::Error;
+
+/// The URI schemes of the stores supported. `az://` is the short form of
+/// `azure://`.
+pub const SCHEMES: [&str; 4] = ["s3", "gs", "az", "azure"];
+
+/// Whether `path` is an object storage URI rather than a file.
+pub fn is_object_url(path: &Path) -> bool {
+    path.to_str()
+        .and_then(|path| path.split_once("://"))
+        .is_some_and(|(scheme, _)| SCHEMES.contains(&scheme))
+}
+
+#[cfg(feature = "object-store")]
+pub use store::{list_pngs, read, write, ObjectStoreSink, ObjectStoreSource};
+
+/// Reads all of the object at `url`. Needs the `object-store` feature.
+#[cfg(not(feature = "object-store"))]
+pub fn read(_url: &str) -> crate::Result<Vec<u8>> {
+    Err(CloudError::Unsupported.into())
+}
+
+/// Replaces the object at `url` with `bytes`. Needs the `object-store`
+/// feature.
+#[cfg(not(feature = "object-store"))]
+pub fn write(_url: &str, _bytes: &[u8]) -> crate::Result<()> {
+    Err(CloudError::Unsupported.into())
+}
+
+/// The URI of every object with a .png extension under the prefix `url`.
+/// Needs the `object-store` feature.
+#[cfg(not(feature = "object-store"))]
+pub fn list_pngs(_url: &str) -> crate::Result<Vec<std::path::PathBuf>> {
+    Err(CloudError::Unsupported.into())
+}
+
+#[cfg(feature = "object-store")]
+mod store {
+    use std::future::Future;
+    use std::io::Cursor;
+    use std::path::PathBuf;
+    use std::sync::OnceLock;
+
+    use futures::TryStreamExt;
+    use object_store::path::Path as ObjectPath;
+    use object_store::{ObjectMeta, ObjectStore, PutPayload};
+    use tokio::runtime::{Builder, Runtime};
+    use url::Url;
+
+    use super::CloudError;
+    use crate::chunk::Chunk;
+    use crate::reader::PngReader;
+    use crate::storage::{ChunkSink, ChunkSource};
+    use crate::writer::PngWriter;
+    use crate::Result;
+
+    /// Runs the store's requests for callers that aren't async themselves.
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
+    fn block_on<F: Future>(future: F) -> Result<F::Output> {
+        if RUNTIME.get().is_none() {
+            let runtime = Builder::new_current_thread().enable_all().build()?;
+            let _ = RUNTIME.set(runtime);
+        }
+        Ok(RUNTIME
+            .get()
+            .expect("runtime was just set")
+            .block_on(future))
+    }
+
+    /// The store `url` is in and the object's path within it, configured
+    /// from the environment.
+    fn open(url: &str) -> Result<(Url, Box<dyn ObjectStore>, ObjectPath)> {
+        let parsed = Url::parse(url).map_err(|_| CloudError::InvalidUrl(url.to_string()))?;
+        let options = std::env::vars().map(|(key, value)| (key.to_ascii_lowercase(), value));
+        let (store, path) =
+            object_store::parse_url_opts(&parsed, options).map_err(CloudError::from)?;
+        Ok((parsed, store, path))
+    }
+
+    /// Reads all of the object at `url`.
+    pub fn read(url: &str) -> Result<Vec<u8>> {
+        let (_, store, path) = open(url)?;
+        tracing::debug!(url, "fetching object");
+        let bytes =
+            block_on(async { store.get(&path).await?.bytes().await })?.map_err(CloudError::from)?;
+        Ok(bytes.to_vec())
+    }
+
+    /// Replaces the object at `url` with `bytes`. Stores replace objects
+    /// whole, so readers never see a partial write.
+    pub fn write(url: &str, bytes: &[u8]) -> Result<()> {
+        let (_, store, path) = open(url)?;
+        tracing::debug!(url, len = bytes.len(), "storing object");
+        block_on(store.put(&path, PutPayload::from(bytes.to_vec())))?.map_err(CloudError::from)?;
+        Ok(())
+    }
+
+    /// The URI of every object with a .png extension under the prefix `url`,
+    /// in sorted order.
+    pub fn list_pngs(url: &str) -> Result<Vec<PathBuf>> {
+        let (parsed, store, prefix) = open(url)?;
+        let objects: Vec<ObjectMeta> =
+            block_on(store.list(Some(&prefix)).try_collect())?.map_err(CloudError::from)?;
+        let root = format!(
+            "{}://{}",
+            parsed.scheme(),
+            parsed.host_str().unwrap_or_default()
+        );
+        let mut urls: Vec<PathBuf> = objects
+            .iter()
+            .map(|object| object.location.as_ref())
+            .filter(|location| location.to_ascii_lowercase().ends_with(".png"))
+            .map(|location| PathBuf::from(format!("{root}/{location}")))
+            .collect();
+        urls.sort();
+        Ok(urls)
+    }
+
+    /// The chunks of the PNG stored at a URI. The object is fetched whole
+    /// when the source is opened.
+    #[derive(Debug)]
+    pub struct ObjectStoreSource {
+        reader: PngReader<Cursor<Vec<u8>>>,
+    }
+
+    impl ObjectStoreSource {
+        pub fn open(url: &str) -> Result<ObjectStoreSource> {
+            Ok(ObjectStoreSource {
+                reader: PngReader::new(Cursor::new(read(url)?))?,
+            })
+        }
+    }
+
+    impl ChunkSource for ObjectStoreSource {
+        fn next_chunk(&mut self) -> Result<Option<Chunk>> {
+            self.reader.next_chunk()
+        }
+    }
+
+    /// Stores the chunks written to it as a PNG at a URI. Nothing is stored
+    /// until [`ChunkSink::flush`].
+    #[derive(Debug)]
+    pub struct ObjectStoreSink {
+        url: String,
+        writer: PngWriter<Vec<u8>>,
+    }
+
+    impl ObjectStoreSink {
+        pub fn new(url: &str) -> Result<ObjectStoreSink> {
+            Ok(ObjectStoreSink {
+                url: url.to_string(),
+                writer: PngWriter::new(Vec::new())?,
+            })
+        }
+    }
+
+    impl ChunkSink for ObjectStoreSink {
+        fn write_chunk(&mut self, chunk: &Chunk) -> Result<()> {
+            self.writer.write_chunk(chunk)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            write(&self.url, self.writer.get_ref())
+        }
+    }
+}
+
+/// Errors raised while reading from or writing to object storage.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum CloudError {
+    /// The store couldn't be configured or a request to it failed.
+    #[cfg(feature = "object-store")]
+    #[error("object storage request failed: {0}")]
+    Store(object_store::Error),
+    /// An object URI was given to a build without the `object-store`
+    /// feature.
+    #[error("object storage needs pngme to be built with the object-store feature")]
+    Unsupported,
+    /// A URI that can't be parsed, named here.
+    #[error("invalid object storage URI: {0}")]
+    InvalidUrl(String),
+}
+
+#[cfg(feature = "object-store")]
+impl From<object_store::Error> for CloudError {
+    fn from(err: object_store::Error) -> Self {
+        CloudError::Store(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_object_url() {
+        assert!(is_object_url(Path::new("s3://bucket/images/a.png")));
+        assert!(is_object_url(Path::new("gs://bucket/")));
+        assert!(is_object_url(Path::new("azure://container/a.png")));
+        assert!(!is_object_url(Path::new("https://example.com/a.png")));
+        assert!(!is_object_url(Path::new("images/s3://a.png")));
+        assert!(!is_object_url(Path::new("a.png")));
+    }
+}
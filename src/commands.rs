@@ -0,0 +1,1679 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Mutex, PoisonError};
+use std::time::SystemTime;
+
+#[cfg(feature = "serde")]
+use congenial_chainsaw::scan::Finding;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use clap::CommandFactory;
+use indicatif::ProgressBar;
+
+use crate::args::{
+    Cli, CompareArgs, CompletionsArgs, DecodeArgs, DecodeOptions, Destination, DiffArgs, DumpArgs,
+    EncodeArgs, EncodeFileArgs, EncodeOptions, ExplainArgs, ExtractFileArgs, IccArgs, IccCommand,
+    IccEmbedArgs, IccExtractArgs, KeygenArgs, ListArgs, MetaArgs, MetaCommand, MetaDelArgs,
+    MetaExifArgs, MetaGetArgs, MetaSetArgs, MetaTimeArgs, OptimizeArgs, PrintArgs, RemoveArgs,
+    RepairArgs, ScanArgs, ScrubArgs, ServeArgs, SignArgs, VerifyArgs, WatchArgs, WatchCommand,
+};
+use crate::progress;
+use congenial_chainsaw::ancillary::{LastModified, RENDERING_CHUNK_TYPES};
+use congenial_chainsaw::apng::{self, AnimationError};
+use congenial_chainsaw::batch::{self, BatchError};
+use congenial_chainsaw::chunk::Chunk;
+use congenial_chainsaw::chunk_type::ChunkType;
+use congenial_chainsaw::clipboard::{self, ClipboardError};
+use congenial_chainsaw::compression;
+use congenial_chainsaw::compression::Compression;
+use congenial_chainsaw::copy_policy::CopyPolicy;
+use congenial_chainsaw::crypto::{self, CryptoError, Identity, Recipient};
+use congenial_chainsaw::dump::HexDump;
+use congenial_chainsaw::envelope::{EnvelopeError, EnvelopeFlags, Expiry, PayloadEnvelope};
+use congenial_chainsaw::exif::Exif;
+use congenial_chainsaw::http;
+use congenial_chainsaw::idat::Recompression;
+use congenial_chainsaw::index::{IndexEntry, PayloadIndex};
+use congenial_chainsaw::integrity::{self, Integrity, IntegrityError};
+use congenial_chainsaw::lsb::{self, LsbError, LsbOptions};
+use congenial_chainsaw::optimize::OptimizeOptions;
+use congenial_chainsaw::ordering::OrderingError;
+use congenial_chainsaw::payload::{
+    self, EmbeddedFile, Method, Payload, PayloadError, MAX_CHUNK_DATA,
+};
+use congenial_chainsaw::payload_source::PayloadEncoding;
+use congenial_chainsaw::png::{ParseOptions, Png};
+use congenial_chainsaw::position::ChunkPosition;
+use congenial_chainsaw::qr::{self, QrError};
+use congenial_chainsaw::reader::PngReader;
+use congenial_chainsaw::repair::RepairPolicy;
+use congenial_chainsaw::scan::ScanReport;
+use congenial_chainsaw::secret::{self, SecretError};
+use congenial_chainsaw::server;
+use congenial_chainsaw::shamir::{self, ShareError};
+use congenial_chainsaw::signing;
+use congenial_chainsaw::stats::Stats;
+use congenial_chainsaw::stdio::{self, WriteOptions};
+use congenial_chainsaw::stego;
+use congenial_chainsaw::summary::{self, OutputFormat};
+use congenial_chainsaw::text::TextChunk;
+use congenial_chainsaw::trailer::{self, TrailerError};
+use congenial_chainsaw::watch;
+use congenial_chainsaw::{PngMeError, Result};
+
+/// Encodes a message into a PNG file and saves the result, or into every
+/// file of a directory or glob pattern.
+pub fn encode(mut args: EncodeArgs, write_options: &WriteOptions) -> Result<()> {
+    resolve_password(&mut args.options)?;
+    warn_registered(&args.chunk_type, &args.options);
+    let message = args.message.source().read()?;
+    if let (Some(shares), Some(threshold)) = (args.shares, args.threshold) {
+        return encode_shares(&args, &message, shares, threshold, write_options);
+    }
+    let payload = Payload::Message(message);
+    match batch::targets(&args.file_path)? {
+        None => embed(
+            &args.file_path,
+            args.output.path(&args.file_path),
+            &args.chunk_type,
+            &payload,
+            &args.options,
+            write_options,
+        ),
+        Some(files) => report_batch(&progress::run_batch(&files, |file| {
+            let output = match &args.output.output {
+                Some(dir) => batch_output(&args.file_path, file, dir)?,
+                None => file.to_path_buf(),
+            };
+            embed(
+                file,
+                &output,
+                &args.chunk_type,
+                &payload,
+                &args.options,
+                write_options,
+            )
+            .map(|()| "encoded")
+        })),
+    }
+}
+
+/// Splits `message` with [`shamir::split`] and embeds one share in each PNG
+/// file that `args.file_path` names.
+fn encode_shares(
+    args: &EncodeArgs,
+    message: &[u8],
+    shares: u8,
+    threshold: u8,
+    write_options: &WriteOptions,
+) -> Result<()> {
+    let files = batch::targets(&args.file_path)?.unwrap_or_else(|| vec![args.file_path.clone()]);
+    if files.len() != shares as usize {
+        return Err(ShareError::WrongFileCount {
+            shares,
+            files: files.len(),
+        }
+        .into());
+    }
+    let split = shamir::split(message, shares, threshold)?;
+    report_batch(&progress::run_batch(&files, |file| {
+        let index = files
+            .iter()
+            .position(|other| other == file)
+            .unwrap_or_default();
+        let output = match &args.output.output {
+            Some(dir) => batch_output(&args.file_path, file, dir)?,
+            None => file.to_path_buf(),
+        };
+        let payload = Payload::Share(split[index].clone());
+        embed(
+            file,
+            &output,
+            &args.chunk_type,
+            &payload,
+            &args.options,
+            write_options,
+        )?;
+        Ok(format!("encoded share {} of {shares}", split[index].index))
+    }))
+}
+
+/// Embeds a file and its metadata into a PNG file and saves the result.
+pub fn encode_file(mut args: EncodeFileArgs, write_options: &WriteOptions) -> Result<()> {
+    resolve_password(&mut args.options)?;
+    warn_registered(args.chunk_type(), &args.options);
+    let payload = Payload::File(EmbeddedFile::from_path(&args.input)?);
+    embed(
+        &args.file_path,
+        args.output.path(&args.file_path),
+        args.chunk_type(),
+        &payload,
+        &args.options,
+        write_options,
+    )
+}
+
+/// Warns if payload chunks would get a type that other software is known to
+/// write, so the collision is noticed before anything is embedded.
+fn warn_registered(chunk_type: &str, options: &EncodeOptions) {
+    let registered = ChunkType::from_str(chunk_type)
+        .ok()
+        .and_then(|chunk_type| chunk_type.registered());
+    if let (Method::Chunk, Some(entry)) = (options.method(), registered) {
+        eprintln!(
+            "warning: {chunk_type} chunks are also written by {} ({}), which may misread them",
+            entry.owner, entry.purpose
+        );
+    }
+}
+
+/// Searches for a message hidden in a PNG file and prints it if one is
+/// found, or in every file of a directory or glob pattern.
+pub fn decode(args: DecodeArgs, write_options: &WriteOptions) -> Result<()> {
+    let destination = args.output.destination();
+    if args.combine {
+        return combine(&args, destination, write_options);
+    }
+    match batch::targets(&args.file_path)? {
+        None => {
+            let outcome = decode_file(
+                &args.file_path,
+                &args.chunk_type,
+                &args.options,
+                destination,
+                write_options,
+            )
+            .inspect_err(|err| {
+                if let PngMeError::Payload(PayloadError::NotUtf8 { .. }) = err {
+                    eprintln!("hint: decode it with --lossy, --binary, --hex or --base64");
+                }
+            })?;
+            match destination {
+                Destination::File(output) => status(output, outcome),
+                _ => println!("{outcome}"),
+            }
+        }
+        Some(_) if matches!(destination, Destination::Clipboard(_)) => {
+            return Err(ClipboardError::SeveralFiles.into())
+        }
+        Some(_) if destination == Destination::Qr => return Err(QrError::SeveralFiles.into()),
+        Some(files) => report_batch(&progress::run_batch(&files, |file| {
+            let output;
+            let destination = match destination {
+                Destination::File(dir) => {
+                    output = batch_output(&args.file_path, file, dir)?.with_extension("bin");
+                    Destination::File(&output)
+                }
+                destination => destination,
+            };
+            decode_file(
+                file,
+                &args.chunk_type,
+                &args.options,
+                destination,
+                write_options,
+            )
+        }))?,
+    }
+    Ok(())
+}
+
+/// Rebuilds a message from the shares in the PNG files that
+/// `args.file_path` names and writes it to `destination`. Files without a
+/// share are skipped with a warning, as long as enough others have one.
+fn combine(
+    args: &DecodeArgs,
+    destination: Destination,
+    write_options: &WriteOptions,
+) -> Result<()> {
+    let files = batch::targets(&args.file_path)?.unwrap_or_else(|| vec![args.file_path.clone()]);
+    let mut shares = Vec::new();
+    for file in &files {
+        let share = match extract(file, &args.chunk_type, &args.options, write_options) {
+            Ok(Some(Payload::Share(share))) => Ok(share),
+            Ok(Some(_)) => Err(ShareError::NotAShare.into()),
+            Ok(None) => Err(not_found(&args.chunk_type, &args.options).into()),
+            Err(err) => Err(err),
+        };
+        match share {
+            Ok(share) => shares.push(share),
+            Err(err) => eprintln!("warning: skipping {}: {err}", file.display()),
+        }
+    }
+    let message = shamir::combine(&shares)?;
+    let outcome = write_message(message, destination, write_options)?;
+    match destination {
+        Destination::File(output) => status(output, outcome),
+        _ => println!("{outcome}"),
+    }
+    Ok(())
+}
+
+/// The decoded message of one file as text, or a note on what was found
+/// instead. When the message goes to a file or the clipboard, the note
+/// says so.
+fn decode_file(
+    file_path: &Path,
+    chunk_type: &str,
+    options: &DecodeOptions,
+    destination: Destination,
+    write_options: &WriteOptions,
+) -> Result<String> {
+    let message = match extract(file_path, chunk_type, options, write_options)? {
+        Some(Payload::Message(message)) => message,
+        Some(Payload::File(file)) => {
+            return Ok(format!(
+                "{chunk_type} chunk holds the file {} ({} bytes), use extract-file to restore it",
+                file.name, file.size
+            ))
+        }
+        Some(Payload::Share(share)) if matches!(destination, Destination::Print(_)) => {
+            return Ok(format!(
+                "{chunk_type} chunk holds share {} of a split message, rebuild it with --combine",
+                share.index
+            ))
+        }
+        Some(Payload::Share(share)) => share.to_bytes(),
+        None => return Err(not_found(chunk_type, options).into()),
+    };
+    write_message(message, destination, write_options)
+}
+
+/// Writes `message` to `destination`, returning it as text for
+/// [`Destination::Print`] and a note on where it went otherwise.
+fn write_message(
+    message: Vec<u8>,
+    destination: Destination,
+    write_options: &WriteOptions,
+) -> Result<String> {
+    match destination {
+        Destination::Print(encoding) => encoding.encode(message),
+        Destination::File(output) => {
+            stdio::write(output, &message, write_options)?;
+            Ok(format!(
+                "Wrote {} bytes to {}",
+                message.len(),
+                output.display()
+            ))
+        }
+        Destination::Clipboard(encoding) => {
+            let len = message.len();
+            clipboard::write(&encoding.encode(message)?)?;
+            Ok(format!("Copied {len} bytes to the clipboard"))
+        }
+        Destination::Qr => qr::render(&message),
+    }
+}
+
+/// Prints the result of each file in a batch, its outcome or why it
+/// failed, followed by a summary. Fails if any file did.
+fn report_batch<T: fmt::Display>(
+    results: &[(PathBuf, std::result::Result<T, String>)],
+) -> Result<()> {
+    let mut failed = 0;
+    for (file, result) in results {
+        match result {
+            Ok(outcome) => println!("{}: {outcome}", file.display()),
+            Err(err) => {
+                failed += 1;
+                println!("{}: error, {err}", file.display());
+            }
+        }
+    }
+    println!("{} succeeded, {failed} failed", results.len() - failed);
+    match failed {
+        0 => Ok(()),
+        _ => Err(BatchError {
+            failed,
+            total: results.len(),
+        }
+        .into()),
+    }
+}
+
+/// Where the result for `file`, one of the batch targets of `target`, goes
+/// in the output directory `dir`, creating the directories on the way.
+fn batch_output(target: &Path, file: &Path, dir: &Path) -> Result<PathBuf> {
+    let output = batch::output_path(target, file, dir);
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    Ok(output)
+}
+
+/// Prints a progress message, to standard error if `file_path` is being
+/// written to standard output so the message doesn't end up in the stream.
+fn status(file_path: &Path, message: impl fmt::Display) {
+    match stdio::is_stdio(file_path) {
+        true => eprintln!("{message}"),
+        false => println!("{message}"),
+    }
+}
+
+/// Embeds into or decodes each PNG file that appears under a directory,
+/// printing one line per file, until interrupted.
+pub fn watch(args: WatchArgs, write_options: &WriteOptions) -> Result<()> {
+    println!("Watching {} for new PNG files", args.dir.display());
+    match args.command {
+        WatchCommand::Encode(mut encode) => {
+            resolve_password(&mut encode.options)?;
+            warn_registered(&encode.chunk_type, &encode.options);
+            let payload = Payload::Message(encode.message.into_bytes());
+            watch::run(&args.dir, |file| {
+                match embed(
+                    file,
+                    file,
+                    &encode.chunk_type,
+                    &payload,
+                    &encode.options,
+                    write_options,
+                ) {
+                    Ok(()) => println!("{}: encoded", file.display()),
+                    Err(err) => println!("{}: error, {err}", file.display()),
+                }
+            })
+        }
+        WatchCommand::Decode(decode) => watch::run(&args.dir, |file| {
+            match decode_file(
+                file,
+                &decode.chunk_type,
+                &decode.options,
+                Destination::Print(PayloadEncoding::Text),
+                write_options,
+            ) {
+                Ok(message) => println!("{}: {message}", file.display()),
+                Err(err) => println!("{}: error, {err}", file.display()),
+            }
+        }),
+    }
+}
+
+/// Restores a file embedded with [`encode_file`] into the output directory.
+pub fn extract_file(args: ExtractFileArgs, write_options: &WriteOptions) -> Result<()> {
+    match extract(
+        &args.file_path,
+        args.chunk_type(),
+        &args.options,
+        write_options,
+    )? {
+        Some(Payload::File(file)) => {
+            let path = file.write_to_dir(&args.output_dir)?;
+            println!("Extracted {} ({})", path.display(), file.mime_type);
+        }
+        Some(Payload::Message(_)) => {
+            println!(
+                "{} chunk holds a message, use decode to read it",
+                args.chunk_type()
+            )
+        }
+        Some(Payload::Share(share)) => {
+            println!(
+                "{} chunk holds share {} of a split message, use decode --combine to rebuild it",
+                args.chunk_type(),
+                share.index
+            )
+        }
+        None => return Err(not_found(args.chunk_type(), &args.options).into()),
+    }
+    Ok(())
+}
+
+fn not_found(chunk_type: &str, options: &DecodeOptions) -> PayloadError {
+    PayloadError::NotFound {
+        method: options.method(),
+        chunk_type: chunk_type.to_string(),
+    }
+}
+
+/// Serializes, compresses and protects `payload`, then splits it into chunks
+/// inserted at the chosen position, hides it in the pixel data or appends it
+/// after IEND, writing the result to `output`.
+#[tracing::instrument(
+    skip(file_path, output, payload, options, write_options),
+    fields(file = %file_path.display())
+)]
+fn embed(
+    file_path: &Path,
+    output: &Path,
+    chunk_type: &str,
+    payload: &Payload,
+    options: &EncodeOptions,
+    write_options: &WriteOptions,
+) -> Result<()> {
+    let chunk_type = ChunkType::from_str(chunk_type)?;
+    if apng::is_animation_chunk_type(&chunk_type) {
+        return Err(AnimationError::ReservedChunkType(chunk_type.to_string()).into());
+    }
+    let data = compression::compress(options.compress(), &payload.to_bytes())?;
+    let expiry = expiry(options)?;
+    let data = protect(options, &data, &associated_data(expiry))?;
+    let mut envelope = PayloadEnvelope::new(envelope_flags(options, payload), data);
+    envelope.set_expiry(expiry);
+    let data = envelope.encode();
+    tracing::debug!(method = ?options.method(), len = data.len(), "embedding payload");
+
+    if options.method() == Method::Lsb {
+        if options.label.is_some() {
+            return Err(LsbError::Labeled.into());
+        }
+        let mut png = stdio::read_png(file_path)?;
+        let lsb_options = LsbOptions {
+            key: options.lsb_key.clone(),
+            quality: options.quality,
+        };
+        let spinner = progress::spinner("Embedding in the pixel data");
+        let embedded = lsb::embed(&mut png, &data, &lsb_options);
+        spinner.finish_and_clear();
+        embedded?;
+        if options.touch {
+            png.touch();
+        }
+        stdio::write_png(output, &png, write_options)?;
+        return Ok(());
+    }
+
+    if options.method() == Method::Trailer {
+        if options.label.is_some() {
+            return Err(TrailerError::Labeled.into());
+        }
+        let mut bytes = stdio::read(file_path)?;
+        if options.touch {
+            // Rewrite the PNG itself and keep whatever followed it.
+            let end = trailer::png_len(&bytes)?;
+            let mut png = Png::from_bytes_with(&bytes[..end], &ParseOptions::default())?;
+            png.touch();
+            bytes.splice(..end, png.as_bytes());
+        }
+        stdio::write(output, &trailer::embed(&bytes, &data)?, write_options)?;
+        return Ok(());
+    }
+
+    let position = ChunkPosition::from_str(options.position())?;
+    let chunks = match &options.label {
+        Some(label) => payload::split_labeled(&chunk_type, label, &data, MAX_CHUNK_DATA)?,
+        None => payload::split(&chunk_type, &data, MAX_CHUNK_DATA)?,
+    };
+
+    // The whole file is rewritten rather than appended to in place, so it
+    // can be replaced atomically and a crash never leaves half a chunk.
+    let mut png = stdio::read_png(file_path)?;
+    if let Some(label) = &options.label {
+        let mut index = PayloadIndex::from_png(&png)?;
+        if let Some(old) = index.remove(label) {
+            png.retain_chunks(|chunk| !is_labeled(chunk, &old.chunk_type, label));
+        }
+        index.insert(IndexEntry {
+            label: label.clone(),
+            chunk_type,
+            size: data.len() as u64,
+            parts: chunks.len() as u32,
+        });
+        index.store(&mut png)?;
+    }
+    png.insert_chunks(position, chunks)?;
+    if options.touch {
+        png.touch();
+    }
+    stdio::write_png(output, &png, write_options)?;
+    Ok(())
+}
+
+/// Reverses [`embed`], returning `None` if there is no payload.
+#[tracing::instrument(
+    skip(file_path, options, write_options),
+    fields(file = %file_path.display())
+)]
+fn extract(
+    file_path: &Path,
+    chunk_type: &str,
+    options: &DecodeOptions,
+    write_options: &WriteOptions,
+) -> Result<Option<Payload>> {
+    let data = match options.method() {
+        Method::Chunk => read_chunk_payload(file_path, chunk_type, options)?,
+        // Every LSB payload is enveloped, so anything else is image noise.
+        Method::Lsb => lsb::extract(&stdio::read_png(file_path)?, &lsb_options(options))?
+            .filter(|data| PayloadEnvelope::is_envelope(data)),
+        Method::Trailer => trailer::extract(&stdio::read(file_path)?)?,
+    };
+    let Some(data) = data else {
+        tracing::debug!(method = ?options.method(), "no payload found");
+        return Ok(None);
+    };
+
+    let mut envelope = open_envelope(data, options)?;
+    let counted = match envelope.expiry() {
+        Some(expiry) => check_expiry(
+            file_path,
+            chunk_type,
+            options,
+            &envelope,
+            expiry,
+            write_options,
+        )?,
+        None => false,
+    };
+    let aad = associated_data(envelope.expiry());
+    let data = unprotect(options, envelope.payload(), envelope.flags(), &aad)?;
+    let payload = envelope.open(&data, ParseOptions::default().max_payload_size)?;
+
+    // Reads are only counted once the payload opens, so a wrong password
+    // doesn't use one up.
+    if let Some(expiry) = envelope.expiry().filter(|_| counted) {
+        let expiry = expiry.after_read();
+        envelope.set_expiry(Some(expiry));
+        let data = envelope.encode();
+        match options.scrub_expired && expiry.is_expired(SystemTime::now()) {
+            true => scrub_payload(file_path, chunk_type, options, data.len(), write_options)?,
+            false => rewrite_payload(file_path, chunk_type, options, &data, write_options)?,
+        }
+    }
+    Ok(Some(payload))
+}
+
+/// Refuses an expired payload, scrubbing it first with --scrub-expired, and
+/// one whose reads are counted but can't be written back. Returns whether
+/// the read is counted.
+fn check_expiry(
+    file_path: &Path,
+    chunk_type: &str,
+    options: &DecodeOptions,
+    envelope: &PayloadEnvelope,
+    expiry: Expiry,
+    write_options: &WriteOptions,
+) -> Result<bool> {
+    if expiry.is_expired(SystemTime::now()) {
+        if options.scrub_expired {
+            scrub_payload(
+                file_path,
+                chunk_type,
+                options,
+                envelope.encode().len(),
+                write_options,
+            )?;
+            eprintln!("{}: scrubbed the expired payload", file_path.display());
+        }
+        return Err(EnvelopeError::Expired.into());
+    }
+    if expiry.reads_left.is_none() {
+        return Ok(false);
+    }
+    if stdio::is_stdio(file_path) {
+        return Err(EnvelopeError::UncountedRead("standard input".to_string()).into());
+    }
+    if http::is_url(file_path) {
+        return Err(EnvelopeError::UncountedRead(file_path.display().to_string()).into());
+    }
+    Ok(true)
+}
+
+fn lsb_options(options: &DecodeOptions) -> LsbOptions {
+    LsbOptions {
+        key: options.lsb_key.clone(),
+        quality: options.quality,
+    }
+}
+
+/// Whether `chunk` is one of the chunks of the payload stored under
+/// `chunk_type` and `label`, or under no label.
+fn is_payload_chunk(chunk: &Chunk, chunk_type: &ChunkType, label: Option<&str>) -> bool {
+    chunk.chunk_type() == chunk_type
+        && payload::strip_label(chunk.data()).map(|(found, _)| found) == label
+}
+
+/// Writes the enveloped `data` over the payload [`extract`] found, which it
+/// must match in length, so the same chunks or pixels hold it.
+fn rewrite_payload(
+    file_path: &Path,
+    chunk_type: &str,
+    options: &DecodeOptions,
+    data: &[u8],
+    write_options: &WriteOptions,
+) -> Result<()> {
+    match options.method() {
+        Method::Chunk => {
+            let chunk_type = ChunkType::from_str(chunk_type)?;
+            let parts = match &options.label {
+                Some(label) => payload::split_labeled(&chunk_type, label, data, MAX_CHUNK_DATA)?,
+                None => payload::split(&chunk_type, data, MAX_CHUNK_DATA)?,
+            };
+            let mut parts = parts.into_iter();
+            let mut png = stdio::read_png(file_path)?;
+            for mut chunk in png.iter_mut() {
+                if !is_payload_chunk(&chunk, &chunk_type, options.label.as_deref()) {
+                    continue;
+                }
+                if let Some(part) = parts.next() {
+                    chunk.set_data(part.data().to_vec())?;
+                }
+            }
+            stdio::write_png(file_path, &png, write_options)
+        }
+        Method::Lsb => {
+            let mut png = stdio::read_png(file_path)?;
+            lsb::embed(&mut png, data, &lsb_options(options))?;
+            stdio::write_png(file_path, &png, write_options)
+        }
+        Method::Trailer => {
+            let bytes = trailer::embed(&stdio::read(file_path)?, data)?;
+            stdio::write(file_path, &bytes, write_options)
+        }
+    }
+}
+
+/// Removes the payload [`extract`] found, `len` bytes once enveloped. Pixel
+/// data can't be taken out, so an LSB payload is overwritten with zeros.
+fn scrub_payload(
+    file_path: &Path,
+    chunk_type: &str,
+    options: &DecodeOptions,
+    len: usize,
+    write_options: &WriteOptions,
+) -> Result<()> {
+    match options.method() {
+        Method::Chunk => {
+            let chunk_type = ChunkType::from_str(chunk_type)?;
+            let mut png = stdio::read_png(file_path)?;
+            png.retain_chunks(|chunk| {
+                !is_payload_chunk(chunk, &chunk_type, options.label.as_deref())
+            });
+            if let Some(label) = &options.label {
+                let mut index = PayloadIndex::from_png(&png)?;
+                index.remove(label);
+                index.store(&mut png)?;
+            }
+            stdio::write_png(file_path, &png, write_options)
+        }
+        Method::Lsb => {
+            rewrite_payload(file_path, chunk_type, options, &vec![0; len], write_options)
+        }
+        Method::Trailer => {
+            let mut bytes = stdio::read(file_path)?;
+            if let Some(trailer) = trailer::find(&bytes) {
+                bytes.truncate(trailer.start);
+            }
+            stdio::write(file_path, &bytes, write_options)
+        }
+    }
+}
+
+/// Collects and reassembles the chunks of the payload stored under
+/// `chunk_type`, returning `None` if there are none.
+fn read_chunk_payload(
+    file_path: &Path,
+    chunk_type: &str,
+    options: &DecodeOptions,
+) -> Result<Option<Vec<u8>>> {
+    let mut chunks = Vec::new();
+    if http::is_url(file_path) {
+        chunks = http::chunks_of_type(&file_path.to_string_lossy(), chunk_type)?;
+    } else {
+        for chunk in PngReader::new(stdio::open(file_path)?)? {
+            let chunk = chunk?;
+            if chunk.chunk_type().to_string() == chunk_type {
+                chunks.push(chunk);
+            }
+        }
+    }
+
+    // Unlabeled reads skip labeled chunks sharing the type, and vice versa.
+    let parts: Vec<&[u8]> = chunks
+        .iter()
+        .filter_map(|chunk| match payload::strip_label(chunk.data()) {
+            Some((label, rest)) => (options.label.as_deref() == Some(label)).then_some(rest),
+            None => options.label.is_none().then_some(chunk.data()),
+        })
+        .collect();
+    if parts.is_empty() {
+        return Ok(None);
+    }
+    payload::reassemble(&parts).map(Some)
+}
+
+/// Records the layers [`embed`] applies, so decoding can explain what's
+/// missing, and what kind of payload they wrap.
+fn envelope_flags(options: &EncodeOptions, payload: &Payload) -> EnvelopeFlags {
+    let mut flags = EnvelopeFlags::kind_of(payload);
+    flags.set(
+        EnvelopeFlags::COMPRESSED,
+        options.compress() != Compression::None,
+    );
+    flags.set(
+        EnvelopeFlags::ENCRYPTED,
+        options.password.is_some() || options.recipient.is_some(),
+    );
+    flags.set(EnvelopeFlags::AUTHENTICATED, options.auth_key.is_some());
+    flags.set(EnvelopeFlags::DIGESTED, options.checksum);
+    flags.set(EnvelopeFlags::DENIABLE, options.deniable());
+    flags
+}
+
+/// The expiry set by --expires and --max-reads, if either was given.
+fn expiry(options: &EncodeOptions) -> Result<Option<Expiry>> {
+    if options.expires.is_none() && options.max_reads.is_none() {
+        return Ok(None);
+    }
+    let expires_at = match &options.expires {
+        Some(time) => Some(LastModified::from_str(time)?.to_system_time()),
+        None => None,
+    };
+    Ok(Some(Expiry {
+        expires_at,
+        reads_left: options.max_reads,
+    }))
+}
+
+/// Unwraps the payload envelope, checking an authentication key was
+/// supplied if one is needed. Payloads embedded before envelopes existed
+/// are read as [`PayloadEnvelope::unwrapped`].
+fn open_envelope(data: Vec<u8>, options: &DecodeOptions) -> Result<PayloadEnvelope> {
+    if !PayloadEnvelope::is_envelope(&data) {
+        return Ok(PayloadEnvelope::unwrapped(data));
+    }
+    let envelope = PayloadEnvelope::decode(&data)?;
+    if envelope.flags().contains(EnvelopeFlags::AUTHENTICATED) && options.auth_key.is_none() {
+        return Err(EnvelopeError::Authenticated.into());
+    }
+    Ok(envelope)
+}
+
+/// A password kept off the command line: the keyring entry named by
+/// --use-keyring, or else `$CHAINSAW_PASSWORD`.
+fn stored_password(use_keyring: Option<&str>) -> Result<Option<String>> {
+    match use_keyring {
+        Some(name) => secret::keyring_password(name).map(Some),
+        None => Ok(secret::env_password()),
+    }
+}
+
+/// Asks for the password left empty by a bare --password, or fills in a
+/// stored password when no key was given on the command line.
+fn resolve_password(options: &mut EncodeOptions) -> Result<()> {
+    if options.password.as_deref() == Some("") {
+        options.password = Some(ask_password(true, options.no_prompt)?);
+    } else if options.password.is_none()
+        && options.recipient.is_none()
+        && options.auth_key.is_none()
+    {
+        options.password = stored_password(options.use_keyring.as_deref())?;
+    }
+    if options.hidden.is_some() {
+        let given = options
+            .hidden_password
+            .take()
+            .filter(|password| !password.is_empty());
+        let stored = match (&given, &options.hidden_keyring) {
+            (Some(_), _) => None,
+            (None, Some(name)) => Some(secret::keyring_password(name)?),
+            (None, None) => secret::env_hidden_password(),
+        };
+        options.hidden_password = match given.or(stored) {
+            Some(password) => Some(password),
+            None => Some(ask_hidden_password(options.no_prompt)?),
+        };
+    }
+    Ok(())
+}
+
+/// Prompts for a password, at most once per run, so the files of a batch or
+/// a watched directory all reuse the first answer.
+fn ask_password(confirm: bool, no_prompt: bool) -> Result<String> {
+    static ANSWER: Mutex<Option<String>> = Mutex::new(None);
+    ask_once(&ANSWER, no_prompt, || secret::prompt_password(confirm))
+}
+
+/// Like [`ask_password`], for the password of the --hidden message.
+fn ask_hidden_password(no_prompt: bool) -> Result<String> {
+    static ANSWER: Mutex<Option<String>> = Mutex::new(None);
+    ask_once(&ANSWER, no_prompt, || secret::prompt_hidden_password(true))
+}
+
+fn ask_once(
+    answer: &Mutex<Option<String>>,
+    no_prompt: bool,
+    prompt: impl FnOnce() -> Result<String>,
+) -> Result<String> {
+    if no_prompt {
+        return Err(SecretError::PromptDisabled.into());
+    }
+    let mut answer = answer.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(password) = answer.as_ref() {
+        return Ok(password.clone());
+    }
+    let password = prompt()?;
+    *answer = Some(password.clone());
+    Ok(password)
+}
+
+/// What [`protect`] binds a payload to, so its expiry can't be changed
+/// without the key.
+fn associated_data(expiry: Option<Expiry>) -> Vec<u8> {
+    expiry.map_or_else(Vec::new, |expiry| expiry.associated_data().to_vec())
+}
+
+/// Encrypts or authenticates `message` as requested on the command line,
+/// binding it to `aad`.
+fn protect(options: &EncodeOptions, message: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    if options.deniable() {
+        let password = options
+            .password
+            .as_deref()
+            .ok_or(CryptoError::RequiresPassword)?;
+        let hidden = match (&options.hidden, &options.hidden_password) {
+            (Some(hidden), Some(hidden_password)) => Some((
+                hidden_password.as_str(),
+                compression::compress(options.compress(), hidden.as_bytes())?,
+            )),
+            _ => None,
+        };
+        let hidden = hidden
+            .as_ref()
+            .map(|(password, hidden)| (*password, &hidden[..]));
+        crypto::encrypt_deniable(password, message, hidden, aad)
+    } else if let Some(password) = &options.password {
+        match options.deterministic {
+            true => crypto::encrypt_deterministic(options.cipher(), password, message, aad),
+            false => crypto::encrypt(options.cipher(), password, message, aad),
+        }
+    } else if let Some(recipient) = &options.recipient {
+        let recipient = Recipient::from_str(recipient)?;
+        match options.deterministic {
+            true => crypto::encrypt_to_deterministic(options.cipher(), &recipient, message, aad),
+            false => crypto::encrypt_to(options.cipher(), &recipient, message, aad),
+        }
+    } else if let Some(key) = &options.auth_key {
+        match options.deterministic {
+            true => crypto::authenticate_deterministic(key, message, aad),
+            false => crypto::authenticate(key, message, aad),
+        }
+    } else {
+        Ok(message.to_vec())
+    }
+}
+
+/// Reverses [`protect`], decrypting or verifying `data` bound to `aad` as
+/// requested. An encrypted payload with no key on the command line is
+/// decrypted with a [stored password](stored_password), or else one typed
+/// at a prompt.
+fn unprotect(
+    options: &DecodeOptions,
+    data: &[u8],
+    flags: EnvelopeFlags,
+    aad: &[u8],
+) -> Result<Vec<u8>> {
+    let decrypt = match flags.contains(EnvelopeFlags::DENIABLE) {
+        true => crypto::decrypt_deniable,
+        false => crypto::decrypt,
+    };
+    if let Some(password) = &options.password {
+        match password.is_empty() {
+            true => decrypt(&ask_password(false, options.no_prompt)?, data, aad),
+            false => decrypt(password, data, aad),
+        }
+    } else if let Some(identity) = &options.identity {
+        crypto::decrypt_with(&Identity::from_file(identity)?, data, aad)
+    } else if let Some(key) = &options.auth_key {
+        crypto::verify(key, data, aad)
+    } else if flags.contains(EnvelopeFlags::ENCRYPTED) {
+        let password = match stored_password(options.use_keyring.as_deref())? {
+            Some(password) => password,
+            None if options.no_prompt => return Err(EnvelopeError::Encrypted.into()),
+            None => ask_password(false, false)?,
+        };
+        decrypt(&password, data, aad)
+    } else {
+        Ok(data.to_vec())
+    }
+}
+
+/// Removes a chunk from a PNG file and saves the result.
+pub fn remove(args: RemoveArgs, write_options: &WriteOptions) -> Result<()> {
+    let mut png = stdio::read_png(&args.file_path)?;
+    if args.keep_unsafe_to_copy {
+        png.set_copy_policy(CopyPolicy::KeepAll);
+    }
+    match &args.label {
+        Some(label) => {
+            let chunk_type = ChunkType::from_str(&args.chunk_type)?;
+            let before = png.chunks().len();
+            png.retain_chunks(|chunk| !is_labeled(chunk, &chunk_type, label));
+            let removed = before - png.chunks().len();
+            if removed == 0 {
+                return Err(PngMeError::ChunkNotFound(format!(
+                    "{chunk_type} labeled {label}"
+                )));
+            }
+
+            let mut index = PayloadIndex::from_png(&png)?;
+            index.remove(label);
+            index.store(&mut png)?;
+            status(
+                args.output.path(&args.file_path),
+                format_args!("Removed {label} ({removed} {chunk_type} chunks)"),
+            );
+        }
+        None if args.all => {
+            let removed = png.remove_all_chunks(&args.chunk_type)?;
+            status(
+                args.output.path(&args.file_path),
+                format_args!("Removed {} {} chunks", removed.len(), args.chunk_type),
+            );
+        }
+        None => {
+            let removed = match args.index {
+                Some(index) => {
+                    let position = png
+                        .chunks()
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, chunk)| chunk.chunk_type().to_string() == args.chunk_type)
+                        .nth(index)
+                        .map(|(position, _)| position)
+                        .ok_or_else(|| {
+                            PngMeError::ChunkNotFound(format!("{} #{index}", args.chunk_type))
+                        })?;
+                    png.remove_chunk_at(position)?
+                }
+                None => png.remove_first_chunk(&args.chunk_type)?,
+            };
+            status(
+                args.output.path(&args.file_path),
+                format_args!("Removed {}", removed.chunk_type()),
+            );
+        }
+    }
+    stdio::write_png(args.output.path(&args.file_path), &png, write_options)?;
+    Ok(())
+}
+
+/// Prints the labeled payloads listed in a PNG file's index.
+pub fn list_payloads(args: ListArgs) -> Result<()> {
+    let png = stdio::read_png(&args.file_path)?;
+    let index = PayloadIndex::from_png(&png)?;
+    if args.format == OutputFormat::Json {
+        return summary::print_json(index.entries());
+    }
+    if index.entries().is_empty() {
+        println!("No labeled payloads");
+    }
+    for entry in index.entries() {
+        println!(
+            "{}\t{}\t{} bytes\t{} part(s)",
+            entry.label, entry.chunk_type, entry.size, entry.parts
+        );
+    }
+    Ok(())
+}
+
+/// Returns true if `chunk` is part of the payload labeled `label`.
+fn is_labeled(chunk: &Chunk, chunk_type: &ChunkType, label: &str) -> bool {
+    chunk.chunk_type() == chunk_type
+        && payload::strip_label(chunk.data()).is_some_and(|(found, _)| found == label)
+}
+
+/// Prints all of the chunks in a PNG file.
+pub fn print_chunks(args: ListArgs) -> Result<()> {
+    let png = stdio::read_png(&args.file_path)?;
+    match args.format {
+        OutputFormat::Text => print!("{png}"),
+        OutputFormat::Json => summary::print_json(&png.chunk_summaries())?,
+    }
+    Ok(())
+}
+
+/// Prints a hex dump of the data of one chunk of the given type, numbered
+/// by its offset in the file, or writes the data out unchanged.
+pub fn dump(args: DumpArgs) -> Result<()> {
+    let png = stdio::read_png(&args.file_path)?;
+    let (chunk, span) = png
+        .chunks()
+        .iter()
+        .zip(png.chunk_spans())
+        .filter(|(chunk, _)| chunk.chunk_type().to_string() == args.chunk_type)
+        .nth(args.index)
+        .ok_or_else(|| PngMeError::ChunkNotFound(args.chunk_type.clone()))?;
+    if args.raw {
+        return stdio::write(
+            Path::new(stdio::STDIO_PATH),
+            chunk.data(),
+            &WriteOptions::default(),
+        );
+    }
+    let data_offset = span.start + 8;
+    println!(
+        "{} chunk, {} bytes at offset {data_offset}, CRC {:#010x}",
+        chunk.chunk_type(),
+        chunk.length(),
+        chunk.crc()
+    );
+    print!("{}", HexDump::new(chunk.data(), data_offset));
+    Ok(())
+}
+
+/// Prints the chunk differences between two PNG files.
+pub fn diff(args: DiffArgs) -> Result<()> {
+    let old = stdio::read_png(&args.old_path)?;
+    let new = stdio::read_png(&args.new_path)?;
+    let diff = old.diff(&new);
+    match diff.is_empty() {
+        true => println!("no differences"),
+        false => print!("{diff}"),
+    }
+    Ok(())
+}
+
+/// Prints the PSNR and SSIM of a modified PNG file against the original.
+pub fn compare(args: CompareArgs) -> Result<()> {
+    let original = stdio::read_png(&args.original_path)?;
+    let modified = stdio::read_png(&args.modified_path)?;
+    print!("{}", original.compare(&modified)?);
+    Ok(())
+}
+
+/// Prints the properties a chunk type's letters encode and what they mean,
+/// and who else is known to use the type.
+pub fn explain(args: ExplainArgs) -> Result<()> {
+    let chunk_type = ChunkType::from_str(&args.chunk_type)?;
+    let properties = chunk_type.describe();
+    println!("{chunk_type}: {properties}");
+    for explanation in properties.explanations() {
+        println!("  {explanation}");
+    }
+    if properties.standard {
+        println!("{chunk_type} is defined by the PNG specification.");
+    }
+    if let Some(entry) = chunk_type.registered() {
+        println!(
+            "{chunk_type} is also written by {} ({}).",
+            entry.owner, entry.purpose
+        );
+    }
+    Ok(())
+}
+
+/// Prints a random ancillary, private, safe-to-copy chunk type that no known
+/// software uses.
+pub fn suggest_type() -> Result<()> {
+    println!("{}", ChunkType::random_private());
+    Ok(())
+}
+
+/// Answers HTTP requests until the process is stopped.
+pub fn serve(args: ServeArgs) -> Result<()> {
+    if args.grpc {
+        println!("Listening for gRPC on {}", args.listen);
+        return server::serve_grpc(&args.listen, args.options());
+    }
+    println!("Listening on http://{}", args.listen);
+    server::serve(&args.listen, args.options())
+}
+
+/// Prints a completion script for the shell, generated from the command
+/// line definition so it never falls out of date.
+pub fn completions(args: CompletionsArgs) -> Result<()> {
+    let mut command = Cli::command();
+    let name = command.get_name().to_string();
+    clap_complete::generate(args.shell, &mut command, name, &mut io::stdout());
+    Ok(())
+}
+
+/// Prints how much each embedding method can hide in a PNG file.
+pub fn capacity(args: PrintArgs) -> Result<()> {
+    let png = stdio::read_png(&args.file_path)?;
+    for method in [Method::Chunk, Method::Lsb, Method::Trailer] {
+        match png.capacity(method) {
+            Ok(capacity) => print!("{capacity}"),
+            Err(err) => println!("{method:?}: unavailable, {err}"),
+        }
+    }
+    Ok(())
+}
+
+/// Prints the steganalysis report for a PNG file.
+pub fn analyze(args: PrintArgs) -> Result<()> {
+    let png = stdio::read_png(&args.file_path)?;
+    print!("{}", stego::analyze(&png)?);
+    Ok(())
+}
+
+/// Prints the scan findings for a PNG file, or for every PNG file under a
+/// directory or matching a glob pattern. Files that can't be read are
+/// reported and skipped.
+pub fn scan(args: ScanArgs) -> Result<()> {
+    let files = batch::targets(&args.path)?.unwrap_or_else(|| vec![args.path.clone()]);
+    let reports = progress::run_batch(&files, |file| ScanReport::from_bytes(&stdio::read(file)?));
+    #[cfg(feature = "serde")]
+    if args.format == OutputFormat::Json {
+        let results: Vec<FileScan> = reports
+            .iter()
+            .map(|(path, report)| FileScan {
+                path,
+                findings: report.as_ref().ok().map(|report| &report.findings[..]),
+                error: report.as_ref().err().map(String::as_str),
+            })
+            .collect();
+        return summary::print_json(&results);
+    }
+    #[cfg(not(feature = "serde"))]
+    if args.format == OutputFormat::Json {
+        return Err(PngMeError::JsonUnsupported);
+    }
+    let mut flagged = 0;
+    for (file, report) in &reports {
+        match report {
+            Ok(report) if report.is_clean() => println!("{}: clean", file.display()),
+            Ok(report) => {
+                flagged += 1;
+                for finding in &report.findings {
+                    println!("{}: {finding}", file.display());
+                }
+            }
+            Err(err) => println!("{}: unreadable, {err}", file.display()),
+        }
+    }
+    println!("Scanned {} files, {flagged} with findings", files.len());
+    Ok(())
+}
+
+/// Prints why a command failed to standard error, as a line of text or, for
+/// scripts, as a JSON object with the exit code, its name and the message.
+pub fn report_error(err: &PngMeError, format: OutputFormat) {
+    #[cfg(feature = "serde")]
+    if format == OutputFormat::Json {
+        let code = err.exit_code();
+        let report = ErrorReport {
+            code: code as u8,
+            kind: code.name(),
+            message: err.to_string(),
+        };
+        if let Ok(json) = serde_json::to_string(&report) {
+            eprintln!("{json}");
+            return;
+        }
+    }
+    // Without the serde feature JSON errors fall back to text, since there is
+    // no way left to report that JSON is unsupported.
+    #[cfg(not(feature = "serde"))]
+    let _ = format;
+    eprintln!("error: {err}");
+}
+
+/// The JSON form of an error printed by [`report_error`].
+#[cfg(feature = "serde")]
+#[derive(Serialize)]
+struct ErrorReport {
+    code: u8,
+    kind: &'static str,
+    message: String,
+}
+
+/// Prints chunk statistics for a PNG file, or totals for every PNG file
+/// under a directory or matching a glob pattern. Files that can't be read
+/// are reported and left out.
+pub fn stats(args: ScanArgs) -> Result<()> {
+    let files = batch::targets(&args.path)?.unwrap_or_else(|| vec![args.path.clone()]);
+    let results = progress::run_batch(&files, |file| {
+        Ok(Stats::of(
+            &stdio::read_png(file)?,
+            &file.display().to_string(),
+        ))
+    });
+    let mut stats = Stats::default();
+    for (file, result) in results {
+        match result {
+            Ok(file_stats) => stats.merge(file_stats),
+            Err(err) => eprintln!("{}: unreadable, {err}", file.display()),
+        }
+    }
+    match args.format {
+        OutputFormat::Json => summary::print_json(&stats),
+        OutputFormat::Text => {
+            print!("{stats}");
+            Ok(())
+        }
+    }
+}
+
+/// One file's entry in the JSON output of [`scan`]: its findings, or why it
+/// couldn't be scanned.
+#[cfg(feature = "serde")]
+#[derive(Serialize)]
+struct FileScan<'a> {
+    path: &'a Path,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    findings: Option<&'a [Finding]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+}
+
+/// Prints every chunk ordering violation in a PNG file, failing if there are any.
+pub fn lint(args: PrintArgs) -> Result<()> {
+    let png = stdio::read_png(&args.file_path)?;
+    let violations = png.validate_ordering();
+    if violations.is_empty() {
+        println!("{}: OK", args.file_path.display());
+        return Ok(());
+    }
+    for violation in &violations {
+        println!("{}: {violation}", args.file_path.display());
+    }
+    Err(OrderingError {
+        violations: violations.len(),
+    }
+    .into())
+}
+
+/// Repairs a damaged PNG file in place and prints what was fixed.
+pub fn repair(args: RepairArgs, write_options: &WriteOptions) -> Result<()> {
+    let policy = RepairPolicy {
+        fix_crcs: !args.keep_crcs,
+        drop_truncated: !args.keep_truncated,
+        add_iend: !args.no_iend,
+    };
+    let (png, report) = Png::repair(&stdio::read(&args.file_path)?, policy)?;
+    if report.is_clean() {
+        status(args.output.path(&args.file_path), "Nothing to repair");
+    }
+    for fix in &report.fixes {
+        status(args.output.path(&args.file_path), fix);
+    }
+    // A stream is always passed on, even if there was nothing to fix.
+    if !args.dry_run && (!report.is_clean() || args.output.must_write(&args.file_path)) {
+        stdio::write_png(args.output.path(&args.file_path), &png, write_options)?;
+    }
+    Ok(())
+}
+
+/// Strips the metadata and private chunks from a PNG file, printing what
+/// was removed.
+pub fn scrub(args: ScrubArgs, write_options: &WriteOptions) -> Result<()> {
+    let mut keep = RENDERING_CHUNK_TYPES.to_vec();
+    for chunk_type in &args.keep {
+        keep.push(ChunkType::from_str(chunk_type)?);
+    }
+    let mut png = stdio::read_png(&args.file_path)?;
+    let removed = png.strip_ancillary(&keep);
+    if removed.is_empty() {
+        status(args.output.path(&args.file_path), "Nothing to scrub");
+    } else {
+        let mut types: Vec<String> = Vec::new();
+        for chunk_type in &removed {
+            let chunk_type = chunk_type.to_string();
+            if !types.contains(&chunk_type) {
+                types.push(chunk_type);
+            }
+        }
+        status(
+            args.output.path(&args.file_path),
+            format_args!("Removed {} chunks: {}", removed.len(), types.join(", ")),
+        );
+    }
+    if !args.dry_run && (!removed.is_empty() || args.output.must_write(&args.file_path)) {
+        stdio::write_png(args.output.path(&args.file_path), &png, write_options)?;
+    }
+    Ok(())
+}
+
+/// Shrinks a PNG file without changing its image and prints the savings.
+pub fn optimize(args: OptimizeArgs, write_options: &WriteOptions) -> Result<()> {
+    let mut png = stdio::read_png(&args.file_path)?;
+    let recompress = match (args.level, args.zopfli) {
+        (_, Some(iterations)) => Some(Recompression::Zopfli { iterations }),
+        (level, None) => level.map(Recompression::Zlib),
+    };
+    let options = OptimizeOptions {
+        recompress,
+        ..OptimizeOptions::default()
+    };
+    let spinner = match options.recompress {
+        Some(_) => progress::spinner("Recompressing the image data"),
+        None => ProgressBar::hidden(),
+    };
+    let report = png.optimize(&options);
+    spinner.finish_and_clear();
+    let report = report?;
+    status(args.output.path(&args.file_path), &report);
+    if !args.dry_run && (report.saved() > 0 || args.output.must_write(&args.file_path)) {
+        stdio::write_png(args.output.path(&args.file_path), &png, write_options)?;
+    }
+    Ok(())
+}
+
+/// Generates a new identity or signing key and prints its public key.
+pub fn keygen(args: KeygenArgs) -> Result<()> {
+    let (contents, public) = if args.signing {
+        let key = signing::generate_key();
+        let public = hex::encode(key.verifying_key().as_bytes());
+        (signing::key_file_contents(&key), public)
+    } else {
+        let identity = Identity::generate();
+        (
+            identity.to_file_contents(),
+            identity.recipient().to_string(),
+        )
+    };
+
+    match &args.output {
+        Some(path) => {
+            stdio::write_private(path, contents.as_bytes())?;
+            println!("Public key: {public}");
+        }
+        None => print!("{contents}"),
+    }
+    Ok(())
+}
+
+/// Replaces the first chunk of the given type with a signed copy.
+pub fn sign(args: SignArgs, write_options: &WriteOptions) -> Result<()> {
+    let key = signing::read_signing_key(&args.key)?;
+    let mut png = stdio::read_png(&args.file_path)?;
+    let chunk = png.remove_first_chunk(&args.chunk_type)?;
+    png.append_chunk(Chunk::new_signed(*chunk.chunk_type(), chunk.data(), &key));
+    stdio::write_png(args.output.path(&args.file_path), &png, write_options)?;
+    Ok(())
+}
+
+/// Verifies the signature on the first chunk of the given type and prints
+/// the signed message.
+pub fn verify(args: VerifyArgs) -> Result<()> {
+    let (Some(chunk_type), Some(public_key)) = (&args.chunk_type, &args.public_key) else {
+        return verify_payloads(&args.file_path);
+    };
+    let key = signing::parse_verifying_key(public_key)?;
+    let png = stdio::read_png(&args.file_path)?;
+    let chunk = png
+        .chunk_by_type(chunk_type)
+        .ok_or_else(|| PngMeError::ChunkNotFound(chunk_type.clone()))?;
+    let message = String::from_utf8(chunk.signed_payload(&key)?.to_vec())?;
+    println!("Signature OK: {message}");
+    Ok(())
+}
+
+/// Checks every payload in the file against its stored checksum, printing
+/// one line per payload.
+fn verify_payloads(file_path: &Path) -> Result<()> {
+    let checks = integrity::check_payloads(&stdio::read(file_path)?)?;
+    if checks.is_empty() {
+        println!("{}: no payloads found", file_path.display());
+    }
+    for check in &checks {
+        println!(
+            "{}: {}: {}",
+            file_path.display(),
+            check.location,
+            check.integrity
+        );
+    }
+    let corrupt = checks
+        .iter()
+        .filter(|check| matches!(check.integrity, Integrity::Corrupt(_)))
+        .count();
+    match corrupt {
+        0 => Ok(()),
+        _ => Err(IntegrityError { corrupt }.into()),
+    }
+}
+
+/// Runs a `meta` subcommand.
+pub fn meta(args: MetaArgs, write_options: &WriteOptions) -> Result<()> {
+    match args.command {
+        MetaCommand::Set(args) => meta_set(args, write_options),
+        MetaCommand::Get(args) => meta_get(args),
+        MetaCommand::Del(args) => meta_del(args, write_options),
+        MetaCommand::Time(args) => meta_time(args, write_options),
+        MetaCommand::Exif(args) => meta_exif(args, write_options),
+    }
+}
+
+/// Stores a keyword/value pair as tEXt, or as iTXt when a language is given
+/// or the value is not Latin-1.
+fn meta_set(args: MetaSetArgs, write_options: &WriteOptions) -> Result<()> {
+    let mut png = stdio::read_png(&args.file_path)?;
+    let international = args.language.is_some()
+        || args.translated_keyword.is_some()
+        || args.value.chars().any(|c| u8::try_from(c).is_err());
+    if international {
+        png.set_itxt(
+            &args.keyword,
+            args.language.as_deref().unwrap_or_default(),
+            args.translated_keyword.as_deref().unwrap_or_default(),
+            &args.value,
+        )?;
+    } else {
+        png.set_text(&args.keyword, &args.value)?;
+    }
+    stdio::write_png(args.output.path(&args.file_path), &png, write_options)?;
+    Ok(())
+}
+
+/// Prints one keyword's value, or every keyword and value.
+fn meta_get(args: MetaGetArgs) -> Result<()> {
+    let png = stdio::read_png(&args.file_path)?;
+    match &args.keyword {
+        Some(keyword) => {
+            let text = png
+                .text(keyword)
+                .ok_or_else(|| PngMeError::ChunkNotFound(format!("text chunk {keyword}")))?;
+            println!("{}", text.text);
+        }
+        None => {
+            for text in png.text_chunks() {
+                print_text(&text);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_text(text: &TextChunk) {
+    match text.language_tag.as_deref() {
+        Some(language) if !language.is_empty() => {
+            println!("{} [{language}]: {}", text.keyword, text.text)
+        }
+        _ => println!("{}: {}", text.keyword, text.text),
+    }
+}
+
+/// Deletes every text chunk with the given keyword.
+fn meta_del(args: MetaDelArgs, write_options: &WriteOptions) -> Result<()> {
+    let mut png = stdio::read_png(&args.file_path)?;
+    if png.remove_text(&args.keyword) == 0 {
+        return Err(PngMeError::ChunkNotFound(format!(
+            "text chunk {}",
+            args.keyword
+        )));
+    }
+    stdio::write_png(args.output.path(&args.file_path), &png, write_options)?;
+    Ok(())
+}
+
+/// Prints the tIME timestamp, after updating it if requested.
+fn meta_time(args: MetaTimeArgs, write_options: &WriteOptions) -> Result<()> {
+    let mut png = stdio::read_png(&args.file_path)?;
+    let time = match &args.set {
+        Some(time) => Some(LastModified::from_str(time)?),
+        None => args.touch.then(LastModified::now),
+    };
+    if let Some(time) = &time {
+        png.set_ancillary(time);
+        stdio::write_png(args.output.path(&args.file_path), &png, write_options)?;
+        status(args.output.path(&args.file_path), time);
+        return Ok(());
+    }
+
+    match png.ancillary::<LastModified>()? {
+        Some(time) => println!("{time}"),
+        None => println!("No tIME chunk found"),
+    }
+    Ok(())
+}
+
+/// Prints the EXIF metadata, or strips or replaces it.
+fn meta_exif(args: MetaExifArgs, write_options: &WriteOptions) -> Result<()> {
+    let mut png = stdio::read_png(&args.file_path)?;
+    if args.strip {
+        if !png.strip_exif() {
+            return Err(PngMeError::ChunkNotFound("eXIf".to_string()));
+        }
+    } else if let Some(path) = &args.replace {
+        png.set_exif(&Exif::new(stdio::read(path)?)?);
+    } else {
+        match png.exif()? {
+            Some(exif) => print!("{exif}"),
+            None => println!("No eXIf chunk found"),
+        }
+        return Ok(());
+    }
+    stdio::write_png(args.output.path(&args.file_path), &png, write_options)?;
+    Ok(())
+}
+
+/// Runs an `icc` subcommand.
+pub fn icc(args: IccArgs, write_options: &WriteOptions) -> Result<()> {
+    match args.command {
+        IccCommand::Extract(args) => icc_extract(args, write_options),
+        IccCommand::Embed(args) => icc_embed(args, write_options),
+    }
+}
+
+/// Writes the embedded ICC profile to a file.
+fn icc_extract(args: IccExtractArgs, write_options: &WriteOptions) -> Result<()> {
+    let png = stdio::read_png(&args.file_path)?;
+    let profile = png
+        .icc_profile()?
+        .ok_or_else(|| PngMeError::ChunkNotFound("iCCP".to_string()))?;
+    let output = match args.output {
+        Some(output) => output,
+        // The name is only used as a file name, never as a path.
+        None => format!("{}.icc", profile.name.replace(['/', '\\'], "_")).into(),
+    };
+    stdio::write(&output, &profile.data, write_options)?;
+    status(
+        &output,
+        format_args!(
+            "Extracted {} ({} bytes) to {}",
+            profile.name,
+            profile.data.len(),
+            output.display()
+        ),
+    );
+    Ok(())
+}
+
+/// Embeds an ICC profile read from a file.
+fn icc_embed(args: IccEmbedArgs, write_options: &WriteOptions) -> Result<()> {
+    let mut png = stdio::read_png(&args.file_path)?;
+    let name = match args.name {
+        Some(name) => name,
+        None => args
+            .profile
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "ICC profile".to_string()),
+    };
+    png.set_icc_profile(&name, &stdio::read(&args.profile)?)?;
+    stdio::write_png(args.output.path(&args.file_path), &png, write_options)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clap::Parser;
+
+    use crate::args::{Cli, Command};
+
+    fn temp_png(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("pngme-{name}-{}.png", std::process::id()));
+        let png = Png::from_chunks(vec![
+            Chunk::new(ChunkType::IHDR, vec![0; 13]),
+            Chunk::new(ChunkType::IEND, Vec::new()),
+        ]);
+        fs::write(&path, png.as_bytes()).unwrap();
+        path
+    }
+
+    fn encode_args(path: &Path, flags: &[&str]) -> EncodeArgs {
+        let args = [
+            &["pngme", "encode", path.to_str().unwrap(), "ruSt", "hello"],
+            flags,
+        ];
+        match Cli::try_parse_from(args.concat()).unwrap().command {
+            Command::Encode(args) => args,
+            command => panic!("parsed {command:?}"),
+        }
+    }
+
+    fn decode_options(path: &Path, flags: &[&str]) -> DecodeOptions {
+        let args = [&["pngme", "decode", path.to_str().unwrap(), "ruSt"], flags];
+        match Cli::try_parse_from(args.concat()).unwrap().command {
+            Command::Decode(args) => args.options,
+            command => panic!("parsed {command:?}"),
+        }
+    }
+
+    fn has_payload(path: &Path) -> bool {
+        stdio::read_png(path)
+            .unwrap()
+            .chunk_by_type("ruSt")
+            .is_some()
+    }
+
+    #[test]
+    fn test_reads_are_counted_in_the_file() {
+        let write_options = WriteOptions::default();
+        for scrub in [false, true] {
+            let path = temp_png(&format!("max-reads-{scrub}"));
+            let flags = ["--auth-key", "key", "--max-reads", "2"];
+            encode(encode_args(&path, &flags), &write_options).unwrap();
+            let flags: &[&str] = match scrub {
+                true => &["--auth-key", "key", "--scrub-expired"],
+                false => &["--auth-key", "key"],
+            };
+            let options = decode_options(&path, flags);
+
+            for _ in 0..2 {
+                assert_eq!(
+                    extract(&path, "ruSt", &options, &write_options).unwrap(),
+                    Some(Payload::Message(b"hello".to_vec()))
+                );
+            }
+            match scrub {
+                true => assert!(!has_payload(&path)),
+                false => assert!(matches!(
+                    extract(&path, "ruSt", &options, &write_options),
+                    Err(PngMeError::Envelope(EnvelopeError::Expired))
+                )),
+            }
+            fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_expired_payload_is_refused_and_scrubbed() {
+        let write_options = WriteOptions::default();
+        let path = temp_png("expired");
+        let flags = ["--auth-key", "key", "--expires", "2000-01-01T00:00:00Z"];
+        encode(encode_args(&path, &flags), &write_options).unwrap();
+
+        let options = decode_options(&path, &["--auth-key", "key"]);
+        assert!(matches!(
+            extract(&path, "ruSt", &options, &write_options),
+            Err(PngMeError::Envelope(EnvelopeError::Expired))
+        ));
+        assert!(has_payload(&path));
+
+        let options = decode_options(&path, &["--auth-key", "key", "--scrub-expired"]);
+        assert!(matches!(
+            extract(&path, "ruSt", &options, &write_options),
+            Err(PngMeError::Envelope(EnvelopeError::Expired))
+        ));
+        assert!(!has_payload(&path));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,194 @@
+use std::io::{Read, Write};
+
+use clap::ValueEnum;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use thiserror::Error;
+
+use crate::Result;
+
+/// Starts a compressed payload, ahead of the algorithm id. Envelopes
+/// record compression in their flags, so this is checked, never sniffed.
+const MAGIC: [u8; 2] = [0x8f, b'Z'];
+
+/// Algorithm used to compress a payload before it is embedded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Compression {
+    /// Store the payload as-is.
+    #[default]
+    None,
+    Zlib,
+    Zstd,
+}
+
+impl Compression {
+    /// The identifier written in the payload header.
+    pub fn id(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Zlib => 1,
+            Compression::Zstd => 2,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Compression> {
+        match id {
+            0 => Some(Compression::None),
+            1 => Some(Compression::Zlib),
+            2 => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+}
+
+/// Compresses `data`, prefixing it with a header naming the algorithm.
+///
+/// With [`Compression::None`] the data is returned unchanged and without a
+/// header, so uncompressed payloads look exactly as they always have.
+pub fn compress(algorithm: Compression, data: &[u8]) -> Result<Vec<u8>> {
+    let body = match algorithm {
+        Compression::None => return Ok(data.to_vec()),
+        Compression::Zlib => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()?
+        }
+        Compression::Zstd => zstd::encode_all(data, 0)?,
+    };
+
+    let mut payload = Vec::with_capacity(MAGIC.len() + 1 + body.len());
+    payload.extend_from_slice(&MAGIC);
+    payload.push(algorithm.id());
+    payload.extend_from_slice(&body);
+    Ok(payload)
+}
+
+/// Reverses [`compress`] for a payload known to be compressed.
+///
+/// Fails with [`CompressionError::TooLarge`] once the output passes `limit`
+/// bytes, such as [`ParseOptions::max_payload_size`], without reading on.
+///
+/// [`ParseOptions::max_payload_size`]: crate::png::ParseOptions::max_payload_size
+pub fn decompress(payload: &[u8], limit: u64) -> Result<Vec<u8>> {
+    let rest = payload
+        .strip_prefix(&MAGIC)
+        .ok_or(CompressionError::MissingHeader)?;
+    let (&id, body) = rest.split_first().ok_or(CompressionError::Truncated)?;
+
+    let decoder: Box<dyn Read + '_> =
+        match Compression::from_id(id).ok_or(CompressionError::UnknownAlgorithm(id))? {
+            Compression::None => return check_limit(body.to_vec(), limit),
+            Compression::Zlib => Box::new(ZlibDecoder::new(body)),
+            Compression::Zstd => Box::new(zstd::Decoder::new(body)?),
+        };
+    // One byte past the limit is enough to tell that it was passed.
+    let mut data = Vec::new();
+    decoder
+        .take(limit.saturating_add(1))
+        .read_to_end(&mut data)?;
+    check_limit(data, limit)
+}
+
+/// Returns `data`, a payload stored without compression, if it is within
+/// `limit` bytes, the check [`decompress`] applies to its output.
+pub fn check_limit(data: Vec<u8>, limit: u64) -> Result<Vec<u8>> {
+    match data.len() as u64 > limit {
+        true => Err(CompressionError::TooLarge { max: limit }.into()),
+        false => Ok(data),
+    }
+}
+
+/// Errors raised while decompressing a payload.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum CompressionError {
+    /// A payload flagged as compressed doesn't start with a compression
+    /// header.
+    #[error("compressed payload has no compression header")]
+    MissingHeader,
+    /// The header ends before naming an algorithm.
+    #[error("compressed payload header is truncated")]
+    Truncated,
+    /// The header names an algorithm this version doesn't know.
+    #[error("unknown compression algorithm id {0}")]
+    UnknownAlgorithm(u8),
+    /// The payload decompresses to more than this many bytes.
+    #[error("payload decompresses to more than the limit of {max} bytes")]
+    TooLarge { max: u64 },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PngMeError;
+
+    const LIMIT: u64 = 1024;
+
+    /// Undoes `algorithm` the way an envelope's flags choose to.
+    fn open(algorithm: Compression, payload: &[u8], limit: u64) -> Result<Vec<u8>> {
+        match algorithm {
+            Compression::None => check_limit(payload.to_vec(), limit),
+            _ => decompress(payload, limit),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data: Vec<u8> = b"the same words again and again ".repeat(20);
+        for algorithm in [Compression::None, Compression::Zlib, Compression::Zstd] {
+            let compressed = compress(algorithm, &data).unwrap();
+            if algorithm != Compression::None {
+                assert!(compressed.len() < data.len());
+            }
+            assert_eq!(open(algorithm, &compressed, LIMIT).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_output_limit() {
+        let bomb = vec![0; 1024 * 1024];
+        for algorithm in [Compression::None, Compression::Zlib, Compression::Zstd] {
+            let compressed = compress(algorithm, &bomb).unwrap();
+            assert!(matches!(
+                open(algorithm, &compressed, LIMIT),
+                Err(PngMeError::Compression(CompressionError::TooLarge {
+                    max: LIMIT
+                }))
+            ));
+            assert_eq!(
+                open(algorithm, &compressed, bomb.len() as u64).unwrap(),
+                bomb
+            );
+        }
+    }
+
+    #[test]
+    fn test_missing_header() {
+        assert!(matches!(
+            decompress(b"plain", LIMIT),
+            Err(PngMeError::Compression(CompressionError::MissingHeader))
+        ));
+    }
+
+    #[test]
+    fn test_corrupt_body() {
+        assert!(matches!(
+            decompress(&MAGIC, LIMIT),
+            Err(PngMeError::Compression(CompressionError::Truncated))
+        ));
+        assert!(matches!(
+            decompress(&[MAGIC[0], MAGIC[1], 9], LIMIT),
+            Err(PngMeError::Compression(CompressionError::UnknownAlgorithm(
+                9
+            )))
+        ));
+        for algorithm in [Compression::Zlib, Compression::Zstd] {
+            let compressed = compress(algorithm, &[7; 200]).unwrap();
+            let truncated = &compressed[..compressed.len() - 4];
+            assert!(decompress(truncated, LIMIT).is_err());
+            let mut corrupt = compressed.clone();
+            corrupt[4] ^= 0xff;
+            assert!(decompress(&corrupt, LIMIT).is_err());
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use thiserror::Error;
+
+use crate::chunk_type::ChunkType;
+use crate::compression::Compression;
+use crate::crypto::CipherSuite;
+use crate::payload::Method;
+use crate::position::ChunkPosition;
+use crate::Result;
+
+/// Defaults for options that would otherwise need repeating on every
+/// command, read from a TOML file such as:
+///
+/// ```toml
+/// method = "chunk"
+/// chunk_type = "ruSt"
+/// position = "before-idat"
+/// cipher = "chacha20"
+/// compression = "zstd"
+/// checksum = true
+/// ```
+///
+/// Every key is optional, and options given on the command line win.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    pub method: Option<Method>,
+    /// Chunk type for commands that take it as an option, such as
+    /// `encode-file`.
+    pub chunk_type: Option<ChunkType>,
+    pub position: Option<ChunkPosition>,
+    pub cipher: Option<CipherSuite>,
+    pub compression: Option<Compression>,
+    pub checksum: Option<bool>,
+}
+
+impl Config {
+    /// `chainsaw/config.toml` under `$XDG_CONFIG_HOME`, or under
+    /// `~/.config` if that isn't set.
+    pub fn default_path() -> Option<PathBuf> {
+        let base = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+        Some(base.join("chainsaw").join("config.toml"))
+    }
+
+    /// Reads the file at `path`, or the [default path](Config::default_path)
+    /// if `path` is `None`. Only the default path is allowed to be missing.
+    pub fn load(path: Option<&Path>) -> Result<Config> {
+        if let Some(path) = path {
+            return Config::from_str(&fs::read_to_string(path)?);
+        }
+        match Config::default_path() {
+            Some(path) if path.is_file() => Config::from_str(&fs::read_to_string(path)?),
+            _ => Ok(Config::default()),
+        }
+    }
+}
+
+impl FromStr for Config {
+    type Err = crate::PngMeError;
+
+    fn from_str(s: &str) -> Result<Config> {
+        let table: toml::Table = s.parse().map_err(ConfigError::Syntax)?;
+        let mut config = Config::default();
+        for (key, value) in &table {
+            let text = || {
+                value.as_str().ok_or_else(|| ConfigError::WrongType {
+                    key: key.clone(),
+                    expected: "a string",
+                })
+            };
+            let invalid = || ConfigError::InvalidValue {
+                key: key.clone(),
+                value: value.to_string(),
+            };
+            match key.as_str() {
+                "method" => config.method = Some(parse_enum(text()?).ok_or_else(invalid)?),
+                "chunk_type" => {
+                    config.chunk_type = Some(ChunkType::from_str(text()?).map_err(|_| invalid())?)
+                }
+                "position" => {
+                    config.position = Some(ChunkPosition::from_str(text()?).map_err(|_| invalid())?)
+                }
+                "cipher" => config.cipher = Some(parse_enum(text()?).ok_or_else(invalid)?),
+                "compression" => {
+                    config.compression = Some(parse_enum(text()?).ok_or_else(invalid)?)
+                }
+                "checksum" => {
+                    config.checksum =
+                        Some(value.as_bool().ok_or_else(|| ConfigError::WrongType {
+                            key: key.clone(),
+                            expected: "true or false",
+                        })?)
+                }
+                _ => return Err(ConfigError::UnknownKey(key.clone()).into()),
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Parses one of the names the command line accepts for `T`.
+fn parse_enum<T: ValueEnum>(name: &str) -> Option<T> {
+    T::from_str(name, false).ok()
+}
+
+/// Errors raised while reading a config file.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ConfigError {
+    /// The file isn't valid TOML.
+    #[error("invalid config file: {0}")]
+    Syntax(toml::de::Error),
+    /// A key pngme doesn't know, probably misspelled.
+    #[error("unknown config key {0:?}")]
+    UnknownKey(String),
+    #[error("config key {key:?} should be {expected}")]
+    WrongType { key: String, expected: &'static str },
+    /// A value of the right type that isn't one of the accepted ones.
+    #[error("invalid value {value} for config key {key:?}")]
+    InvalidValue { key: String, value: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PngMeError;
+
+    #[test]
+    fn test_parse() {
+        let config = Config::from_str(
+            r#"
+            method = "lsb"
+            chunk_type = "ruSt"
+            position = "after-idat"
+            cipher = "chacha20"
+            compression = "zstd"
+            checksum = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                method: Some(Method::Lsb),
+                chunk_type: Some(ChunkType::from_str("ruSt").unwrap()),
+                position: Some(ChunkPosition::AfterIdat),
+                cipher: Some(CipherSuite::ChaCha20Poly1305),
+                compression: Some(Compression::Zstd),
+                checksum: Some(true),
+            }
+        );
+        assert_eq!(Config::from_str("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_invalid() {
+        for (toml, expected) in [
+            ("cipher = \"rot13\"", "invalid value"),
+            ("cipher = 3", "should be a string"),
+            ("checksum = \"yes\"", "should be true or false"),
+            ("chiper = \"chacha20\"", "unknown config key"),
+            ("cipher = ", "invalid config file"),
+        ] {
+            let err = Config::from_str(toml).unwrap_err();
+            assert!(matches!(err, PngMeError::Config(_)));
+            assert!(err.to_string().contains(expected), "{toml}: {err}");
+        }
+    }
+}
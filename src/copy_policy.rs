@@ -0,0 +1,113 @@
+use crate::chunk::Chunk;
+use crate::png::Png;
+
+/// Chunk types this crate understands, and so can keep up to date itself
+/// when critical chunks change.
+pub(crate) const KNOWN_CHUNK_TYPES: [&[u8; 4]; 26] = [
+    b"IHDR", b"PLTE", b"IDAT", b"IEND", b"cHRM", b"gAMA", b"iCCP", b"sBIT", b"sRGB", b"cICP",
+    b"mDCV", b"cLLI", b"bKGD", b"hIST", b"tRNS", b"pHYs", b"sPLT", b"eXIf", b"tIME", b"tEXt",
+    b"zTXt", b"iTXt", b"acTL", b"fcTL", b"fdAT", b"oFFs",
+];
+
+/// What happens to unknown unsafe-to-copy chunks when a critical chunk such
+/// as PLTE or IDAT is added, removed or replaced.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CopyPolicy {
+    /// Drop them, as section 14.2 of the specification requires: they may
+    /// depend on the image data in ways this crate can't update.
+    #[default]
+    Spec,
+    /// Keep every chunk, for callers that know the chunks are still valid.
+    KeepAll,
+}
+
+impl CopyPolicy {
+    /// Returns true if `chunk` may be kept after critical chunks change.
+    pub fn keeps(self, chunk: &Chunk) -> bool {
+        let chunk_type = chunk.chunk_type();
+        match self {
+            CopyPolicy::KeepAll => true,
+            CopyPolicy::Spec => {
+                chunk_type.is_critical()
+                    || chunk_type.is_safe_to_copy()
+                    || KNOWN_CHUNK_TYPES.contains(&&chunk_type.bytes())
+            }
+        }
+    }
+}
+
+impl Png {
+    /// Applies the copy policy after a critical chunk changed, returning how
+    /// many chunks were dropped.
+    pub(crate) fn critical_chunks_changed(&mut self) -> usize {
+        let policy = self.copy_policy();
+        let before = self.chunks().len();
+        self.chunks_mut().retain(|chunk| policy.keeps(chunk));
+        before - self.chunks().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    use crate::chunk_type::ChunkType;
+    use crate::png::minimal_png;
+
+    fn chunk(chunk_type: &[u8; 4]) -> Chunk {
+        Chunk::new(ChunkType::try_from(*chunk_type).unwrap(), Vec::new())
+    }
+
+    fn chunk_types(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect()
+    }
+
+    fn sample() -> Png {
+        // prIV is unknown and unsafe to copy; prIv is unknown but safe.
+        let mut png = minimal_png();
+        for chunk_type in [b"gAMA", b"prIV", b"prIv"] {
+            png.append_chunk(chunk(chunk_type));
+        }
+        png
+    }
+
+    #[test]
+    fn test_critical_edit_drops_unknown_unsafe_chunks() {
+        let mut png = sample();
+        png.append_chunk(chunk(b"IDAT"));
+        assert_eq!(chunk_types(&png), ["IHDR", "gAMA", "prIv", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_retain_dropping_a_critical_chunk_applies_the_policy() {
+        let mut png = sample();
+        png.append_chunk(chunk(b"tEXt"));
+        png.retain_chunks(|chunk| chunk.chunk_type().to_string() != "tEXt");
+        assert!(png.chunk_by_type("prIV").is_some());
+
+        png.set_copy_policy(CopyPolicy::KeepAll);
+        png.insert_chunk(3, chunk(b"PLTE"));
+        png.set_copy_policy(CopyPolicy::Spec);
+        png.retain_chunks(|chunk| chunk.chunk_type().to_string() != "PLTE");
+        assert_eq!(chunk_types(&png), ["IHDR", "gAMA", "prIv", "IEND"]);
+    }
+
+    #[test]
+    fn test_ancillary_edit_keeps_everything() {
+        let mut png = sample();
+        png.append_chunk(chunk(b"tEXt"));
+        assert_eq!(png.chunks().len(), 6);
+    }
+
+    #[test]
+    fn test_keep_all_override() {
+        let mut png = sample();
+        png.set_copy_policy(CopyPolicy::KeepAll);
+        png.append_chunk(chunk(b"IDAT"));
+        assert!(png.chunk_by_type("prIV").is_some());
+    }
+}
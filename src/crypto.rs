@@ -0,0 +1,928 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{self, Aead, AeadCore, KeyInit, Nonce, OsRng};
+use aes_gcm::Aes256Gcm;
+use argon2::Argon2;
+use chacha20poly1305::ChaCha20Poly1305;
+use clap::ValueEnum;
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use thiserror::Error;
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+use crate::{PngMeError, Result};
+
+/// Length of the random Argon2id salt stored in each payload.
+pub const SALT_LEN: usize = 16;
+/// Length of the AEAD nonce stored after the salt. Both suites use 96-bit nonces.
+pub const NONCE_LEN: usize = 12;
+/// Bytes added to the plaintext: suite id, salt, nonce and 16-byte tag.
+pub const OVERHEAD: usize = 1 + SALT_LEN + NONCE_LEN + 16;
+/// Bytes added by [`encrypt_to`]: header, ephemeral public key, nonce and tag.
+pub const RECIPIENT_OVERHEAD: usize = 1 + KEY_LEN + NONCE_LEN + 16;
+/// Bytes added to each slot of [`encrypt_deniable`]: salt, nonce, length and
+/// tag.
+pub const SLOT_OVERHEAD: usize = SALT_LEN + NONCE_LEN + 4 + 16;
+/// The smallest plaintext a deniable slot is padded to.
+const MIN_SLOT_LEN: usize = 64;
+
+const KEY_LEN: usize = 32;
+/// Set in the header byte of payloads encrypted to an X25519 public key.
+const RECIPIENT_FLAG: u8 = 0x80;
+/// HKDF context string binding derived keys to this payload format.
+const HKDF_INFO: &[u8] = b"pngme x25519 payload key";
+/// Length of the HMAC-SHA256 tag appended to authenticated payloads.
+pub const TAG_LEN: usize = 32;
+/// Bytes added by [`authenticate`]: the Argon2id salt and the tag.
+pub const AUTH_OVERHEAD: usize = SALT_LEN + TAG_LEN;
+/// HMAC context strings for the values derived, rather than drawn at
+/// random, in deterministic mode. The salt's also salts the Argon2id hash
+/// that keys it.
+const DETERMINISTIC_SALT: &[u8] = b"pngme deterministic salt";
+const DETERMINISTIC_NONCE: &[u8] = b"pngme deterministic nonce";
+const DETERMINISTIC_EPHEMERAL: &[u8] = b"pngme deterministic ephemeral key";
+const DETERMINISTIC_TAG_SALT: &[u8] = b"pngme deterministic tag salt";
+/// HMAC context string for the sub-key of [`authenticate`] tags over
+/// associated data.
+const ASSOCIATED_DATA_KEY: &[u8] = b"pngme associated data key";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The AEAD used to encrypt a payload.
+///
+/// The suite is recorded in the first byte of every encrypted payload so
+/// decryption can select it automatically.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum CipherSuite {
+    /// AES-256-GCM, fastest on CPUs with AES instructions.
+    #[default]
+    #[value(name = "aes256gcm")]
+    Aes256Gcm,
+    /// ChaCha20-Poly1305, fast in software on targets without AES hardware.
+    #[value(name = "chacha20")]
+    ChaCha20Poly1305,
+}
+
+impl CipherSuite {
+    /// The identifier written in the payload header.
+    pub fn id(self) -> u8 {
+        match self {
+            CipherSuite::Aes256Gcm => 1,
+            CipherSuite::ChaCha20Poly1305 => 2,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<CipherSuite> {
+        match id {
+            1 => Some(CipherSuite::Aes256Gcm),
+            2 => Some(CipherSuite::ChaCha20Poly1305),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CipherSuite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CipherSuite::Aes256Gcm => write!(f, "AES-256-GCM"),
+            CipherSuite::ChaCha20Poly1305 => write!(f, "ChaCha20-Poly1305"),
+        }
+    }
+}
+
+/// Encrypts `plaintext` with a key derived from `password`.
+///
+/// The output is `suite || salt || nonce || ciphertext`, where the
+/// ciphertext carries the 16-byte authentication tag at its end. `aad` is
+/// authenticated but not stored, and [`decrypt`] fails unless it is given
+/// the same bytes; pass `&[]` for none.
+pub fn encrypt(
+    suite: CipherSuite,
+    password: &str,
+    plaintext: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>> {
+    let mut salt = [0; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    encrypt_with_salt(suite, password, plaintext, aad, salt, Nonces::Random)
+}
+
+/// Like [`encrypt`], but derives the salt and nonce from the password and
+/// plaintext, so the same inputs always produce the same bytes.
+///
+/// The stored salt is keyed by an Argon2id hash of the password rather than
+/// the password itself, so someone who guesses the plaintext still pays for
+/// Argon2 on every password they try. Nonces still differ whenever the
+/// plaintext does, so this is safe to use with one password for many
+/// messages, but it reveals when two payloads hold the same message.
+pub fn encrypt_deterministic(
+    suite: CipherSuite,
+    password: &str,
+    plaintext: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>> {
+    let base = derive_key(password, DETERMINISTIC_SALT)?;
+    let mut salt = [0; SALT_LEN];
+    salt.copy_from_slice(&derive(&base, DETERMINISTIC_SALT, suite, aad, plaintext)[..SALT_LEN]);
+    encrypt_with_salt(suite, password, plaintext, aad, salt, Nonces::Derived)
+}
+
+fn encrypt_with_salt(
+    suite: CipherSuite,
+    password: &str,
+    plaintext: &[u8],
+    aad: &[u8],
+    salt: [u8; SALT_LEN],
+    nonces: Nonces,
+) -> Result<Vec<u8>> {
+    let key = derive_key(password, &salt)?;
+    let (nonce, ciphertext) = seal(suite, &key, plaintext, aad, nonces)?;
+
+    let mut payload = Vec::with_capacity(OVERHEAD + plaintext.len());
+    payload.push(suite.id());
+    payload.extend_from_slice(&salt);
+    payload.extend_from_slice(&nonce);
+    payload.extend_from_slice(&ciphertext);
+    Ok(payload)
+}
+
+/// Encrypts a decoy `plaintext` with `password` and a `hidden` message with
+/// its own password into two slots of the same size, so that giving up the
+/// decoy's password doesn't reveal whether there is a hidden message.
+/// Without `hidden` the second slot is filled with random bytes, which look
+/// like any other slot.
+///
+/// Each slot is `salt || nonce || ciphertext` with AES-256-GCM, and the
+/// slots are in random order with no header. Both plaintexts are prefixed
+/// with their length (u32) and padded to the same power of two, so a decoy
+/// much shorter than the hidden message is the one giveaway, to whoever
+/// holds the decoy's password. Both slots authenticate `aad` as
+/// [`encrypt`] does.
+pub fn encrypt_deniable(
+    password: &str,
+    plaintext: &[u8],
+    hidden: Option<(&str, &[u8])>,
+    aad: &[u8],
+) -> Result<Vec<u8>> {
+    if hidden.is_some_and(|(hidden_password, _)| hidden_password == password) {
+        return Err(CryptoError::SamePassword.into());
+    }
+    let longest = plaintext
+        .len()
+        .max(hidden.map_or(0, |(_, message)| message.len()));
+    let padded_len = (longest + 4).next_power_of_two().max(MIN_SLOT_LEN);
+    let decoy = seal_slot(password, plaintext, aad, padded_len)?;
+    let other = match hidden {
+        Some((hidden_password, message)) => seal_slot(hidden_password, message, aad, padded_len)?,
+        None => {
+            let mut random = vec![0; decoy.len()];
+            OsRng.fill_bytes(&mut random);
+            random
+        }
+    };
+    Ok(match OsRng.next_u32() & 1 {
+        0 => [decoy, other].concat(),
+        _ => [other, decoy].concat(),
+    })
+}
+
+/// One slot of [`encrypt_deniable`], holding `message` in a plaintext of
+/// `padded_len` bytes.
+fn seal_slot(password: &str, message: &[u8], aad: &[u8], padded_len: usize) -> Result<Vec<u8>> {
+    let len = u32::try_from(message.len()).map_err(|_| CryptoError::Encryption)?;
+    let mut padded = Vec::with_capacity(padded_len);
+    padded.extend_from_slice(&len.to_be_bytes());
+    padded.extend_from_slice(message);
+    padded.resize(padded_len, 0);
+
+    let mut salt = [0; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let key = derive_key(password, &salt)?;
+    let (nonce, ciphertext) = seal(CipherSuite::Aes256Gcm, &key, &padded, aad, Nonces::Random)?;
+    Ok([&salt[..], &nonce, &ciphertext].concat())
+}
+
+/// Decrypts whichever slot of a payload produced by [`encrypt_deniable`]
+/// `password` opens with `aad`.
+pub fn decrypt_deniable(password: &str, payload: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    if !payload.len().is_multiple_of(2) || payload.len() < 2 * SLOT_OVERHEAD {
+        return Err(CryptoError::Truncated.into());
+    }
+    for slot in payload.chunks(payload.len() / 2) {
+        let (salt, rest) = slot.split_at(SALT_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let key = derive_key(password, salt)?;
+        let Ok(padded) = open(CipherSuite::Aes256Gcm, &key, nonce, ciphertext, aad) else {
+            continue;
+        };
+        let (len, message) = padded
+            .split_first_chunk::<4>()
+            .ok_or(CryptoError::Truncated)?;
+        return match message.get(..u32::from_be_bytes(*len) as usize) {
+            Some(message) => Ok(message.to_vec()),
+            None => Err(CryptoError::Truncated.into()),
+        };
+    }
+    Err(CryptoError::Authentication.into())
+}
+
+/// Encrypts `plaintext` so that only the holder of `recipient`'s identity
+/// can read it.
+///
+/// A fresh ephemeral X25519 key is agreed with the recipient and the shared
+/// secret is expanded with HKDF-SHA256. The output is
+/// `suite | 0x80 || ephemeral public key || nonce || ciphertext`, and `aad`
+/// is authenticated as for [`encrypt`].
+pub fn encrypt_to(
+    suite: CipherSuite,
+    recipient: &Recipient,
+    plaintext: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>> {
+    let ephemeral = EphemeralSecret::random_from_rng(OsRng);
+    let ephemeral_public = PublicKey::from(&ephemeral);
+    let shared = ephemeral.diffie_hellman(&recipient.0);
+    encrypt_to_with(
+        suite,
+        recipient,
+        plaintext,
+        aad,
+        ephemeral_public,
+        shared.as_bytes(),
+        Nonces::Random,
+    )
+}
+
+/// Like [`encrypt_to`], but derives the ephemeral key and nonce from the
+/// recipient and plaintext, so the same inputs always produce the same
+/// bytes.
+///
+/// Besides revealing when two payloads hold the same message, this lets
+/// anyone who knows the recipient's public key confirm a guess at the
+/// message.
+pub fn encrypt_to_deterministic(
+    suite: CipherSuite,
+    recipient: &Recipient,
+    plaintext: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>> {
+    let ephemeral = StaticSecret::from(derive(
+        recipient.0.as_bytes(),
+        DETERMINISTIC_EPHEMERAL,
+        suite,
+        aad,
+        plaintext,
+    ));
+    let ephemeral_public = PublicKey::from(&ephemeral);
+    let shared = ephemeral.diffie_hellman(&recipient.0);
+    encrypt_to_with(
+        suite,
+        recipient,
+        plaintext,
+        aad,
+        ephemeral_public,
+        shared.as_bytes(),
+        Nonces::Derived,
+    )
+}
+
+fn encrypt_to_with(
+    suite: CipherSuite,
+    recipient: &Recipient,
+    plaintext: &[u8],
+    aad: &[u8],
+    ephemeral_public: PublicKey,
+    shared: &[u8],
+    nonces: Nonces,
+) -> Result<Vec<u8>> {
+    let key = expand_shared_secret(shared, &ephemeral_public, &recipient.0)?;
+    let (nonce, ciphertext) = seal(suite, &key, plaintext, aad, nonces)?;
+
+    let mut payload = Vec::with_capacity(OVERHEAD + plaintext.len());
+    payload.push(suite.id() | RECIPIENT_FLAG);
+    payload.extend_from_slice(ephemeral_public.as_bytes());
+    payload.extend_from_slice(&nonce);
+    payload.extend_from_slice(&ciphertext);
+    Ok(payload)
+}
+
+/// Decrypts and authenticates a payload produced by [`encrypt`], using the
+/// cipher suite recorded in its header and the `aad` it was encrypted with.
+pub fn decrypt(password: &str, payload: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let (suite, recipient_mode, rest) = split_header(payload)?;
+    tracing::debug!(?suite, recipient_mode, "decrypting with a password");
+    if recipient_mode {
+        return Err(CryptoError::RequiresIdentity.into());
+    }
+    let (salt, rest) = rest.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let key = derive_key(password, salt)?;
+    open(suite, &key, nonce, ciphertext, aad)
+}
+
+/// Decrypts and authenticates a payload produced by [`encrypt_to`] with
+/// `aad`.
+pub fn decrypt_with(identity: &Identity, payload: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let (suite, recipient_mode, rest) = split_header(payload)?;
+    if !recipient_mode {
+        return Err(CryptoError::RequiresPassword.into());
+    }
+    let (ephemeral_public, rest) = rest.split_at(KEY_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+    let ephemeral_public = PublicKey::from(<[u8; KEY_LEN]>::try_from(ephemeral_public)?);
+    let shared = identity.0.diffie_hellman(&ephemeral_public);
+    let key = expand_shared_secret(
+        shared.as_bytes(),
+        &ephemeral_public,
+        &identity.recipient().0,
+    )?;
+    open(suite, &key, nonce, ciphertext, aad)
+}
+
+/// Appends a random salt and an HMAC-SHA256 tag over `payload` and `aad`,
+/// keyed by an Argon2id hash of `passphrase` with that salt. `aad` isn't
+/// stored, as for [`encrypt`].
+///
+/// This detects tampering without hiding the payload; use [`encrypt`] when
+/// the contents should also be secret. Hashing the passphrase means a copy
+/// of the payload costs an Argon2 hash per guess, as a ciphertext does.
+pub fn authenticate(passphrase: &str, payload: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let mut salt = [0; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    authenticate_with_salt(passphrase, payload, aad, salt)
+}
+
+/// Like [`authenticate`], but derives the salt from the passphrase and
+/// payload, as [`encrypt_deterministic`] does.
+pub fn authenticate_deterministic(passphrase: &str, payload: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let base = derive_key(passphrase, DETERMINISTIC_SALT)?;
+    let mut salt = [0; SALT_LEN];
+    let mut mac =
+        <HmacSha256 as Mac>::new_from_slice(&base).expect("HMAC accepts keys of any length");
+    mac.update(DETERMINISTIC_TAG_SALT);
+    mac.update(&(aad.len() as u64).to_be_bytes());
+    mac.update(aad);
+    mac.update(payload);
+    salt.copy_from_slice(&mac.finalize().into_bytes()[..SALT_LEN]);
+    authenticate_with_salt(passphrase, payload, aad, salt)
+}
+
+fn authenticate_with_salt(
+    passphrase: &str,
+    payload: &[u8],
+    aad: &[u8],
+    salt: [u8; SALT_LEN],
+) -> Result<Vec<u8>> {
+    let key = derive_key(passphrase, &salt)?;
+    let mut data = Vec::with_capacity(payload.len() + AUTH_OVERHEAD);
+    data.extend_from_slice(payload);
+    data.extend_from_slice(&salt);
+    data.extend_from_slice(&hmac(&key, aad, payload).finalize().into_bytes());
+    Ok(data)
+}
+
+/// Checks the trailing salt and tag written by [`authenticate`] with `aad`
+/// and returns the payload in front of them.
+pub fn verify(passphrase: &str, data: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let split = data
+        .len()
+        .checked_sub(AUTH_OVERHEAD)
+        .ok_or(CryptoError::Truncated)?;
+    let (payload, rest) = data.split_at(split);
+    let (salt, tag) = rest.split_at(SALT_LEN);
+    hmac(&derive_key(passphrase, salt)?, aad, payload)
+        .verify_slice(tag)
+        .map_err(|_| CryptoError::Tampered)?;
+    Ok(payload.to_vec())
+}
+
+fn hmac(key: &[u8], aad: &[u8], payload: &[u8]) -> HmacSha256 {
+    // KeyInit, imported for the ciphers, offers a new_from_slice as well.
+    let mut mac =
+        <HmacSha256 as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    if !aad.is_empty() {
+        // Tags over associated data get a sub-key of their own, so they
+        // can't pass for tags without.
+        mac.update(ASSOCIATED_DATA_KEY);
+        mac = <HmacSha256 as Mac>::new_from_slice(&mac.finalize().into_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(&(aad.len() as u64).to_be_bytes());
+        mac.update(aad);
+    }
+    mac.update(payload);
+    mac
+}
+
+/// Derives 32 bytes from `plaintext` for deterministic mode: an HMAC-SHA256
+/// keyed by `key` over `context`, the suite id, the length-prefixed `aad`
+/// and the plaintext.
+fn derive(
+    key: &[u8],
+    context: &[u8],
+    suite: CipherSuite,
+    aad: &[u8],
+    plaintext: &[u8],
+) -> [u8; 32] {
+    let mut mac =
+        <HmacSha256 as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(context);
+    mac.update(&[suite.id()]);
+    mac.update(&(aad.len() as u64).to_be_bytes());
+    mac.update(aad);
+    mac.update(plaintext);
+    let mut derived = [0; 32];
+    derived.copy_from_slice(&mac.finalize().into_bytes());
+    derived
+}
+
+/// Splits off the header byte, checking there is room for the salt or
+/// ephemeral key and the nonce that follow it.
+fn split_header(payload: &[u8]) -> Result<(CipherSuite, bool, &[u8])> {
+    let (&header, rest) = payload.split_first().ok_or(CryptoError::Truncated)?;
+    let id = header & !RECIPIENT_FLAG;
+    let suite = CipherSuite::from_id(id).ok_or(CryptoError::UnknownSuite(id))?;
+    let recipient_mode = header & RECIPIENT_FLAG != 0;
+
+    let key_material = if recipient_mode { KEY_LEN } else { SALT_LEN };
+    if rest.len() < key_material + NONCE_LEN {
+        return Err(CryptoError::Truncated.into());
+    }
+    Ok((suite, recipient_mode, rest))
+}
+
+/// Whether [`seal`] draws a random nonce or derives one from the key and
+/// plaintext.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Nonces {
+    Random,
+    Derived,
+}
+
+fn seal(
+    suite: CipherSuite,
+    key: &[u8],
+    plaintext: &[u8],
+    aad: &[u8],
+    nonces: Nonces,
+) -> Result<(Vec<u8>, Vec<u8>)> {
+    match suite {
+        CipherSuite::Aes256Gcm => seal_with::<Aes256Gcm>(suite, key, plaintext, aad, nonces),
+        CipherSuite::ChaCha20Poly1305 => {
+            seal_with::<ChaCha20Poly1305>(suite, key, plaintext, aad, nonces)
+        }
+    }
+}
+
+fn open(
+    suite: CipherSuite,
+    key: &[u8],
+    nonce: &[u8],
+    ciphertext: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>> {
+    match suite {
+        CipherSuite::Aes256Gcm => open_with::<Aes256Gcm>(key, nonce, ciphertext, aad),
+        CipherSuite::ChaCha20Poly1305 => open_with::<ChaCha20Poly1305>(key, nonce, ciphertext, aad),
+    }
+}
+
+fn seal_with<A: Aead + AeadCore + KeyInit>(
+    suite: CipherSuite,
+    key: &[u8],
+    plaintext: &[u8],
+    aad: &[u8],
+    nonces: Nonces,
+) -> Result<(Vec<u8>, Vec<u8>)> {
+    let cipher = A::new_from_slice(key).map_err(|_| CryptoError::Encryption)?;
+    let nonce = match nonces {
+        Nonces::Random => A::generate_nonce(&mut OsRng),
+        Nonces::Derived => Nonce::<A>::clone_from_slice(
+            &derive(key, DETERMINISTIC_NONCE, suite, aad, plaintext)[..NONCE_LEN],
+        ),
+    };
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            aead::Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| CryptoError::Encryption)?;
+    Ok((nonce.to_vec(), ciphertext))
+}
+
+fn open_with<A: Aead + AeadCore + KeyInit>(
+    key: &[u8],
+    nonce: &[u8],
+    ciphertext: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>> {
+    let cipher = A::new_from_slice(key).map_err(|_| CryptoError::Authentication)?;
+    let payload = aead::Payload {
+        msg: ciphertext,
+        aad,
+    };
+    cipher
+        .decrypt(Nonce::<A>::from_slice(nonce), payload)
+        .map_err(|_| CryptoError::Authentication.into())
+}
+
+/// Expands an X25519 shared secret into a symmetric key, binding both public
+/// keys into the derivation.
+fn expand_shared_secret(
+    shared: &[u8],
+    ephemeral: &PublicKey,
+    recipient: &PublicKey,
+) -> Result<[u8; KEY_LEN]> {
+    let mut salt = [0; 2 * KEY_LEN];
+    salt[..KEY_LEN].copy_from_slice(ephemeral.as_bytes());
+    salt[KEY_LEN..].copy_from_slice(recipient.as_bytes());
+
+    let mut key = [0; KEY_LEN];
+    Hkdf::<Sha256>::new(Some(&salt), shared)
+        .expand(HKDF_INFO, &mut key)
+        .map_err(|_| CryptoError::KeyDerivation)?;
+    Ok(key)
+}
+
+/// Derives a 256-bit key from `password` using Argon2id with default parameters.
+fn derive_key(password: &str, salt: &[u8]) -> Result<[u8; KEY_LEN]> {
+    let mut key = [0; KEY_LEN];
+    Argon2::default()
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|_| CryptoError::KeyDerivation)?;
+    Ok(key)
+}
+
+/// An X25519 public key that payloads can be encrypted to, written as hex.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Recipient(PublicKey);
+
+impl FromStr for Recipient {
+    type Err = PngMeError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(Recipient(PublicKey::from(parse_hex_key(s)?)))
+    }
+}
+
+impl fmt::Display for Recipient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.0.as_bytes()))
+    }
+}
+
+impl fmt::Debug for Recipient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Recipient({self})")
+    }
+}
+
+/// An X25519 secret key able to decrypt payloads sent to its [`Recipient`].
+///
+/// Identity files hold the secret key as a hex line, preceded by a comment
+/// line with the matching public key. Lines starting with `#` are ignored.
+pub struct Identity(StaticSecret);
+
+impl Identity {
+    /// Generates a new random identity.
+    pub fn generate() -> Identity {
+        Identity(StaticSecret::random_from_rng(OsRng))
+    }
+
+    /// The public key matching this identity.
+    pub fn recipient(&self) -> Recipient {
+        Recipient(PublicKey::from(&self.0))
+    }
+
+    /// Loads an identity file written by [`Identity::to_file_contents`].
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Identity> {
+        Ok(Identity(StaticSecret::from(read_key_file(path)?)))
+    }
+
+    /// Renders the identity in the format read by [`Identity::from_file`].
+    pub fn to_file_contents(&self) -> String {
+        key_file_contents(&self.recipient().to_string(), &self.0.to_bytes())
+    }
+}
+
+impl fmt::Debug for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Identity({})", self.recipient())
+    }
+}
+
+/// Decodes a 32-byte key written as hex.
+pub(crate) fn parse_hex_key(s: &str) -> Result<[u8; KEY_LEN]> {
+    <[u8; KEY_LEN]>::try_from(hex::decode(s.trim())?.as_slice())
+        .map_err(|_| CryptoError::InvalidKey.into())
+}
+
+/// Reads the secret key from a key file: the first line that is neither
+/// blank nor a `#` comment, as hex.
+pub(crate) fn read_key_file<P: AsRef<Path>>(path: P) -> Result<[u8; KEY_LEN]> {
+    let contents = fs::read_to_string(path)?;
+    let line = contents
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .ok_or(CryptoError::InvalidKey)?;
+    parse_hex_key(line)
+}
+
+/// Renders a key file read by [`read_key_file`], noting the public key in a
+/// comment so it can be shared without touching the secret.
+pub(crate) fn key_file_contents(public: &str, secret: &[u8; KEY_LEN]) -> String {
+    format!("# public key: {public}\n{}\n", hex::encode(secret))
+}
+
+/// Errors raised while encrypting or decrypting a payload.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum CryptoError {
+    /// Argon2 rejected the password or salt.
+    #[error("failed to derive a key from the password")]
+    KeyDerivation,
+    #[error("failed to encrypt the payload")]
+    Encryption,
+    /// The password is wrong or the ciphertext was modified.
+    #[error("wrong password or the payload has been tampered with")]
+    Authentication,
+    /// The payload is too short to hold its header.
+    #[error("encrypted payload is truncated")]
+    Truncated,
+    /// The payload header names a cipher suite this version doesn't know.
+    #[error("unknown cipher suite id {0}")]
+    UnknownSuite(u8),
+    /// A public or secret key is not 32 hex-encoded bytes.
+    #[error("keys must be 32 bytes encoded as hex")]
+    InvalidKey,
+    /// The payload was encrypted to a public key, not with a password.
+    #[error("payload is encrypted to a public key, use --identity")]
+    RequiresIdentity,
+    /// The payload was encrypted with a password, not to a public key.
+    #[error("payload is encrypted with a password, use --password")]
+    RequiresPassword,
+    /// The HMAC tag doesn't match: wrong passphrase or modified payload.
+    #[error("authentication tag mismatch: wrong key or the payload was tampered with")]
+    Tampered,
+    /// A hidden message was given the same password as its decoy.
+    #[error("the hidden message needs a different password from the decoy")]
+    SamePassword,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::envelope::{EnvelopeFlags, Expiry, PayloadEnvelope, HEADER_LEN};
+    use crate::payload;
+    use crate::png::minimal_png;
+    use crate::position::ChunkPosition;
+
+    const SUITES: [CipherSuite; 2] = [CipherSuite::Aes256Gcm, CipherSuite::ChaCha20Poly1305];
+
+    #[test]
+    fn test_round_trip_each_suite() {
+        for suite in SUITES {
+            let payload = encrypt(suite, "hunter2", b"attack at dawn", &[]).unwrap();
+            assert_eq!(payload[0], suite.id());
+            assert_eq!(payload.len(), b"attack at dawn".len() + OVERHEAD);
+            assert_eq!(
+                decrypt("hunter2", &payload, &[]).unwrap(),
+                b"attack at dawn"
+            );
+        }
+    }
+
+    #[test]
+    fn test_wrong_password() {
+        for suite in SUITES {
+            let payload = encrypt(suite, "hunter2", b"attack at dawn", &[]).unwrap();
+            assert!(matches!(
+                decrypt("hunter3", &payload, &[]),
+                Err(PngMeError::Crypto(CryptoError::Authentication))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_flipped_ciphertext_byte() {
+        for suite in SUITES {
+            let mut payload = encrypt(suite, "hunter2", b"attack at dawn", &[]).unwrap();
+            *payload.last_mut().unwrap() ^= 1;
+            assert!(matches!(
+                decrypt("hunter2", &payload, &[]),
+                Err(PngMeError::Crypto(CryptoError::Authentication))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_malformed_header() {
+        assert!(matches!(
+            decrypt("hunter2", &[], &[]),
+            Err(PngMeError::Crypto(CryptoError::Truncated))
+        ));
+        assert!(matches!(
+            decrypt("hunter2", &[9; OVERHEAD], &[]),
+            Err(PngMeError::Crypto(CryptoError::UnknownSuite(9)))
+        ));
+    }
+
+    #[test]
+    fn test_argon2_derivation() {
+        let salt = [7; SALT_LEN];
+        let key = derive_key("hunter2", &salt).unwrap();
+        assert_eq!(key, derive_key("hunter2", &salt).unwrap());
+        assert_ne!(key, derive_key("hunter2", &[8; SALT_LEN]).unwrap());
+        assert_ne!(key, derive_key("hunter3", &salt).unwrap());
+        // Argon2 rejects salts shorter than 8 bytes.
+        assert!(matches!(
+            derive_key("hunter2", &[0; 4]),
+            Err(PngMeError::Crypto(CryptoError::KeyDerivation))
+        ));
+    }
+
+    #[test]
+    fn test_recipient_round_trip() {
+        let identity = Identity::generate();
+        let payload =
+            encrypt_to(CipherSuite::Aes256Gcm, &identity.recipient(), b"hi", &[]).unwrap();
+        assert_eq!(decrypt_with(&identity, &payload, &[]).unwrap(), b"hi");
+        assert!(matches!(
+            decrypt("hunter2", &payload, &[]),
+            Err(PngMeError::Crypto(CryptoError::RequiresIdentity))
+        ));
+        assert!(matches!(
+            decrypt_with(&Identity::generate(), &payload, &[]),
+            Err(PngMeError::Crypto(CryptoError::Authentication))
+        ));
+    }
+
+    #[test]
+    fn test_deterministic_encode_is_reproducible() {
+        let encode = || {
+            let mut png = minimal_png();
+            let data =
+                encrypt_deterministic(CipherSuite::Aes256Gcm, "hunter2", b"same", &[]).unwrap();
+            let chunk_type = "ruSt".parse().unwrap();
+            let chunks = payload::split(&chunk_type, &data, payload::MAX_CHUNK_DATA).unwrap();
+            png.insert_chunks(ChunkPosition::BeforeIend, chunks)
+                .unwrap();
+            png.as_bytes()
+        };
+        assert_eq!(encode(), encode());
+
+        let data = encrypt_deterministic(CipherSuite::Aes256Gcm, "hunter2", b"same", &[]).unwrap();
+        assert_eq!(decrypt("hunter2", &data, &[]).unwrap(), b"same");
+        let other = encrypt_deterministic(CipherSuite::Aes256Gcm, "hunter2", b"diff", &[]).unwrap();
+        assert_ne!(data[1..1 + SALT_LEN], other[1..1 + SALT_LEN]);
+    }
+
+    #[test]
+    fn test_deterministic_salt_needs_argon2() {
+        // The salt must not be checkable with the password and plaintext alone.
+        let data = encrypt_deterministic(CipherSuite::Aes256Gcm, "hunter2", b"same", &[]).unwrap();
+        let cheap = derive(
+            b"hunter2",
+            DETERMINISTIC_SALT,
+            CipherSuite::Aes256Gcm,
+            &[],
+            b"same",
+        );
+        assert_ne!(data[1..1 + SALT_LEN], cheap[..SALT_LEN]);
+    }
+
+    #[test]
+    fn test_authenticate_round_trip() {
+        let data = authenticate("shared secret", b"in the clear", &[]).unwrap();
+        assert_eq!(data.len(), b"in the clear".len() + AUTH_OVERHEAD);
+        assert_eq!(
+            verify("shared secret", &data, &[]).unwrap(),
+            b"in the clear"
+        );
+    }
+
+    #[test]
+    fn test_verify_rejects_tampering() {
+        let mut data = authenticate("shared secret", b"in the clear", &[]).unwrap();
+        data[0] ^= 1;
+        assert!(matches!(
+            verify("shared secret", &data, &[]),
+            Err(PngMeError::Crypto(CryptoError::Tampered))
+        ));
+        assert!(matches!(
+            verify("shared secret", &[0; AUTH_OVERHEAD - 1], &[]),
+            Err(PngMeError::Crypto(CryptoError::Truncated))
+        ));
+    }
+
+    #[test]
+    fn test_authenticate_salts_the_key() {
+        let first = authenticate("shared secret", b"in the clear", &[]).unwrap();
+        let second = authenticate("shared secret", b"in the clear", &[]).unwrap();
+        assert_ne!(first, second);
+        let deterministic =
+            authenticate_deterministic("shared secret", b"in the clear", &[9]).unwrap();
+        assert_eq!(
+            authenticate_deterministic("shared secret", b"in the clear", &[9]).unwrap(),
+            deterministic
+        );
+        assert_eq!(
+            verify("shared secret", &deterministic, &[9]).unwrap(),
+            b"in the clear"
+        );
+    }
+
+    #[test]
+    fn test_verify_rejects_wrong_passphrase() {
+        let data = authenticate("shared secret", b"in the clear", &[]).unwrap();
+        assert!(matches!(
+            verify("other secret", &data, &[]),
+            Err(PngMeError::Crypto(CryptoError::Tampered))
+        ));
+    }
+
+    #[test]
+    fn test_deniable_passwords_open_their_own_slot() {
+        let payload =
+            encrypt_deniable("decoy", b"grocery list", Some(("real", b"the plans")), &[]).unwrap();
+        assert_eq!(
+            decrypt_deniable("decoy", &payload, &[]).unwrap(),
+            b"grocery list"
+        );
+        assert_eq!(
+            decrypt_deniable("real", &payload, &[]).unwrap(),
+            b"the plans"
+        );
+        assert!(matches!(
+            decrypt_deniable("neither", &payload, &[]),
+            Err(PngMeError::Crypto(CryptoError::Authentication))
+        ));
+        assert!(matches!(
+            encrypt_deniable("same", b"a", Some(("same", b"b")), &[]),
+            Err(PngMeError::Crypto(CryptoError::SamePassword))
+        ));
+    }
+
+    #[test]
+    fn test_deniable_size_hides_the_hidden_message() {
+        let without = encrypt_deniable("decoy", b"grocery list", None, &[]).unwrap();
+        let with =
+            encrypt_deniable("decoy", b"grocery list", Some(("real", b"plans")), &[]).unwrap();
+        assert_eq!(with.len(), without.len());
+        assert_eq!(
+            decrypt_deniable("decoy", &without, &[]).unwrap(),
+            b"grocery list"
+        );
+    }
+
+    #[test]
+    fn test_tampered_expiry_fails() {
+        let expiry = Expiry {
+            expires_at: Some(UNIX_EPOCH + Duration::from_secs(4_000_000_000)),
+            reads_left: Some(3),
+        };
+        let aad = expiry.associated_data();
+        let sealed = [
+            encrypt(CipherSuite::Aes256Gcm, "hunter2", b"soon gone", &aad).unwrap(),
+            authenticate("hunter2", b"soon gone", &aad).unwrap(),
+        ];
+        for (protected, data) in sealed.into_iter().enumerate() {
+            let mut envelope = PayloadEnvelope::new(EnvelopeFlags::default(), data);
+            envelope.set_expiry(Some(expiry));
+            let mut bytes = envelope.encode();
+            // Push the expiry time back by a few years.
+            bytes[HEADER_LEN] += 1;
+            let tampered = PayloadEnvelope::decode(&bytes).unwrap();
+            let aad = tampered.expiry().unwrap().associated_data();
+            let opened = match protected {
+                0 => decrypt("hunter2", tampered.payload(), &aad),
+                _ => verify("hunter2", tampered.payload(), &aad),
+            };
+            assert!(opened.is_err());
+
+            // Counting a read leaves the payload readable.
+            envelope.set_expiry(Some(expiry.after_read()));
+            let read = PayloadEnvelope::decode(&envelope.encode()).unwrap();
+            let aad = read.expiry().unwrap().associated_data();
+            let opened = match protected {
+                0 => decrypt("hunter2", read.payload(), &aad),
+                _ => verify("hunter2", read.payload(), &aad),
+            };
+            assert_eq!(opened.unwrap(), b"soon gone");
+        }
+        // Nor can the expiry be stripped.
+        let data = authenticate("hunter2", b"soon gone", &aad).unwrap();
+        assert!(verify("hunter2", &data, &[]).is_err());
+    }
+}
@@ -0,0 +1,287 @@
+use std::fmt;
+use std::ops::Range;
+
+use crate::chunk::Chunk;
+use crate::png::Png;
+
+/// Byte ranges listed for a modified chunk before the rest are summarized.
+const MAX_SHOWN_RANGES: usize = 8;
+
+/// How one chunk differs between two PNGs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ChunkChange {
+    /// The chunk at `position` in the new PNG has no counterpart in the old.
+    Added {
+        position: usize,
+        chunk_type: String,
+        length: u32,
+    },
+    /// The chunk at `position` in the old PNG has no counterpart in the new.
+    Removed {
+        position: usize,
+        chunk_type: String,
+        length: u32,
+    },
+    /// A chunk kept its type but not its data. `ranges` are the byte ranges
+    /// of the data that differ, counting bytes past the end of the shorter
+    /// chunk as different.
+    Modified {
+        old_position: usize,
+        new_position: usize,
+        chunk_type: String,
+        old_length: u32,
+        new_length: u32,
+        ranges: Vec<Range<usize>>,
+    },
+}
+
+impl fmt::Display for ChunkChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChunkChange::Added {
+                position,
+                chunk_type,
+                length,
+            } => write!(
+                f,
+                "+ chunk {position} ({chunk_type}): added, {length} bytes"
+            ),
+            ChunkChange::Removed {
+                position,
+                chunk_type,
+                length,
+            } => write!(
+                f,
+                "- chunk {position} ({chunk_type}): removed, {length} bytes"
+            ),
+            ChunkChange::Modified {
+                old_position,
+                new_position,
+                chunk_type,
+                old_length,
+                new_length,
+                ranges,
+            } => {
+                write!(
+                    f,
+                    "~ chunk {old_position} -> {new_position} ({chunk_type}): \
+                     {old_length} -> {new_length} bytes, differs at "
+                )?;
+                for (i, range) in ranges.iter().take(MAX_SHOWN_RANGES).enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}..{}", range.start, range.end)?;
+                }
+                if ranges.len() > MAX_SHOWN_RANGES {
+                    write!(f, " and {} more", ranges.len() - MAX_SHOWN_RANGES)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Every chunk that differs between two PNGs, in file order. Chunks that are
+/// identical in both, CRC included, are left out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PngDiff {
+    pub changes: Vec<ChunkChange>,
+}
+
+impl PngDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl fmt::Display for PngDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{change}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Png {
+    /// Compares this PNG with `other`, chunk by chunk.
+    ///
+    /// Chunks that appear unchanged in both, in the same relative order, are
+    /// matched first. Between those, a chunk only in `self` is paired with
+    /// the next unmatched chunk of the same type in `other` and reported as
+    /// modified; the rest are removed or added.
+    pub fn diff(&self, other: &Png) -> PngDiff {
+        let (old, new) = (self.chunks(), other.chunks());
+        let mut changes = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        for (next_i, next_j) in unchanged(old, new)
+            .into_iter()
+            .chain([(old.len(), new.len())])
+        {
+            diff_gap(old, i..next_i, new, j..next_j, &mut changes);
+            (i, j) = (next_i + 1, next_j + 1);
+        }
+        PngDiff { changes }
+    }
+}
+
+fn same(a: &Chunk, b: &Chunk) -> bool {
+    a.chunk_type() == b.chunk_type() && a.crc() == b.crc() && a.data() == b.data()
+}
+
+/// Positions of the chunks left unchanged, as a longest common subsequence
+/// of the two chunk lists.
+fn unchanged(old: &[Chunk], new: &[Chunk]) -> Vec<(usize, usize)> {
+    let width = new.len() + 1;
+    // lengths[i * width + j] is the LCS length of old[i..] and new[j..].
+    let mut lengths = vec![0u32; (old.len() + 1) * width];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i * width + j] = match same(&old[i], &new[j]) {
+                true => lengths[(i + 1) * width + j + 1] + 1,
+                false => lengths[(i + 1) * width + j].max(lengths[i * width + j + 1]),
+            };
+        }
+    }
+
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if same(&old[i], &new[j]) {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+/// Reports the chunks between two unchanged ones.
+fn diff_gap(
+    old: &[Chunk],
+    old_gap: Range<usize>,
+    new: &[Chunk],
+    new_gap: Range<usize>,
+    changes: &mut Vec<ChunkChange>,
+) {
+    let mut paired = vec![false; new_gap.len()];
+    for old_position in old_gap {
+        let chunk = &old[old_position];
+        let counterpart = new_gap
+            .clone()
+            .zip(paired.iter_mut())
+            .find(|(position, paired)| {
+                !**paired && new[*position].chunk_type() == chunk.chunk_type()
+            });
+        changes.push(match counterpart {
+            Some((new_position, paired)) => {
+                *paired = true;
+                let other = &new[new_position];
+                ChunkChange::Modified {
+                    old_position,
+                    new_position,
+                    chunk_type: chunk.chunk_type().to_string(),
+                    old_length: chunk.length(),
+                    new_length: other.length(),
+                    ranges: differing_ranges(chunk.data(), other.data()),
+                }
+            }
+            None => ChunkChange::Removed {
+                position: old_position,
+                chunk_type: chunk.chunk_type().to_string(),
+                length: chunk.length(),
+            },
+        });
+    }
+    for (position, _) in new_gap.zip(paired).filter(|(_, paired)| !paired) {
+        changes.push(ChunkChange::Added {
+            position,
+            chunk_type: new[position].chunk_type().to_string(),
+            length: new[position].length(),
+        });
+    }
+}
+
+/// Runs of differing bytes between `old` and `new`, with any bytes past the
+/// end of the shorter one counted as differing.
+fn differing_ranges(old: &[u8], new: &[u8]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    let mut extend = |range: Range<usize>| match ranges.last_mut() {
+        Some(last) if last.end == range.start => last.end = range.end,
+        _ => ranges.push(range),
+    };
+    let common = old.len().min(new.len());
+    for i in (0..common).filter(|&i| old[i] != new[i]) {
+        extend(i..i + 1);
+    }
+    let end = old.len().max(new.len());
+    if end > common {
+        extend(common..end);
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    use crate::chunk_type::ChunkType;
+
+    fn chunk(chunk_type: &[u8; 4], data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::try_from(*chunk_type).unwrap(), data.to_vec())
+    }
+
+    #[test]
+    fn test_identical() {
+        let png = || Png::from_chunks(vec![chunk(b"IHDR", &[0; 13]), chunk(b"IEND", &[])]);
+        assert!(png().diff(&png()).is_empty());
+    }
+
+    #[test]
+    fn test_changes() {
+        let old = Png::from_chunks(vec![
+            chunk(b"IHDR", &[0; 13]),
+            chunk(b"tEXt", b"Comment\0old text"),
+            chunk(b"gAMA", &[0; 4]),
+            chunk(b"IDAT", &[1, 2, 3]),
+            chunk(b"IEND", &[]),
+        ]);
+        let new = Png::from_chunks(vec![
+            chunk(b"IHDR", &[0; 13]),
+            chunk(b"tEXt", b"Comment\0new text!"),
+            chunk(b"IDAT", &[1, 2, 3]),
+            chunk(b"ruSt", b"secret"),
+            chunk(b"IEND", &[]),
+        ]);
+        assert_eq!(
+            old.diff(&new).changes,
+            [
+                ChunkChange::Modified {
+                    old_position: 1,
+                    new_position: 1,
+                    chunk_type: "tEXt".to_string(),
+                    old_length: 16,
+                    new_length: 17,
+                    ranges: vec![8..11, 16..17],
+                },
+                ChunkChange::Removed {
+                    position: 2,
+                    chunk_type: "gAMA".to_string(),
+                    length: 4,
+                },
+                ChunkChange::Added {
+                    position: 3,
+                    chunk_type: "ruSt".to_string(),
+                    length: 6,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,66 @@
+use std::fmt;
+
+/// Bytes shown on each line of a [`HexDump`].
+pub const BYTES_PER_LINE: usize = 16;
+
+/// Displays bytes the way `hexdump -C` does: an offset, sixteen bytes in hex
+/// split into two groups of eight, then the same bytes as ASCII with
+/// anything unprintable shown as `.`.
+#[derive(Debug, Clone, Copy)]
+pub struct HexDump<'a> {
+    data: &'a [u8],
+    offset: u64,
+}
+
+impl<'a> HexDump<'a> {
+    /// Dumps `data`, numbering lines from `offset`, typically where the data
+    /// sits in its file.
+    pub fn new(data: &'a [u8], offset: u64) -> HexDump<'a> {
+        HexDump { data, offset }
+    }
+}
+
+impl fmt::Display for HexDump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut offset = self.offset;
+        for line in self.data.chunks(BYTES_PER_LINE) {
+            write!(f, "{offset:08x} ")?;
+            for column in 0..BYTES_PER_LINE {
+                if column % 8 == 0 {
+                    write!(f, " ")?;
+                }
+                match line.get(column) {
+                    Some(byte) => write!(f, "{byte:02x} ")?,
+                    None => write!(f, "   ")?,
+                }
+            }
+            let ascii: String = line
+                .iter()
+                .map(|&byte| match byte.is_ascii_graphic() || byte == b' ' {
+                    true => byte as char,
+                    false => '.',
+                })
+                .collect();
+            writeln!(f, " |{ascii}|")?;
+            offset += line.len() as u64;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_dump() {
+        let data = b"IHDR\x00\x00\x00\x01 hello, world\x7f!";
+        let dump = HexDump::new(data, 0x0c).to_string();
+        assert_eq!(
+            dump,
+            "0000000c  49 48 44 52 00 00 00 01  20 68 65 6c 6c 6f 2c 20  |IHDR.... hello, |\n\
+             0000001c  77 6f 72 6c 64 7f 21                              |world.!|\n"
+        );
+        assert_eq!(HexDump::new(&[], 0).to_string(), "");
+    }
+}
@@ -0,0 +1,567 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::chunk;
+use crate::compression;
+use crate::payload::{EmbeddedFile, Payload};
+use crate::shamir::Share;
+use crate::Result;
+
+/// Identifies an enveloped payload. The first byte is not valid UTF-8, so a
+/// plain text payload can't be mistaken for an envelope.
+pub const ENVELOPE_MAGIC: [u8; 4] = [0x8f, b'P', b'M', b'E'];
+/// The envelope version written by this release.
+pub const CURRENT_VERSION: u8 = 2;
+/// Magic, version, flags (u16), length (u64) and checksum (u32).
+pub const HEADER_LEN: usize = ENVELOPE_MAGIC.len() + 1 + 2 + 8 + 4;
+/// Length of the SHA-256 digest that follows the header when
+/// [`EnvelopeFlags::DIGESTED`] is set.
+pub const DIGEST_LEN: usize = 32;
+/// Length of the [`Expiry`] that follows the digest when
+/// [`EnvelopeFlags::EXPIRING`] is set.
+pub const EXPIRY_LEN: usize = 8 + 4;
+
+/// Records which layers were applied to an enveloped payload.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EnvelopeFlags(u16);
+
+impl EnvelopeFlags {
+    pub const COMPRESSED: EnvelopeFlags = EnvelopeFlags(0b001);
+    pub const ENCRYPTED: EnvelopeFlags = EnvelopeFlags(0b010);
+    pub const AUTHENTICATED: EnvelopeFlags = EnvelopeFlags(0b100);
+    /// A SHA-256 of the payload is stored after the header.
+    pub const DIGESTED: EnvelopeFlags = EnvelopeFlags(0b1000);
+    /// The payload was encrypted with [`crypto::encrypt_deniable`], and may
+    /// hold a second message under another password.
+    ///
+    /// [`crypto::encrypt_deniable`]: crate::crypto::encrypt_deniable
+    pub const DENIABLE: EnvelopeFlags = EnvelopeFlags(0b1_0000);
+    /// An [`Expiry`] is stored after the digest.
+    pub const EXPIRING: EnvelopeFlags = EnvelopeFlags(0b10_0000);
+    /// The payload is a [`Payload::File`].
+    pub const FILE: EnvelopeFlags = EnvelopeFlags(0b100_0000);
+    /// The payload is a [`Payload::Share`].
+    pub const SHARE: EnvelopeFlags = EnvelopeFlags(0b1000_0000);
+
+    const KNOWN: u16 = 0b1111_1111;
+
+    /// The [`FILE`](Self::FILE) or [`SHARE`](Self::SHARE) bit recording what
+    /// kind of payload `payload` is.
+    pub fn kind_of(payload: &Payload) -> EnvelopeFlags {
+        match payload {
+            Payload::Message(_) => EnvelopeFlags::default(),
+            Payload::File(_) => EnvelopeFlags::FILE,
+            Payload::Share(_) => EnvelopeFlags::SHARE,
+        }
+    }
+
+    pub fn bits(self) -> u16 {
+        self.0
+    }
+
+    pub fn contains(self, other: EnvelopeFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Sets or clears the bits of `other`.
+    pub fn set(&mut self, other: EnvelopeFlags, enabled: bool) {
+        if enabled {
+            self.0 |= other.0;
+        } else {
+            self.0 &= !other.0;
+        }
+    }
+}
+
+/// When an enveloped payload stops being readable: after a point in time,
+/// after a number of reads, or whichever comes first.
+///
+/// The reads are counted by whoever decodes the payload rewriting the file
+/// with one fewer left, so a copy taken beforehand keeps its count. Stored
+/// as the expiry time in seconds since the Unix epoch, `0` for none, then
+/// the reads left, `u32::MAX` for unlimited.
+///
+/// An encrypted or authenticated payload is bound to its
+/// [`associated_data`](Expiry::associated_data), so the time can't be
+/// pushed back, nor the read limit removed, without the key. On a payload that
+/// is neither, the expiry is advisory: anyone can edit it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Expiry {
+    pub expires_at: Option<SystemTime>,
+    pub reads_left: Option<u32>,
+}
+
+impl Expiry {
+    /// Whether the payload may no longer be read at `now`.
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires_at.is_some_and(|at| at <= now) || self.reads_left == Some(0)
+    }
+
+    /// The expiry after one more read. Unlimited reads stay unlimited.
+    pub fn after_read(self) -> Expiry {
+        Expiry {
+            reads_left: self.reads_left.map(|reads| reads.saturating_sub(1)),
+            ..self
+        }
+    }
+
+    /// What the payload is bound to: the expiry time and whether reads are
+    /// counted. The reads left aren't, since every read rewrites them
+    /// without the key.
+    pub fn associated_data(self) -> [u8; 9] {
+        let mut bytes = [0; 9];
+        bytes[..8].copy_from_slice(&self.seconds().to_be_bytes());
+        bytes[8] = u8::from(self.reads_left.is_some());
+        bytes
+    }
+
+    fn seconds(self) -> u64 {
+        self.expires_at
+            .map_or(0, |at| match at.duration_since(UNIX_EPOCH) {
+                // Times at or before the epoch have passed already.
+                Ok(since) => since.as_secs().max(1),
+                Err(_) => 1,
+            })
+    }
+
+    fn to_bytes(self) -> [u8; EXPIRY_LEN] {
+        let mut bytes = [0; EXPIRY_LEN];
+        bytes[..8].copy_from_slice(&self.seconds().to_be_bytes());
+        bytes[8..].copy_from_slice(&self.reads_left.unwrap_or(u32::MAX).to_be_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Expiry> {
+        let seconds = u64::from_be_bytes(bytes[..8].try_into()?);
+        let reads = u32::from_be_bytes(bytes[8..EXPIRY_LEN].try_into()?);
+        Ok(Expiry {
+            expires_at: (seconds != 0).then(|| UNIX_EPOCH + Duration::from_secs(seconds)),
+            reads_left: (reads != u32::MAX).then_some(reads),
+        })
+    }
+}
+
+/// A versioned wrapper around every embedded payload.
+///
+/// The flags record every layer applied to the payload and whether it holds
+/// a message, a file or a share, so nothing is guessed from the payload's
+/// first bytes. The layout, all integers big-endian:
+///
+/// | field    | size | contents                            |
+/// |----------|------|-------------------------------------|
+/// | magic    | 4    | [`ENVELOPE_MAGIC`]                  |
+/// | version  | 1    | [`CURRENT_VERSION`]                 |
+/// | flags    | 2    | [`EnvelopeFlags`] bits              |
+/// | length   | 8    | payload length in bytes             |
+/// | checksum | 4    | CRC-32 of the payload               |
+/// | digest   | 0/32 | SHA-256 of the payload, if digested |
+/// | expiry   | 0/12 | [`Expiry`], if expiring             |
+/// | payload  | ...  | compressed and/or encrypted payload |
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PayloadEnvelope {
+    version: u8,
+    flags: EnvelopeFlags,
+    expiry: Option<Expiry>,
+    payload: Vec<u8>,
+}
+
+impl PayloadEnvelope {
+    /// Wraps `payload` in an envelope of the current version. The
+    /// [`EnvelopeFlags::EXPIRING`] bit follows [`set_expiry`](Self::set_expiry)
+    /// rather than `flags`.
+    pub fn new(mut flags: EnvelopeFlags, payload: Vec<u8>) -> PayloadEnvelope {
+        flags.set(EnvelopeFlags::EXPIRING, false);
+        PayloadEnvelope {
+            version: CURRENT_VERSION,
+            flags,
+            expiry: None,
+            payload,
+        }
+    }
+
+    /// Returns true if `bytes` start with the envelope magic.
+    pub fn is_envelope(bytes: &[u8]) -> bool {
+        bytes.starts_with(&ENVELOPE_MAGIC)
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    pub fn flags(&self) -> EnvelopeFlags {
+        self.flags
+    }
+
+    pub fn expiry(&self) -> Option<Expiry> {
+        self.expiry
+    }
+
+    pub fn set_expiry(&mut self, expiry: Option<Expiry>) {
+        self.flags.set(EnvelopeFlags::EXPIRING, expiry.is_some());
+        self.expiry = expiry;
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    pub fn into_payload(self) -> Vec<u8> {
+        self.payload
+    }
+
+    /// Stands in for an envelope around `payload`, a plain message embedded
+    /// before envelopes existed. It opens like an envelope with no flags.
+    pub fn unwrapped(payload: Vec<u8>) -> PayloadEnvelope {
+        PayloadEnvelope::new(EnvelopeFlags::default(), payload)
+    }
+
+    /// Decompresses and parses `data`, this envelope's payload once it has
+    /// been decrypted or verified, failing if it decompresses to more than
+    /// `limit` bytes.
+    pub fn open(&self, data: &[u8], limit: u64) -> Result<Payload> {
+        let data = match self.flags.contains(EnvelopeFlags::COMPRESSED) {
+            true => compression::decompress(data, limit)?,
+            false => compression::check_limit(data.to_vec(), limit)?,
+        };
+        let file = self.flags.contains(EnvelopeFlags::FILE);
+        match (file, self.flags.contains(EnvelopeFlags::SHARE)) {
+            (false, false) => Ok(Payload::Message(data)),
+            (true, false) => EmbeddedFile::from_bytes(&data).map(Payload::File),
+            (false, true) => Share::from_bytes(&data).map(Payload::Share),
+            (true, true) => Err(EnvelopeError::UnknownFlags(self.flags.bits()).into()),
+        }
+    }
+
+    /// Serializes the envelope header followed by the payload.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes =
+            Vec::with_capacity(HEADER_LEN + DIGEST_LEN + EXPIRY_LEN + self.payload.len());
+        bytes.extend_from_slice(&ENVELOPE_MAGIC);
+        bytes.push(self.version);
+        bytes.extend_from_slice(&self.flags.bits().to_be_bytes());
+        bytes.extend_from_slice(&(self.payload.len() as u64).to_be_bytes());
+        bytes.extend_from_slice(&chunk::crc32(&self.payload).to_be_bytes());
+        if self.flags.contains(EnvelopeFlags::DIGESTED) {
+            bytes.extend_from_slice(&Sha256::digest(&self.payload));
+        }
+        if let Some(expiry) = self.expiry {
+            bytes.extend_from_slice(&expiry.to_bytes());
+        }
+        bytes.extend_from_slice(&self.payload);
+        bytes
+    }
+
+    /// Parses an envelope, checking its version, length, checksum and, if
+    /// one is stored, digest.
+    #[tracing::instrument(level = "debug", skip_all, fields(len = bytes.len()))]
+    pub fn decode(bytes: &[u8]) -> Result<PayloadEnvelope> {
+        let rest = bytes
+            .strip_prefix(&ENVELOPE_MAGIC)
+            .ok_or(EnvelopeError::MissingMagic)?;
+        if rest.len() < HEADER_LEN - ENVELOPE_MAGIC.len() {
+            return Err(EnvelopeError::Truncated.into());
+        }
+
+        let version = rest[0];
+        if version != CURRENT_VERSION {
+            return Err(EnvelopeError::UnsupportedVersion(version).into());
+        }
+        let flags = u16::from_be_bytes(rest[1..3].try_into()?);
+        if flags & !EnvelopeFlags::KNOWN != 0 {
+            return Err(EnvelopeError::UnknownFlags(flags).into());
+        }
+        let length = u64::from_be_bytes(rest[3..11].try_into()?);
+        let checksum = u32::from_be_bytes(rest[11..15].try_into()?);
+
+        let mut payload = &rest[15..];
+        let digest = match EnvelopeFlags(flags).contains(EnvelopeFlags::DIGESTED) {
+            true => {
+                let (digest, rest) = payload
+                    .split_at_checked(DIGEST_LEN)
+                    .ok_or(EnvelopeError::Truncated)?;
+                payload = rest;
+                Some(digest)
+            }
+            false => None,
+        };
+        let expiry = match EnvelopeFlags(flags).contains(EnvelopeFlags::EXPIRING) {
+            true => {
+                let (expiry, rest) = payload
+                    .split_at_checked(EXPIRY_LEN)
+                    .ok_or(EnvelopeError::Truncated)?;
+                payload = rest;
+                Some(Expiry::from_bytes(expiry)?)
+            }
+            false => None,
+        };
+        if payload.len() as u64 != length {
+            return Err(EnvelopeError::LengthMismatch {
+                expected: length,
+                found: payload.len() as u64,
+            }
+            .into());
+        }
+        let computed = chunk::crc32(payload);
+        if computed != checksum {
+            tracing::debug!(
+                expected = computed,
+                found = checksum,
+                "payload CRC mismatch"
+            );
+            return Err(EnvelopeError::ChecksumMismatch.into());
+        }
+        if digest.is_some_and(|digest| *digest != *Sha256::digest(payload)) {
+            return Err(EnvelopeError::DigestMismatch.into());
+        }
+        tracing::debug!(version, flags, "opened envelope");
+
+        Ok(PayloadEnvelope {
+            version,
+            flags: EnvelopeFlags(flags),
+            expiry,
+            payload: payload.to_vec(),
+        })
+    }
+}
+
+/// Errors raised while decoding a [`PayloadEnvelope`].
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum EnvelopeError {
+    #[error("payload is not enveloped")]
+    MissingMagic,
+    /// The header ends early.
+    #[error("payload envelope header is truncated")]
+    Truncated,
+    /// Written by a newer release using a format this one can't read.
+    #[error("unsupported payload envelope version {0}")]
+    UnsupportedVersion(u8),
+    /// Flag bits this version doesn't know are set.
+    #[error("unknown payload envelope flags {0:#06x}")]
+    UnknownFlags(u16),
+    #[error("payload envelope declares {expected} bytes but holds {found}")]
+    LengthMismatch { expected: u64, found: u64 },
+    /// The payload was corrupted after embedding.
+    #[error("payload checksum mismatch")]
+    ChecksumMismatch,
+    /// The payload doesn't match its stored SHA-256, though its CRC-32 does.
+    #[error("payload SHA-256 mismatch")]
+    DigestMismatch,
+    /// The payload is encrypted but no password or identity was given.
+    #[error("payload is encrypted, use --password or --identity")]
+    Encrypted,
+    /// The payload carries an HMAC tag but no key was given to verify it.
+    #[error("payload carries an authentication tag, use --auth-key")]
+    Authenticated,
+    /// The payload's [`Expiry`] time has passed or its reads are used up.
+    #[error("payload has expired")]
+    Expired,
+    /// The payload allows a limited number of reads, but was read from
+    /// somewhere its count can't be written back to, such as this.
+    #[error("payload allows a limited number of reads, which can't be counted on {0}")]
+    UncountedRead(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::compression::Compression;
+    use crate::payload;
+    use crate::PngMeError;
+
+    const PLAIN: &[u8] = include_bytes!("../tests/fixtures/envelope_v2_plain.bin");
+    const COMPRESSED_ENCRYPTED: &[u8] =
+        include_bytes!("../tests/fixtures/envelope_v2_compressed_encrypted.bin");
+
+    #[test]
+    fn test_decode_fixture() {
+        let envelope = PayloadEnvelope::decode(PLAIN).unwrap();
+        assert_eq!(envelope.version(), CURRENT_VERSION);
+        assert_eq!(envelope.flags(), EnvelopeFlags::default());
+        assert_eq!(
+            envelope.payload(),
+            b"This is where your secret message will be!"
+        );
+    }
+
+    #[test]
+    fn test_decode_fixture_flags() {
+        let envelope = PayloadEnvelope::decode(COMPRESSED_ENCRYPTED).unwrap();
+        assert!(envelope.flags().contains(EnvelopeFlags::COMPRESSED));
+        assert!(envelope.flags().contains(EnvelopeFlags::ENCRYPTED));
+        assert!(!envelope.flags().contains(EnvelopeFlags::AUTHENTICATED));
+        assert_eq!(envelope.payload(), (0..32).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn test_encode_matches_fixture() {
+        let envelope = PayloadEnvelope::new(
+            EnvelopeFlags::default(),
+            b"This is where your secret message will be!".to_vec(),
+        );
+        assert_eq!(envelope.encode(), PLAIN);
+        assert_eq!(PayloadEnvelope::decode(PLAIN).unwrap().encode(), PLAIN);
+    }
+
+    #[test]
+    fn test_messages_starting_with_magic() {
+        // The headers of compressed payloads, files, shares and split
+        // payloads, and the envelope's own magic.
+        let prefixes: [&[u8]; 6] = [
+            &[0x8f, b'Z', 0],
+            &[0x8f, b'F', 0, 1],
+            &[0x8f, b'H', 2, 1],
+            &[0x8f, b'S', 0, 0, 0, 0, 0, 0, 0, 1],
+            &[0x8f, b'L', 5],
+            &ENVELOPE_MAGIC,
+        ];
+        for prefix in prefixes {
+            let message = [prefix, b"ABC"].concat();
+            for compression in [Compression::None, Compression::Zlib] {
+                let mut flags = EnvelopeFlags::default();
+                flags.set(EnvelopeFlags::COMPRESSED, compression != Compression::None);
+                let data = compression::compress(compression, &message).unwrap();
+                let bytes = PayloadEnvelope::new(flags, data).encode();
+
+                let chunk_type = "ruSt".parse().unwrap();
+                let chunks = payload::split(&chunk_type, &bytes, 64).unwrap();
+                let parts: Vec<&[u8]> = chunks.iter().map(|chunk| chunk.data()).collect();
+                let envelope =
+                    PayloadEnvelope::decode(&payload::reassemble(&parts).unwrap()).unwrap();
+                assert_eq!(
+                    envelope.open(envelope.payload(), 1024).unwrap(),
+                    Payload::Message(message.clone())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_payload_kinds() {
+        let share = Share {
+            index: 1,
+            threshold: 2,
+            data: b"part".to_vec(),
+        };
+        let file = EmbeddedFile {
+            name: "notes.txt".to_string(),
+            size: 2,
+            modified: None,
+            mime_type: "text/plain".to_string(),
+            contents: b"hi".to_vec(),
+        };
+        for payload in [Payload::Share(share), Payload::File(file)] {
+            let flags = EnvelopeFlags::kind_of(&payload);
+            let envelope = PayloadEnvelope::new(flags, payload.to_bytes());
+            let decoded = PayloadEnvelope::decode(&envelope.encode()).unwrap();
+            assert_eq!(decoded.open(decoded.payload(), 1024).unwrap(), payload);
+            // Without the flag the same bytes are just a message.
+            let plain = PayloadEnvelope::new(EnvelopeFlags::default(), payload.to_bytes());
+            assert_eq!(
+                plain.open(plain.payload(), 1024).unwrap(),
+                Payload::Message(payload.to_bytes())
+            );
+        }
+    }
+
+    #[test]
+    fn test_unwrapped_is_a_message() {
+        // Data embedded before envelopes existed is never sniffed.
+        let compressed = compression::compress(Compression::Zlib, b"old").unwrap();
+        let envelope = PayloadEnvelope::unwrapped(compressed.clone());
+        assert_eq!(
+            envelope.open(envelope.payload(), 1024).unwrap(),
+            Payload::Message(compressed)
+        );
+    }
+
+    #[test]
+    fn test_unknown_flags_are_rejected() {
+        let mut bytes = PLAIN.to_vec();
+        bytes[5..7].copy_from_slice(&0x0100u16.to_be_bytes());
+        assert!(matches!(
+            PayloadEnvelope::decode(&bytes),
+            Err(PngMeError::Envelope(EnvelopeError::UnknownFlags(0x0100)))
+        ));
+    }
+
+    #[test]
+    fn test_corrupt_payload_is_rejected() {
+        let mut bytes = PLAIN.to_vec();
+        *bytes.last_mut().unwrap() ^= 1;
+        assert!(PayloadEnvelope::decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_digest() {
+        let payload = b"This is where your secret message will be!".to_vec();
+        let envelope = PayloadEnvelope::new(EnvelopeFlags::DIGESTED, payload);
+        let mut bytes = envelope.encode();
+        assert_eq!(bytes.len(), PLAIN.len() + DIGEST_LEN);
+        assert_eq!(PayloadEnvelope::decode(&bytes).unwrap(), envelope);
+
+        // A corrupt digest is caught even though the CRC still matches.
+        bytes[HEADER_LEN] ^= 1;
+        let err = PayloadEnvelope::decode(&bytes).unwrap_err();
+        assert!(matches!(
+            err,
+            PngMeError::Envelope(EnvelopeError::DigestMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_deniable_flag() {
+        let mut flags = EnvelopeFlags::ENCRYPTED;
+        flags.set(EnvelopeFlags::DENIABLE, true);
+        let envelope = PayloadEnvelope::new(flags, vec![0; 8]);
+        let decoded = PayloadEnvelope::decode(&envelope.encode()).unwrap();
+        assert_eq!(decoded.flags(), flags);
+    }
+
+    #[test]
+    fn test_expiry() {
+        let at = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let expiry = Expiry {
+            expires_at: Some(at),
+            reads_left: Some(2),
+        };
+        let mut envelope = PayloadEnvelope::new(EnvelopeFlags::DIGESTED, b"burn after".to_vec());
+        envelope.set_expiry(Some(expiry));
+        let bytes = envelope.encode();
+        assert_eq!(bytes.len(), HEADER_LEN + DIGEST_LEN + EXPIRY_LEN + 10);
+        let decoded = PayloadEnvelope::decode(&bytes).unwrap();
+        assert!(decoded.flags().contains(EnvelopeFlags::EXPIRING));
+        assert_eq!(decoded.expiry(), Some(expiry));
+        assert_eq!(decoded.payload(), b"burn after");
+
+        assert!(!expiry.is_expired(at - Duration::from_secs(1)));
+        assert!(expiry.is_expired(at));
+        let unlimited = Expiry::default();
+        assert_eq!(unlimited.after_read(), unlimited);
+        assert!(!unlimited.is_expired(SystemTime::now()));
+        let last_read = Expiry {
+            expires_at: None,
+            reads_left: Some(1),
+        };
+        assert!(last_read.after_read().is_expired(UNIX_EPOCH));
+
+        envelope.set_expiry(None);
+        assert_eq!(envelope.flags(), EnvelopeFlags::DIGESTED);
+    }
+
+    #[test]
+    fn test_other_versions_are_rejected() {
+        for version in [1, 3] {
+            let mut bytes = PLAIN.to_vec();
+            bytes[4] = version;
+            let err = PayloadEnvelope::decode(&bytes).unwrap_err();
+            assert!(matches!(
+                err,
+                PngMeError::Envelope(EnvelopeError::UnsupportedVersion(v)) if v == version
+            ));
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use std::array::TryFromSliceError;
+use std::io;
+use std::str::Utf8Error;
+use std::string::FromUtf8Error;
+
+use thiserror::Error;
+
+use crate::ancillary::AncillaryError;
+use crate::apng::AnimationError;
+use crate::batch::BatchError;
+use crate::chunk::ChunkError;
+use crate::chunk_type::ChunkTypeError;
+use crate::clipboard::ClipboardError;
+use crate::cloud::CloudError;
+use crate::compression::CompressionError;
+use crate::config::ConfigError;
+use crate::crypto::CryptoError;
+use crate::envelope::EnvelopeError;
+use crate::exif::ExifError;
+use crate::http::HttpError;
+use crate::icc::IccError;
+use crate::idat::IdatError;
+use crate::ihdr::IhdrError;
+use crate::index::IndexError;
+use crate::integrity::IntegrityError;
+use crate::lsb::LsbError;
+use crate::ordering::OrderingError;
+use crate::palette::PaletteError;
+use crate::payload::PayloadError;
+use crate::pixels::PixelError;
+use crate::png::PngError;
+use crate::qr::QrError;
+use crate::secret::SecretError;
+use crate::server::ServerError;
+use crate::shamir::ShareError;
+use crate::signing::SignatureError;
+use crate::stego::StegoError;
+use crate::text::TextError;
+use crate::trailer::TrailerError;
+
+/// Every error pngme raises.
+///
+/// The failures callers most often need to tell apart are variants of their
+/// own. The rest wrap the error type of the module that raised them, so a
+/// caller can match on, say, [`CryptoError::Authentication`] through
+/// [`PngMeError::Crypto`].
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum PngMeError {
+    /// The input does not start with the PNG signature.
+    #[error("input is not a PNG file")]
+    InvalidSignature,
+    /// A chunk's stored CRC does not match the one computed over its type
+    /// and data.
+    #[error("{chunk_type} chunk is corrupt: CRC is {found:#010x}, expected {expected:#010x}")]
+    CrcMismatch {
+        chunk_type: String,
+        expected: u32,
+        found: u32,
+    },
+    /// A chunk declares more data than [`ParseOptions::max_chunk_size`]
+    /// allows. Raised before any of the data is read.
+    ///
+    /// [`ParseOptions::max_chunk_size`]: crate::png::ParseOptions::max_chunk_size
+    #[error("chunk declares {length} data bytes, more than the limit of {max}")]
+    ChunkTooLarge { length: u32, max: u32 },
+    /// The input runs past [`ParseOptions::max_total_size`].
+    ///
+    /// [`ParseOptions::max_total_size`]: crate::png::ParseOptions::max_total_size
+    #[error("input is larger than the limit of {max} bytes")]
+    InputTooLarge { max: u64 },
+    /// No chunk of the requested type exists.
+    #[error("no {0} chunk found")]
+    ChunkNotFound(String),
+    #[error(transparent)]
+    InvalidChunkType(#[from] ChunkTypeError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// A message or text field is not valid UTF-8.
+    #[error(transparent)]
+    Utf8(#[from] FromUtf8Error),
+    /// A string passed in by reference, such as through the C API, is not
+    /// valid UTF-8.
+    #[error(transparent)]
+    Utf8Str(#[from] Utf8Error),
+    /// A fixed-size field was cut short.
+    #[error(transparent)]
+    FieldLength(#[from] TryFromSliceError),
+    #[error(transparent)]
+    Hex(#[from] hex::FromHexError),
+    #[error(transparent)]
+    Base64(#[from] base64::DecodeError),
+    #[cfg(feature = "serde")]
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// JSON output was requested from a build without the `serde` feature.
+    #[cfg(not(feature = "serde"))]
+    #[error("JSON output needs pngme to be built with the serde feature")]
+    JsonUnsupported,
+    #[error(transparent)]
+    GlobPattern(#[from] glob::PatternError),
+    #[error(transparent)]
+    Glob(#[from] glob::GlobError),
+    #[error(transparent)]
+    Watch(#[from] notify::Error),
+
+    #[error(transparent)]
+    Chunk(#[from] ChunkError),
+    #[error(transparent)]
+    Png(#[from] PngError),
+    #[error(transparent)]
+    Ancillary(#[from] AncillaryError),
+    #[error(transparent)]
+    Animation(#[from] AnimationError),
+    #[error(transparent)]
+    Batch(#[from] BatchError),
+    #[error(transparent)]
+    Clipboard(#[from] ClipboardError),
+    #[error(transparent)]
+    Cloud(#[from] CloudError),
+    #[error(transparent)]
+    Compression(#[from] CompressionError),
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error(transparent)]
+    Crypto(#[from] CryptoError),
+    #[error(transparent)]
+    Envelope(#[from] EnvelopeError),
+    #[error(transparent)]
+    Exif(#[from] ExifError),
+    #[error(transparent)]
+    Http(#[from] HttpError),
+    #[error(transparent)]
+    Icc(#[from] IccError),
+    #[error(transparent)]
+    Idat(#[from] IdatError),
+    #[error(transparent)]
+    Ihdr(#[from] IhdrError),
+    #[error(transparent)]
+    Index(#[from] IndexError),
+    #[error(transparent)]
+    Integrity(#[from] IntegrityError),
+    #[error(transparent)]
+    Lsb(#[from] LsbError),
+    #[error(transparent)]
+    Ordering(#[from] OrderingError),
+    #[error(transparent)]
+    Palette(#[from] PaletteError),
+    #[error(transparent)]
+    Payload(#[from] PayloadError),
+    #[error(transparent)]
+    Pixel(#[from] PixelError),
+    #[error(transparent)]
+    Qr(#[from] QrError),
+    #[error(transparent)]
+    Secret(#[from] SecretError),
+    #[error(transparent)]
+    Server(#[from] ServerError),
+    #[error(transparent)]
+    Share(#[from] ShareError),
+    #[error(transparent)]
+    Signature(#[from] SignatureError),
+    #[error(transparent)]
+    Stego(#[from] StegoError),
+    #[error(transparent)]
+    Text(#[from] TextError),
+    #[error(transparent)]
+    Trailer(#[from] TrailerError),
+}
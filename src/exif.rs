@@ -0,0 +1,358 @@
+use std::fmt;
+
+use thiserror::Error;
+
+use crate::ancillary::AncillaryChunk;
+use crate::png::Png;
+use crate::Result;
+
+/// Tags read from the primary image directory (IFD0).
+const TAG_MAKE: u16 = 0x010f;
+const TAG_MODEL: u16 = 0x0110;
+const TAG_ORIENTATION: u16 = 0x0112;
+const TAG_GPS_IFD: u16 = 0x8825;
+
+/// Tags read from the GPS directory.
+const TAG_GPS_LATITUDE_REF: u16 = 1;
+const TAG_GPS_LATITUDE: u16 = 2;
+const TAG_GPS_LONGITUDE_REF: u16 = 3;
+const TAG_GPS_LONGITUDE: u16 = 4;
+
+/// TIFF field types used by the tags above.
+const TYPE_ASCII: u16 = 2;
+const TYPE_SHORT: u16 = 3;
+const TYPE_LONG: u16 = 4;
+const TYPE_RATIONAL: u16 = 5;
+
+/// The contents of an `eXIf` chunk: EXIF metadata as a raw TIFF structure.
+///
+/// The bytes are kept as-is so they round-trip unchanged; the accessors
+/// parse only the few tags they need.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exif {
+    tiff: Vec<u8>,
+}
+
+/// A GPS position in decimal degrees, negative south of the equator and
+/// west of Greenwich.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GpsPosition {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Exif {
+    /// Wraps raw TIFF bytes, checking the byte order mark and magic number.
+    pub fn new(tiff: Vec<u8>) -> Result<Exif> {
+        Tiff::new(&tiff)?;
+        Ok(Exif { tiff })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.tiff
+    }
+
+    /// The EXIF orientation, 1 to 8, describing how to rotate or flip the
+    /// image for display.
+    pub fn orientation(&self) -> Option<u16> {
+        let tiff = self.tiff();
+        let entry = tiff.entry(tiff.first_ifd()?, TAG_ORIENTATION)?;
+        match entry.field_type {
+            TYPE_SHORT => tiff.u16_at(entry.value_offset),
+            _ => None,
+        }
+    }
+
+    pub fn camera_make(&self) -> Option<String> {
+        let tiff = self.tiff();
+        tiff.ascii(tiff.first_ifd()?, TAG_MAKE)
+    }
+
+    pub fn camera_model(&self) -> Option<String> {
+        let tiff = self.tiff();
+        tiff.ascii(tiff.first_ifd()?, TAG_MODEL)
+    }
+
+    /// The position recorded in the GPS directory, if both coordinates are present.
+    pub fn gps(&self) -> Option<GpsPosition> {
+        let tiff = self.tiff();
+        let pointer = tiff.entry(tiff.first_ifd()?, TAG_GPS_IFD)?;
+        if pointer.field_type != TYPE_LONG {
+            return None;
+        }
+        let gps_ifd = tiff.u32_at(pointer.value_offset)? as usize;
+        let coordinate = |value_tag, ref_tag, negative: &str| {
+            let degrees = tiff.degrees(gps_ifd, value_tag)?;
+            let reference = tiff.ascii(gps_ifd, ref_tag)?;
+            Some(if reference == negative {
+                -degrees
+            } else {
+                degrees
+            })
+        };
+        Some(GpsPosition {
+            latitude: coordinate(TAG_GPS_LATITUDE, TAG_GPS_LATITUDE_REF, "S")?,
+            longitude: coordinate(TAG_GPS_LONGITUDE, TAG_GPS_LONGITUDE_REF, "W")?,
+        })
+    }
+
+    fn tiff(&self) -> Tiff<'_> {
+        Tiff::new(&self.tiff).expect("header was checked on construction")
+    }
+}
+
+impl AncillaryChunk for Exif {
+    const CHUNK_TYPE: [u8; 4] = *b"eXIf";
+
+    fn parse(data: &[u8]) -> Result<Exif> {
+        Exif::new(data.to_vec())
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        self.tiff.clone()
+    }
+}
+
+impl Png {
+    /// Parses the eXIf chunk, if there is one.
+    pub fn exif(&self) -> Result<Option<Exif>> {
+        self.ancillary()
+    }
+
+    /// Replaces any existing EXIF metadata with `exif`.
+    pub fn set_exif(&mut self, exif: &Exif) {
+        self.set_ancillary(exif);
+    }
+
+    /// Removes the eXIf chunk, returning true if there was one.
+    pub fn strip_exif(&mut self) -> bool {
+        self.remove_ancillary::<Exif>() > 0
+    }
+}
+
+impl fmt::Display for Exif {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "EXIF: {} bytes", self.tiff.len())?;
+        if let Some(make) = self.camera_make() {
+            writeln!(f, "  Make: {make}")?;
+        }
+        if let Some(model) = self.camera_model() {
+            writeln!(f, "  Model: {model}")?;
+        }
+        if let Some(orientation) = self.orientation() {
+            writeln!(f, "  Orientation: {orientation}")?;
+        }
+        if let Some(gps) = self.gps() {
+            writeln!(f, "  GPS: {:.6}, {:.6}", gps.latitude, gps.longitude)?;
+        }
+        Ok(())
+    }
+}
+
+/// One entry of an image file directory.
+struct Entry {
+    field_type: u16,
+    count: u32,
+    /// Where the value starts: inside the entry if it fits in 4 bytes,
+    /// otherwise wherever the entry points.
+    value_offset: usize,
+}
+
+/// A bounds-checked view of TIFF bytes in either byte order.
+struct Tiff<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a [u8]) -> Result<Tiff<'a>> {
+        let big_endian = match data.get(..2) {
+            Some(b"MM") => true,
+            Some(b"II") => false,
+            _ => return Err(ExifError::InvalidHeader.into()),
+        };
+        let tiff = Tiff { data, big_endian };
+        if tiff.u16_at(2) != Some(42) {
+            return Err(ExifError::InvalidHeader.into());
+        }
+        Ok(tiff)
+    }
+
+    fn first_ifd(&self) -> Option<usize> {
+        self.u32_at(4).map(|offset| offset as usize)
+    }
+
+    fn bytes<const N: usize>(&self, offset: usize) -> Option<[u8; N]> {
+        self.data
+            .get(offset..offset.checked_add(N)?)?
+            .try_into()
+            .ok()
+    }
+
+    fn u16_at(&self, offset: usize) -> Option<u16> {
+        let bytes = self.bytes(offset)?;
+        Some(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32_at(&self, offset: usize) -> Option<u32> {
+        let bytes = self.bytes(offset)?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    /// Finds `tag` in the directory at `ifd`.
+    fn entry(&self, ifd: usize, tag: u16) -> Option<Entry> {
+        let count = self.u16_at(ifd)? as usize;
+        (0..count).find_map(|i| {
+            let offset = ifd + 2 + i * 12;
+            if self.u16_at(offset)? != tag {
+                return None;
+            }
+            let field_type = self.u16_at(offset + 2)?;
+            let count = self.u32_at(offset + 4)?;
+            let size = match field_type {
+                TYPE_SHORT => 2,
+                TYPE_LONG => 4,
+                TYPE_RATIONAL => 8,
+                _ => 1,
+            } * count as usize;
+            let value_offset = if size <= 4 {
+                offset + 8
+            } else {
+                self.u32_at(offset + 8)? as usize
+            };
+            Some(Entry {
+                field_type,
+                count,
+                value_offset,
+            })
+        })
+    }
+
+    /// Reads an ASCII field, dropping the NUL terminator and padding.
+    fn ascii(&self, ifd: usize, tag: u16) -> Option<String> {
+        let entry = self.entry(ifd, tag)?;
+        if entry.field_type != TYPE_ASCII {
+            return None;
+        }
+        let end = entry.value_offset.checked_add(entry.count as usize)?;
+        let bytes = self.data.get(entry.value_offset..end)?;
+        let text = bytes.split(|&b| b == 0).next().unwrap_or_default();
+        Some(String::from_utf8_lossy(text).trim().to_string())
+    }
+
+    /// Reads a degrees, minutes, seconds triple of rationals as decimal degrees.
+    fn degrees(&self, ifd: usize, tag: u16) -> Option<f64> {
+        let entry = self.entry(ifd, tag)?;
+        if entry.field_type != TYPE_RATIONAL || entry.count != 3 {
+            return None;
+        }
+        let rational = |i: usize| {
+            let offset = entry.value_offset + i * 8;
+            let numerator = self.u32_at(offset)? as f64;
+            let denominator = self.u32_at(offset + 4)? as f64;
+            (denominator != 0.0).then(|| numerator / denominator)
+        };
+        Some(rational(0)? + rational(1)? / 60.0 + rational(2)? / 3600.0)
+    }
+}
+
+/// Errors raised while reading EXIF metadata.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ExifError {
+    /// The data does not start with a TIFF header.
+    #[error("EXIF data does not start with a TIFF header")]
+    InvalidHeader,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A big-endian TIFF with an orientation, a camera model and a GPS
+    /// position of 37°46'30"N 122°25'0"W.
+    fn sample_tiff() -> Vec<u8> {
+        let entry = |tag: u16, field_type: u16, count: u32, value: [u8; 4]| {
+            let mut bytes = tag.to_be_bytes().to_vec();
+            bytes.extend(field_type.to_be_bytes());
+            bytes.extend(count.to_be_bytes());
+            bytes.extend(value);
+            bytes
+        };
+        let rationals = |values: [u32; 3]| -> Vec<u8> {
+            values
+                .iter()
+                .flat_map(|v| v.to_be_bytes().into_iter().chain(1u32.to_be_bytes()))
+                .collect()
+        };
+
+        let mut tiff = b"MM\0\x2a\0\0\0\x08".to_vec();
+        // IFD0 at 8, 3 entries, ending at 50.
+        tiff.extend(3u16.to_be_bytes());
+        tiff.extend(entry(TAG_ORIENTATION, TYPE_SHORT, 1, [0, 6, 0, 0]));
+        tiff.extend(entry(TAG_MODEL, TYPE_ASCII, 6, 50u32.to_be_bytes()));
+        tiff.extend(entry(TAG_GPS_IFD, TYPE_LONG, 1, 56u32.to_be_bytes()));
+        tiff.extend([0; 4]);
+        tiff.extend(b"Pixel\0");
+        // GPS IFD at 56, 4 entries, ending at 110.
+        tiff.extend(4u16.to_be_bytes());
+        tiff.extend(entry(TAG_GPS_LATITUDE_REF, TYPE_ASCII, 2, *b"N\0\0\0"));
+        tiff.extend(entry(
+            TAG_GPS_LATITUDE,
+            TYPE_RATIONAL,
+            3,
+            110u32.to_be_bytes(),
+        ));
+        tiff.extend(entry(TAG_GPS_LONGITUDE_REF, TYPE_ASCII, 2, *b"W\0\0\0"));
+        tiff.extend(entry(
+            TAG_GPS_LONGITUDE,
+            TYPE_RATIONAL,
+            3,
+            134u32.to_be_bytes(),
+        ));
+        tiff.extend([0; 4]);
+        tiff.extend(rationals([37, 46, 30]));
+        tiff.extend(rationals([122, 25, 0]));
+        tiff
+    }
+
+    #[test]
+    fn test_common_tags() {
+        let exif = Exif::new(sample_tiff()).unwrap();
+        assert_eq!(exif.orientation(), Some(6));
+        assert_eq!(exif.camera_model().as_deref(), Some("Pixel"));
+        assert_eq!(exif.camera_make(), None);
+
+        let gps = exif.gps().unwrap();
+        assert!((gps.latitude - 37.775).abs() < 1e-9);
+        assert!((gps.longitude + 122.416_666).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        assert!(Exif::new(b"JFIF".to_vec()).is_err());
+        assert!(Exif::new(b"II\x2b\0".to_vec()).is_err());
+        // A valid header with a dangling IFD offset parses, but has no tags.
+        let exif = Exif::new(b"II\x2a\0\xff\0\0\0".to_vec()).unwrap();
+        assert_eq!(exif.orientation(), None);
+    }
+
+    #[test]
+    fn test_replace_and_strip() {
+        let mut png = Png::from_chunks(Vec::new());
+        png.set_exif(&Exif::new(sample_tiff()).unwrap());
+        png.set_exif(&Exif::new(sample_tiff()).unwrap());
+        assert_eq!(png.chunks().len(), 1);
+        assert_eq!(png.exif().unwrap().unwrap().as_bytes(), sample_tiff());
+        assert!(png.strip_exif());
+        assert!(png.exif().unwrap().is_none());
+    }
+}
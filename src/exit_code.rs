@@ -0,0 +1,167 @@
+use std::fmt;
+use std::io;
+
+use crate::clipboard::ClipboardError;
+use crate::cloud::CloudError;
+use crate::crypto::CryptoError;
+use crate::envelope::EnvelopeError;
+use crate::http::HttpError;
+use crate::idat::IdatError;
+use crate::payload::PayloadError;
+use crate::qr::QrError;
+use crate::secret::SecretError;
+use crate::server::ServerError;
+use crate::shamir::ShareError;
+use crate::signing::SignatureError;
+use crate::PngMeError;
+
+/// The status pngme exits with, so scripts can tell failures apart without
+/// parsing messages. The values are part of the command-line interface and
+/// won't change between releases; new failures get new values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+#[repr(u8)]
+pub enum ExitCode {
+    Success = 0,
+    /// Any failure without a code of its own.
+    Failure = 1,
+    /// The requested chunk, payload or file doesn't exist.
+    NotFound = 2,
+    /// A chunk or payload fails its CRC or digest check.
+    CrcMismatch = 3,
+    /// A password, key or signature is wrong or missing.
+    AuthFailure = 4,
+    /// The input isn't a well-formed PNG.
+    InvalidPng = 5,
+    /// Reading or writing a file failed for any other reason.
+    Io = 6,
+    /// The command line or config file is invalid. Matches `EX_USAGE` from
+    /// sysexits.h.
+    Usage = 64,
+}
+
+impl ExitCode {
+    /// Kebab-case name of the failure, as in JSON error output.
+    pub fn name(self) -> &'static str {
+        match self {
+            ExitCode::Success => "success",
+            ExitCode::Failure => "failure",
+            ExitCode::NotFound => "not-found",
+            ExitCode::CrcMismatch => "crc-mismatch",
+            ExitCode::AuthFailure => "auth-failure",
+            ExitCode::InvalidPng => "invalid-png",
+            ExitCode::Io => "io",
+            ExitCode::Usage => "usage",
+        }
+    }
+}
+
+impl fmt::Display for ExitCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl From<ExitCode> for std::process::ExitCode {
+    fn from(code: ExitCode) -> Self {
+        std::process::ExitCode::from(code as u8)
+    }
+}
+
+impl PngMeError {
+    /// The status a command failing with this error exits with.
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            PngMeError::ChunkNotFound(_) | PngMeError::Payload(PayloadError::NotFound { .. }) => {
+                ExitCode::NotFound
+            }
+            PngMeError::Io(err) if err.kind() == io::ErrorKind::NotFound => ExitCode::NotFound,
+            PngMeError::CrcMismatch { .. }
+            | PngMeError::Envelope(
+                EnvelopeError::ChecksumMismatch | EnvelopeError::DigestMismatch,
+            )
+            | PngMeError::Integrity(_) => ExitCode::CrcMismatch,
+            PngMeError::Crypto(
+                CryptoError::Authentication
+                | CryptoError::Tampered
+                | CryptoError::RequiresIdentity
+                | CryptoError::RequiresPassword,
+            )
+            | PngMeError::Envelope(EnvelopeError::Encrypted | EnvelopeError::Authenticated)
+            | PngMeError::Secret(SecretError::PromptDisabled | SecretError::PasswordMismatch)
+            | PngMeError::Signature(SignatureError::Missing | SignatureError::Invalid) => {
+                ExitCode::AuthFailure
+            }
+            #[cfg(feature = "object-store")]
+            PngMeError::Cloud(CloudError::Store(object_store::Error::NotFound { .. })) => {
+                ExitCode::NotFound
+            }
+            PngMeError::Idat(IdatError::ZopfliUnsupported)
+            | PngMeError::Http(HttpError::Unsupported)
+            | PngMeError::Clipboard(ClipboardError::Unsupported)
+            | PngMeError::Cloud(CloudError::Unsupported)
+            | PngMeError::Qr(QrError::Unsupported)
+            | PngMeError::Server(ServerError::Unsupported(_)) => ExitCode::Failure,
+            PngMeError::Http(HttpError::ReadOnly(_))
+            | PngMeError::Clipboard(ClipboardError::SeveralFiles)
+            | PngMeError::Qr(QrError::SeveralFiles)
+            | PngMeError::Crypto(CryptoError::SamePassword)
+            | PngMeError::Envelope(EnvelopeError::UncountedRead(_))
+            | PngMeError::Share(
+                ShareError::InvalidThreshold { .. } | ShareError::WrongFileCount { .. },
+            ) => ExitCode::Usage,
+            PngMeError::InvalidSignature
+            | PngMeError::ChunkTooLarge { .. }
+            | PngMeError::InputTooLarge { .. }
+            | PngMeError::Chunk(_)
+            | PngMeError::Png(_)
+            | PngMeError::Ihdr(_)
+            | PngMeError::Idat(_) => ExitCode::InvalidPng,
+            PngMeError::Io(_) | PngMeError::Server(ServerError::Bind(_)) => ExitCode::Io,
+            #[cfg(feature = "http")]
+            PngMeError::Http(HttpError::Request(_)) => ExitCode::Io,
+            #[cfg(feature = "object-store")]
+            PngMeError::Cloud(CloudError::Store(_)) => ExitCode::Io,
+            PngMeError::InvalidChunkType(_)
+            | PngMeError::Config(_)
+            | PngMeError::GlobPattern(_)
+            | PngMeError::Cloud(CloudError::InvalidUrl(_)) => ExitCode::Usage,
+            _ => ExitCode::Failure,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes() {
+        let crc = PngMeError::CrcMismatch {
+            chunk_type: "IDAT".to_string(),
+            expected: 0,
+            found: 1,
+        };
+        assert_eq!(crc.exit_code() as u8, 3);
+        assert_eq!(
+            PngMeError::ChunkNotFound("ruSt".to_string()).exit_code(),
+            ExitCode::NotFound
+        );
+        assert_eq!(
+            PngMeError::from(CryptoError::Authentication).exit_code(),
+            ExitCode::AuthFailure
+        );
+        assert_eq!(
+            PngMeError::from(io::Error::from(io::ErrorKind::NotFound)).exit_code(),
+            ExitCode::NotFound
+        );
+        assert_eq!(
+            PngMeError::from(io::Error::from(io::ErrorKind::PermissionDenied)).exit_code(),
+            ExitCode::Io
+        );
+        assert_eq!(
+            PngMeError::InvalidSignature.exit_code().name(),
+            "invalid-png"
+        );
+    }
+}
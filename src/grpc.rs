@@ -0,0 +1,116 @@
+//! The gRPC service defined in `proto/pngme.proto`, built with the `grpc`
+//! feature. Like [`server`](crate::server), it hides and reveals messages
+//! with [`message::embed`] and [`message::extract`], so the CLI, the HTTP
+//! service and gRPC clients can all read what the others wrote.
+
+use std::net::SocketAddr;
+
+use futures::stream::{self, Iter};
+use tonic::transport::Server;
+use tonic::{Request, Response, Status, Streaming};
+
+use crate::exit_code::ExitCode;
+use crate::message;
+use crate::server::ServerOptions;
+use crate::PngMeError;
+
+/// The messages and service traits generated from `proto/pngme.proto`.
+pub mod proto {
+    tonic::include_proto!("pngme");
+}
+
+use proto::pngme_server::{Pngme, PngmeServer};
+use proto::{DecodeRequest, DecodeResponse, EncodeRequest, EncodeResponse};
+
+/// How many bytes of the new PNG each [`EncodeResponse`] carries.
+const RESPONSE_PART: usize = 64 * 1024;
+
+/// Answers the `Pngme` service, refusing PNGs and messages larger than
+/// [`ServerOptions::max_body`] together.
+#[derive(Debug, Default)]
+pub struct PngmeService {
+    options: ServerOptions,
+}
+
+impl PngmeService {
+    pub fn new(options: ServerOptions) -> PngmeService {
+        PngmeService { options }
+    }
+
+    fn check_size(&self, len: usize) -> Result<(), Status> {
+        match len as u64 > self.options.max_body {
+            true => Err(Status::resource_exhausted(format!(
+                "requests are limited to {} bytes",
+                self.options.max_body
+            ))),
+            false => Ok(()),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Pngme for PngmeService {
+    type EncodeStream = Iter<std::vec::IntoIter<Result<EncodeResponse, Status>>>;
+
+    async fn encode(
+        &self,
+        request: Request<Streaming<EncodeRequest>>,
+    ) -> Result<Response<Self::EncodeStream>, Status> {
+        let mut requests = request.into_inner();
+        let mut chunk_type = None;
+        let (mut text, mut png) = (Vec::new(), Vec::new());
+        while let Some(part) = requests.message().await? {
+            chunk_type.get_or_insert(part.chunk_type);
+            text.extend(part.message);
+            png.extend(part.png);
+            self.check_size(text.len() + png.len())?;
+        }
+        let encoded =
+            message::embed(&png, &chunk_type.unwrap_or_default(), &text).map_err(status)?;
+        let parts: Vec<_> = encoded
+            .chunks(RESPONSE_PART)
+            .map(|png| Ok(EncodeResponse { png: png.to_vec() }))
+            .collect();
+        Ok(Response::new(stream::iter(parts)))
+    }
+
+    async fn decode(
+        &self,
+        request: Request<Streaming<DecodeRequest>>,
+    ) -> Result<Response<DecodeResponse>, Status> {
+        let mut requests = request.into_inner();
+        let mut chunk_type = None;
+        let mut png = Vec::new();
+        while let Some(part) = requests.message().await? {
+            chunk_type.get_or_insert(part.chunk_type);
+            png.extend(part.png);
+            self.check_size(png.len())?;
+        }
+        let message = message::extract(&png, &chunk_type.unwrap_or_default()).map_err(status)?;
+        Ok(Response::new(DecodeResponse { message }))
+    }
+}
+
+/// The status a failed call ends with, chosen as the exit code would be.
+fn status(err: PngMeError) -> Status {
+    let message = err.to_string();
+    match err.exit_code() {
+        ExitCode::NotFound => Status::not_found(message),
+        ExitCode::AuthFailure => Status::permission_denied(message),
+        ExitCode::Io => Status::internal(message),
+        _ => Status::invalid_argument(message),
+    }
+}
+
+/// Answers calls on `addr` until the process is stopped, giving up on calls
+/// that take longer than [`ServerOptions::timeout`].
+pub async fn serve(
+    addr: SocketAddr,
+    options: ServerOptions,
+) -> Result<(), tonic::transport::Error> {
+    Server::builder()
+        .timeout(options.timeout)
+        .add_service(PngmeServer::new(PngmeService::new(options)))
+        .serve(addr)
+        .await
+}
@@ -0,0 +1,240 @@
+//! Reading PNGs from http:// and https:// URLs, built with the `http`
+//! feature. Everywhere [`stdio`](crate::stdio) reads a path, a URL can be
+//! given instead.
+
+use std::path::Path;
+
+use thiserror::Error;
+
+use crate::Result;
+
+/// Whether `path` is an http:// or https:// URL rather than a file.
+pub fn is_url(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|path| path.starts_with("http://") || path.starts_with("https://"))
+}
+
+#[cfg(feature = "http")]
+pub use fetch::{chunks_of_type, HttpReader};
+
+/// Reads the chunks of type `chunk_type` from the PNG at `url`. Needs the
+/// `http` feature.
+#[cfg(not(feature = "http"))]
+pub fn chunks_of_type(_url: &str, _chunk_type: &str) -> Result<Vec<crate::chunk::Chunk>> {
+    Err(HttpError::Unsupported.into())
+}
+
+/// Opens `url` for reading. Needs the `http` feature.
+#[cfg(not(feature = "http"))]
+pub fn open(_url: &str) -> Result<Box<dyn std::io::Read>> {
+    Err(HttpError::Unsupported.into())
+}
+
+/// Opens `url` for reading.
+#[cfg(feature = "http")]
+pub fn open(url: &str) -> Result<Box<dyn std::io::Read>> {
+    Ok(Box::new(HttpReader::open(url)?))
+}
+
+#[cfg(feature = "http")]
+mod fetch {
+    use std::io::{self, Read, Seek, SeekFrom};
+    use std::str::FromStr;
+
+    use super::HttpError;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::png::ParseOptions;
+    use crate::reader;
+    use crate::Result;
+
+    /// How many bytes each range request asks for.
+    const BLOCK_SIZE: u64 = 64 * 1024;
+
+    /// Reads the chunks of type `chunk_type` from the PNG at `url`. If the
+    /// server supports range requests, only the chunk headers in between
+    /// and the chunks themselves are fetched, and nothing after IEND.
+    pub fn chunks_of_type(url: &str, chunk_type: &str) -> Result<Vec<Chunk>> {
+        let chunk_type = ChunkType::from_str(chunk_type)?;
+        reader::read_chunks_of_type(
+            HttpReader::open(url)?,
+            &chunk_type,
+            &ParseOptions::default(),
+        )
+    }
+
+    /// Reads a remote file, in blocks fetched with range requests if the
+    /// server supports them, or as one streamed response if it doesn't.
+    /// Streamed files can only be seeked forward, by reading past the bytes
+    /// skipped.
+    pub struct HttpReader {
+        url: String,
+        position: u64,
+        body: Body,
+    }
+
+    enum Body {
+        /// The block of the file starting at `start`, and the length of the
+        /// whole file if the server said.
+        Ranges {
+            block: Vec<u8>,
+            start: u64,
+            len: Option<u64>,
+        },
+        Stream(Box<dyn Read + Send + Sync>),
+    }
+
+    impl HttpReader {
+        /// Requests the first block of `url`, falling back to streaming the
+        /// whole response if the server ignores the range.
+        pub fn open(url: &str) -> Result<HttpReader> {
+            let response = get(url, 0).map_err(HttpError::from)?;
+            let body = match response.status() {
+                206 => {
+                    let len = response
+                        .header("Content-Range")
+                        .and_then(|range| range.rsplit('/').next())
+                        .and_then(|len| len.parse().ok());
+                    let mut block = Vec::new();
+                    response.into_reader().read_to_end(&mut block)?;
+                    Body::Ranges {
+                        block,
+                        start: 0,
+                        len,
+                    }
+                }
+                _ => Body::Stream(response.into_reader()),
+            };
+            Ok(HttpReader {
+                url: url.to_string(),
+                position: 0,
+                body,
+            })
+        }
+
+        /// Whether the server supports range requests, so seeking doesn't
+        /// download the bytes skipped.
+        pub fn supports_ranges(&self) -> bool {
+            matches!(self.body, Body::Ranges { .. })
+        }
+    }
+
+    impl std::fmt::Debug for HttpReader {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("HttpReader")
+                .field("url", &self.url)
+                .field("position", &self.position)
+                .field("ranges", &self.supports_ranges())
+                .finish()
+        }
+    }
+
+    /// Requests [`BLOCK_SIZE`] bytes of `url` from `start`. Servers answer a
+    /// start past the end of the file with 416 Range Not Satisfiable.
+    fn get(url: &str, start: u64) -> std::result::Result<ureq::Response, ureq::Error> {
+        let range = format!("bytes={start}-{}", start + BLOCK_SIZE - 1);
+        tracing::debug!(url, range, "fetching");
+        ureq::get(url).set("Range", &range).call()
+    }
+
+    impl Read for HttpReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let read = match &mut self.body {
+                Body::Stream(body) => body.read(buf)?,
+                Body::Ranges { block, start, len } => {
+                    let end = *start + block.len() as u64;
+                    if !(*start..end).contains(&self.position) {
+                        if len.is_some_and(|len| self.position >= len) {
+                            return Ok(0);
+                        }
+                        *start = self.position;
+                        block.clear();
+                        match get(&self.url, self.position) {
+                            Ok(response) if response.status() == 206 => {
+                                response.into_reader().read_to_end(block)?;
+                            }
+                            Ok(_) => {
+                                return Err(io::Error::other("server stopped honoring ranges"))
+                            }
+                            // Range Not Satisfiable, starting past the end.
+                            Err(ureq::Error::Status(416, _)) => return Ok(0),
+                            Err(err) => return Err(io::Error::other(err)),
+                        }
+                    }
+                    let offset = (self.position - *start) as usize;
+                    let read = buf.len().min(block.len() - offset);
+                    buf[..read].copy_from_slice(&block[offset..offset + read]);
+                    read
+                }
+            };
+            self.position += read as u64;
+            Ok(read)
+        }
+    }
+
+    impl Seek for HttpReader {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            let len = match &self.body {
+                Body::Ranges { len, .. } => *len,
+                Body::Stream(_) => None,
+            };
+            let target = match pos {
+                SeekFrom::Start(offset) => Some(offset),
+                SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+                SeekFrom::End(offset) => len.and_then(|len| len.checked_add_signed(offset)),
+            }
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek"))?;
+            match &mut self.body {
+                Body::Ranges { .. } => self.position = target,
+                Body::Stream(body) if target >= self.position => {
+                    let skipped =
+                        io::copy(&mut body.take(target - self.position), &mut io::sink())?;
+                    self.position += skipped;
+                }
+                Body::Stream(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        "can't seek backwards in a streamed response",
+                    ))
+                }
+            }
+            Ok(self.position)
+        }
+    }
+}
+
+/// Errors raised while reading from a URL.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum HttpError {
+    /// The request failed or the server answered with an error status.
+    #[cfg(feature = "http")]
+    #[error("HTTP request failed: {0}")]
+    Request(Box<ureq::Error>),
+    /// A URL was given to a build without the `http` feature.
+    #[error("reading URLs needs pngme to be built with the http feature")]
+    Unsupported,
+    /// A URL was given as the place to write a result. Names the URL.
+    #[error("can't write to {0}, give a file to write to with -o")]
+    ReadOnly(String),
+}
+
+#[cfg(feature = "http")]
+impl From<ureq::Error> for HttpError {
+    fn from(err: ureq::Error) -> Self {
+        HttpError::Request(Box::new(err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_url() {
+        assert!(is_url(Path::new("https://example.com/image.png")));
+        assert!(is_url(Path::new("http://example.com/image.png?size=large")));
+        assert!(!is_url(Path::new("image.png")));
+        assert!(!is_url(Path::new("ftp://example.com/image.png")));
+    }
+}
@@ -0,0 +1,90 @@
+use thiserror::Error;
+
+use crate::ancillary::{AncillaryChunk, Srgb};
+use crate::png::Png;
+use crate::text::{self, latin1, split_nul, to_latin1, validate_keyword};
+use crate::Result;
+
+/// The contents of an `iCCP` chunk: a named ICC color profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IccProfile {
+    /// Profile name, following the same rules as text chunk keywords.
+    pub name: String,
+    /// The decompressed profile.
+    pub data: Vec<u8>,
+}
+
+impl AncillaryChunk for IccProfile {
+    const CHUNK_TYPE: [u8; 4] = *b"iCCP";
+
+    fn parse(data: &[u8]) -> Result<IccProfile> {
+        let (name, rest) = split_nul(data)?;
+        let (&method, compressed) = rest.split_first().ok_or(IccError::Truncated)?;
+        Ok(IccProfile {
+            name: latin1(name),
+            data: text::inflate(method, compressed)?,
+        })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut data = to_latin1(&self.name).expect("name was validated by set_icc_profile");
+        data.extend([0, 0]);
+        data.extend(text::deflate(&self.data).expect("compressing into memory cannot fail"));
+        data
+    }
+}
+
+impl Png {
+    /// Parses and decompresses the embedded ICC profile, if there is one.
+    pub fn icc_profile(&self) -> Result<Option<IccProfile>> {
+        self.ancillary()
+    }
+
+    /// Embeds `profile` under `name`, replacing any existing profile. An sRGB
+    /// chunk is removed, since the specification forbids having both.
+    pub fn set_icc_profile(&mut self, name: &str, profile: &[u8]) -> Result<()> {
+        validate_keyword(name)?;
+        self.remove_ancillary::<Srgb>();
+        self.set_ancillary(&IccProfile {
+            name: name.to_string(),
+            data: profile.to_vec(),
+        });
+        Ok(())
+    }
+}
+
+/// Errors raised while reading an [`IccProfile`].
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum IccError {
+    /// The chunk ends before its compression method.
+    #[error("iCCP chunk is truncated")]
+    Truncated,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ancillary::RenderingIntent;
+
+    #[test]
+    fn test_icc_round_trip() {
+        let mut png = Png::from_chunks(Vec::new());
+        png.set_srgb(RenderingIntent::Perceptual);
+        png.set_icc_profile("Display P3", b"fake profile bytes")
+            .unwrap();
+
+        let profile = png.icc_profile().unwrap().unwrap();
+        assert_eq!(profile.name, "Display P3");
+        assert_eq!(profile.data, b"fake profile bytes");
+        assert_eq!(png.ancillary::<Srgb>().unwrap(), None);
+    }
+
+    #[test]
+    fn test_invalid_profile_name() {
+        let mut png = Png::from_chunks(Vec::new());
+        assert!(png.set_icc_profile(" padded", b"").is_err());
+        assert!(IccProfile::parse(b"no separator").is_err());
+        assert!(IccProfile::parse(b"name\0").is_err());
+    }
+}
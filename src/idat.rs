@@ -0,0 +1,459 @@
+use std::io::{Read, Write};
+use std::num::NonZeroU64;
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use thiserror::Error;
+
+use crate::chunk::{Chunk, MAX_CHUNK_LEN};
+use crate::chunk_type::ChunkType;
+use crate::ihdr::Ihdr;
+use crate::pixels;
+use crate::png::Png;
+use crate::{PngMeError, Result};
+
+/// Largest data field written when splitting recompressed image data.
+pub const IDAT_CHUNK_LEN: usize = 1 << 20;
+
+/// Scanline filter types from section 7.3 of the specification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    None,
+    Sub,
+    Up,
+    Average,
+    Paeth,
+}
+
+impl Filter {
+    pub fn from_id(id: u8) -> Option<Filter> {
+        match id {
+            0 => Some(Filter::None),
+            1 => Some(Filter::Sub),
+            2 => Some(Filter::Up),
+            3 => Some(Filter::Average),
+            4 => Some(Filter::Paeth),
+            _ => None,
+        }
+    }
+}
+
+/// Unfiltered image data: one row of raw bytes per scanline, each
+/// remembering the filter it was stored with so it can be refiltered the
+/// same way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scanlines {
+    /// Bytes in each row.
+    pub stride: usize,
+    /// Bytes per complete pixel, rounded up to 1, used by the filters.
+    pub bytes_per_pixel: usize,
+    pub filters: Vec<Filter>,
+    /// The rows back to back, `stride * filters.len()` bytes.
+    pub data: Vec<u8>,
+}
+
+impl Scanlines {
+    /// Unfilters decompressed, non-interlaced IDAT data.
+    pub fn unfilter(ihdr: &Ihdr, filtered: &[u8]) -> Result<Scanlines> {
+        let stride = row_bytes(ihdr)?;
+        let bytes_per_pixel = ihdr.bits_per_pixel().div_ceil(8);
+        let height = ihdr.height as usize;
+        let needed = (stride + 1)
+            .checked_mul(height)
+            .ok_or(IdatError::TooLarge)?;
+        if filtered.len() < needed {
+            return Err(IdatError::Truncated {
+                expected: needed,
+                found: filtered.len(),
+            }
+            .into());
+        }
+
+        let mut filters = Vec::with_capacity(height);
+        let mut data = vec![0; stride * height];
+        for (row, line) in filtered[..needed].chunks_exact(stride + 1).enumerate() {
+            let filter = Filter::from_id(line[0]).ok_or(IdatError::UnknownFilter(line[0]))?;
+            filters.push(filter);
+            let (done, rest) = data.split_at_mut(row * stride);
+            let previous = (row > 0).then(|| &done[(row - 1) * stride..]);
+            let current = &mut rest[..stride];
+            current.copy_from_slice(&line[1..]);
+            for i in 0..stride {
+                let a = if i >= bytes_per_pixel {
+                    current[i - bytes_per_pixel]
+                } else {
+                    0
+                };
+                let b = previous.map_or(0, |p| p[i]);
+                let c = match previous {
+                    Some(p) if i >= bytes_per_pixel => p[i - bytes_per_pixel],
+                    _ => 0,
+                };
+                current[i] = current[i].wrapping_add(predict(filter, a, b, c));
+            }
+        }
+        Ok(Scanlines {
+            stride,
+            bytes_per_pixel,
+            filters,
+            data,
+        })
+    }
+
+    /// Wraps raw rows laid out as `ihdr` describes, choosing each row's
+    /// filter with the minimum sum of absolute differences heuristic from
+    /// section 12.8 of the specification.
+    pub fn with_adaptive_filters(ihdr: &Ihdr, mut data: Vec<u8>) -> Result<Scanlines> {
+        let stride = row_bytes(ihdr)?;
+        let bytes_per_pixel = ihdr.bits_per_pixel().div_ceil(8);
+        let needed = stride
+            .checked_mul(ihdr.height as usize)
+            .ok_or(IdatError::TooLarge)?;
+        if data.len() < needed {
+            return Err(IdatError::Truncated {
+                expected: needed,
+                found: data.len(),
+            }
+            .into());
+        }
+        data.truncate(needed);
+
+        let candidates = [
+            Filter::None,
+            Filter::Sub,
+            Filter::Up,
+            Filter::Average,
+            Filter::Paeth,
+        ];
+        let mut filters = Vec::with_capacity(ihdr.height as usize);
+        let mut line = Vec::with_capacity(stride + 1);
+        for row in 0..ihdr.height as usize {
+            let current = &data[row * stride..(row + 1) * stride];
+            let previous = (row > 0).then(|| &data[(row - 1) * stride..row * stride]);
+            let best = candidates
+                .into_iter()
+                .min_by_key(|&filter| {
+                    line.clear();
+                    filter_row(filter, current, previous, bytes_per_pixel, &mut line);
+                    line[1..]
+                        .iter()
+                        .map(|&byte| u64::from((byte as i8).unsigned_abs()))
+                        .sum::<u64>()
+                })
+                .unwrap_or(Filter::None);
+            filters.push(best);
+        }
+        Ok(Scanlines {
+            stride,
+            bytes_per_pixel,
+            filters,
+            data,
+        })
+    }
+
+    /// Refilters every row with the filter it was read with.
+    pub fn filter(&self) -> Vec<u8> {
+        let mut filtered = Vec::with_capacity((self.stride + 1) * self.filters.len());
+        for (row, &filter) in self.filters.iter().enumerate() {
+            let current = &self.data[row * self.stride..(row + 1) * self.stride];
+            let previous =
+                (row > 0).then(|| &self.data[(row - 1) * self.stride..row * self.stride]);
+            filter_row(
+                filter,
+                current,
+                previous,
+                self.bytes_per_pixel,
+                &mut filtered,
+            );
+        }
+        filtered
+    }
+}
+
+/// Appends the filter type byte and the filtered bytes of `current` to `out`.
+fn filter_row(
+    filter: Filter,
+    current: &[u8],
+    previous: Option<&[u8]>,
+    bpp: usize,
+    out: &mut Vec<u8>,
+) {
+    out.push(filter as u8);
+    for i in 0..current.len() {
+        let a = if i >= bpp { current[i - bpp] } else { 0 };
+        let b = previous.map_or(0, |p| p[i]);
+        let c = match previous {
+            Some(p) if i >= bpp => p[i - bpp],
+            _ => 0,
+        };
+        out.push(current[i].wrapping_sub(predict(filter, a, b, c)));
+    }
+}
+
+/// Bytes in one unfiltered row, without the filter type byte.
+pub(crate) fn row_bytes(ihdr: &Ihdr) -> Result<usize> {
+    (ihdr.width as usize)
+        .checked_mul(ihdr.bits_per_pixel())
+        .map(|bits| bits.div_ceil(8))
+        .ok_or_else(|| IdatError::TooLarge.into())
+}
+
+/// The value a filter predicts from the bytes to the left (`a`), above (`b`)
+/// and above-left (`c`).
+fn predict(filter: Filter, a: u8, b: u8, c: u8) -> u8 {
+    match filter {
+        Filter::None => 0,
+        Filter::Sub => a,
+        Filter::Up => b,
+        Filter::Average => ((u16::from(a) + u16::from(b)) / 2) as u8,
+        Filter::Paeth => {
+            let p = i16::from(a) + i16::from(b) - i16::from(c);
+            let (pa, pb, pc) = (
+                (p - i16::from(a)).abs(),
+                (p - i16::from(b)).abs(),
+                (p - i16::from(c)).abs(),
+            );
+            if pa <= pb && pa <= pc {
+                a
+            } else if pb <= pc {
+                b
+            } else {
+                c
+            }
+        }
+    }
+}
+
+impl Png {
+    /// Concatenates and decompresses the IDAT chunks, failing if they hold
+    /// more data than IHDR says the image needs.
+    pub fn image_data(&self) -> Result<Vec<u8>> {
+        let mut idat = self
+            .chunks()
+            .iter()
+            .filter(|chunk| *chunk.chunk_type() == ChunkType::IDAT)
+            .peekable();
+        if idat.peek().is_none() {
+            return Err(PngMeError::ChunkNotFound("IDAT".to_string()));
+        }
+        let compressed: Vec<u8> = idat
+            .flat_map(|chunk| chunk.data().iter().copied())
+            .collect();
+        // One byte past what IHDR allows is enough to tell that the data
+        // runs over, without inflating all of a hostile stream.
+        let max = pixels::image_data_len(&self.ihdr()?)?;
+        let mut data = Vec::new();
+        ZlibDecoder::new(compressed.as_slice())
+            .take(max.saturating_add(1))
+            .read_to_end(&mut data)?;
+        match data.len() as u64 > max {
+            true => Err(IdatError::Oversized { max }.into()),
+            false => Ok(data),
+        }
+    }
+
+    /// Compresses `data` and stores it in place of the existing IDAT chunks.
+    pub fn set_image_data(&mut self, data: &[u8]) -> Result<()> {
+        self.replace_idat(&compress(data, flate2::Compression::default())?)
+    }
+
+    /// Recompresses the image data as `recompression` says, keeping the
+    /// result only if it is smaller, so a file that grew after embedding can
+    /// be shrunk back down. Returns the bytes saved.
+    ///
+    /// The pixels don't change, so unlike [`Png::set_image_data`] this keeps
+    /// unsafe-to-copy chunks.
+    pub fn recompress_idat(&mut self, recompression: Recompression) -> Result<u64> {
+        let is_idat = |chunk: &Chunk| *chunk.chunk_type() == ChunkType::IDAT;
+        let before: u64 = self
+            .chunks()
+            .iter()
+            .filter(|chunk| is_idat(chunk))
+            .map(Chunk::total_size)
+            .sum();
+        let compressed = recompression.compress(&self.image_data()?)?;
+        let idat: Vec<Chunk> = compressed
+            .chunks(MAX_CHUNK_LEN as usize)
+            .map(|part| Chunk::new(ChunkType::IDAT, part.to_vec()))
+            .collect();
+        let after: u64 = idat.iter().map(Chunk::total_size).sum();
+        if after >= before {
+            return Ok(0);
+        }
+
+        let chunks = self.chunks_mut();
+        let position = chunks.iter().position(is_idat).unwrap_or(chunks.len());
+        chunks.retain(|chunk| !is_idat(chunk));
+        chunks.splice(position..position, idat);
+        Ok(before - after)
+    }
+
+    /// Replaces the IDAT chunks with `compressed`, split into chunks of at
+    /// most [`IDAT_CHUNK_LEN`] bytes, where the first IDAT used to be.
+    pub(crate) fn replace_idat(&mut self, compressed: &[u8]) -> Result<()> {
+        let is_idat = |chunk: &Chunk| *chunk.chunk_type() == ChunkType::IDAT;
+        let position = self
+            .chunks()
+            .iter()
+            .position(is_idat)
+            .ok_or_else(|| PngMeError::ChunkNotFound("IDAT".to_string()))?;
+        self.retain_chunks(|chunk| !is_idat(chunk));
+        let chunk_type = ChunkType::IDAT;
+        for (i, part) in compressed.chunks(IDAT_CHUNK_LEN).enumerate() {
+            self.insert_chunk(position + i, Chunk::new(chunk_type, part.to_vec()));
+        }
+        Ok(())
+    }
+
+    /// Decompresses and unfilters the image data.
+    pub fn scanlines(&self) -> Result<Scanlines> {
+        Scanlines::unfilter(&self.ihdr()?, &self.image_data()?)
+    }
+
+    /// Refilters and recompresses `scanlines` into the IDAT chunks.
+    pub fn set_scanlines(&mut self, scanlines: &Scanlines) -> Result<()> {
+        self.set_image_data(&scanlines.filter())
+    }
+}
+
+/// How [`Png::recompress_idat`] compresses the image data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recompression {
+    /// zlib at this level, 0 to 9.
+    Zlib(u32),
+    /// Zopfli with this many iterations, typically a few percent smaller
+    /// than zlib level 9 and orders of magnitude slower. Needs the `zopfli`
+    /// feature.
+    Zopfli { iterations: NonZeroU64 },
+}
+
+impl Recompression {
+    fn compress(self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Recompression::Zlib(level @ 0..=9) => compress(data, flate2::Compression::new(level)),
+            Recompression::Zlib(level) => Err(IdatError::InvalidLevel(level).into()),
+            #[cfg(feature = "zopfli")]
+            Recompression::Zopfli { iterations } => {
+                let options = zopfli::Options {
+                    iteration_count: iterations,
+                    ..zopfli::Options::default()
+                };
+                let mut compressed = Vec::new();
+                zopfli::compress(options, zopfli::Format::Zlib, data, &mut compressed)?;
+                Ok(compressed)
+            }
+            #[cfg(not(feature = "zopfli"))]
+            Recompression::Zopfli { .. } => Err(IdatError::ZopfliUnsupported.into()),
+        }
+    }
+}
+
+fn compress(data: &[u8], level: flate2::Compression) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), level);
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+/// Errors raised while decoding image data.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum IdatError {
+    /// The decompressed data is shorter than IHDR says it should be.
+    #[error("image data is {found} bytes, expected at least {expected}")]
+    Truncated { expected: usize, found: usize },
+    #[error("unknown scanline filter {0}")]
+    UnknownFilter(u8),
+    /// The image is too large to address in memory.
+    #[error("image is too large to decode")]
+    TooLarge,
+    /// The IDAT chunks decompress to more than the `max` bytes IHDR implies.
+    #[error("image data decompresses to more than the {max} bytes IHDR describes")]
+    Oversized { max: u64 },
+    /// A zlib compression level above 9.
+    #[error("invalid zlib level {0}, expected 0 to 9")]
+    InvalidLevel(u32),
+    /// Zopfli was requested from a build without the `zopfli` feature.
+    #[error("Zopfli needs pngme to be built with the zopfli feature")]
+    ZopfliUnsupported,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::{ColorType, Interlace};
+
+    #[test]
+    fn test_filters_round_trip() {
+        let ihdr = Ihdr::new(5, 5, 8, ColorType::Rgb).unwrap();
+        let stride = 15;
+        let data: Vec<u8> = (0..stride * 5).map(|i| (i * 37 % 251) as u8).collect();
+        let scanlines = Scanlines {
+            stride,
+            bytes_per_pixel: 3,
+            filters: vec![
+                Filter::None,
+                Filter::Sub,
+                Filter::Up,
+                Filter::Average,
+                Filter::Paeth,
+            ],
+            data,
+        };
+        let filtered = scanlines.filter();
+        assert_eq!(filtered.len(), 16 * 5);
+        assert_eq!(Scanlines::unfilter(&ihdr, &filtered).unwrap(), scanlines);
+    }
+
+    #[test]
+    fn test_truncated_data() {
+        let ihdr = Ihdr::new(2, 2, 8, ColorType::Grayscale).unwrap();
+        assert!(Scanlines::unfilter(&ihdr, &[0, 1, 2, 0]).is_err());
+        assert!(Scanlines::unfilter(&ihdr, &[5, 1, 2, 0, 1, 2]).is_err());
+    }
+
+    #[test]
+    fn test_recompress_idat() {
+        let ihdr = Ihdr::new(16, 16, 8, ColorType::Grayscale).unwrap();
+        let data = vec![0; 17 * 16];
+        let stored = compress(&data, flate2::Compression::none()).unwrap();
+        let mut png = Png::from_chunks(vec![
+            ihdr.to_chunk(),
+            Chunk::new(ChunkType::IDAT, stored),
+            Chunk::new(ChunkType::IEND, Vec::new()),
+        ]);
+
+        png.recompress_idat(Recompression::Zlib(9)).unwrap();
+        assert_eq!(png.image_data().unwrap(), data);
+        assert!(matches!(
+            png.recompress_idat(Recompression::Zlib(10)),
+            Err(PngMeError::Idat(IdatError::InvalidLevel(10)))
+        ));
+    }
+
+    #[test]
+    fn test_image_data_limit() {
+        let ihdr = Ihdr::new(16, 16, 8, ColorType::Grayscale).unwrap();
+        let bomb = compress(&vec![0; 17 * 16 * 1000], flate2::Compression::best()).unwrap();
+        let png = Png::from_chunks(vec![
+            ihdr.to_chunk(),
+            Chunk::new(ChunkType::IDAT, bomb),
+            Chunk::new(ChunkType::IEND, Vec::new()),
+        ]);
+        assert!(matches!(
+            png.image_data(),
+            Err(PngMeError::Idat(IdatError::Oversized { max: 272 }))
+        ));
+    }
+
+    #[test]
+    fn test_interlaced_image_data_len() {
+        let mut ihdr = Ihdr::new(5, 5, 8, ColorType::Grayscale).unwrap();
+        assert_eq!(pixels::image_data_len(&ihdr).unwrap(), 6 * 5);
+        ihdr.interlace = Interlace::Adam7;
+        // Passes of 1x1, 1x1, 2x1, 1x2, 3x1, 2x3 and 5x2 pixels.
+        assert_eq!(
+            pixels::image_data_len(&ihdr).unwrap(),
+            2 + 2 + 3 + 2 * 2 + 4 + 3 * 3 + 6 * 2
+        );
+    }
+}
@@ -0,0 +1,290 @@
+use std::convert::TryFrom;
+use std::fmt;
+
+use thiserror::Error;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::{PngMeError, Result};
+
+/// How pixels are laid out, as stored in the IHDR color type field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorType {
+    Grayscale,
+    Rgb,
+    Indexed,
+    GrayscaleAlpha,
+    Rgba,
+}
+
+impl ColorType {
+    pub fn id(self) -> u8 {
+        match self {
+            ColorType::Grayscale => 0,
+            ColorType::Rgb => 2,
+            ColorType::Indexed => 3,
+            ColorType::GrayscaleAlpha => 4,
+            ColorType::Rgba => 6,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<ColorType> {
+        match id {
+            0 => Some(ColorType::Grayscale),
+            2 => Some(ColorType::Rgb),
+            3 => Some(ColorType::Indexed),
+            4 => Some(ColorType::GrayscaleAlpha),
+            6 => Some(ColorType::Rgba),
+            _ => None,
+        }
+    }
+
+    /// Number of samples in each pixel.
+    pub fn channels(self) -> u8 {
+        match self {
+            ColorType::Grayscale | ColorType::Indexed => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+        }
+    }
+
+    /// Bit depths the specification allows for this color type (table 11.1).
+    pub fn allowed_bit_depths(self) -> &'static [u8] {
+        match self {
+            ColorType::Grayscale => &[1, 2, 4, 8, 16],
+            ColorType::Indexed => &[1, 2, 4, 8],
+            ColorType::Rgb | ColorType::GrayscaleAlpha | ColorType::Rgba => &[8, 16],
+        }
+    }
+}
+
+impl fmt::Display for ColorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ColorType::Grayscale => "grayscale",
+            ColorType::Rgb => "RGB",
+            ColorType::Indexed => "indexed",
+            ColorType::GrayscaleAlpha => "grayscale + alpha",
+            ColorType::Rgba => "RGBA",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Whether the image data is stored row by row or in seven Adam7 passes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interlace {
+    None,
+    Adam7,
+}
+
+/// The fields of the IHDR chunk, which must come first in every PNG.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ihdr {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: ColorType,
+    /// Always 0 (deflate) in this version of the specification.
+    pub compression: u8,
+    /// Always 0 (adaptive filtering) in this version of the specification.
+    pub filter: u8,
+    pub interlace: Interlace,
+}
+
+impl Ihdr {
+    /// Size of the IHDR data field.
+    pub const LENGTH: usize = 13;
+
+    /// Describes a non-interlaced image, checking the combination is valid.
+    pub fn new(width: u32, height: u32, bit_depth: u8, color_type: ColorType) -> Result<Ihdr> {
+        let ihdr = Ihdr {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            compression: 0,
+            filter: 0,
+            interlace: Interlace::None,
+        };
+        ihdr.validate()?;
+        Ok(ihdr)
+    }
+
+    /// Checks the fields against the limits and combinations in section 11.2.2
+    /// of the specification.
+    pub fn validate(&self) -> Result<()> {
+        // Dimensions are limited to 2^31 - 1 so they fit in a signed integer.
+        let max = i32::MAX as u32;
+        if self.width == 0 || self.height == 0 || self.width > max || self.height > max {
+            return Err(IhdrError::InvalidDimensions {
+                width: self.width,
+                height: self.height,
+            }
+            .into());
+        }
+        if !self
+            .color_type
+            .allowed_bit_depths()
+            .contains(&self.bit_depth)
+        {
+            return Err(IhdrError::InvalidBitDepth {
+                color_type: self.color_type,
+                bit_depth: self.bit_depth,
+            }
+            .into());
+        }
+        if self.compression != 0 {
+            return Err(IhdrError::UnknownCompression(self.compression).into());
+        }
+        if self.filter != 0 {
+            return Err(IhdrError::UnknownFilter(self.filter).into());
+        }
+        Ok(())
+    }
+
+    /// Bits used by one pixel.
+    pub fn bits_per_pixel(&self) -> usize {
+        self.bit_depth as usize * self.color_type.channels() as usize
+    }
+
+    /// Serializes the fields into the 13-byte IHDR data layout.
+    pub fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::LENGTH);
+        bytes.extend(self.width.to_be_bytes());
+        bytes.extend(self.height.to_be_bytes());
+        bytes.extend([
+            self.bit_depth,
+            self.color_type.id(),
+            self.compression,
+            self.filter,
+            match self.interlace {
+                Interlace::None => 0,
+                Interlace::Adam7 => 1,
+            },
+        ]);
+        bytes
+    }
+
+    /// Builds the IHDR chunk for these fields.
+    pub fn to_chunk(self) -> Chunk {
+        let chunk_type = ChunkType::IHDR;
+        Chunk::new(chunk_type, self.to_bytes())
+    }
+}
+
+impl TryFrom<&[u8]> for Ihdr {
+    type Error = PngMeError;
+
+    /// Parses and validates the IHDR data field.
+    fn try_from(data: &[u8]) -> Result<Ihdr> {
+        let data: &[u8; Ihdr::LENGTH] = data
+            .try_into()
+            .map_err(|_| IhdrError::InvalidLength(data.len()))?;
+        let color_type = ColorType::from_id(data[9]).ok_or(IhdrError::UnknownColorType(data[9]))?;
+        let interlace = match data[12] {
+            0 => Interlace::None,
+            1 => Interlace::Adam7,
+            other => return Err(IhdrError::UnknownInterlace(other).into()),
+        };
+        let ihdr = Ihdr {
+            width: u32::from_be_bytes(data[0..4].try_into()?),
+            height: u32::from_be_bytes(data[4..8].try_into()?),
+            bit_depth: data[8],
+            color_type,
+            compression: data[10],
+            filter: data[11],
+            interlace,
+        };
+        ihdr.validate()?;
+        Ok(ihdr)
+    }
+}
+
+impl Png {
+    /// Parses the IHDR chunk, failing if it is missing or invalid.
+    pub fn ihdr(&self) -> Result<Ihdr> {
+        let chunk = self
+            .chunk_by_type("IHDR")
+            .ok_or_else(|| PngMeError::ChunkNotFound("IHDR".to_string()))?;
+        Ihdr::try_from(chunk.data())
+    }
+}
+
+impl fmt::Display for Ihdr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}x{}, {}-bit {}",
+            self.width, self.height, self.bit_depth, self.color_type
+        )?;
+        if self.interlace == Interlace::Adam7 {
+            write!(f, ", interlaced")?;
+        }
+        Ok(())
+    }
+}
+
+/// Errors raised while parsing an [`Ihdr`].
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum IhdrError {
+    /// The IHDR data field must be exactly 13 bytes.
+    #[error("IHDR must be {} bytes long, got {0}", Ihdr::LENGTH)]
+    InvalidLength(usize),
+    /// Width and height must be between 1 and 2^31 - 1.
+    #[error("invalid image dimensions {width}x{height}")]
+    InvalidDimensions { width: u32, height: u32 },
+    #[error("unknown color type {0}")]
+    UnknownColorType(u8),
+    /// The bit depth is not allowed for the color type.
+    #[error("{color_type} images cannot have bit depth {bit_depth}")]
+    InvalidBitDepth {
+        color_type: ColorType,
+        bit_depth: u8,
+    },
+    #[error("unknown compression method {0}")]
+    UnknownCompression(u8),
+    #[error("unknown filter method {0}")]
+    UnknownFilter(u8),
+    #[error("unknown interlace method {0}")]
+    UnknownInterlace(u8),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ihdr_round_trip() {
+        let ihdr = Ihdr::new(640, 480, 8, ColorType::Rgba).unwrap();
+        let bytes = ihdr.to_bytes();
+        assert_eq!(bytes.len(), Ihdr::LENGTH);
+        assert_eq!(Ihdr::try_from(bytes.as_slice()).unwrap(), ihdr);
+        assert_eq!(ihdr.bits_per_pixel(), 32);
+    }
+
+    #[test]
+    fn test_png_ihdr() {
+        let ihdr = Ihdr::new(1, 1, 1, ColorType::Grayscale).unwrap();
+        let png = Png::from_chunks(vec![ihdr.to_chunk()]);
+        assert_eq!(png.ihdr().unwrap(), ihdr);
+        assert!(Png::from_chunks(Vec::new()).ihdr().is_err());
+    }
+
+    #[test]
+    fn test_invalid_combinations() {
+        assert!(Ihdr::new(0, 1, 8, ColorType::Rgb).is_err());
+        assert!(Ihdr::new(1, 1, 4, ColorType::Rgb).is_err());
+        assert!(Ihdr::new(1, 1, 16, ColorType::Indexed).is_err());
+
+        let mut bytes = Ihdr::new(1, 1, 8, ColorType::Rgb).unwrap().to_bytes();
+        bytes[9] = 5;
+        assert!(Ihdr::try_from(bytes.as_slice()).is_err());
+        bytes[9] = 2;
+        bytes[12] = 2;
+        assert!(Ihdr::try_from(bytes.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,203 @@
+use std::convert::TryFrom;
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::Result;
+
+/// Type of the chunk listing the labeled payloads in a PNG.
+pub const INDEX_CHUNK_TYPE: [u8; 4] = *b"inDx";
+
+/// One labeled payload recorded in the index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct IndexEntry {
+    pub label: String,
+    pub chunk_type: ChunkType,
+    /// Size of the embedded payload in bytes, after compression and encryption.
+    pub size: u64,
+    /// Number of chunks the payload is split across.
+    pub parts: u32,
+}
+
+/// The labeled payloads stored in a PNG, kept in an `inDx` chunk.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PayloadIndex {
+    entries: Vec<IndexEntry>,
+}
+
+impl PayloadIndex {
+    /// Reads the index chunk of `png`, or returns an empty index if there is none.
+    pub fn from_png(png: &Png) -> Result<PayloadIndex> {
+        match png
+            .chunks()
+            .iter()
+            .find(|chunk| chunk.chunk_type().bytes() == INDEX_CHUNK_TYPE)
+        {
+            Some(chunk) => PayloadIndex::from_bytes(chunk.data()),
+            None => Ok(PayloadIndex::default()),
+        }
+    }
+
+    /// Replaces the index chunk of `png` with this index, dropping it when
+    /// the index is empty.
+    pub fn store(&self, png: &mut Png) -> Result<()> {
+        png.retain_chunks(|chunk| chunk.chunk_type().bytes() != INDEX_CHUNK_TYPE);
+        if !self.entries.is_empty() {
+            png.append_chunk(Chunk::new(
+                ChunkType::try_from(INDEX_CHUNK_TYPE)?,
+                self.to_bytes()?,
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    pub fn get(&self, label: &str) -> Option<&IndexEntry> {
+        self.entries.iter().find(|entry| entry.label == label)
+    }
+
+    /// Adds `entry`, replacing any existing entry with the same label.
+    pub fn insert(&mut self, entry: IndexEntry) {
+        self.remove(&entry.label);
+        self.entries.push(entry);
+    }
+
+    pub fn remove(&mut self, label: &str) -> Option<IndexEntry> {
+        let position = self.entries.iter().position(|entry| entry.label == label)?;
+        Some(self.entries.remove(position))
+    }
+
+    /// Layout: entry count (u16), then for each entry the label length (u8)
+    /// and label, chunk type, size (u64) and part count (u32), big-endian.
+    fn to_bytes(&self) -> Result<Vec<u8>> {
+        let count = u16::try_from(self.entries.len()).map_err(|_| IndexError::TooManyEntries)?;
+        let mut bytes = count.to_be_bytes().to_vec();
+        for entry in &self.entries {
+            let label_len = u8::try_from(entry.label.len())
+                .map_err(|_| IndexError::LabelTooLong(entry.label.len()))?;
+            bytes.push(label_len);
+            bytes.extend_from_slice(entry.label.as_bytes());
+            bytes.extend_from_slice(&entry.chunk_type.bytes());
+            bytes.extend_from_slice(&entry.size.to_be_bytes());
+            bytes.extend_from_slice(&entry.parts.to_be_bytes());
+        }
+        Ok(bytes)
+    }
+
+    fn from_bytes(mut bytes: &[u8]) -> Result<PayloadIndex> {
+        let mut take = |len: usize| -> Result<&[u8]> {
+            if bytes.len() < len {
+                return Err(IndexError::Truncated.into());
+            }
+            let (field, rest) = bytes.split_at(len);
+            bytes = rest;
+            Ok(field)
+        };
+
+        let count = u16::from_be_bytes(take(2)?.try_into()?);
+        let mut entries = Vec::with_capacity(count.into());
+        for _ in 0..count {
+            let label_len = take(1)?[0];
+            let label = String::from_utf8(take(label_len.into())?.to_vec())?;
+            let chunk_type = ChunkType::try_from(<[u8; 4]>::try_from(take(4)?)?)?;
+            let size = u64::from_be_bytes(take(8)?.try_into()?);
+            let parts = u32::from_be_bytes(take(4)?.try_into()?);
+            entries.push(IndexEntry {
+                label,
+                chunk_type,
+                size,
+                parts,
+            });
+        }
+        Ok(PayloadIndex { entries })
+    }
+}
+
+/// Errors raised while reading the payload index.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum IndexError {
+    /// The index chunk ends in the middle of an entry.
+    #[error("payload index chunk is truncated")]
+    Truncated,
+    /// The index can list at most 65535 payloads.
+    #[error("payload index is limited to {} entries", u16::MAX)]
+    TooManyEntries,
+    /// A label, of the length given, is longer than the 255 bytes an index
+    /// entry can hold.
+    #[error("payload label is {0} bytes, more than the 255 an index entry holds")]
+    LabelTooLong(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::png::minimal_png;
+    use crate::PngMeError;
+
+    fn entry(label: &str) -> IndexEntry {
+        IndexEntry {
+            label: label.to_string(),
+            chunk_type: ChunkType::from_str("ruSt").unwrap(),
+            size: 1234,
+            parts: 3,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut index = PayloadIndex::default();
+        index.insert(entry("notes"));
+        index.insert(entry("photo"));
+        let mut png = minimal_png();
+        index.store(&mut png).unwrap();
+        assert_eq!(PayloadIndex::from_png(&png).unwrap(), index);
+
+        index.remove("notes");
+        index.remove("photo");
+        index.store(&mut png).unwrap();
+        assert_eq!(png.chunks().len(), 2);
+        assert!(PayloadIndex::from_png(&png).unwrap().entries().is_empty());
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut index = PayloadIndex::default();
+        index.insert(entry("notes"));
+        let bytes = index.to_bytes().unwrap();
+        for len in 0..bytes.len() {
+            assert!(matches!(
+                PayloadIndex::from_bytes(&bytes[..len]),
+                Err(PngMeError::Index(IndexError::Truncated))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_oversized_fields_are_refused() {
+        let mut index = PayloadIndex::default();
+        index.insert(entry(&"a".repeat(256)));
+        assert!(matches!(
+            index.to_bytes(),
+            Err(PngMeError::Index(IndexError::LabelTooLong(256)))
+        ));
+
+        let index = PayloadIndex {
+            entries: vec![entry("a"); usize::from(u16::MAX) + 1],
+        };
+        assert!(matches!(
+            index.to_bytes(),
+            Err(PngMeError::Index(IndexError::TooManyEntries))
+        ));
+    }
+}
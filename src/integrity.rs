@@ -0,0 +1,198 @@
+use std::fmt;
+
+use thiserror::Error;
+
+use crate::copy_policy::KNOWN_CHUNK_TYPES;
+use crate::envelope::{EnvelopeFlags, PayloadEnvelope};
+use crate::payload;
+use crate::png::{ParseOptions, Png};
+use crate::trailer;
+use crate::Result;
+
+/// Where a payload checked by [`check_payloads`] was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PayloadLocation {
+    /// In chunks of this type, under `label` if it was stored with one.
+    Chunks {
+        chunk_type: String,
+        label: Option<String>,
+    },
+    /// After IEND, written by the trailer backend.
+    Trailer,
+}
+
+impl fmt::Display for PayloadLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PayloadLocation::Chunks {
+                chunk_type,
+                label: Some(label),
+            } => write!(f, "{chunk_type} ({label})"),
+            PayloadLocation::Chunks { chunk_type, .. } => write!(f, "{chunk_type}"),
+            PayloadLocation::Trailer => write!(f, "trailer"),
+        }
+    }
+}
+
+/// The outcome of checking one payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Integrity {
+    /// The payload matches its stored SHA-256.
+    Verified,
+    /// No SHA-256 was stored, but the payload matches its CRC-32.
+    ChecksumOnly,
+    /// The payload is damaged, for the reason given.
+    Corrupt(String),
+}
+
+impl fmt::Display for Integrity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Integrity::Verified => write!(f, "OK, SHA-256 matches"),
+            Integrity::ChecksumOnly => write!(f, "OK, CRC-32 matches but no SHA-256 was stored"),
+            Integrity::Corrupt(reason) => write!(f, "corrupt, {reason}"),
+        }
+    }
+}
+
+/// One payload and whether it survived intact.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PayloadCheck {
+    pub location: PayloadLocation,
+    pub integrity: Integrity,
+}
+
+/// Checks every enveloped payload stored in chunks or a trailer of the PNG
+/// in `bytes`, in file order. LSB payloads can't be found without their
+/// key, and payloads written before envelopes existed carry no checksum, so
+/// neither is reported.
+pub fn check_payloads(bytes: &[u8]) -> Result<Vec<PayloadCheck>> {
+    let png = Png::from_bytes_with(bytes, &ParseOptions::permissive())?;
+
+    // Group the parts of each payload, keyed by chunk type and label.
+    let mut groups: Vec<(PayloadLocation, Vec<&[u8]>)> = Vec::new();
+    for chunk in png.chunks() {
+        if KNOWN_CHUNK_TYPES.contains(&&chunk.chunk_type().bytes()) {
+            continue;
+        }
+        let (label, data) = match payload::strip_label(chunk.data()) {
+            Some((label, rest)) => (Some(label.to_string()), rest),
+            None => (None, chunk.data()),
+        };
+        let location = PayloadLocation::Chunks {
+            chunk_type: chunk.chunk_type().to_string(),
+            label,
+        };
+        match groups.iter_mut().find(|(known, _)| *known == location) {
+            Some((_, parts)) => parts.push(data),
+            None => groups.push((location, vec![data])),
+        }
+    }
+
+    let mut checks: Vec<PayloadCheck> = groups
+        .into_iter()
+        .filter_map(|(location, parts)| {
+            let integrity = match payload::reassemble(&parts) {
+                Ok(data) => check(&data)?,
+                Err(err) => Integrity::Corrupt(err.to_string()),
+            };
+            Some(PayloadCheck {
+                location,
+                integrity,
+            })
+        })
+        .collect();
+
+    let trailer = match trailer::extract(bytes) {
+        Ok(Some(data)) => check(&data),
+        Ok(None) => None,
+        Err(err) => Some(Integrity::Corrupt(err.to_string())),
+    };
+    if let Some(integrity) = trailer {
+        checks.push(PayloadCheck {
+            location: PayloadLocation::Trailer,
+            integrity,
+        });
+    }
+    Ok(checks)
+}
+
+/// Checks one reassembled payload, or returns `None` if it isn't enveloped.
+fn check(data: &[u8]) -> Option<Integrity> {
+    if !PayloadEnvelope::is_envelope(data) {
+        return None;
+    }
+    Some(match PayloadEnvelope::decode(data) {
+        Ok(envelope) if envelope.flags().contains(EnvelopeFlags::DIGESTED) => Integrity::Verified,
+        Ok(_) => Integrity::ChecksumOnly,
+        Err(err) => Integrity::Corrupt(err.to_string()),
+    })
+}
+
+/// Raised by the `verify` command when any payload is corrupt.
+#[derive(Debug, Error)]
+#[error("found {corrupt} corrupt payload(s)")]
+pub struct IntegrityError {
+    pub corrupt: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::png::minimal_png;
+
+    fn enveloped(flags: EnvelopeFlags, message: &[u8]) -> Vec<u8> {
+        PayloadEnvelope::new(flags, message.to_vec()).encode()
+    }
+
+    #[test]
+    fn test_check_payloads() {
+        let hidden = ChunkType::try_from(*b"ruSt").unwrap();
+        let mut corrupt = enveloped(EnvelopeFlags::DIGESTED, b"damaged");
+        *corrupt.last_mut().unwrap() ^= 1;
+        let mut chunks =
+            payload::split(&hidden, &enveloped(EnvelopeFlags::DIGESTED, b"intact"), 64).unwrap();
+        chunks.extend(
+            payload::split_labeled(
+                &hidden,
+                "old",
+                &enveloped(EnvelopeFlags::default(), b"crc"),
+                64,
+            )
+            .unwrap(),
+        );
+        chunks.push(Chunk::new(ChunkType::try_from(*b"coRt").unwrap(), corrupt));
+        chunks.push(Chunk::new(
+            ChunkType::try_from(*b"plAn").unwrap(),
+            b"legacy".to_vec(),
+        ));
+        let mut png = minimal_png();
+        for chunk in chunks {
+            png.append_chunk(chunk);
+        }
+        let png = png.as_bytes();
+        let bytes = trailer::embed(&png, &enveloped(EnvelopeFlags::DIGESTED, b"tail")).unwrap();
+
+        let results: Vec<(String, Integrity)> = check_payloads(&bytes)
+            .unwrap()
+            .into_iter()
+            .map(|check| (check.location.to_string(), check.integrity))
+            .collect();
+        assert_eq!(
+            results,
+            [
+                ("ruSt".to_string(), Integrity::Verified),
+                ("ruSt (old)".to_string(), Integrity::ChecksumOnly),
+                (
+                    "coRt".to_string(),
+                    Integrity::Corrupt("payload checksum mismatch".to_string())
+                ),
+                ("trailer".to_string(), Integrity::Verified),
+            ]
+        );
+    }
+}
@@ -0,0 +1,83 @@
+//! Hide messages and files in PNG images, and inspect, edit and repair the
+//! chunks around them.
+//!
+//! [`Png`] parses and serializes a file as a list of [`Chunk`]s, each
+//! labeled with a [`ChunkType`]. The other modules build on those: embedding
+//! payloads in chunks, pixels or a trailer, encrypting and signing them,
+//! editing metadata, and checking files against the specification. Every
+//! fallible operation returns a [`PngMeError`].
+
+pub mod ancillary;
+pub mod apng;
+#[cfg(feature = "tokio")]
+pub mod async_io;
+pub mod batch;
+pub mod capacity;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod chunk;
+pub mod chunk_type;
+pub mod clipboard;
+pub mod cloud;
+pub mod compression;
+pub mod config;
+pub mod copy_policy;
+pub mod crypto;
+pub mod diff;
+pub mod dump;
+pub mod envelope;
+pub mod error;
+pub mod exif;
+pub mod exit_code;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod http;
+pub mod icc;
+pub mod idat;
+pub mod ihdr;
+pub mod index;
+pub mod integrity;
+pub mod lsb;
+pub mod message;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+pub mod optimize;
+pub mod ordering;
+pub mod palette;
+pub mod payload;
+pub mod payload_source;
+pub mod pixels;
+pub mod png;
+pub mod position;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod qr;
+pub mod quality;
+pub mod reader;
+pub mod registry;
+pub mod repair;
+pub mod scan;
+pub mod secret;
+#[cfg(feature = "serde")]
+mod serde_impls;
+pub mod server;
+pub mod shamir;
+pub mod signing;
+pub mod stats;
+pub mod stdio;
+pub mod stego;
+pub mod storage;
+pub mod summary;
+pub mod text;
+pub mod trailer;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod watch;
+pub mod writer;
+
+pub use chunk::{Chunk, ChunkRef, MAX_CHUNK_LEN};
+pub use chunk_type::{ChunkType, ChunkTypeBuilder};
+pub use error::PngMeError;
+pub use png::{ChunkMut, ParseOptions, ParseReport, Png};
+
+pub type Result<T> = std::result::Result<T, PngMeError>;
@@ -0,0 +1,551 @@
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::ihdr::{ColorType, Ihdr};
+use crate::png::Png;
+use crate::Result;
+
+/// Bytes of the big-endian length written ahead of the payload bits.
+const LENGTH_PREFIX: usize = 4;
+
+/// Domain separator mixed into the seed of the position permutation.
+const POSITION_SEED_CONTEXT: &[u8] = b"pngme lsb positions";
+
+/// Most low bits [`LsbOptions::quality`] lets a single sample carry.
+pub const MAX_BITS_PER_SAMPLE: u8 = 3;
+
+/// Local variance, in squared sample levels, below which a sample counts as
+/// part of a flat region and keeps to a single bit.
+const MIN_BUSY_VARIANCE: u32 = 64;
+
+/// How [`embed`] and [`extract`] lay the payload bits out over the image.
+/// Extraction needs the same options the payload was embedded with.
+#[derive(Debug, Clone, Default)]
+pub struct LsbOptions {
+    /// Scatter the bits over the image in an order derived from this
+    /// passphrase instead of writing them in row order. Extraction needs the
+    /// same passphrase to find them. This only hides where the payload is;
+    /// encrypt it to keep its contents secret.
+    pub key: Option<String>,
+    /// Let samples in busy areas of the image carry up to
+    /// [`MAX_BITS_PER_SAMPLE`] bits, as long as the PSNR of the color samples
+    /// against the original stays at or above this many decibels even if
+    /// every changed bit flips. Without it each sample carries one bit.
+    pub quality: Option<f64>,
+}
+
+/// Hides `payload` in the low bits of each color sample, preceded by its
+/// length. Alpha samples are left alone, since changing them is visible
+/// wherever the image is transparent.
+///
+/// A 16-bit sample carries bits in its low byte. Samples are numbered in
+/// raster order, so an interlaced image is
+/// deinterlaced first and the payload lands in the same pixels it would in
+/// the same image stored without interlacing.
+#[tracing::instrument(level = "debug", skip_all, fields(len = payload.len()))]
+pub fn embed(png: &mut Png, payload: &[u8], options: &LsbOptions) -> Result<()> {
+    let ihdr = png.ihdr()?;
+    check_supported(&ihdr)?;
+    let mut raster = png.raster()?;
+    let widths = bit_widths(&ihdr, &raster.data, options)?;
+
+    let capacity = adaptive_capacity(&ihdr, &widths);
+    let needed = LENGTH_PREFIX + payload.len();
+    tracing::debug!(needed, capacity, "embedding in the pixel data");
+    if needed > capacity {
+        return Err(LsbError::PayloadTooLarge { needed, capacity }.into());
+    }
+
+    let length =
+        u32::try_from(payload.len()).map_err(|_| LsbError::PayloadTooLarge { needed, capacity })?;
+    let bytes = length
+        .to_be_bytes()
+        .into_iter()
+        .chain(payload.iter().copied());
+    let mut bits = bytes
+        .flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1))
+        .peekable();
+    for sample in positions(&ihdr, options) {
+        if bits.peek().is_none() {
+            break;
+        }
+        let byte = &mut raster.data[sample];
+        for i in (0..widths[sample]).rev() {
+            if let Some(bit) = bits.next() {
+                *byte = (*byte & !(1 << i)) | (bit << i);
+            }
+        }
+    }
+    png.set_raster(&raster)
+}
+
+/// Reads a payload written by [`embed`], or returns `None` if the stored
+/// length can't be right, meaning nothing was embedded or `options` differ
+/// from the ones it was embedded with.
+#[tracing::instrument(level = "debug", skip_all)]
+pub fn extract(png: &Png, options: &LsbOptions) -> Result<Option<Vec<u8>>> {
+    let ihdr = png.ihdr()?;
+    check_supported(&ihdr)?;
+    let raster = png.raster()?;
+    let widths = bit_widths(&ihdr, &raster.data, options)?;
+
+    let mut bits = positions(&ihdr, options).flat_map(|sample| {
+        let byte = raster.data[sample];
+        (0..widths[sample]).rev().map(move |i| (byte >> i) & 1)
+    });
+    let mut next_byte = || (0..8).try_fold(0u8, |byte, _| bits.next().map(|bit| byte << 1 | bit));
+    let mut length = [0; LENGTH_PREFIX];
+    for byte in &mut length {
+        *byte = next_byte().ok_or(LsbError::TooSmall)?;
+    }
+    let length = u32::from_be_bytes(length) as usize;
+    if LENGTH_PREFIX + length > adaptive_capacity(&ihdr, &widths) {
+        tracing::debug!(length, "stored length exceeds the capacity, no payload");
+        return Ok(None);
+    }
+    (0..length)
+        .map(|_| next_byte().ok_or_else(|| LsbError::TooSmall.into()))
+        .collect::<Result<Vec<u8>>>()
+        .map(Some)
+}
+
+/// Bytes of payload, including the length prefix, that the image can hold.
+pub fn capacity(ihdr: &Ihdr) -> usize {
+    sample_indices(ihdr).count() / 8
+}
+
+/// Like [`capacity`], for samples carrying `widths` bits each.
+fn adaptive_capacity(ihdr: &Ihdr, widths: &[u8]) -> usize {
+    sample_indices(ihdr)
+        .map(|sample| widths[sample] as usize)
+        .sum::<usize>()
+        / 8
+}
+
+/// Bytes of payload [`embed`] accepts for an image, failing if the image
+/// format is not supported.
+pub fn payload_capacity(ihdr: &Ihdr) -> Result<usize> {
+    check_supported(ihdr)?;
+    Ok(capacity(ihdr).saturating_sub(LENGTH_PREFIX))
+}
+
+/// Indexes into the unfiltered, deinterlaced image data of the byte that
+/// carries bits in every color sample.
+pub(crate) fn sample_indices(ihdr: &Ihdr) -> impl Iterator<Item = usize> {
+    let channels = ihdr.color_type.channels() as usize;
+    let has_alpha = matches!(ihdr.color_type, ColorType::Rgba | ColorType::GrayscaleAlpha);
+    let samples = ihdr.width as usize * ihdr.height as usize * channels;
+    let bytes = sample_bytes(ihdr);
+    (0..samples)
+        .filter(move |i| !has_alpha || i % channels != channels - 1)
+        .map(move |sample| carrier(sample, bytes))
+}
+
+/// Bytes in one sample of a supported image.
+fn sample_bytes(ihdr: &Ihdr) -> usize {
+    ihdr.bit_depth as usize / 8
+}
+
+/// The index of the low byte of `sample`, which is the one bits go in.
+fn carrier(sample: usize, bytes: usize) -> usize {
+    sample * bytes + bytes - 1
+}
+
+/// How many low bits each sample of the unfiltered image `data` carries.
+///
+/// Only the bits above [`MAX_BITS_PER_SAMPLE`] are looked at, since embedding
+/// never changes them, so extraction arrives at the same widths. Samples are
+/// widened busiest first while the worst-case squared error, `(2^bits - 1)^2`
+/// per sample, stays within what the quality target allows.
+fn bit_widths(ihdr: &Ihdr, data: &[u8], options: &LsbOptions) -> Result<Vec<u8>> {
+    let mut widths = vec![1; data.len()];
+    let Some(quality) = options.quality else {
+        return Ok(widths);
+    };
+
+    let colors: Vec<usize> = sample_indices(ihdr).collect();
+    let peak = 2f64.powi(ihdr.bit_depth.into()) - 1.0;
+    let budget = colors.len() as f64 * peak.powi(2) / 10f64.powf(quality / 10.0);
+    // Every color sample already costs 1 for the bit it always carries.
+    let mut spare = budget - colors.len() as f64;
+    if spare.is_nan() || spare < 0.0 {
+        return Err(LsbError::QualityUnreachable(quality).into());
+    }
+
+    let variance = local_variance(ihdr, data);
+    let mut busy: Vec<usize> = colors
+        .into_iter()
+        .filter(|&sample| variance[sample] >= MIN_BUSY_VARIANCE)
+        .collect();
+    busy.sort_by(|&a, &b| variance[b].cmp(&variance[a]).then(a.cmp(&b)));
+    for sample in busy {
+        let extra = |bits: u8| f64::from((1u32 << bits) - 1).powi(2) - 1.0;
+        match (2..=MAX_BITS_PER_SAMPLE)
+            .rev()
+            .find(|&bits| extra(bits) <= spare)
+        {
+            Some(bits) => {
+                spare -= extra(bits);
+                widths[sample] = bits;
+            }
+            None => break,
+        }
+    }
+    Ok(widths)
+}
+
+/// The variance of each sample's 3x3 neighborhood in its own channel, with
+/// the low [`MAX_BITS_PER_SAMPLE`] bits masked off, indexed like the data.
+/// Only the byte carrying bits is looked at, so for 16-bit samples this
+/// measures the fine detail the low byte holds.
+fn local_variance(ihdr: &Ihdr, data: &[u8]) -> Vec<u32> {
+    let channels = ihdr.color_type.channels() as usize;
+    let (width, height) = (ihdr.width as usize, ihdr.height as usize);
+    let stride = width * channels;
+    let bytes = sample_bytes(ihdr);
+    let mut variance = vec![0; data.len()];
+    for y in 0..height {
+        for x in 0..width {
+            for channel in 0..channels {
+                let (mut n, mut sum, mut squares) = (0u32, 0u32, 0u32);
+                for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                    for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                        let sample = ny * stride + nx * channels + channel;
+                        let level = u32::from(data[carrier(sample, bytes)]) >> MAX_BITS_PER_SAMPLE;
+                        n += 1;
+                        sum += level;
+                        squares += level * level;
+                    }
+                }
+                let scale = 1 << (2 * MAX_BITS_PER_SAMPLE);
+                variance[carrier(y * stride + x * channels + channel, bytes)] =
+                    scale * (n * squares - sum * sum) / (n * n);
+            }
+        }
+    }
+    variance
+}
+
+/// The order in which payload bits are written to the samples.
+fn positions<'a>(ihdr: &Ihdr, options: &'a LsbOptions) -> Box<dyn Iterator<Item = usize> + 'a> {
+    match &options.key {
+        Some(key) => Box::new(Shuffled {
+            indices: sample_indices(ihdr).collect(),
+            next: 0,
+            rng: KeyedRng::new(key),
+        }),
+        None => Box::new(sample_indices(ihdr)),
+    }
+}
+
+/// A Fisher-Yates shuffle run one step per item, so reading the first few
+/// positions doesn't pay for shuffling the whole image.
+struct Shuffled {
+    indices: Vec<usize>,
+    next: usize,
+    rng: KeyedRng,
+}
+
+impl Iterator for Shuffled {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let remaining = self.indices.len() - self.next;
+        if remaining == 0 {
+            return None;
+        }
+        let pick = self.next + self.rng.below(remaining as u64) as usize;
+        self.indices.swap(self.next, pick);
+        self.next += 1;
+        Some(self.indices[self.next - 1])
+    }
+}
+
+/// A deterministic stream of random numbers: SHA-256 in counter mode over a
+/// seed hashed from the passphrase.
+struct KeyedRng {
+    seed: [u8; 32],
+    counter: u64,
+    block: Vec<u8>,
+}
+
+impl KeyedRng {
+    fn new(key: &str) -> KeyedRng {
+        let mut seed = [0; 32];
+        seed.copy_from_slice(
+            &Sha256::new()
+                .chain_update(POSITION_SEED_CONTEXT)
+                .chain_update(key.as_bytes())
+                .finalize(),
+        );
+        KeyedRng {
+            seed,
+            counter: 0,
+            block: Vec::new(),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        if self.block.is_empty() {
+            self.block = Sha256::new()
+                .chain_update(self.seed)
+                .chain_update(self.counter.to_be_bytes())
+                .finalize()
+                .to_vec();
+            self.counter += 1;
+        }
+        let bytes = self.block.split_off(self.block.len() - 8);
+        u64::from_be_bytes(bytes.try_into().expect("split off 8 bytes"))
+    }
+
+    /// A uniform number in `0..n`, rejecting draws from the uneven tail of
+    /// the `u64` range so no result is favored.
+    fn below(&mut self, n: u64) -> u64 {
+        let zone = u64::MAX - u64::MAX % n;
+        loop {
+            let draw = self.next_u64();
+            if draw < zone {
+                return draw % n;
+            }
+        }
+    }
+}
+
+/// Samples need at least a byte to themselves, and flipping bits of palette
+/// indices would swap in unrelated colors.
+fn check_supported(ihdr: &Ihdr) -> Result<()> {
+    let supported = matches!(ihdr.bit_depth, 8 | 16) && ihdr.color_type != ColorType::Indexed;
+    if !supported {
+        return Err(LsbError::UnsupportedImage(*ihdr).into());
+    }
+    Ok(())
+}
+
+/// Errors raised by the LSB backend.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum LsbError {
+    /// The image format is not one the backend can write to.
+    #[error("LSB embedding needs an 8- or 16-bit grayscale or truecolor image, not {0}")]
+    UnsupportedImage(Ihdr),
+    /// The payload needs more sample bits than the image has.
+    #[error("payload needs {needed} bytes but the image can only hide {capacity}")]
+    PayloadTooLarge { needed: usize, capacity: usize },
+    /// The image is too small to hold even the length prefix.
+    #[error("image is too small to hold a payload")]
+    TooSmall,
+    /// Labels only apply to chunk payloads; an image holds one LSB payload.
+    #[error("labels cannot be used with LSB embedding")]
+    Labeled,
+    /// Even one bit per sample could lower the PSNR below this target.
+    #[error("a quality of {0} dB is out of reach; LSB embedding can only promise about 48 dB")]
+    QualityUnreachable(f64),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::ihdr::Interlace;
+    use crate::PngMeError;
+
+    fn image(width: u32, height: u32, color_type: ColorType) -> Png {
+        let ihdr = Ihdr::new(width, height, 8, color_type).unwrap();
+        let stride = width as usize * color_type.channels() as usize;
+        let filtered: Vec<u8> = (0..height as usize)
+            .flat_map(|row| {
+                let filter = (row % 5) as u8;
+                std::iter::once(filter).chain((0..stride).map(move |i| (row * 7 + i * 13) as u8))
+            })
+            .collect();
+        let mut png = Png::from_chunks(vec![
+            ihdr.to_chunk(),
+            Chunk::new(ChunkType::try_from(*b"IDAT").unwrap(), Vec::new()),
+            Chunk::new(ChunkType::try_from(*b"IEND").unwrap(), Vec::new()),
+        ]);
+        png.set_image_data(&filtered).unwrap();
+        png
+    }
+
+    #[test]
+    fn test_lsb_round_trip() {
+        let mut png = image(16, 16, ColorType::Rgba);
+        let before = png.scanlines().unwrap();
+        let options = LsbOptions::default();
+        embed(&mut png, b"hidden in plain sight", &options).unwrap();
+        assert_eq!(
+            extract(&png, &options).unwrap().unwrap(),
+            b"hidden in plain sight"
+        );
+
+        let after = png.scanlines().unwrap();
+        assert_eq!(after.filters, before.filters);
+        for (i, (old, new)) in before.data.iter().zip(&after.data).enumerate() {
+            assert!(old ^ new <= 1);
+            if i % 4 == 3 {
+                assert_eq!(old, new, "alpha sample {i} changed");
+            }
+        }
+    }
+
+    #[test]
+    fn test_interlaced_round_trip() {
+        let rgb: Vec<u8> = (0..13 * 11 * 3).map(|i| (i * 29 % 253) as u8).collect();
+        let plain = Png::from_pixels(13, 11, ColorType::Rgb, &rgb).unwrap();
+        let interlaced =
+            Png::from_pixels_with(13, 11, ColorType::Rgb, &rgb, Interlace::Adam7).unwrap();
+        let options = LsbOptions::default();
+        let mut embedded = Vec::new();
+        for mut png in [plain, interlaced] {
+            embed(&mut png, b"seven passes", &options).unwrap();
+            assert_eq!(extract(&png, &options).unwrap().unwrap(), b"seven passes");
+            embedded.push(png.decode_pixels().unwrap());
+        }
+        assert_eq!(embedded[0], embedded[1]);
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut png = image(4, 4, ColorType::Rgb);
+        let ihdr = png.ihdr().unwrap();
+        assert_eq!(capacity(&ihdr), 4 * 4 * 3 / 8);
+        let options = LsbOptions::default();
+        assert!(embed(&mut png, &[0; 3], &options).is_err());
+        embed(&mut png, &[0; 2], &options).unwrap();
+    }
+
+    #[test]
+    fn test_unsupported_format() {
+        let mut png = image(4, 4, ColorType::Indexed);
+        assert!(embed(&mut png, b"x", &LsbOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_color_types() {
+        let options = LsbOptions::default();
+        for (color_type, samples) in [(ColorType::Grayscale, 1), (ColorType::GrayscaleAlpha, 1)] {
+            let mut png = image(8, 8, color_type);
+            let ihdr = png.ihdr().unwrap();
+            assert_eq!(capacity(&ihdr), 8 * 8 * samples / 8);
+            embed(&mut png, b"gray", &options).unwrap();
+            assert_eq!(extract(&png, &options).unwrap().unwrap(), b"gray");
+        }
+    }
+
+    #[test]
+    fn test_sixteen_bit() {
+        let ihdr = Ihdr::new(8, 8, 16, ColorType::GrayscaleAlpha).unwrap();
+        let filtered: Vec<u8> = (0..8)
+            .flat_map(|row: u8| std::iter::once(0).chain((0..32).map(move |i| row * 32 + i)))
+            .collect();
+        let mut png = Png::from_chunks(vec![
+            ihdr.to_chunk(),
+            Chunk::new(ChunkType::IDAT, Vec::new()),
+            Chunk::new(ChunkType::IEND, Vec::new()),
+        ]);
+        png.set_image_data(&filtered).unwrap();
+        assert_eq!(capacity(&ihdr), 8 * 8 / 8);
+
+        let before = png.scanlines().unwrap();
+        let options = LsbOptions::default();
+        embed(&mut png, b"deep", &options).unwrap();
+        assert_eq!(extract(&png, &options).unwrap().unwrap(), b"deep");
+        let after = png.scanlines().unwrap();
+        for (i, (old, new)) in before.data.iter().zip(&after.data).enumerate() {
+            // Only the low byte of each gray sample may change.
+            if i % 4 != 1 {
+                assert_eq!(old, new, "byte {i} changed");
+            }
+        }
+    }
+
+    #[test]
+    fn test_keyed_positions() {
+        let keyed = LsbOptions {
+            key: Some("correct horse".to_string()),
+            ..LsbOptions::default()
+        };
+        let ihdr = Ihdr::new(8, 8, 8, ColorType::Rgb).unwrap();
+        let mut order: Vec<usize> = positions(&ihdr, &keyed).collect();
+        assert_ne!(order, sample_indices(&ihdr).collect::<Vec<_>>());
+        assert_eq!(order, positions(&ihdr, &keyed).collect::<Vec<_>>());
+        order.sort_unstable();
+        assert_eq!(order, sample_indices(&ihdr).collect::<Vec<_>>());
+
+        let mut png = image(16, 16, ColorType::Rgb);
+        embed(&mut png, b"scattered", &keyed).unwrap();
+        assert_eq!(extract(&png, &keyed).unwrap().unwrap(), b"scattered");
+        let other = LsbOptions {
+            key: Some("battery staple".to_string()),
+            ..LsbOptions::default()
+        };
+        assert_ne!(
+            extract(&png, &other).unwrap().as_deref(),
+            Some(&b"scattered"[..])
+        );
+    }
+
+    fn psnr(original: &[u8], modified: &[u8]) -> f64 {
+        let squares: f64 = original
+            .iter()
+            .zip(modified)
+            .map(|(&a, &b)| (f64::from(a) - f64::from(b)).powi(2))
+            .sum();
+        10.0 * (255f64.powi(2) * original.len() as f64 / squares).log10()
+    }
+
+    #[test]
+    fn test_adaptive_quality() {
+        // Noise on the left half, a flat gray on the right.
+        let mut png = image(16, 16, ColorType::Rgb);
+        let mut before = png.scanlines().unwrap();
+        for (i, sample) in before.data.iter_mut().enumerate() {
+            let (row, column) = (i / 48, i % 48);
+            *sample = match column < 24 {
+                true => (row * 151 + column * 97 + row * column * 13) as u8,
+                false => 128,
+            };
+        }
+        png.set_scanlines(&before).unwrap();
+        let adaptive = LsbOptions {
+            quality: Some(40.0),
+            ..LsbOptions::default()
+        };
+        let widths = bit_widths(&png.ihdr().unwrap(), &before.data, &adaptive).unwrap();
+        assert!(widths.iter().any(|&bits| bits > 1));
+        let payload = vec![0xa5; adaptive_capacity(&png.ihdr().unwrap(), &widths) - 4];
+        assert!(payload.len() > capacity(&png.ihdr().unwrap()) - 4);
+
+        embed(&mut png, &payload, &adaptive).unwrap();
+        assert_eq!(extract(&png, &adaptive).unwrap().unwrap(), payload);
+        let after = png.scanlines().unwrap();
+        assert!(psnr(&before.data, &after.data) >= 40.0);
+        for (i, (old, new)) in before.data.iter().zip(&after.data).enumerate() {
+            // Pixels from x = 9 on have no noisy neighbors.
+            if i % 48 >= 27 {
+                assert!(old ^ new <= 1, "flat sample {i} lost more than one bit");
+            }
+        }
+        assert_ne!(
+            extract(&png, &LsbOptions::default()).unwrap().as_deref(),
+            Some(&payload[..])
+        );
+    }
+
+    #[test]
+    fn test_unreachable_quality() {
+        let mut png = image(4, 4, ColorType::Rgb);
+        let options = LsbOptions {
+            quality: Some(60.0),
+            ..LsbOptions::default()
+        };
+        let err = embed(&mut png, b"x", &options).unwrap_err();
+        assert!(matches!(
+            err,
+            PngMeError::Lsb(LsbError::QualityUnreachable(_))
+        ));
+    }
+}
@@ -1,7 +1,90 @@
-mod chunk_type;
-mod chunk;
-mod png;
+use std::process;
 
-fn main() {
-    println!("Hello, world!");
+use clap::Parser;
+use tracing::Level;
+
+use congenial_chainsaw::config::Config;
+use congenial_chainsaw::exit_code::ExitCode;
+use congenial_chainsaw::stdio::WriteOptions;
+
+use args::{Cli, Command};
+
+mod args;
+mod commands;
+mod progress;
+
+fn main() -> process::ExitCode {
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(err) if err.use_stderr() => {
+            let _ = err.print();
+            return ExitCode::Usage.into();
+        }
+        // --help and --version.
+        Err(err) => err.exit(),
+    };
+    let error_format = cli.error_format;
+    match run(cli) {
+        Ok(()) => ExitCode::Success.into(),
+        Err(err) => {
+            commands::report_error(&err, error_format);
+            err.exit_code().into()
+        }
+    }
+}
+
+fn run(mut cli: Cli) -> congenial_chainsaw::Result<()> {
+    init_logging(cli.verbose);
+    // Log lines would tear through a progress bar redrawing the same line.
+    progress::init(cli.quiet || cli.verbose > 0);
+    cli.command
+        .apply_config(&Config::load(cli.config.as_deref())?);
+    let write_options = WriteOptions {
+        backup_suffix: cli.backup.map(Into::into),
+    };
+
+    match cli.command {
+        Command::Encode(args) => commands::encode(args, &write_options),
+        Command::EncodeFile(args) => commands::encode_file(args, &write_options),
+        Command::Decode(args) => commands::decode(args, &write_options),
+        Command::ExtractFile(args) => commands::extract_file(args, &write_options),
+        Command::Remove(args) => commands::remove(args, &write_options),
+        Command::ListPayloads(args) => commands::list_payloads(args),
+        Command::Print(args) => commands::print_chunks(args),
+        Command::Dump(args) => commands::dump(args),
+        Command::Diff(args) => commands::diff(args),
+        Command::Compare(args) => commands::compare(args),
+        Command::Capacity(args) => commands::capacity(args),
+        Command::Analyze(args) => commands::analyze(args),
+        Command::Watch(args) => commands::watch(args, &write_options),
+        Command::Scan(args) => commands::scan(args),
+        Command::Stats(args) => commands::stats(args),
+        Command::Lint(args) => commands::lint(args),
+        Command::Repair(args) => commands::repair(args, &write_options),
+        Command::Scrub(args) => commands::scrub(args, &write_options),
+        Command::Optimize(args) => commands::optimize(args, &write_options),
+        Command::Keygen(args) => commands::keygen(args),
+        Command::Sign(args) => commands::sign(args, &write_options),
+        Command::Verify(args) => commands::verify(args),
+        Command::Meta(args) => commands::meta(args, &write_options),
+        Command::Icc(args) => commands::icc(args, &write_options),
+        Command::Explain(args) => commands::explain(args),
+        Command::SuggestType => commands::suggest_type(),
+        Command::Serve(args) => commands::serve(args),
+        Command::Completions(args) => commands::completions(args),
+    }
+}
+
+/// Sends log events at the level picked by -v to standard error, which
+/// keeps them out of decoded messages and PNGs written to standard output.
+fn init_logging(verbose: u8) {
+    let level = match verbose {
+        0 => Level::WARN,
+        1 => Level::DEBUG,
+        _ => Level::TRACE,
+    };
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(std::io::stderr)
+        .init();
 }
@@ -0,0 +1,166 @@
+//! Hiding and revealing plain messages in PNGs held in memory, the way the
+//! `encode` and `decode` commands do with their default options. The
+//! language bindings are built on these, so either side can read what the
+//! other wrote.
+
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use crate::apng::{self, AnimationError};
+use crate::chunk_type::ChunkType;
+use crate::envelope::{EnvelopeError, EnvelopeFlags, PayloadEnvelope};
+use crate::payload::{self, Payload, PayloadError, MAX_CHUNK_DATA};
+use crate::png::{ParseOptions, Png};
+use crate::storage::{ChunkSink, ChunkSource};
+use crate::writer::PngWriter;
+use crate::{PngMeError, Result};
+
+/// Hides `message` in the PNG `bytes` in chunks of type `chunk_type`,
+/// returning the new file.
+pub fn embed(bytes: &[u8], chunk_type: &str, message: &[u8]) -> Result<Vec<u8>> {
+    let mut writer = PngWriter::new(Vec::new())?;
+    embed_chunks(
+        Png::chunk_refs(bytes, &ParseOptions::default())?,
+        &mut writer,
+        chunk_type,
+        message,
+    )?;
+    writer.finish()
+}
+
+/// Like [`embed`], copying the chunks of `source` to `sink` with the
+/// payload chunks inserted before IEND, or after the last chunk if there is
+/// no IEND.
+pub fn embed_chunks<S: ChunkSource, K: ChunkSink>(
+    mut source: S,
+    mut sink: K,
+    chunk_type: &str,
+    message: &[u8],
+) -> Result<()> {
+    let chunk_type = ChunkType::from_str(chunk_type)?;
+    if apng::is_animation_chunk_type(&chunk_type) {
+        return Err(AnimationError::ReservedChunkType(chunk_type.to_string()).into());
+    }
+    let data = PayloadEnvelope::new(EnvelopeFlags::default(), message.to_vec()).encode();
+    let mut payload = Some(payload::split(&chunk_type, &data, MAX_CHUNK_DATA)?);
+    while let Some(chunk) = source.next_chunk()? {
+        if *chunk.chunk_type() == ChunkType::IEND {
+            for part in payload.take().into_iter().flatten() {
+                sink.write_chunk(&part)?;
+            }
+        }
+        sink.write_chunk(&chunk)?;
+    }
+    for part in payload.into_iter().flatten() {
+        sink.write_chunk(&part)?;
+    }
+    sink.flush()
+}
+
+/// Reveals the message hidden in the PNG `bytes` in chunks of type
+/// `chunk_type`.
+///
+/// Encrypted and authenticated payloads need keys, and embedded files an
+/// output directory, so those are refused rather than returned as noise.
+/// Expired payloads are refused too, and so are those allowing a limited
+/// number of reads, which can't be counted without the file to write to.
+pub fn extract(bytes: &[u8], chunk_type: &str) -> Result<Vec<u8>> {
+    extract_with(bytes, chunk_type, &ParseOptions::default())
+}
+
+/// Like [`extract`], parsing `bytes` with `options` and refusing a message
+/// that decompresses to more than [`ParseOptions::max_payload_size`].
+pub fn extract_with(bytes: &[u8], chunk_type: &str, options: &ParseOptions) -> Result<Vec<u8>> {
+    read_message(
+        Png::chunk_refs(bytes, options)?,
+        chunk_type,
+        options.max_payload_size,
+    )
+}
+
+/// Like [`extract`], reading the chunks from `source`.
+pub fn extract_chunks<S: ChunkSource>(source: S, chunk_type: &str) -> Result<Vec<u8>> {
+    read_message(source, chunk_type, ParseOptions::default().max_payload_size)
+}
+
+fn read_message<S: ChunkSource>(mut source: S, chunk_type: &str, limit: u64) -> Result<Vec<u8>> {
+    let mut parts = Vec::new();
+    while let Some(chunk) = source.next_chunk()? {
+        if chunk.chunk_type().to_string() == chunk_type
+            && payload::strip_label(chunk.data()).is_none()
+        {
+            parts.push(chunk.data().to_vec());
+        }
+    }
+    let parts: Vec<&[u8]> = parts.iter().map(Vec::as_slice).collect();
+    if parts.is_empty() {
+        return Err(PngMeError::ChunkNotFound(chunk_type.to_string()));
+    }
+
+    let data = payload::reassemble(&parts)?;
+    let envelope = match PayloadEnvelope::is_envelope(&data) {
+        true => PayloadEnvelope::decode(&data)?,
+        false => PayloadEnvelope::unwrapped(data),
+    };
+    if envelope.flags().contains(EnvelopeFlags::ENCRYPTED) {
+        return Err(EnvelopeError::Encrypted.into());
+    }
+    if envelope.flags().contains(EnvelopeFlags::AUTHENTICATED) {
+        return Err(EnvelopeError::Authenticated.into());
+    }
+    if let Some(expiry) = envelope.expiry() {
+        if expiry.is_expired(SystemTime::now()) {
+            return Err(EnvelopeError::Expired.into());
+        }
+        if expiry.reads_left.is_some() {
+            let source = "a PNG held in memory".to_string();
+            return Err(EnvelopeError::UncountedRead(source).into());
+        }
+    }
+    match envelope.open(envelope.payload(), limit)? {
+        Payload::Message(message) => Ok(message),
+        Payload::File(file) => Err(PayloadError::NotAMessage(file.name).into()),
+        // Shares come out as the bytes `shamir::combine` reads back.
+        Payload::Share(share) => Ok(share.to_bytes()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::minimal_png;
+    use std::collections::VecDeque;
+
+    #[test]
+    fn test_round_trip() {
+        let bytes = embed(&minimal_png().as_bytes(), "ruSt", b"hello from the browser").unwrap();
+        assert_eq!(extract(&bytes, "ruSt").unwrap(), b"hello from the browser");
+    }
+
+    #[test]
+    fn test_chunk_round_trip() {
+        let source =
+            Png::from_bytes_with(&minimal_png().as_bytes(), &ParseOptions::default()).unwrap();
+        let mut chunks = Vec::new();
+        embed_chunks(
+            VecDeque::from(source.chunks().to_vec()),
+            &mut chunks,
+            "ruSt",
+            b"hello",
+        )
+        .unwrap();
+        assert_eq!(chunks.last().unwrap().chunk_type(), &ChunkType::IEND);
+        assert_eq!(
+            extract_chunks(VecDeque::from(chunks), "ruSt").unwrap(),
+            b"hello"
+        );
+    }
+
+    #[test]
+    fn test_missing_chunk() {
+        assert!(matches!(
+            extract(&minimal_png().as_bytes(), "ruSt"),
+            Err(PngMeError::ChunkNotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,110 @@
+//! Reading PNG files through a memory map, built with the `mmap` feature.
+//!
+//! Chunk data is borrowed from the map rather than copied, so locating the
+//! chunks of a multi-gigabyte file costs little more than reading their
+//! headers. With CRC checks off, only the headers are touched at all.
+
+use std::fs::File;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::chunk::{Chunk, ChunkRef};
+use crate::png::{ParseOptions, Png};
+use crate::Result;
+
+impl Png {
+    /// Maps the file at `path` and locates its chunks, checking them as the
+    /// default [`ParseOptions`] require.
+    pub fn open_mmap<P: AsRef<Path>>(path: P) -> Result<MappedPng> {
+        MappedPng::open_with(path, &ParseOptions::default())
+    }
+}
+
+/// A PNG file mapped into memory, with its chunks located but not copied.
+///
+/// The map reflects later changes to the file, so files that another process
+/// may rewrite or truncate while they are open should be read with
+/// [`Png::from_file`] instead.
+#[derive(Debug)]
+pub struct MappedPng {
+    map: Mmap,
+    /// Where each chunk's length field is, and how long its data is.
+    chunks: Vec<(usize, usize)>,
+}
+
+impl MappedPng {
+    /// Like [`Png::open_mmap`], checking the file as `options` require.
+    pub fn open_with<P: AsRef<Path>>(path: P, options: &ParseOptions) -> Result<MappedPng> {
+        let file = File::open(path)?;
+        // SAFETY: the map is only ever read, and the type's documentation
+        // warns against mapping files that may change underneath it.
+        let map = unsafe { Mmap::map(&file)? };
+        let chunks = Png::chunk_refs(&map, options)?
+            .map(|chunk| {
+                chunk.map(|chunk| {
+                    let offset = chunk.offset().expect("parsed chunks have offsets");
+                    (offset as usize, chunk.data().len())
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(MappedPng { map, chunks })
+    }
+
+    pub fn chunks(&self) -> impl ExactSizeIterator<Item = ChunkRef<'_>> {
+        self.chunks.iter().map(|&(offset, length)| {
+            // Already checked when the file was opened.
+            ChunkRef::from_bytes_with(
+                &self.map[offset..offset + length + Chunk::OVERHEAD],
+                &ParseOptions::permissive(),
+            )
+            .expect("chunk was parsed when the file was opened")
+            .at_offset(offset as u64)
+        })
+    }
+
+    pub fn chunk_by_type(&self, chunk_type: &str) -> Option<ChunkRef<'_>> {
+        self.chunks()
+            .find(|chunk| chunk.chunk_type().to_string() == chunk_type)
+    }
+
+    /// The whole file, trailing bytes included.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.map
+    }
+
+    /// Copies every chunk into an owned [`Png`], for editing.
+    pub fn to_png(&self) -> Png {
+        Png::from_chunks(self.chunks().map(Chunk::from).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    use crate::chunk_type::ChunkType;
+    use crate::png::minimal_png;
+
+    #[test]
+    fn test_matches_owned_parse() {
+        let mut png = minimal_png();
+        png.append_chunk(Chunk::new(
+            ChunkType::new_unchecked(*b"ruSt"),
+            b"mapped".to_vec(),
+        ));
+        let path = std::env::temp_dir().join(format!("pngme-mmap-{}.png", std::process::id()));
+        fs::write(&path, png.as_bytes()).unwrap();
+
+        let mapped = Png::open_mmap(&path).unwrap();
+        let parsed = Png::from_file(&path).unwrap();
+        assert_eq!(mapped.chunks().len(), 3);
+        assert_eq!(mapped.chunk_by_type("ruSt").unwrap().data(), b"mapped");
+        for (mapped, parsed) in mapped.chunks().zip(parsed.chunks()) {
+            assert_eq!(mapped.offset(), parsed.offset());
+            assert_eq!(mapped.to_chunk(), *parsed);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
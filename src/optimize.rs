@@ -0,0 +1,207 @@
+use std::fmt;
+
+use crate::chunk::{Chunk, MAX_CHUNK_LEN};
+use crate::chunk_type::ChunkType;
+use crate::idat::Recompression;
+use crate::ordering::SINGLE;
+use crate::png::Png;
+use crate::trailer;
+use crate::Result;
+
+/// What [`Png::optimize`] does. None of it changes the decoded image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OptimizeOptions {
+    /// Merge each run of consecutive IDAT chunks into one.
+    pub merge_idat: bool,
+    /// Drop repeats of ancillary chunk types the specification allows only
+    /// once, keeping the first, and exact copies of any other ancillary chunk.
+    pub drop_duplicates: bool,
+    /// Recompress the image data, keeping the result only if it is smaller.
+    pub recompress: Option<Recompression>,
+}
+
+impl Default for OptimizeOptions {
+    fn default() -> Self {
+        OptimizeOptions {
+            merge_idat: true,
+            drop_duplicates: true,
+            recompress: None,
+        }
+    }
+}
+
+/// What [`Png::optimize`] changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OptimizeReport {
+    /// Encoded size before and after, in bytes.
+    pub before: u64,
+    pub after: u64,
+    /// IDAT chunks removed by merging them into their neighbours.
+    pub merged_idat: usize,
+    /// Types of the duplicate chunks dropped, in file order.
+    pub dropped: Vec<ChunkType>,
+    /// Bytes saved by recompressing the image data.
+    pub recompressed: u64,
+}
+
+impl OptimizeReport {
+    pub fn saved(&self) -> u64 {
+        self.before.saturating_sub(self.after)
+    }
+}
+
+impl fmt::Display for OptimizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} -> {} bytes, saved {}",
+            self.before,
+            self.after,
+            self.saved()
+        )?;
+        if self.merged_idat > 0 {
+            write!(f, ", merged {} IDAT chunks", self.merged_idat)?;
+        }
+        if !self.dropped.is_empty() {
+            write!(f, ", dropped {} duplicate chunks", self.dropped.len())?;
+        }
+        if self.recompressed > 0 {
+            write!(f, ", recompression saved {}", self.recompressed)?;
+        }
+        Ok(())
+    }
+}
+
+impl Png {
+    /// Shrinks the file without changing the image, as `options` allow.
+    pub fn optimize(&mut self, options: &OptimizeOptions) -> Result<OptimizeReport> {
+        let mut report = OptimizeReport {
+            before: trailer::encoded_len(self) as u64,
+            ..OptimizeReport::default()
+        };
+        if options.drop_duplicates {
+            report.dropped = self.drop_duplicates();
+        }
+        if let Some(recompression) = options.recompress {
+            report.recompressed = self.recompress_idat(recompression)?;
+        }
+        if options.merge_idat {
+            report.merged_idat = self.merge_idat();
+        }
+        report.after = trailer::encoded_len(self) as u64;
+        Ok(report)
+    }
+
+    fn drop_duplicates(&mut self) -> Vec<ChunkType> {
+        let duplicate: Vec<bool> = {
+            let mut seen: Vec<(ChunkType, Option<&[u8]>)> = Vec::new();
+            self.chunks()
+                .iter()
+                .map(|chunk| {
+                    let chunk_type = *chunk.chunk_type();
+                    if chunk_type.is_critical() {
+                        return false;
+                    }
+                    // Single chunks repeat by type, others only by content.
+                    let key = match SINGLE.contains(&&chunk_type.bytes()) {
+                        true => (chunk_type, None),
+                        false => (chunk_type, Some(chunk.data())),
+                    };
+                    let repeat = seen.contains(&key);
+                    if !repeat {
+                        seen.push(key);
+                    }
+                    repeat
+                })
+                .collect()
+        };
+
+        let mut dropped = Vec::new();
+        let mut duplicate = duplicate.into_iter();
+        self.chunks_mut().retain(|chunk| {
+            let drop = duplicate.next().unwrap_or_default();
+            if drop {
+                dropped.push(*chunk.chunk_type());
+            }
+            !drop
+        });
+        dropped
+    }
+
+    /// Merges each run of IDAT chunks into as few chunks as
+    /// [`MAX_CHUNK_LEN`] allows, returning how many chunks went away.
+    fn merge_idat(&mut self) -> usize {
+        let chunks = std::mem::take(self.chunks_mut());
+        let before = chunks.len();
+        let mut merged: Vec<Chunk> = Vec::with_capacity(before);
+        let mut run: Vec<u8> = Vec::new();
+        let mut in_run = false;
+        for chunk in chunks {
+            if *chunk.chunk_type() == ChunkType::IDAT {
+                run.extend_from_slice(chunk.data());
+                in_run = true;
+                continue;
+            }
+            if in_run {
+                flush_idat(&mut merged, &mut run);
+                in_run = false;
+            }
+            merged.push(chunk);
+        }
+        if in_run {
+            flush_idat(&mut merged, &mut run);
+        }
+        let removed = before - merged.len();
+        *self.chunks_mut() = merged;
+        removed
+    }
+}
+
+fn flush_idat(chunks: &mut Vec<Chunk>, run: &mut Vec<u8>) {
+    if run.is_empty() {
+        chunks.push(Chunk::new(ChunkType::IDAT, Vec::new()));
+    }
+    for part in run.chunks(MAX_CHUNK_LEN as usize) {
+        chunks.push(Chunk::new(ChunkType::IDAT, part.to_vec()));
+    }
+    run.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    fn chunk(chunk_type: &[u8; 4], data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::try_from(*chunk_type).unwrap(), data.to_vec())
+    }
+
+    #[test]
+    fn test_optimize() {
+        let mut png = Png::from_chunks(vec![
+            chunk(b"IHDR", &[0; 13]),
+            chunk(b"gAMA", &[0, 0, 0, 1]),
+            chunk(b"gAMA", &[0, 0, 0, 2]),
+            chunk(b"tEXt", b"Title\0a"),
+            chunk(b"tEXt", b"Title\0a"),
+            chunk(b"tEXt", b"Title\0b"),
+            chunk(b"IDAT", b"abc"),
+            chunk(b"IDAT", b"def"),
+            chunk(b"IDAT", b"ghi"),
+            chunk(b"IEND", &[]),
+        ]);
+        let before = png.as_bytes().len() as u64;
+
+        let report = png.optimize(&OptimizeOptions::default()).unwrap();
+        assert_eq!(report.merged_idat, 2);
+        assert_eq!(report.dropped, [ChunkType::GAMA, ChunkType::TEXT]);
+        assert_eq!(report.before, before);
+        assert_eq!(report.after, png.as_bytes().len() as u64);
+        assert_eq!(report.saved(), 4 * Chunk::OVERHEAD as u64 + 4 + 7);
+
+        let chunks = png.chunks();
+        assert_eq!(chunks.len(), 6);
+        assert_eq!(chunks[1].data(), [0, 0, 0, 1]);
+        assert_eq!(chunks[4].data(), b"abcdefghi");
+    }
+}
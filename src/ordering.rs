@@ -0,0 +1,247 @@
+use std::fmt;
+
+use thiserror::Error;
+
+use crate::ihdr::ColorType;
+use crate::png::Png;
+
+/// Chunk types that may appear at most once.
+pub(crate) const SINGLE: [&[u8; 4]; 18] = [
+    b"IHDR", b"PLTE", b"IEND", b"cHRM", b"gAMA", b"iCCP", b"sBIT", b"sRGB", b"cICP", b"mDCV",
+    b"cLLI", b"bKGD", b"hIST", b"tRNS", b"pHYs", b"tIME", b"eXIf", b"acTL",
+];
+
+/// Chunk types that must come before both PLTE and the first IDAT.
+const BEFORE_PLTE: [&[u8; 4]; 8] = [
+    b"cHRM", b"gAMA", b"iCCP", b"sBIT", b"sRGB", b"cICP", b"mDCV", b"cLLI",
+];
+
+/// Chunk types that must come after PLTE, if there is one, and before the first IDAT.
+const AFTER_PLTE: [&[u8; 4]; 3] = [b"bKGD", b"hIST", b"tRNS"];
+
+/// Other chunk types that must come before the first IDAT.
+const BEFORE_IDAT: [&[u8; 4]; 4] = [b"pHYs", b"sPLT", b"eXIf", b"acTL"];
+
+/// A placement rule from section 5.6 of the PNG specification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    MissingIhdr,
+    IhdrNotFirst,
+    MissingIend,
+    IendNotLast,
+    MissingIdat,
+    /// IDAT chunks must form a single unbroken run.
+    IdatNotConsecutive,
+    /// The chunk type may only appear once.
+    Duplicate,
+    /// The chunk must come before the named chunk type.
+    MustPrecede(&'static str),
+    /// The chunk must come after the named chunk type.
+    MustFollow(&'static str),
+    /// Indexed-color images need a PLTE chunk.
+    MissingPlte,
+    /// Grayscale images may not have a PLTE chunk.
+    UnexpectedPlte,
+    /// The chunk may not appear together with the named chunk type.
+    ConflictsWith(&'static str),
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rule::MissingIhdr => write!(f, "there is no IHDR chunk"),
+            Rule::IhdrNotFirst => write!(f, "IHDR must be the first chunk"),
+            Rule::MissingIend => write!(f, "there is no IEND chunk"),
+            Rule::IendNotLast => write!(f, "IEND must be the last chunk"),
+            Rule::MissingIdat => write!(f, "there is no IDAT chunk"),
+            Rule::IdatNotConsecutive => write!(f, "IDAT chunks must be consecutive"),
+            Rule::Duplicate => write!(f, "may only appear once"),
+            Rule::MustPrecede(other) => write!(f, "must come before {other}"),
+            Rule::MustFollow(other) => write!(f, "must come after {other}"),
+            Rule::MissingPlte => write!(f, "indexed-color images need a PLTE chunk"),
+            Rule::UnexpectedPlte => write!(f, "grayscale images may not have a PLTE chunk"),
+            Rule::ConflictsWith(other) => write!(f, "may not appear together with {other}"),
+        }
+    }
+}
+
+/// One broken ordering rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Index of the offending chunk, or `None` when a required chunk is missing.
+    pub position: Option<usize>,
+    pub chunk_type: String,
+    pub rule: Rule,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.position {
+            Some(position) => write!(f, "chunk {position} ({}): {}", self.chunk_type, self.rule),
+            None => write!(f, "{}", self.rule),
+        }
+    }
+}
+
+impl Png {
+    /// Checks the chunk order against the rules in the PNG specification,
+    /// returning every violation found. An empty list means the order is valid.
+    pub fn validate_ordering(&self) -> Vec<Violation> {
+        let types: Vec<[u8; 4]> = self
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().bytes())
+            .collect();
+        let first = |chunk_type: &[u8; 4]| types.iter().position(|t| t == chunk_type);
+        let last = |chunk_type: &[u8; 4]| types.iter().rposition(|t| t == chunk_type);
+
+        let mut violations = Vec::new();
+        let mut violation = |position: Option<usize>, chunk_type: &[u8; 4], rule| {
+            violations.push(Violation {
+                position,
+                chunk_type: String::from_utf8_lossy(chunk_type).into_owned(),
+                rule,
+            })
+        };
+
+        match first(b"IHDR") {
+            None => violation(None, b"IHDR", Rule::MissingIhdr),
+            Some(0) => {}
+            Some(position) => violation(Some(position), b"IHDR", Rule::IhdrNotFirst),
+        }
+        match last(b"IEND") {
+            None => violation(None, b"IEND", Rule::MissingIend),
+            Some(position) if position + 1 == types.len() => {}
+            Some(position) => violation(Some(position), b"IEND", Rule::IendNotLast),
+        }
+
+        for chunk_type in SINGLE {
+            let positions = types.iter().enumerate().filter(|(_, t)| *t == chunk_type);
+            for (position, _) in positions.skip(1) {
+                violation(Some(position), chunk_type, Rule::Duplicate);
+            }
+        }
+
+        let plte = first(b"PLTE");
+        let idat = first(b"IDAT");
+        match (idat, last(b"IDAT")) {
+            (None, _) => violation(None, b"IDAT", Rule::MissingIdat),
+            (Some(start), Some(end)) => {
+                if let Some(gap) = (start..=end).find(|&i| &types[i] != b"IDAT") {
+                    let resumed = (gap..=end).find(|&i| &types[i] == b"IDAT").unwrap_or(end);
+                    violation(Some(resumed), b"IDAT", Rule::IdatNotConsecutive);
+                }
+            }
+            (Some(_), None) => unreachable!("a first IDAT implies a last one"),
+        }
+        if let (Some(plte), Some(idat)) = (plte, idat) {
+            if plte > idat {
+                violation(Some(plte), b"PLTE", Rule::MustPrecede("IDAT"));
+            }
+        }
+
+        for (position, chunk_type) in types.iter().enumerate() {
+            let after = |other: Option<usize>| other.is_some_and(|other| position > other);
+            let before = |other: Option<usize>| other.is_some_and(|other| position < other);
+            if BEFORE_PLTE.contains(&chunk_type) {
+                if after(plte) {
+                    violation(Some(position), chunk_type, Rule::MustPrecede("PLTE"));
+                } else if after(idat) {
+                    violation(Some(position), chunk_type, Rule::MustPrecede("IDAT"));
+                }
+            }
+            if AFTER_PLTE.contains(&chunk_type) {
+                if before(plte) {
+                    violation(Some(position), chunk_type, Rule::MustFollow("PLTE"));
+                } else if after(idat) {
+                    violation(Some(position), chunk_type, Rule::MustPrecede("IDAT"));
+                }
+            }
+            if BEFORE_IDAT.contains(&chunk_type) && after(idat) {
+                violation(Some(position), chunk_type, Rule::MustPrecede("IDAT"));
+            }
+        }
+
+        if let Ok(ihdr) = self.ihdr() {
+            match (ihdr.color_type, plte) {
+                (ColorType::Indexed, None) => violation(None, b"PLTE", Rule::MissingPlte),
+                (ColorType::Grayscale | ColorType::GrayscaleAlpha, Some(position)) => {
+                    violation(Some(position), b"PLTE", Rule::UnexpectedPlte)
+                }
+                _ => {}
+            }
+        }
+        if let (Some(_), Some(srgb)) = (first(b"iCCP"), first(b"sRGB")) {
+            violation(Some(srgb), b"sRGB", Rule::ConflictsWith("iCCP"));
+        }
+
+        violations
+    }
+}
+
+/// Raised by the `lint` command when the file breaks any ordering rule.
+#[derive(Debug, Error)]
+#[error("found {violations} chunk ordering violation(s)")]
+pub struct OrderingError {
+    pub violations: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::ihdr::Ihdr;
+
+    fn png(types: &[&[u8; 4]]) -> Png {
+        let ihdr = Ihdr::new(1, 1, 8, ColorType::Indexed).unwrap();
+        Png::from_chunks(
+            types
+                .iter()
+                .map(|&chunk_type| match chunk_type {
+                    b"IHDR" => ihdr.to_chunk(),
+                    _ => Chunk::new(ChunkType::try_from(*chunk_type).unwrap(), Vec::new()),
+                })
+                .collect(),
+        )
+    }
+
+    fn rules(png: &Png) -> Vec<(String, Rule)> {
+        png.validate_ordering()
+            .into_iter()
+            .map(|violation| (violation.chunk_type, violation.rule))
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_order() {
+        let png = png(&[
+            b"IHDR", b"gAMA", b"PLTE", b"tRNS", b"IDAT", b"IDAT", b"tEXt", b"IEND",
+        ]);
+        assert_eq!(rules(&png), []);
+    }
+
+    #[test]
+    fn test_violations() {
+        let png = png(&[
+            b"gAMA", b"IHDR", b"tRNS", b"IDAT", b"PLTE", b"tEXt", b"IDAT", b"gAMA",
+        ]);
+        let found = rules(&png);
+        let has = |chunk_type: &str, rule| found.contains(&(chunk_type.to_string(), rule));
+        assert!(has("IHDR", Rule::IhdrNotFirst));
+        assert!(has("IEND", Rule::MissingIend));
+        assert!(has("gAMA", Rule::Duplicate));
+        assert!(has("IDAT", Rule::IdatNotConsecutive));
+        assert!(has("PLTE", Rule::MustPrecede("IDAT")));
+        assert!(has("tRNS", Rule::MustFollow("PLTE")));
+        assert!(has("gAMA", Rule::MustPrecede("PLTE")));
+    }
+
+    #[test]
+    fn test_missing_palette() {
+        let png = png(&[b"IHDR", b"IDAT", b"IEND"]);
+        assert_eq!(rules(&png), [("PLTE".to_string(), Rule::MissingPlte)]);
+    }
+}
@@ -0,0 +1,310 @@
+use std::fmt;
+
+use thiserror::Error;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::ColorType;
+use crate::png::Png;
+use crate::Result;
+
+/// Largest palette an 8-bit indexed image can address.
+pub const MAX_PALETTE_LEN: usize = 256;
+
+/// One palette color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub fn new(r: u8, g: u8, b: u8) -> Rgb {
+        Rgb { r, g, b }
+    }
+}
+
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+/// The colors of a `PLTE` chunk, with the per-entry alpha from `tRNS`.
+///
+/// Pixels of indexed images refer to entries by position, so reordering or
+/// removing entries changes how the image looks; recoloring is safe.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    colors: Vec<Rgb>,
+    /// Alpha of the leading entries, without trailing opaque values so equal
+    /// palettes compare equal. Entries past the end are opaque.
+    alpha: Vec<u8>,
+}
+
+impl Palette {
+    /// Builds a fully opaque palette of 1 to 256 colors.
+    pub fn new(colors: Vec<Rgb>) -> Result<Palette> {
+        if colors.is_empty() || colors.len() > MAX_PALETTE_LEN {
+            return Err(PaletteError::InvalidSize(colors.len()).into());
+        }
+        Ok(Palette {
+            colors,
+            alpha: Vec::new(),
+        })
+    }
+
+    /// Parses PLTE data, and the tRNS data of an indexed image if present.
+    pub fn parse(plte: &[u8], trns: Option<&[u8]>) -> Result<Palette> {
+        if !plte.len().is_multiple_of(3) {
+            return Err(PaletteError::InvalidLength(plte.len()).into());
+        }
+        let colors = plte
+            .chunks_exact(3)
+            .map(|rgb| Rgb::new(rgb[0], rgb[1], rgb[2]))
+            .collect();
+        let mut palette = Palette::new(colors)?;
+        if let Some(alpha) = trns {
+            if alpha.len() > palette.len() {
+                return Err(PaletteError::TooMuchAlpha {
+                    entries: palette.len(),
+                    alpha: alpha.len(),
+                }
+                .into());
+            }
+            palette.alpha = alpha.to_vec();
+            palette.trim_alpha();
+        }
+        Ok(palette)
+    }
+
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    pub fn colors(&self) -> &[Rgb] {
+        &self.colors
+    }
+
+    /// The colors, for recoloring entries in place.
+    pub fn colors_mut(&mut self) -> &mut [Rgb] {
+        &mut self.colors
+    }
+
+    pub fn into_colors(self) -> Vec<Rgb> {
+        self.colors
+    }
+
+    pub fn get(&self, index: usize) -> Option<Rgb> {
+        self.colors.get(index).copied()
+    }
+
+    /// Recolors the entry at `index`.
+    pub fn set(&mut self, index: usize, color: Rgb) -> Result<()> {
+        let entry = self
+            .colors
+            .get_mut(index)
+            .ok_or(PaletteError::IndexOutOfRange(index))?;
+        *entry = color;
+        Ok(())
+    }
+
+    /// Appends an opaque color, returning its index.
+    pub fn push(&mut self, color: Rgb) -> Result<usize> {
+        if self.colors.len() == MAX_PALETTE_LEN {
+            return Err(PaletteError::InvalidSize(MAX_PALETTE_LEN + 1).into());
+        }
+        self.colors.push(color);
+        Ok(self.colors.len() - 1)
+    }
+
+    /// Alpha of the entry at `index`, 255 meaning opaque.
+    pub fn alpha(&self, index: usize) -> u8 {
+        self.alpha.get(index).copied().unwrap_or(u8::MAX)
+    }
+
+    pub fn set_alpha(&mut self, index: usize, alpha: u8) -> Result<()> {
+        if index >= self.colors.len() {
+            return Err(PaletteError::IndexOutOfRange(index).into());
+        }
+        if self.alpha.len() <= index {
+            self.alpha.resize(index + 1, u8::MAX);
+        }
+        self.alpha[index] = alpha;
+        self.trim_alpha();
+        Ok(())
+    }
+
+    pub fn is_transparent(&self, index: usize) -> bool {
+        self.alpha(index) < u8::MAX
+    }
+
+    /// Serializes the colors as PLTE data.
+    pub fn plte_bytes(&self) -> Vec<u8> {
+        self.colors
+            .iter()
+            .flat_map(|color| [color.r, color.g, color.b])
+            .collect()
+    }
+
+    /// Serializes the alpha values as tRNS data, without trailing opaque
+    /// entries, or `None` if every entry is opaque.
+    pub fn trns_bytes(&self) -> Option<Vec<u8>> {
+        (!self.alpha.is_empty()).then(|| self.alpha.clone())
+    }
+
+    fn trim_alpha(&mut self) {
+        let len = self
+            .alpha
+            .iter()
+            .rposition(|&a| a != u8::MAX)
+            .map_or(0, |last| last + 1);
+        self.alpha.truncate(len);
+    }
+}
+
+impl Png {
+    /// Parses the palette and, for indexed images, its transparency.
+    pub fn palette(&self) -> Result<Option<Palette>> {
+        let Some(plte) = self.chunk_by_type("PLTE") else {
+            return Ok(None);
+        };
+        // tRNS holds a single color key, not per-entry alpha, for other color types.
+        let indexed = self
+            .ihdr()
+            .map_or(true, |ihdr| ihdr.color_type == ColorType::Indexed);
+        let trns = self
+            .chunk_by_type("tRNS")
+            .filter(|_| indexed)
+            .map(Chunk::data);
+        Palette::parse(plte.data(), trns).map(Some)
+    }
+
+    /// Replaces the PLTE and tRNS chunks with `palette`. A new PLTE goes
+    /// before the first IDAT, and tRNS always follows PLTE.
+    pub fn set_palette(&mut self, palette: &Palette) -> Result<()> {
+        if let Ok(ihdr) = self.ihdr() {
+            if ihdr.color_type == ColorType::Indexed && palette.len() > 1 << ihdr.bit_depth {
+                return Err(PaletteError::TooManyForBitDepth {
+                    entries: palette.len(),
+                    bit_depth: ihdr.bit_depth,
+                }
+                .into());
+            }
+        }
+
+        let is = |chunk_type: &'static [u8; 4]| {
+            move |chunk: &Chunk| chunk.chunk_type().bytes() == *chunk_type
+        };
+        let position = self
+            .chunks()
+            .iter()
+            .position(is(b"PLTE"))
+            .or_else(|| self.chunks().iter().position(is(b"IDAT")))
+            .or_else(|| self.chunks().iter().position(is(b"IEND")))
+            .unwrap_or(self.chunks().len());
+        // Chunks removed from before `position` shift it back.
+        let removed_before = self.chunks()[..position]
+            .iter()
+            .filter(|chunk| is(b"tRNS")(chunk))
+            .count();
+        self.retain_chunks(|chunk| !is(b"PLTE")(chunk) && !is(b"tRNS")(chunk));
+        let position = position - removed_before;
+
+        self.insert_chunk(position, Chunk::new(ChunkType::PLTE, palette.plte_bytes()));
+        if let Some(trns) = palette.trns_bytes() {
+            self.insert_chunk(position + 1, Chunk::new(ChunkType::TRNS, trns));
+        }
+        Ok(())
+    }
+}
+
+/// Errors raised while reading or editing a [`Palette`].
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum PaletteError {
+    /// PLTE data must be a whole number of RGB triples.
+    #[error("PLTE length {0} is not a multiple of 3")]
+    InvalidLength(usize),
+    /// A palette must have between 1 and 256 entries.
+    #[error(
+        "palette must have 1 to {MAX_PALETTE_LEN} entries, got {0}",
+        MAX_PALETTE_LEN = MAX_PALETTE_LEN
+    )]
+    InvalidSize(usize),
+    /// tRNS has more alpha values than the palette has entries.
+    #[error("tRNS has {alpha} alpha values for a palette of {entries} entries")]
+    TooMuchAlpha { entries: usize, alpha: usize },
+    /// The image's bit depth cannot address every entry.
+    #[error("{bit_depth}-bit indexed images cannot use {entries} palette entries")]
+    TooManyForBitDepth { entries: usize, bit_depth: u8 },
+    #[error("palette has no entry {0}")]
+    IndexOutOfRange(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::Ihdr;
+
+    fn chunk(chunk_type: &[u8; 4], data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::try_from(*chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn chunk_types(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_with_transparency() {
+        let palette = Palette::parse(&[255, 0, 0, 0, 255, 0, 0, 0, 255], Some(&[0])).unwrap();
+        assert_eq!(palette.len(), 3);
+        assert_eq!(palette.get(1), Some(Rgb::new(0, 255, 0)));
+        assert!(palette.is_transparent(0));
+        assert_eq!(palette.alpha(2), 255);
+
+        assert!(Palette::parse(&[1, 2], None).is_err());
+        assert!(Palette::parse(&[1, 2, 3], Some(&[0, 0])).is_err());
+    }
+
+    #[test]
+    fn test_trns_drops_trailing_opaque_entries() {
+        let mut palette = Palette::new(vec![Rgb::default(); 4]).unwrap();
+        assert_eq!(palette.trns_bytes(), None);
+        palette.set_alpha(1, 128).unwrap();
+        palette.set_alpha(3, 255).unwrap();
+        assert_eq!(palette.trns_bytes(), Some(vec![255, 128]));
+        assert!(palette.set_alpha(4, 0).is_err());
+    }
+
+    #[test]
+    fn test_set_palette_placement() {
+        let ihdr = Ihdr::new(1, 1, 1, ColorType::Indexed).unwrap();
+        let mut png = Png::from_chunks(vec![
+            ihdr.to_chunk(),
+            chunk(b"IDAT", &[]),
+            chunk(b"IEND", &[]),
+        ]);
+        let mut palette = Palette::new(vec![Rgb::new(0, 0, 0), Rgb::new(9, 9, 9)]).unwrap();
+        palette.set_alpha(0, 0).unwrap();
+        png.set_palette(&palette).unwrap();
+        assert_eq!(chunk_types(&png), ["IHDR", "PLTE", "tRNS", "IDAT", "IEND"]);
+
+        palette.set(1, Rgb::new(1, 2, 3)).unwrap();
+        palette.set_alpha(0, 255).unwrap();
+        png.set_palette(&palette).unwrap();
+        assert_eq!(chunk_types(&png), ["IHDR", "PLTE", "IDAT", "IEND"]);
+        assert_eq!(png.palette().unwrap(), Some(palette.clone()));
+
+        palette.push(Rgb::default()).unwrap();
+        assert!(png.set_palette(&palette).is_err());
+    }
+}
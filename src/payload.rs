@@ -0,0 +1,516 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use clap::ValueEnum;
+use thiserror::Error;
+
+use crate::chunk::{Chunk, MAX_CHUNK_LEN};
+use crate::chunk_type::ChunkType;
+use crate::shamir::Share;
+use crate::Result;
+
+/// Largest data field the PNG specification allows in a single chunk.
+pub const MAX_CHUNK_DATA: usize = MAX_CHUNK_LEN as usize;
+
+/// Marks one part of a payload split across several chunks. Like the
+/// compression header, the first byte is not valid UTF-8.
+const SPLIT_MAGIC: [u8; 2] = [0x8f, b'S'];
+/// Magic, part index and part count.
+const SPLIT_HEADER_LEN: usize = SPLIT_MAGIC.len() + 4 + 4;
+
+/// Marks a chunk holding (part of) a labeled payload. The label length (u8)
+/// and label follow.
+const LABEL_MAGIC: [u8; 2] = [0x8f, b'L'];
+
+/// Marks a [`Payload::File`]. Plain messages are stored without a header.
+const FILE_MAGIC: [u8; 2] = [0x8f, b'F'];
+
+/// Where a payload is hidden.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Method {
+    /// In ancillary chunks of the given type.
+    #[default]
+    Chunk,
+    /// In the least significant bits of the pixel data. The chunk type is ignored.
+    Lsb,
+    /// After the IEND chunk, where PNG decoders never look. The chunk type is ignored.
+    Trailer,
+}
+
+/// What is hidden in a PNG: a bare message, a file with its metadata, or
+/// one share of a message split by [`shamir::split`](crate::shamir::split).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Payload {
+    Message(Vec<u8>),
+    File(EmbeddedFile),
+    Share(Share),
+}
+
+impl Payload {
+    /// Serializes the payload. Messages are written as-is; files are written
+    /// as a header holding their metadata followed by their contents, and
+    /// shares by [`Share::to_bytes`]. The envelope records which it is.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Payload::Message(message) => message.clone(),
+            Payload::File(file) => file.to_bytes(),
+            Payload::Share(share) => share.to_bytes(),
+        }
+    }
+}
+
+/// A file embedded with its name, size, modification time and MIME type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbeddedFile {
+    pub name: String,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    pub mime_type: String,
+    pub contents: Vec<u8>,
+}
+
+impl EmbeddedFile {
+    /// Reads the file at `path` along with its metadata. The MIME type is
+    /// guessed from the file extension.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<EmbeddedFile> {
+        let path = path.as_ref();
+        let contents = fs::read(path)?;
+        let name = path
+            .file_name()
+            .ok_or_else(|| PayloadError::InvalidFileName(path.display().to_string()))?
+            .to_string_lossy()
+            .into_owned();
+
+        Ok(EmbeddedFile {
+            name,
+            size: contents.len() as u64,
+            modified: fs::metadata(path)?.modified().ok(),
+            mime_type: mime_guess::from_path(path)
+                .first_or_octet_stream()
+                .essence_str()
+                .to_owned(),
+            contents,
+        })
+    }
+
+    /// Writes the file into `dir` under its original name, restoring its
+    /// modification time, and returns the path written.
+    ///
+    /// Only the final component of the stored name is used, so a crafted
+    /// name can't write outside `dir`, and nothing already at that path,
+    /// including a symlink, is replaced or followed.
+    pub fn write_to_dir<P: AsRef<Path>>(&self, dir: P) -> Result<PathBuf> {
+        let name = Path::new(&self.name)
+            .file_name()
+            .ok_or_else(|| PayloadError::InvalidFileName(self.name.clone()))?;
+        let path = dir.as_ref().join(name);
+
+        let mut file = match File::options().write(true).create_new(true).open(&path) {
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                return Err(PayloadError::Exists(path.display().to_string()).into())
+            }
+            file => file?,
+        };
+        file.write_all(&self.contents)?;
+        if let Some(modified) = self.modified {
+            file.set_modified(modified)?;
+        }
+        Ok(path)
+    }
+
+    /// Header layout after the magic: name length (u16) and name, MIME type
+    /// length (u16) and MIME type, size (u64), a byte flagging whether a
+    /// modification time follows, then that time as seconds since the Unix
+    /// epoch (u64). All integers are big-endian. The contents follow.
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = FILE_MAGIC.to_vec();
+        for field in [self.name.as_bytes(), self.mime_type.as_bytes()] {
+            let len = u16::try_from(field.len()).unwrap_or(u16::MAX);
+            bytes.extend_from_slice(&len.to_be_bytes());
+            bytes.extend_from_slice(&field[..len as usize]);
+        }
+        bytes.extend_from_slice(&self.size.to_be_bytes());
+
+        let modified = self
+            .modified
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|since_epoch| since_epoch.as_secs());
+        match modified {
+            Some(secs) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&secs.to_be_bytes());
+            }
+            None => bytes.push(0),
+        }
+
+        bytes.extend_from_slice(&self.contents);
+        bytes
+    }
+
+    /// Parses bytes written by [`EmbeddedFile::to_bytes`], header included.
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<EmbeddedFile> {
+        let bytes = bytes
+            .strip_prefix(&FILE_MAGIC)
+            .ok_or(PayloadError::TruncatedHeader)?;
+        let mut reader = FieldReader(bytes);
+        let name_len = u16::from_be_bytes(reader.take()?);
+        let name = String::from_utf8(reader.take_slice(name_len.into())?.to_vec())?;
+        let mime_len = u16::from_be_bytes(reader.take()?);
+        let mime_type = String::from_utf8(reader.take_slice(mime_len.into())?.to_vec())?;
+        let size = u64::from_be_bytes(reader.take()?);
+        let modified = match reader.take::<1>()? {
+            [0] => None,
+            _ => Some(UNIX_EPOCH + Duration::from_secs(u64::from_be_bytes(reader.take()?))),
+        };
+
+        let contents = reader.0.to_vec();
+        if contents.len() as u64 != size {
+            return Err(PayloadError::SizeMismatch {
+                expected: size,
+                found: contents.len() as u64,
+            }
+            .into());
+        }
+
+        Ok(EmbeddedFile {
+            name,
+            size,
+            modified,
+            mime_type,
+            contents,
+        })
+    }
+}
+
+/// Reads fixed-size fields off the front of a byte slice.
+struct FieldReader<'a>(&'a [u8]);
+
+impl<'a> FieldReader<'a> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take_slice(N)?.try_into()?)
+    }
+
+    fn take_slice(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(PayloadError::TruncatedHeader.into());
+        }
+        let (field, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(field)
+    }
+}
+
+/// Wraps `payload` in as many chunks of `chunk_type` as needed to keep each
+/// chunk's data within `max_len` bytes.
+///
+/// A payload that fits in one chunk is stored as-is. Otherwise each part is
+/// prefixed with a header holding its index and the total part count so
+/// [`reassemble`] can restore it. Fails if `max_len` leaves no room for a
+/// part after its header.
+pub fn split(chunk_type: &ChunkType, payload: &[u8], max_len: usize) -> Result<Vec<Chunk>> {
+    Ok(split_parts(payload, max_len)?
+        .into_iter()
+        .map(|data| Chunk::new(*chunk_type, data))
+        .collect())
+}
+
+/// Like [`split`], but starts every chunk with a header naming `label`, so
+/// several payloads can share one chunk type. See [`strip_label`].
+pub fn split_labeled(
+    chunk_type: &ChunkType,
+    label: &str,
+    payload: &[u8],
+    max_len: usize,
+) -> Result<Vec<Chunk>> {
+    let label_len = u8::try_from(label.len()).map_err(|_| PayloadError::LabelTooLong)?;
+    let mut header = LABEL_MAGIC.to_vec();
+    header.push(label_len);
+    header.extend_from_slice(label.as_bytes());
+
+    let max_len = max_len
+        .checked_sub(header.len())
+        .ok_or(PayloadError::MaxLenTooSmall(max_len))?;
+    Ok(split_parts(payload, max_len)?
+        .into_iter()
+        .map(|part| Chunk::new(*chunk_type, [header.as_slice(), &part].concat()))
+        .collect())
+}
+
+/// Splits a labeled chunk's data into its label and the data that follows,
+/// or returns `None` if the chunk has no label.
+pub fn strip_label(data: &[u8]) -> Option<(&str, &[u8])> {
+    let (&len, rest) = data.strip_prefix(&LABEL_MAGIC)?.split_first()?;
+    let (label, rest) = rest.split_at_checked(len.into())?;
+    Some((std::str::from_utf8(label).ok()?, rest))
+}
+
+/// Rebuilds a payload from the chunk data written by [`split`], in any order.
+///
+/// If the first part has no split header it is returned unchanged.
+pub fn reassemble(chunks: &[&[u8]]) -> Result<Vec<u8>> {
+    let first = chunks.first().ok_or(PayloadError::MissingParts(0))?;
+    if !first.starts_with(&SPLIT_MAGIC) {
+        return Ok(first.to_vec());
+    }
+
+    // The count is untrusted: every part needs a chunk of its own, so a
+    // larger one can't be complete and isn't worth allocating for.
+    let (_, count) = split_header(first)?;
+    if count == 0 || count as usize > chunks.len() {
+        return Err(PayloadError::InconsistentCount.into());
+    }
+    let mut parts: Vec<Option<&[u8]>> = vec![None; count as usize];
+    for data in chunks {
+        let (index, part_count) = split_header(data)?;
+        if part_count != count {
+            return Err(PayloadError::InconsistentCount.into());
+        }
+        let slot = parts
+            .get_mut(index as usize)
+            .ok_or(PayloadError::InconsistentCount)?;
+        if slot.replace(&data[SPLIT_HEADER_LEN..]).is_some() {
+            return Err(PayloadError::DuplicatePart(index).into());
+        }
+    }
+
+    let missing = parts.iter().filter(|part| part.is_none()).count();
+    if missing > 0 {
+        return Err(PayloadError::MissingParts(missing).into());
+    }
+    Ok(parts.into_iter().flatten().flatten().copied().collect())
+}
+
+/// Splits `payload` into pieces of at most `max_len` bytes, adding split
+/// headers only when more than one piece is needed.
+fn split_parts(payload: &[u8], max_len: usize) -> Result<Vec<Vec<u8>>> {
+    if payload.len() <= max_len {
+        return Ok(vec![payload.to_vec()]);
+    }
+
+    let part_len = max_len
+        .checked_sub(SPLIT_HEADER_LEN)
+        .filter(|&len| len > 0)
+        .ok_or(PayloadError::MaxLenTooSmall(max_len))?;
+    let parts = payload.chunks(part_len);
+    let count = u32::try_from(parts.len()).map_err(|_| PayloadError::TooManyParts)?;
+
+    Ok(parts
+        .zip(0u32..)
+        .map(|(part, index)| {
+            let mut data = Vec::with_capacity(SPLIT_HEADER_LEN + part.len());
+            data.extend_from_slice(&SPLIT_MAGIC);
+            data.extend_from_slice(&index.to_be_bytes());
+            data.extend_from_slice(&count.to_be_bytes());
+            data.extend_from_slice(part);
+            data
+        })
+        .collect())
+}
+
+/// Parses the index and count from a split part's header.
+fn split_header(data: &[u8]) -> Result<(u32, u32)> {
+    if data.len() < SPLIT_HEADER_LEN || !data.starts_with(&SPLIT_MAGIC) {
+        return Err(PayloadError::NotSplit.into());
+    }
+    let index = u32::from_be_bytes(data[2..6].try_into()?);
+    let count = u32::from_be_bytes(data[6..10].try_into()?);
+    Ok((index, count))
+}
+
+/// Errors raised while parsing or reassembling a payload.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum PayloadError {
+    /// A chunk expected to be a split part has no split header.
+    #[error("chunk is not part of a split payload")]
+    NotSplit,
+    /// Parts disagree on how many parts there are, or an index is out of range.
+    #[error("split payload parts are inconsistent")]
+    InconsistentCount,
+    /// The same part index appears twice.
+    #[error("split payload part {0} appears more than once")]
+    DuplicatePart(u32),
+    /// Some parts are missing from the file.
+    #[error("split payload is missing {0} part(s)")]
+    MissingParts(usize),
+    /// Chunks of at most this many bytes leave no room for a part after its
+    /// headers.
+    #[error("chunks of {0} bytes are too small to split a payload into")]
+    MaxLenTooSmall(usize),
+    /// The payload would need more than `u32::MAX` parts.
+    #[error("payload is too large to split")]
+    TooManyParts,
+    /// An embedded file's header ends early.
+    #[error("embedded file header is truncated")]
+    TruncatedHeader,
+    /// An embedded file's contents don't match its recorded size.
+    #[error("embedded file should be {expected} bytes but {found} were found")]
+    SizeMismatch { expected: u64, found: u64 },
+    /// A path has no usable file name.
+    #[error("invalid file name: {0}")]
+    InvalidFileName(String),
+    /// An embedded file would be restored over the file already at this
+    /// path.
+    #[error("{0} already exists")]
+    Exists(String),
+    /// Labels are limited to 255 bytes.
+    #[error("payload labels are limited to 255 bytes")]
+    LabelTooLong,
+    /// An embedded file, named here, was found where a message was expected.
+    #[error("payload is the file {0}, not a message")]
+    NotAMessage(String),
+    /// Nothing was hidden with `method`, under `chunk_type` if it is
+    /// [`Method::Chunk`].
+    #[error("{}", not_found(.method, .chunk_type))]
+    NotFound { method: Method, chunk_type: String },
+    /// A message asked for as text isn't UTF-8. `offset` is that of the
+    /// first byte that isn't.
+    #[error("message isn't UTF-8 text: invalid byte at offset {offset}")]
+    NotUtf8 { offset: usize },
+}
+
+/// The message of [`PayloadError::NotFound`].
+fn not_found(method: &Method, chunk_type: &str) -> String {
+    match method {
+        Method::Chunk => format!("no {chunk_type} chunk found"),
+        Method::Lsb => "no LSB payload found".to_string(),
+        Method::Trailer => "no trailer found".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PngMeError;
+    use std::str::FromStr;
+
+    fn chunk_type() -> ChunkType {
+        ChunkType::from_str("ruSt").unwrap()
+    }
+
+    #[test]
+    fn test_small_payload_is_not_split() {
+        let chunks = split(&chunk_type(), b"hello", 64).unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].data(), b"hello");
+        assert_eq!(reassemble(&[chunks[0].data()]).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_split_round_trip_in_any_order() {
+        let payload: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let mut chunks = split(&chunk_type(), &payload, 64).unwrap();
+        assert_eq!(chunks.len(), 19);
+        assert!(chunks.iter().all(|chunk| chunk.length() <= 64));
+
+        chunks.reverse();
+        let parts: Vec<&[u8]> = chunks.iter().map(Chunk::data).collect();
+        assert_eq!(reassemble(&parts).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_file_payload_round_trip() {
+        let file = EmbeddedFile {
+            name: "notes.txt".to_string(),
+            size: 5,
+            modified: Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+            mime_type: "text/plain".to_string(),
+            contents: b"hello".to_vec(),
+        };
+        assert_eq!(EmbeddedFile::from_bytes(&file.to_bytes()).unwrap(), file);
+    }
+
+    #[test]
+    fn test_write_to_dir_keeps_existing_files() {
+        let dir = std::env::temp_dir().join(format!("pngme-extract-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = EmbeddedFile {
+            name: "../notes.txt".to_string(),
+            size: 5,
+            modified: Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+            mime_type: "text/plain".to_string(),
+            contents: b"hello".to_vec(),
+        };
+
+        let path = file.write_to_dir(&dir).unwrap();
+        assert_eq!(path, dir.join("notes.txt"));
+        assert_eq!(fs::read(&path).unwrap(), b"hello");
+        assert_eq!(
+            fs::metadata(&path).unwrap().modified().unwrap(),
+            file.modified.unwrap()
+        );
+        fs::write(&path, b"mine").unwrap();
+        assert!(matches!(
+            file.write_to_dir(&dir),
+            Err(PngMeError::Payload(PayloadError::Exists(_)))
+        ));
+        assert_eq!(fs::read(&path).unwrap(), b"mine");
+
+        #[cfg(unix)]
+        {
+            let target = dir.join("target");
+            fs::remove_file(&path).unwrap();
+            std::os::unix::fs::symlink(&target, &path).unwrap();
+            assert!(file.write_to_dir(&dir).is_err());
+            assert!(!target.exists());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_missing_part_is_an_error() {
+        let payload = vec![7; 200];
+        let chunks = split(&chunk_type(), &payload, 64).unwrap();
+        let parts: Vec<&[u8]> = chunks.iter().skip(1).map(Chunk::data).collect();
+        assert!(reassemble(&parts).is_err());
+    }
+
+    #[test]
+    fn test_untrusted_part_count_is_rejected() {
+        // A lone part claiming u32::MAX parts must not allocate for them.
+        let mut data = SPLIT_MAGIC.to_vec();
+        data.extend_from_slice(&0u32.to_be_bytes());
+        data.extend_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(
+            reassemble(&[&data]),
+            Err(PngMeError::Payload(PayloadError::InconsistentCount))
+        ));
+        data[6..10].copy_from_slice(&0u32.to_be_bytes());
+        assert!(matches!(
+            reassemble(&[&data]),
+            Err(PngMeError::Payload(PayloadError::InconsistentCount))
+        ));
+    }
+
+    #[test]
+    fn test_small_max_len_is_an_error() {
+        assert!(matches!(
+            split(&chunk_type(), &[0; 100], SPLIT_HEADER_LEN),
+            Err(PngMeError::Payload(PayloadError::MaxLenTooSmall(_)))
+        ));
+        assert!(matches!(
+            split_labeled(&chunk_type(), "notes", &[0; 100], 4),
+            Err(PngMeError::Payload(PayloadError::MaxLenTooSmall(4)))
+        ));
+    }
+
+    #[test]
+    fn test_labeled_split_round_trip() {
+        let payload = vec![3; 200];
+        let chunks = split_labeled(&chunk_type(), "notes", &payload, 64).unwrap();
+        assert!(chunks.iter().all(|chunk| chunk.length() <= 64));
+
+        let parts: Vec<&[u8]> = chunks
+            .iter()
+            .map(|chunk| {
+                let (label, rest) = strip_label(chunk.data()).unwrap();
+                assert_eq!(label, "notes");
+                rest
+            })
+            .collect();
+        assert_eq!(reassemble(&parts).unwrap(), payload);
+        assert_eq!(strip_label(b"plain"), None);
+    }
+}
@@ -0,0 +1,120 @@
+//! Where the bytes of a message to embed come from, and how a decoded
+//! message is written as text, so payloads that aren't UTF-8 can be passed
+//! on the command line and printed back.
+
+use std::path::PathBuf;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+use crate::clipboard;
+use crate::payload::PayloadError;
+use crate::qr;
+use crate::stdio;
+use crate::Result;
+
+/// How message bytes are written as text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PayloadEncoding {
+    /// As themselves, which only works for UTF-8.
+    #[default]
+    Text,
+    /// As themselves, with each sequence that isn't UTF-8 replaced by
+    /// U+FFFD. Decoding such text doesn't give back the original bytes.
+    Lossy,
+    Hex,
+    Base64,
+}
+
+impl PayloadEncoding {
+    /// The bytes `text` stands for. Surrounding whitespace is ignored for
+    /// hex and base64, so the output of other tools can be pasted in.
+    pub fn decode(self, text: &str) -> Result<Vec<u8>> {
+        Ok(match self {
+            PayloadEncoding::Text | PayloadEncoding::Lossy => text.as_bytes().to_vec(),
+            PayloadEncoding::Hex => hex::decode(text.trim())?,
+            PayloadEncoding::Base64 => BASE64.decode(text.trim())?,
+        })
+    }
+
+    /// `bytes` written as text. Fails for [`PayloadEncoding::Text`] with
+    /// [`PayloadError::NotUtf8`] if they aren't UTF-8.
+    pub fn encode(self, bytes: Vec<u8>) -> Result<String> {
+        Ok(match self {
+            PayloadEncoding::Text => {
+                String::from_utf8(bytes).map_err(|err| PayloadError::NotUtf8 {
+                    offset: err.utf8_error().valid_up_to(),
+                })?
+            }
+            PayloadEncoding::Lossy => String::from_utf8_lossy(&bytes).into_owned(),
+            PayloadEncoding::Hex => hex::encode(bytes),
+            PayloadEncoding::Base64 => BASE64.encode(bytes),
+        })
+    }
+}
+
+/// The message to embed, given on the command line or read from a file,
+/// the clipboard or a QR code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PayloadSource {
+    Inline {
+        text: String,
+        encoding: PayloadEncoding,
+    },
+    /// A file whose contents are the message, or - for standard input.
+    File(PathBuf),
+    /// The text on the clipboard, written with an encoding.
+    Clipboard(PayloadEncoding),
+    /// The data of the QR code in a PNG image.
+    Qr(PathBuf),
+}
+
+impl PayloadSource {
+    /// The message bytes.
+    pub fn read(&self) -> Result<Vec<u8>> {
+        match self {
+            PayloadSource::Inline { text, encoding } => encoding.decode(text),
+            PayloadSource::File(path) => stdio::read(path),
+            PayloadSource::Clipboard(encoding) => encoding.decode(&clipboard::read()?),
+            PayloadSource::Qr(path) => qr::scan(&stdio::read_png(path)?),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PngMeError;
+
+    #[test]
+    fn test_encodings() {
+        let bytes = vec![0, 0xff, b'h', b'i'];
+        for encoding in [PayloadEncoding::Hex, PayloadEncoding::Base64] {
+            let text = encoding.encode(bytes.clone()).unwrap();
+            assert_eq!(encoding.decode(&format!(" {text}\n")).unwrap(), bytes);
+        }
+        assert_eq!(
+            PayloadEncoding::Hex.encode(bytes.clone()).unwrap(),
+            "00ff6869"
+        );
+        assert_eq!(
+            PayloadEncoding::Base64.encode(bytes.clone()).unwrap(),
+            "AP9oaQ=="
+        );
+        assert_eq!(
+            PayloadEncoding::Lossy.encode(bytes.clone()).unwrap(),
+            "\0\u{fffd}hi"
+        );
+        assert!(matches!(
+            PayloadEncoding::Text.encode(bytes),
+            Err(PngMeError::Payload(PayloadError::NotUtf8 { offset: 1 }))
+        ));
+        assert!(PayloadEncoding::Hex.decode("0g").is_err());
+
+        let source = PayloadSource::Inline {
+            text: " hi ".to_string(),
+            encoding: PayloadEncoding::Text,
+        };
+        assert_eq!(source.read().unwrap(), b" hi ");
+    }
+}
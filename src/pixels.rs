@@ -0,0 +1,619 @@
+use thiserror::Error;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::idat::{row_bytes, Filter, IdatError, Scanlines};
+use crate::ihdr::{ColorType, Ihdr, Interlace};
+use crate::palette::Palette;
+use crate::png::Png;
+use crate::Result;
+
+/// An image decoded to 8-bit RGBA, four bytes per pixel, row by row from
+/// the top left.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageBuffer {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl ImageBuffer {
+    /// Wraps RGBA `data`, which must hold exactly `width * height` pixels.
+    pub fn new(width: u32, height: u32, data: Vec<u8>) -> Result<ImageBuffer> {
+        let expected = buffer_len(width, height, 4)?;
+        if data.len() != expected {
+            return Err(PixelError::BufferSize {
+                expected,
+                found: data.len(),
+            }
+            .into());
+        }
+        Ok(ImageBuffer {
+            width,
+            height,
+            data,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The RGBA value of the pixel at column `x` of row `y`.
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let start = (y as usize * self.width as usize + x as usize) * 4;
+        self.data[start..start + 4]
+            .try_into()
+            .expect("four bytes per pixel")
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// One pass over the image: the first pixel it covers and the spacing
+/// between pixels. A non-interlaced image is a single pass over every pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Pass {
+    x: u32,
+    y: u32,
+    dx: u32,
+    dy: u32,
+}
+
+/// A single pass covering every pixel, row by row.
+const FULL: Pass = Pass {
+    x: 0,
+    y: 0,
+    dx: 1,
+    dy: 1,
+};
+
+/// The seven Adam7 passes from section 8.2 of the specification.
+pub(crate) const ADAM7: [Pass; 7] = [
+    Pass {
+        x: 0,
+        y: 0,
+        dx: 8,
+        dy: 8,
+    },
+    Pass {
+        x: 4,
+        y: 0,
+        dx: 8,
+        dy: 8,
+    },
+    Pass {
+        x: 0,
+        y: 4,
+        dx: 4,
+        dy: 8,
+    },
+    Pass {
+        x: 2,
+        y: 0,
+        dx: 4,
+        dy: 4,
+    },
+    Pass {
+        x: 0,
+        y: 2,
+        dx: 2,
+        dy: 4,
+    },
+    Pass {
+        x: 1,
+        y: 0,
+        dx: 2,
+        dy: 2,
+    },
+    Pass {
+        x: 0,
+        y: 1,
+        dx: 1,
+        dy: 2,
+    },
+];
+
+impl Pass {
+    /// The dimensions of the reduced image this pass holds, either of
+    /// which may be 0 for small images.
+    pub(crate) fn size(self, width: u32, height: u32) -> (u32, u32) {
+        let count =
+            |length: u32, start: u32, step: u32| length.saturating_sub(start).div_ceil(step);
+        (
+            count(width, self.x, self.dx),
+            count(height, self.y, self.dy),
+        )
+    }
+
+    /// The position in the full image of pixel `column` of row `row`.
+    pub(crate) fn position(self, column: u32, row: u32) -> (u32, u32) {
+        (self.x + column * self.dx, self.y + row * self.dy)
+    }
+}
+
+/// The passes image data is stored in, for the interlace method in `ihdr`.
+pub(crate) fn passes(ihdr: &Ihdr) -> &'static [Pass] {
+    match ihdr.interlace {
+        Interlace::None => std::slice::from_ref(&FULL),
+        Interlace::Adam7 => &ADAM7,
+    }
+}
+
+/// Each pass that holds any pixels, with the IHDR of its reduced image.
+fn reduced_images(ihdr: &Ihdr) -> impl Iterator<Item = (Pass, Ihdr)> + '_ {
+    passes(ihdr).iter().filter_map(|&pass| {
+        let (width, height) = pass.size(ihdr.width, ihdr.height);
+        (width > 0 && height > 0).then_some((
+            pass,
+            Ihdr {
+                width,
+                height,
+                ..*ihdr
+            },
+        ))
+    })
+}
+
+/// Bytes of decompressed image data `ihdr` implies: every row of every
+/// non-empty pass with its filter type byte.
+pub(crate) fn image_data_len(ihdr: &Ihdr) -> Result<u64> {
+    reduced_images(ihdr).try_fold(0u64, |len, (_, reduced)| {
+        let rows = (row_bytes(&reduced)? as u64 + 1) * u64::from(reduced.height);
+        len.checked_add(rows)
+            .ok_or_else(|| IdatError::TooLarge.into())
+    })
+}
+
+/// Unfilters decompressed image data pass by pass, skipping empty passes.
+pub(crate) fn unfilter_passes(ihdr: &Ihdr, data: &[u8]) -> Result<Vec<(Pass, Scanlines)>> {
+    let mut offset = 0;
+    let mut unfiltered = Vec::new();
+    for (pass, reduced) in reduced_images(ihdr) {
+        let scanlines = Scanlines::unfilter(&reduced, data.get(offset..).unwrap_or_default())?;
+        offset += (scanlines.stride + 1) * reduced.height as usize;
+        unfiltered.push((pass, scanlines));
+    }
+    Ok(unfiltered)
+}
+
+/// Splits raw full-size rows laid out as `ihdr` describes into the raw rows
+/// of each non-empty pass, in storage order.
+fn split_passes(ihdr: &Ihdr, data: &[u8]) -> Result<Vec<(Ihdr, Vec<u8>)>> {
+    if ihdr.interlace == Interlace::None {
+        return Ok(vec![(*ihdr, data.to_vec())]);
+    }
+    let stride = row_bytes(ihdr)?;
+    let bits = ihdr.bits_per_pixel();
+    reduced_images(ihdr)
+        .map(|(pass, reduced)| {
+            let reduced_stride = row_bytes(&reduced)?;
+            let mut rows = vec![0; reduced_stride * reduced.height as usize];
+            for (row, line) in rows.chunks_exact_mut(reduced_stride).enumerate() {
+                for column in 0..reduced.width {
+                    let (x, y) = pass.position(column, row as u32);
+                    let from = &data[y as usize * stride..][..stride];
+                    copy_pixel(from, x as usize, line, column as usize, bits);
+                }
+            }
+            Ok((reduced, rows))
+        })
+        .collect()
+}
+
+/// Copies pixel `from_index` of row `from` to pixel `to_index` of row `to`,
+/// both packed at `bits` bits per pixel.
+fn copy_pixel(from: &[u8], from_index: usize, to: &mut [u8], to_index: usize, bits: usize) {
+    if bits >= 8 {
+        let bytes = bits / 8;
+        to[to_index * bytes..][..bytes].copy_from_slice(&from[from_index * bytes..][..bytes]);
+        return;
+    }
+    // Pixels under a byte wide only occur with a single channel.
+    let value = read_sample(from, from_index, bits as u8) as u8;
+    let bit = to_index * bits;
+    let shift = 8 - bits - bit % 8;
+    let mask = ((1u8 << bits) - 1) << shift;
+    to[bit / 8] = (to[bit / 8] & !mask) | (value << shift);
+}
+
+/// Reads sample `index` of a row packed at `bit_depth` bits per sample.
+pub(crate) fn read_sample(row: &[u8], index: usize, bit_depth: u8) -> u16 {
+    match bit_depth {
+        16 => u16::from_be_bytes([row[2 * index], row[2 * index + 1]]),
+        8 => row[index].into(),
+        _ => {
+            let bits = bit_depth as usize;
+            let bit = index * bits;
+            let shift = 8 - bits - bit % 8;
+            ((row[bit / 8] >> shift) & ((1 << bits) - 1)).into()
+        }
+    }
+}
+
+/// Scales a sample of `bit_depth` bits to 8 bits.
+fn to_u8(sample: u16, bit_depth: u8) -> u8 {
+    match bit_depth {
+        16 => (sample >> 8) as u8,
+        8 => sample as u8,
+        _ => (u32::from(sample) * 255 / ((1 << bit_depth) - 1)) as u8,
+    }
+}
+
+/// Bytes needed for `width * height` pixels of `bytes_per_pixel` bytes.
+pub(crate) fn buffer_len(width: u32, height: u32, bytes_per_pixel: usize) -> Result<usize> {
+    (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(bytes_per_pixel))
+        .ok_or_else(|| IdatError::TooLarge.into())
+}
+
+/// Unfiltered image data in raster order, deinterlaced if it was stored
+/// interlaced, remembering each pass's filters so it can be stored back the
+/// way it was read. Code that works on samples by position, such as LSB
+/// embedding, sees the same layout whatever the interlace method.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Raster {
+    /// The rows of the full image back to back, `stride` bytes each, packed
+    /// as IHDR describes.
+    pub(crate) data: Vec<u8>,
+    pub(crate) stride: usize,
+    filters: Vec<Vec<Filter>>,
+}
+
+impl Png {
+    /// Decompresses, unfilters and deinterlaces the image data.
+    pub(crate) fn raster(&self) -> Result<Raster> {
+        let ihdr = self.ihdr()?;
+        let passes = unfilter_passes(&ihdr, &self.image_data()?)?;
+        let stride = row_bytes(&ihdr)?;
+        let filters = passes
+            .iter()
+            .map(|(_, scanlines)| scanlines.filters.clone())
+            .collect();
+        if ihdr.interlace == Interlace::None {
+            let data = passes
+                .into_iter()
+                .next()
+                .map(|(_, scanlines)| scanlines.data);
+            return Ok(Raster {
+                data: data.unwrap_or_default(),
+                stride,
+                filters,
+            });
+        }
+
+        let len = stride
+            .checked_mul(ihdr.height as usize)
+            .ok_or(IdatError::TooLarge)?;
+        let mut data = vec![0; len];
+        let bits = ihdr.bits_per_pixel();
+        for (pass, scanlines) in &passes {
+            let (width, _) = pass.size(ihdr.width, ihdr.height);
+            for (row, line) in scanlines.data.chunks_exact(scanlines.stride).enumerate() {
+                for column in 0..width {
+                    let (x, y) = pass.position(column, row as u32);
+                    let to = &mut data[y as usize * stride..][..stride];
+                    copy_pixel(line, column as usize, to, x as usize, bits);
+                }
+            }
+        }
+        Ok(Raster {
+            data,
+            stride,
+            filters,
+        })
+    }
+
+    /// Reinterlaces `raster` as IHDR says, refilters each pass with the
+    /// filters it was read with, and recompresses it into the IDAT chunks.
+    pub(crate) fn set_raster(&mut self, raster: &Raster) -> Result<()> {
+        let ihdr = self.ihdr()?;
+        let mut filtered = Vec::new();
+        for ((reduced, data), filters) in split_passes(&ihdr, &raster.data)?
+            .into_iter()
+            .zip(&raster.filters)
+        {
+            let scanlines = Scanlines {
+                stride: row_bytes(&reduced)?,
+                bytes_per_pixel: reduced.bits_per_pixel().div_ceil(8),
+                filters: filters.clone(),
+                data,
+            };
+            filtered.extend(scanlines.filter());
+        }
+        self.set_image_data(&filtered)
+    }
+
+    /// Decodes the image to 8-bit RGBA, whatever its color type, bit depth
+    /// and interlacing. Indexed colors come from PLTE, and transparency from
+    /// tRNS. 16-bit samples keep their high byte.
+    pub fn decode_pixels(&self) -> Result<ImageBuffer> {
+        let ihdr = self.ihdr()?;
+        ihdr.validate()?;
+        let palette = match ihdr.color_type {
+            ColorType::Indexed => Some(self.palette()?.ok_or(PixelError::MissingPalette)?),
+            _ => None,
+        };
+        let color_key = self.color_key(&ihdr);
+
+        let raster = self.raster()?;
+        let channels = ihdr.color_type.channels() as usize;
+        let mut data = Vec::with_capacity(buffer_len(ihdr.width, ihdr.height, 4)?);
+        for line in raster.data.chunks_exact(raster.stride) {
+            for x in 0..ihdr.width as usize {
+                let mut samples = [0; 4];
+                for (channel, sample) in samples[..channels].iter_mut().enumerate() {
+                    *sample = read_sample(line, x * channels + channel, ihdr.bit_depth);
+                }
+                data.extend(to_rgba(
+                    &ihdr,
+                    &samples,
+                    palette.as_ref(),
+                    color_key.as_deref(),
+                )?);
+            }
+        }
+        ImageBuffer::new(ihdr.width, ihdr.height, data)
+    }
+
+    /// Encodes 8-bit `pixels` laid out as `color_type` describes, row by row
+    /// from the top left, into a new non-interlaced PNG with just IHDR, IDAT
+    /// and IEND. An indexed image needs a palette added with
+    /// [`Png::set_palette`] before it can be decoded again.
+    ///
+    /// With [`ColorType::Rgba`], this writes back an [`ImageBuffer`] from
+    /// [`Png::decode_pixels`].
+    pub fn from_pixels(
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+        pixels: &[u8],
+    ) -> Result<Png> {
+        Png::from_pixels_with(width, height, color_type, pixels, Interlace::None)
+    }
+
+    /// Like [`Png::from_pixels`], storing the image with `interlace`.
+    pub fn from_pixels_with(
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+        pixels: &[u8],
+        interlace: Interlace,
+    ) -> Result<Png> {
+        let ihdr = Ihdr {
+            interlace,
+            ..Ihdr::new(width, height, 8, color_type)?
+        };
+        let expected = buffer_len(width, height, color_type.channels() as usize)?;
+        if pixels.len() != expected {
+            return Err(PixelError::BufferSize {
+                expected,
+                found: pixels.len(),
+            }
+            .into());
+        }
+        let mut filtered = Vec::new();
+        for (reduced, data) in split_passes(&ihdr, pixels)? {
+            filtered.extend(Scanlines::with_adaptive_filters(&reduced, data)?.filter());
+        }
+        let mut png = Png::from_chunks(vec![
+            ihdr.to_chunk(),
+            Chunk::new(ChunkType::IDAT, Vec::new()),
+            Chunk::new(ChunkType::IEND, Vec::new()),
+        ]);
+        png.set_image_data(&filtered)?;
+        Ok(png)
+    }
+
+    /// The samples tRNS marks as fully transparent in a grayscale or RGB
+    /// image, if any.
+    fn color_key(&self, ihdr: &Ihdr) -> Option<Vec<u16>> {
+        let channels = match ihdr.color_type {
+            ColorType::Grayscale => 1,
+            ColorType::Rgb => 3,
+            _ => return None,
+        };
+        let trns = self.chunk_by_type("tRNS")?.data();
+        (trns.len() == channels * 2).then(|| {
+            trns.chunks_exact(2)
+                .map(|sample| u16::from_be_bytes([sample[0], sample[1]]))
+                .collect()
+        })
+    }
+}
+
+fn to_rgba(
+    ihdr: &Ihdr,
+    samples: &[u16; 4],
+    palette: Option<&Palette>,
+    color_key: Option<&[u16]>,
+) -> Result<[u8; 4]> {
+    let depth = ihdr.bit_depth;
+    let keyed = |channels: usize| color_key == Some(&samples[..channels]);
+    let opaque = |keyed: bool| if keyed { 0 } else { u8::MAX };
+    Ok(match ihdr.color_type {
+        ColorType::Grayscale => {
+            let gray = to_u8(samples[0], depth);
+            [gray, gray, gray, opaque(keyed(1))]
+        }
+        ColorType::GrayscaleAlpha => {
+            let gray = to_u8(samples[0], depth);
+            [gray, gray, gray, to_u8(samples[1], depth)]
+        }
+        ColorType::Rgb => [
+            to_u8(samples[0], depth),
+            to_u8(samples[1], depth),
+            to_u8(samples[2], depth),
+            opaque(keyed(3)),
+        ],
+        ColorType::Rgba => samples.map(|sample| to_u8(sample, depth)),
+        ColorType::Indexed => {
+            let index = samples[0] as usize;
+            let palette = palette.ok_or(PixelError::MissingPalette)?;
+            let color = palette
+                .get(index)
+                .ok_or(PixelError::InvalidIndex(index as u8))?;
+            [color.r, color.g, color.b, palette.alpha(index)]
+        }
+    })
+}
+
+/// Errors raised while decoding or encoding pixels.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum PixelError {
+    /// An indexed image has no PLTE chunk.
+    #[error("indexed image has no PLTE chunk")]
+    MissingPalette,
+    /// A pixel refers to a palette entry that doesn't exist.
+    #[error("pixel refers to palette entry {0}, which doesn't exist")]
+    InvalidIndex(u8),
+    /// A pixel buffer holds the wrong number of bytes for its dimensions.
+    #[error("pixel buffer is {found} bytes, expected {expected}")]
+    BufferSize { expected: usize, found: usize },
+    /// Two images being compared have different dimensions.
+    #[error(
+        "images are {}x{} and {}x{}, expected the same size",
+        .first.0,
+        .first.1,
+        .second.0,
+        .second.1
+    )]
+    SizeMismatch {
+        first: (u32, u32),
+        second: (u32, u32),
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::Rgb;
+
+    fn png(ihdr: Ihdr, filtered: &[u8]) -> Png {
+        let mut png = Png::from_chunks(vec![
+            ihdr.to_chunk(),
+            Chunk::new(ChunkType::IDAT, Vec::new()),
+            Chunk::new(ChunkType::IEND, Vec::new()),
+        ]);
+        png.set_image_data(filtered).unwrap();
+        png
+    }
+
+    #[test]
+    fn test_decode_rgb16() {
+        let ihdr = Ihdr::new(2, 1, 16, ColorType::Rgb).unwrap();
+        let png = png(ihdr, &[0, 0xff, 0, 0x80, 0, 0x10, 0, 1, 2, 3, 4, 5, 6]);
+        let image = png.decode_pixels().unwrap();
+        assert_eq!(image.pixel(0, 0), [0xff, 0x80, 0x10, 0xff]);
+        assert_eq!(image.pixel(1, 0), [1, 3, 5, 0xff]);
+    }
+
+    #[test]
+    fn test_decode_gray2_with_color_key() {
+        let ihdr = Ihdr::new(3, 1, 2, ColorType::Grayscale).unwrap();
+        // Samples 3, 1 and 0, packed most significant bits first.
+        let mut png = png(ihdr, &[0, 0b11_01_00_00]);
+        png.append_chunk(Chunk::new(ChunkType::TRNS, vec![0, 1]));
+        let image = png.decode_pixels().unwrap();
+        assert_eq!(
+            image.as_bytes(),
+            [255, 255, 255, 255, 85, 85, 85, 0, 0, 0, 0, 255]
+        );
+    }
+
+    #[test]
+    fn test_decode_indexed() {
+        let ihdr = Ihdr::new(2, 2, 1, ColorType::Indexed).unwrap();
+        let mut png = png(ihdr, &[0, 0b0100_0000, 2, 0b1000_0000]);
+        let mut palette = Palette::new(vec![Rgb::new(1, 2, 3), Rgb::new(4, 5, 6)]).unwrap();
+        palette.set_alpha(1, 7).unwrap();
+        png.set_palette(&palette).unwrap();
+
+        let image = png.decode_pixels().unwrap();
+        assert_eq!(image.pixel(0, 0), [1, 2, 3, 255]);
+        assert_eq!(image.pixel(1, 0), [4, 5, 6, 7]);
+        // The Up filter adds the row above, byte-wise.
+        assert_eq!(image.pixel(0, 1), [4, 5, 6, 7]);
+        assert_eq!(image.pixel(1, 1), [4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn test_from_pixels_round_trip() {
+        let rgba: Vec<u8> = (0..5 * 3 * 4).map(|i| (i * 37 % 251) as u8).collect();
+        let png = Png::from_pixels(5, 3, ColorType::Rgba, &rgba).unwrap();
+        let reparsed = Png::try_from(png.as_bytes().as_slice()).unwrap();
+        assert_eq!(reparsed.decode_pixels().unwrap().as_bytes(), rgba);
+
+        let gray = Png::from_pixels(2, 1, ColorType::Grayscale, &[7, 9]).unwrap();
+        assert_eq!(
+            gray.decode_pixels().unwrap().as_bytes(),
+            [7, 7, 7, 255, 9, 9, 9, 255]
+        );
+        assert!(Png::from_pixels(2, 2, ColorType::Rgb, &[0; 11]).is_err());
+    }
+
+    #[test]
+    fn test_adam7_round_trip() {
+        // Too small for some passes to hold any pixels.
+        for (width, height) in [(1, 1), (3, 2), (9, 10)] {
+            let len = (width * height * 4) as usize;
+            let rgba: Vec<u8> = (0..len).map(|i| (i * 71 % 241) as u8).collect();
+            let png =
+                Png::from_pixels_with(width, height, ColorType::Rgba, &rgba, Interlace::Adam7)
+                    .unwrap();
+            assert_eq!(png.ihdr().unwrap().interlace, Interlace::Adam7);
+            assert_eq!(png.decode_pixels().unwrap().as_bytes(), rgba);
+        }
+    }
+
+    #[test]
+    fn test_adam7_packed_raster() {
+        let ihdr = Ihdr {
+            interlace: Interlace::Adam7,
+            ..Ihdr::new(11, 5, 1, ColorType::Grayscale).unwrap()
+        };
+        let raw: Vec<u8> = (0..2 * 5)
+            .map(|i| (i * 89 % 256) as u8 & 0xe0 | 0x15)
+            .collect();
+        // Bits past the right edge are padding, which deinterlacing zeroes.
+        let expected: Vec<u8> = raw
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| if i % 2 == 1 { byte & 0xe0 } else { *byte })
+            .collect();
+        let filtered: Vec<u8> = split_passes(&ihdr, &raw)
+            .unwrap()
+            .into_iter()
+            .flat_map(|(reduced, rows)| {
+                let stride = row_bytes(&reduced).unwrap();
+                rows.chunks_exact(stride)
+                    .flat_map(|row| std::iter::once(0).chain(row.iter().copied()))
+                    .collect::<Vec<u8>>()
+            })
+            .collect();
+
+        let mut png = png(ihdr, &filtered);
+        let raster = png.raster().unwrap();
+        assert_eq!(raster.data, expected);
+        png.set_raster(&raster).unwrap();
+        assert_eq!(png.image_data().unwrap(), filtered);
+    }
+
+    #[test]
+    fn test_buffer_size() {
+        assert!(ImageBuffer::new(2, 2, vec![0; 15]).is_err());
+        assert_eq!(ImageBuffer::new(2, 2, vec![0; 16]).unwrap().width(), 2);
+    }
+}
@@ -0,0 +1,1005 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Read};
+use std::ops::{Deref, Range};
+use std::path::Path;
+use std::slice;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use thiserror::Error;
+
+use crate::chunk::{self, Chunk, ChunkRef};
+use crate::chunk_type::ChunkType;
+use crate::copy_policy::{CopyPolicy, KNOWN_CHUNK_TYPES};
+use crate::crypto::{self, CipherSuite};
+use crate::ordering::Violation;
+use crate::reader::PngReader;
+use crate::writer;
+use crate::{PngMeError, Result};
+
+/// Controls how strictly PNG input is checked while parsing.
+///
+/// The default rejects anything malformed. Forensic tools can relax the
+/// checks to load damaged files, and servers can lower `max_chunk_size` and
+/// `max_total_size` to bound the memory hostile input can claim.
+///
+/// Start from [`default`](ParseOptions::default) or
+/// [`permissive`](ParseOptions::permissive) and change single checks with
+/// the `with_*` methods, since more options may be added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ParseOptions {
+    /// Reject chunks whose stored CRC does not match their contents. When
+    /// false the stored CRC is kept, and [`Chunk::has_valid_crc`] reports it.
+    pub strict_crc: bool,
+    /// Stop at IEND and ignore whatever follows, instead of parsing it as
+    /// more chunks.
+    pub allow_trailing_garbage: bool,
+    /// Largest chunk data length accepted. Checked before the data is read.
+    pub max_chunk_size: u32,
+    /// Largest number of bytes accepted across all chunks, framing included.
+    /// Checked before each chunk's data is read, so many chunks just under
+    /// `max_chunk_size` can't add up to an unbounded allocation either.
+    pub max_total_size: u64,
+    /// Reject chunk types containing anything but ASCII letters. When false
+    /// such types are kept as read, and [`ChunkType::is_valid`] reports them.
+    pub strict_chunk_types: bool,
+    /// What to do with critical chunks this crate doesn't know, which the
+    /// specification says a decoder can't safely ignore.
+    pub unknown_critical: UnknownCriticalPolicy,
+    /// Largest payload accepted once decompressed. Decompression stops as
+    /// soon as the output passes it, so a small chunk can't inflate into an
+    /// unbounded allocation.
+    pub max_payload_size: u64,
+}
+
+/// How parsing treats critical chunks of an unknown type.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnknownCriticalPolicy {
+    /// Fail with [`PngError::UnknownCriticalChunk`].
+    Error,
+    /// Keep the chunk and record a [`ParseWarning`] in the [`ParseReport`].
+    #[default]
+    Warn,
+    /// Keep the chunk without comment.
+    Keep,
+}
+
+impl ParseOptions {
+    /// The largest chunk length the specification allows, 2^31 - 1.
+    pub const SPEC_MAX_CHUNK_SIZE: u32 = chunk::MAX_CHUNK_LEN;
+    /// The default [`max_payload_size`](Self::max_payload_size), 256 MiB.
+    pub const DEFAULT_MAX_PAYLOAD_SIZE: u64 = 256 * 1024 * 1024;
+
+    /// Options that accept as much damaged input as possible.
+    pub fn permissive() -> ParseOptions {
+        ParseOptions {
+            strict_crc: false,
+            allow_trailing_garbage: true,
+            strict_chunk_types: false,
+            ..ParseOptions::default()
+        }
+    }
+
+    pub fn with_strict_crc(mut self, strict_crc: bool) -> ParseOptions {
+        self.strict_crc = strict_crc;
+        self
+    }
+
+    pub fn with_allow_trailing_garbage(mut self, allow_trailing_garbage: bool) -> ParseOptions {
+        self.allow_trailing_garbage = allow_trailing_garbage;
+        self
+    }
+
+    pub fn with_max_chunk_size(mut self, max_chunk_size: u32) -> ParseOptions {
+        self.max_chunk_size = max_chunk_size;
+        self
+    }
+
+    pub fn with_max_total_size(mut self, max_total_size: u64) -> ParseOptions {
+        self.max_total_size = max_total_size;
+        self
+    }
+
+    pub fn with_strict_chunk_types(mut self, strict_chunk_types: bool) -> ParseOptions {
+        self.strict_chunk_types = strict_chunk_types;
+        self
+    }
+
+    pub fn with_unknown_critical(
+        mut self,
+        unknown_critical: UnknownCriticalPolicy,
+    ) -> ParseOptions {
+        self.unknown_critical = unknown_critical;
+        self
+    }
+
+    pub fn with_max_payload_size(mut self, max_payload_size: u64) -> ParseOptions {
+        self.max_payload_size = max_payload_size;
+        self
+    }
+
+    pub(crate) fn check_length(&self, length: u32) -> Result<()> {
+        if length > self.max_chunk_size {
+            return Err(PngMeError::ChunkTooLarge {
+                length,
+                max: self.max_chunk_size,
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks that a chunk of `length` data bytes starting at byte `offset`
+    /// of the input ends within `max_total_size`.
+    pub(crate) fn check_total(&self, offset: u64, length: u32) -> Result<()> {
+        let end = offset + Chunk::OVERHEAD as u64 + u64::from(length);
+        if end > self.max_total_size {
+            return Err(PngMeError::InputTooLarge {
+                max: self.max_total_size,
+            });
+        }
+        Ok(())
+    }
+
+    /// Applies the unknown-critical policy to a chunk starting at byte
+    /// `offset` of the input.
+    pub(crate) fn check_critical(&self, chunk_type: &ChunkType, offset: u64) -> Result<()> {
+        if self.unknown_critical == UnknownCriticalPolicy::Error && is_unknown_critical(chunk_type)
+        {
+            return Err(PngError::UnknownCriticalChunk {
+                chunk_type: chunk_type.to_string(),
+                offset,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    pub(crate) fn chunk_type(&self, bytes: [u8; 4]) -> Result<ChunkType> {
+        match self.strict_chunk_types {
+            true => ChunkType::try_from(bytes),
+            false => Ok(ChunkType::new_unchecked(bytes)),
+        }
+    }
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            strict_crc: true,
+            allow_trailing_garbage: false,
+            max_chunk_size: ParseOptions::SPEC_MAX_CHUNK_SIZE,
+            max_total_size: u64::MAX,
+            strict_chunk_types: true,
+            unknown_critical: UnknownCriticalPolicy::default(),
+            max_payload_size: ParseOptions::DEFAULT_MAX_PAYLOAD_SIZE,
+        }
+    }
+}
+
+fn is_unknown_critical(chunk_type: &ChunkType) -> bool {
+    chunk_type.is_critical() && !KNOWN_CHUNK_TYPES.contains(&&chunk_type.bytes())
+}
+
+/// Something parsing let through that the caller may want to know about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParseWarning {
+    /// The chunk at `position`, starting at byte `offset`, is critical but of
+    /// a type this crate doesn't know, so the image may not decode as its
+    /// author intended.
+    UnknownCriticalChunk {
+        position: usize,
+        offset: Option<u64>,
+        chunk_type: String,
+    },
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseWarning::UnknownCriticalChunk {
+                position,
+                chunk_type,
+                ..
+            } => write!(f, "chunk {position} ({chunk_type}): unknown critical chunk"),
+        }
+    }
+}
+
+/// The warnings raised while parsing a [`Png`], from methods such as
+/// [`Png::from_bytes_with_report`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParseReport {
+    pub warnings: Vec<ParseWarning>,
+}
+
+impl ParseReport {
+    fn new(png: &Png, options: &ParseOptions) -> ParseReport {
+        if options.unknown_critical != UnknownCriticalPolicy::Warn {
+            return ParseReport::default();
+        }
+        let warnings = png
+            .chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| is_unknown_critical(chunk.chunk_type()))
+            .map(|(position, chunk)| ParseWarning::UnknownCriticalChunk {
+                position,
+                offset: chunk.offset(),
+                chunk_type: chunk.chunk_type().to_string(),
+            })
+            .collect();
+        ParseReport { warnings }
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.warnings.is_empty()
+    }
+}
+
+/// A PNG file: the 8-byte signature followed by a sequence of chunks.
+///
+/// Adding, removing or replacing a critical chunk applies the PNG's
+/// [`CopyPolicy`] to the chunks that are left.
+///
+/// Every method edits this in-memory copy only. The file it was read from
+/// is left as it was until the result is written out with
+/// [`Png::write_to`].
+#[derive(Debug, Clone)]
+pub struct Png {
+    chunks: Vec<Chunk>,
+    copy_policy: CopyPolicy,
+}
+
+impl Png {
+    /// The signature every PNG file starts with.
+    pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+    /// Builds a PNG from an already-parsed list of chunks.
+    pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
+        Png {
+            chunks,
+            copy_policy: CopyPolicy::default(),
+        }
+    }
+
+    /// Reads and parses the PNG at `path`, one chunk at a time.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Png> {
+        Png::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Parses a PNG from any reader without buffering the whole input first.
+    pub fn from_reader<R: Read>(reader: R) -> Result<Png> {
+        Png::from_reader_with(reader, ParseOptions::default())
+    }
+
+    /// Like [`Png::from_reader`], checking the input as `options` require.
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub fn from_reader_with<R: Read>(reader: R, options: ParseOptions) -> Result<Png> {
+        let chunks = PngReader::with_options(reader, options)?.collect::<Result<Vec<_>>>()?;
+        Ok(Png::from_chunks(chunks))
+    }
+
+    /// Like [`Png::from_reader_with`], also returning the warnings raised.
+    pub fn from_reader_with_report<R: Read>(
+        reader: R,
+        options: ParseOptions,
+    ) -> Result<(Png, ParseReport)> {
+        let png = Png::from_reader_with(reader, options)?;
+        let report = ParseReport::new(&png, &options);
+        Ok((png, report))
+    }
+
+    /// Parses a PNG held in memory, checking the input as `options` require.
+    #[tracing::instrument(level = "debug", skip_all, fields(len = bytes.len()))]
+    pub fn from_bytes_with(bytes: &[u8], options: &ParseOptions) -> Result<Png> {
+        let chunks = Png::chunk_refs(bytes, options)?
+            .map(|chunk| chunk.map(Chunk::from))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Png::from_chunks(chunks))
+    }
+
+    /// Like [`Png::from_bytes_with`], also returning the warnings raised.
+    pub fn from_bytes_with_report(
+        bytes: &[u8],
+        options: &ParseOptions,
+    ) -> Result<(Png, ParseReport)> {
+        let png = Png::from_bytes_with(bytes, options)?;
+        let report = ParseReport::new(&png, options);
+        Ok((png, report))
+    }
+
+    /// Iterates over the chunks of a PNG held in memory without copying their
+    /// data, checking each as `options` require. Only the signature is
+    /// checked up front.
+    pub fn chunk_refs<'a>(bytes: &'a [u8], options: &ParseOptions) -> Result<ChunkRefs<'a>> {
+        let rest = bytes
+            .strip_prefix(&Self::STANDARD_HEADER)
+            .ok_or(PngMeError::InvalidSignature)?;
+        Ok(ChunkRefs {
+            bytes,
+            rest,
+            options: *options,
+            done: false,
+        })
+    }
+
+    /// Inserts `chunk` just before the trailing IEND chunk, or at the end if
+    /// there is none. In an animated PNG this is after the last frame, so
+    /// sequence numbers and frame data stay intact.
+    pub fn append_chunk(&mut self, chunk: Chunk) {
+        let position = match self.chunks.last() {
+            Some(last) if *last.chunk_type() == ChunkType::IEND => self.chunks.len() - 1,
+            _ => self.chunks.len(),
+        };
+        self.insert_chunk(position, chunk);
+    }
+
+    /// Inserts `chunk` at `position`, shifting later chunks back.
+    pub(crate) fn insert_chunk(&mut self, position: usize, chunk: Chunk) {
+        let critical = chunk.chunk_type().is_critical();
+        self.chunks.insert(position, chunk);
+        if critical {
+            self.critical_chunks_changed();
+        }
+    }
+
+    /// The chunks, for rewriting them in place without applying the copy
+    /// policy. This is the raw escape hatch for edits that leave the image
+    /// as it was, such as merging or recompressing IDAT chunks; anything
+    /// else goes through the methods that apply it.
+    pub(crate) fn chunks_mut(&mut self) -> &mut Vec<Chunk> {
+        &mut self.chunks
+    }
+
+    /// Inserts `chunk` before the IEND chunk of the file at `path` without
+    /// reading or rewriting the chunks that precede it.
+    pub fn append_chunk_in_place<P: AsRef<Path>>(path: P, chunk: Chunk) -> Result<()> {
+        Png::append_chunks_in_place(path, &[chunk])
+    }
+
+    /// Like [`Png::append_chunk_in_place`], for several chunks kept in order.
+    pub fn append_chunks_in_place<P: AsRef<Path>>(path: P, chunks: &[Chunk]) -> Result<()> {
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        writer::insert_before_iend(&mut file, chunks)
+    }
+
+    /// Encrypts `message` with `password` and appends it as a new chunk.
+    pub fn encode_encrypted(
+        &mut self,
+        chunk_type: ChunkType,
+        message: &[u8],
+        password: &str,
+        suite: CipherSuite,
+    ) -> Result<()> {
+        let payload = crypto::encrypt(suite, password, message, &[])?;
+        self.append_chunk(Chunk::new(chunk_type, payload));
+        Ok(())
+    }
+
+    /// Decrypts the first chunk of the given type with `password`, using
+    /// whichever cipher suite it was encrypted with.
+    pub fn decode_encrypted(&self, chunk_type: &str, password: &str) -> Result<Vec<u8>> {
+        let chunk = self
+            .chunk_by_type(chunk_type)
+            .ok_or_else(|| PngMeError::ChunkNotFound(chunk_type.to_string()))?;
+        crypto::decrypt(password, chunk.data(), &[])
+    }
+
+    /// Checks the CRC of every chunk, failing on the first mismatch in file
+    /// order. With the `parallel` feature the chunks are checked across
+    /// threads, which pays off for files with many large IDAT chunks.
+    pub fn validate(&self) -> Result<()> {
+        #[cfg(feature = "parallel")]
+        let bad = self
+            .chunks
+            .par_iter()
+            .find_first(|chunk| !chunk.has_valid_crc());
+        #[cfg(not(feature = "parallel"))]
+        let bad = self.chunks.iter().find(|chunk| !chunk.has_valid_crc());
+        bad.map_or(Ok(()), Chunk::verify_crc)
+    }
+
+    /// Removes and returns the first chunk of the given type.
+    pub fn remove_first_chunk(&mut self, chunk_type: &str) -> Result<Chunk> {
+        let position = self
+            .position_of(chunk_type)
+            .ok_or_else(|| PngMeError::ChunkNotFound(chunk_type.to_string()))?;
+        self.remove_chunk_at(position)
+    }
+
+    /// Removes and returns every chunk of the given type, in file order.
+    pub fn remove_all_chunks(&mut self, chunk_type: &str) -> Result<Vec<Chunk>> {
+        let (removed, kept) = std::mem::take(&mut self.chunks)
+            .into_iter()
+            .partition(|chunk: &Chunk| chunk.chunk_type().to_string() == chunk_type);
+        self.chunks = kept;
+        if removed.is_empty() {
+            return Err(PngMeError::ChunkNotFound(chunk_type.to_string()));
+        }
+        if removed.iter().any(|chunk| chunk.chunk_type().is_critical()) {
+            self.critical_chunks_changed();
+        }
+        Ok(removed)
+    }
+
+    /// Removes and returns the chunk at `position`.
+    pub fn remove_chunk_at(&mut self, position: usize) -> Result<Chunk> {
+        self.check_position(position, self.chunks.len())?;
+        let chunk = self.chunks.remove(position);
+        if chunk.chunk_type().is_critical() {
+            self.critical_chunks_changed();
+        }
+        Ok(chunk)
+    }
+
+    /// Puts `chunk` in place of the first chunk of the given type, returning
+    /// the chunk it replaced.
+    pub fn replace_chunk(&mut self, chunk_type: &str, chunk: Chunk) -> Result<Chunk> {
+        let position = self
+            .position_of(chunk_type)
+            .ok_or_else(|| PngMeError::ChunkNotFound(chunk_type.to_string()))?;
+        let critical = chunk.chunk_type().is_critical();
+        let old = std::mem::replace(&mut self.chunks[position], chunk);
+        if critical || old.chunk_type().is_critical() {
+            self.critical_chunks_changed();
+        }
+        Ok(old)
+    }
+
+    /// Inserts `chunk` at `position`, shifting later chunks back. Fails,
+    /// leaving the PNG untouched, if that would break a chunk ordering rule
+    /// the PNG didn't already break, such as putting PLTE after IDAT.
+    pub fn insert_chunk_at(&mut self, position: usize, chunk: Chunk) -> Result<()> {
+        self.check_position(position, self.chunks.len() + 1)?;
+        let mut existing: Vec<_> = self
+            .validate_ordering()
+            .into_iter()
+            .map(|violation| (violation.chunk_type, violation.rule))
+            .collect();
+
+        let critical = chunk.chunk_type().is_critical();
+        self.chunks.insert(position, chunk);
+        let introduced = self.validate_ordering().into_iter().find(|violation| {
+            let known = existing
+                .iter()
+                .position(|(chunk_type, rule)| {
+                    *chunk_type == violation.chunk_type && *rule == violation.rule
+                })
+                .map(|known| existing.swap_remove(known));
+            known.is_none()
+        });
+        if let Some(violation) = introduced {
+            self.chunks.remove(position);
+            return Err(PngError::Misplaced(violation).into());
+        }
+        if critical {
+            self.critical_chunks_changed();
+        }
+        Ok(())
+    }
+
+    fn check_position(&self, position: usize, end: usize) -> Result<()> {
+        match position < end {
+            true => Ok(()),
+            false => Err(PngError::PositionOutOfRange {
+                position,
+                len: self.chunks.len(),
+            }
+            .into()),
+        }
+    }
+
+    /// Keeps only the chunks for which `keep` returns true, applying the
+    /// copy policy if a critical chunk was dropped.
+    pub fn retain_chunks<F: FnMut(&Chunk) -> bool>(&mut self, mut keep: F) {
+        let mut critical = false;
+        self.chunks.retain(|chunk| {
+            let kept = keep(chunk);
+            critical |= !kept && chunk.chunk_type().is_critical();
+            kept
+        });
+        if critical {
+            self.critical_chunks_changed();
+        }
+    }
+
+    pub fn copy_policy(&self) -> CopyPolicy {
+        self.copy_policy
+    }
+
+    /// Sets what happens to unsafe-to-copy chunks on later critical edits.
+    pub fn set_copy_policy(&mut self, policy: CopyPolicy) {
+        self.copy_policy = policy;
+    }
+
+    /// The PNG signature.
+    pub fn header(&self) -> &[u8; 8] {
+        &Self::STANDARD_HEADER
+    }
+
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+
+    /// Returns the first chunk of the given type, if any.
+    pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
+        self.position_of(chunk_type).map(|i| &self.chunks[i])
+    }
+
+    /// Every chunk of the given type, in file order.
+    pub fn chunks_of_type(&self, chunk_type: ChunkType) -> impl Iterator<Item = &Chunk> {
+        self.chunks
+            .iter()
+            .filter(move |chunk| *chunk.chunk_type() == chunk_type)
+    }
+
+    /// Iterates over the chunks with handles that can rewrite their data.
+    pub fn iter_mut(&mut self) -> IterMut<'_> {
+        IterMut {
+            inner: self.chunks.iter_mut(),
+        }
+    }
+
+    /// The byte range each chunk would take if the PNG were serialized now,
+    /// framing included, in file order. For a PNG that hasn't been edited
+    /// since it was parsed, each range starts at the chunk's
+    /// [`Chunk::offset`], so a chunk can be patched in the original file
+    /// without rewriting the rest.
+    pub fn chunk_spans(&self) -> Vec<Range<u64>> {
+        let mut offset = Self::STANDARD_HEADER.len() as u64;
+        self.chunks
+            .iter()
+            .map(|chunk| {
+                let start = offset;
+                offset += chunk.total_size();
+                start..offset
+            })
+            .collect()
+    }
+
+    /// Serializes the signature followed by every chunk.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        Self::STANDARD_HEADER
+            .iter()
+            .copied()
+            .chain(self.chunks.iter().flat_map(Chunk::as_bytes))
+            .collect()
+    }
+
+    fn position_of(&self, chunk_type: &str) -> Option<usize> {
+        self.chunks
+            .iter()
+            .position(|chunk| chunk.chunk_type().to_string() == chunk_type)
+    }
+}
+
+impl<'a> IntoIterator for &'a Png {
+    type Item = &'a Chunk;
+    type IntoIter = slice::Iter<'a, Chunk>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.chunks.iter()
+    }
+}
+
+/// The chunks of a [`Png`] as [`ChunkMut`] handles, from [`Png::iter_mut`].
+#[derive(Debug)]
+pub struct IterMut<'a> {
+    inner: slice::IterMut<'a, Chunk>,
+}
+
+impl<'a> Iterator for IterMut<'a> {
+    type Item = ChunkMut<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|chunk| ChunkMut { chunk })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl ExactSizeIterator for IterMut<'_> {}
+
+/// A chunk of a [`Png`] whose data can be rewritten in place.
+///
+/// Critical chunks can't be: changing them has to apply the PNG's
+/// [`CopyPolicy`], which only the methods on [`Png`] itself do.
+#[derive(Debug)]
+pub struct ChunkMut<'a> {
+    chunk: &'a mut Chunk,
+}
+
+impl ChunkMut<'_> {
+    /// Replaces the chunk's data, updating its length and CRC.
+    pub fn set_data(&mut self, data: Vec<u8>) -> Result<()> {
+        let chunk_type = *self.chunk.chunk_type();
+        if chunk_type.is_critical() {
+            return Err(PngError::CriticalChunk(chunk_type.to_string()).into());
+        }
+        *self.chunk = Chunk::new(chunk_type, data);
+        Ok(())
+    }
+}
+
+impl Deref for ChunkMut<'_> {
+    type Target = Chunk;
+
+    fn deref(&self) -> &Chunk {
+        self.chunk
+    }
+}
+
+impl TryFrom<&[u8]> for Png {
+    type Error = PngMeError;
+
+    fn try_from(bytes: &[u8]) -> Result<Png> {
+        Png::from_bytes_with(bytes, &ParseOptions::default())
+    }
+}
+
+/// The chunks of a PNG held in memory, from [`Png::chunk_refs`]. Iteration
+/// stops at end of input or after the first error.
+#[derive(Debug, Clone)]
+pub struct ChunkRefs<'a> {
+    bytes: &'a [u8],
+    rest: &'a [u8],
+    options: ParseOptions,
+    done: bool,
+}
+
+impl<'a> Iterator for ChunkRefs<'a> {
+    type Item = Result<ChunkRef<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.rest.is_empty() {
+            return None;
+        }
+        let offset = (self.bytes.len() - self.rest.len()) as u64;
+        let _span = tracing::debug_span!("chunk", offset).entered();
+        let chunk = split_chunk(self.rest, &self.options).and_then(|(chunk, tail)| {
+            self.options.check_total(offset, chunk.length())?;
+            self.options.check_critical(chunk.chunk_type(), offset)?;
+            Ok((chunk, tail))
+        });
+        match chunk {
+            Ok((chunk, tail)) => {
+                tracing::trace!(chunk_type = %chunk.chunk_type(), length = chunk.length(), "read chunk");
+                self.rest = tail;
+                self.done =
+                    *chunk.chunk_type() == ChunkType::IEND && self.options.allow_trailing_garbage;
+                Some(Ok(chunk.at_offset(offset)))
+            }
+            Err(err) => {
+                tracing::debug!(%err, "stopped reading");
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Splits the first chunk off `bytes`, returning it with the remaining input.
+fn split_chunk<'a>(bytes: &'a [u8], options: &ParseOptions) -> Result<(ChunkRef<'a>, &'a [u8])> {
+    if bytes.len() < Chunk::OVERHEAD {
+        return Err(PngError::Truncated.into());
+    }
+    let length = u32::from_be_bytes(bytes[..4].try_into()?);
+    options.check_length(length)?;
+    let end = (length as usize)
+        .checked_add(Chunk::OVERHEAD)
+        .filter(|&end| end <= bytes.len())
+        .ok_or(PngError::Truncated)?;
+
+    let (chunk, rest) = bytes.split_at(end);
+    Ok((ChunkRef::from_bytes_with(chunk, options)?, rest))
+}
+
+impl fmt::Display for Png {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for chunk in &self.chunks {
+            writeln!(f, "{chunk}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Errors raised while parsing or editing a [`Png`].
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum PngError {
+    /// The input ended in the middle of a chunk.
+    #[error("input ends in the middle of a chunk")]
+    Truncated,
+    /// A critical chunk, of the type given, can't be edited through a
+    /// [`ChunkMut`].
+    #[error("critical {0} chunk can't be edited in place")]
+    CriticalChunk(String),
+    /// A chunk position is past the end of the PNG's `len` chunks.
+    #[error("position {position} is out of range for {len} chunks")]
+    PositionOutOfRange { position: usize, len: usize },
+    /// Inserting a chunk would break the given ordering rule.
+    #[error("inserting there would break chunk ordering: {0}")]
+    Misplaced(Violation),
+    /// A chunk position that isn't `before-idat`, `after-idat`,
+    /// `before-iend` or `index:N`.
+    #[error(
+        "invalid chunk position {0:?}, expected before-idat, after-idat, before-iend or index:N"
+    )]
+    InvalidPosition(String),
+    /// A critical chunk of an unknown type, starting at byte `offset`, was
+    /// found under [`UnknownCriticalPolicy::Error`].
+    #[error("unknown critical {chunk_type} chunk at offset {offset}")]
+    UnknownCriticalChunk { chunk_type: String, offset: u64 },
+}
+
+/// A PNG of a zeroed IHDR and an IEND, for tests that need one to hold
+/// chunks but don't care about the image.
+#[cfg(test)]
+pub(crate) fn minimal_png() -> Png {
+    Png::from_chunks(vec![
+        Chunk::new(ChunkType::IHDR, vec![0; 13]),
+        Chunk::new(ChunkType::IEND, Vec::new()),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn png() -> Png {
+        let mut png = minimal_png();
+        png.append_chunk(Chunk::new(ChunkType::TEXT, b"Title\0first".to_vec()));
+        png.append_chunk(Chunk::new(ChunkType::IDAT, Vec::new()));
+        png.append_chunk(Chunk::new(ChunkType::TEXT, b"Title\0second".to_vec()));
+        png
+    }
+
+    fn testing_chunk() -> Chunk {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        Chunk::new(
+            chunk_type,
+            b"This is where your secret message will be!".to_vec(),
+        )
+    }
+
+    #[test]
+    fn test_from_chunks() {
+        let png = png();
+        assert_eq!(png.header(), &Png::STANDARD_HEADER);
+        assert_eq!(png.chunks().len(), 5);
+        assert_eq!(png.chunks()[0].chunk_type(), &ChunkType::IHDR);
+        assert_eq!(&png.as_bytes()[..8], &Png::STANDARD_HEADER);
+        let parsed = Png::try_from(png.as_bytes().as_slice()).unwrap();
+        assert_eq!(parsed.chunks(), png.chunks());
+    }
+
+    #[test]
+    fn test_append_chunk_goes_before_iend() {
+        let mut png = png();
+        png.append_chunk(Chunk::new(ChunkType::TEXT, b"Title\0third".to_vec()));
+        assert_eq!(png.chunks().len(), 6);
+        assert_eq!(png.chunks()[4].data(), b"Title\0third");
+        assert_eq!(png.chunks()[5].chunk_type(), &ChunkType::IEND);
+    }
+
+    #[test]
+    fn test_append_chunk_without_iend() {
+        let mut png = Png::from_chunks(vec![Chunk::new(ChunkType::IHDR, vec![0; 13])]);
+        png.append_chunk(Chunk::new(ChunkType::TEXT, b"Title\0only".to_vec()));
+        assert_eq!(png.chunks().len(), 2);
+        assert_eq!(png.chunks()[1].data(), b"Title\0only");
+    }
+
+    #[test]
+    fn test_chunk_by_type() {
+        let png = png();
+        assert_eq!(png.chunk_by_type("tEXt").unwrap().data(), b"Title\0first");
+        assert!(png.chunk_by_type("ruSt").is_none());
+    }
+
+    #[test]
+    fn test_remove_first_chunk() {
+        let mut png = png();
+        let removed = png.remove_first_chunk("tEXt").unwrap();
+        assert_eq!(removed.data(), b"Title\0first");
+        assert_eq!(png.chunks().len(), 4);
+        assert_eq!(png.chunk_by_type("tEXt").unwrap().data(), b"Title\0second");
+        assert!(matches!(
+            png.remove_first_chunk("ruSt"),
+            Err(PngMeError::ChunkNotFound(chunk_type)) if chunk_type == "ruSt"
+        ));
+        assert_eq!(png.chunks().len(), 4);
+    }
+
+    #[test]
+    fn test_iterators() {
+        let png = png();
+        assert_eq!((&png).into_iter().count(), 5);
+        let texts: Vec<&[u8]> = png
+            .chunks_of_type(ChunkType::TEXT)
+            .map(Chunk::data)
+            .collect();
+        assert_eq!(texts, [&b"Title\0first"[..], b"Title\0second"]);
+    }
+
+    #[test]
+    fn test_iter_mut() {
+        let mut png = png();
+        for mut chunk in png.iter_mut() {
+            if *chunk.chunk_type() == ChunkType::TEXT {
+                chunk.set_data(b"Title\0edited".to_vec()).unwrap();
+            } else if *chunk.chunk_type() == ChunkType::IDAT {
+                assert!(matches!(
+                    chunk.set_data(vec![0]),
+                    Err(PngMeError::Png(PngError::CriticalChunk(_)))
+                ));
+            }
+        }
+        assert!(png
+            .chunks_of_type(ChunkType::TEXT)
+            .all(|chunk| chunk.data() == b"Title\0edited" && chunk.has_valid_crc()));
+        assert_eq!(png.chunks().len(), 5);
+    }
+
+    #[test]
+    fn test_remove_all_and_replace() {
+        let mut png = png();
+        let removed = png.remove_all_chunks("tEXt").unwrap();
+        assert_eq!(removed.len(), 2);
+        assert!(png.chunks_of_type(ChunkType::TEXT).next().is_none());
+        assert!(matches!(
+            png.remove_all_chunks("tEXt"),
+            Err(PngMeError::ChunkNotFound(_))
+        ));
+
+        let chunk = Chunk::new(ChunkType::IDAT, vec![1, 2, 3]);
+        let old = png.replace_chunk("IDAT", chunk.clone()).unwrap();
+        assert!(old.data().is_empty());
+        assert_eq!(png.chunks()[1], chunk);
+    }
+
+    #[test]
+    fn test_insert_chunk_at() {
+        let mut png = png();
+        let gama = Chunk::new(ChunkType::try_from(*b"gAMA").unwrap(), vec![0; 4]);
+        png.insert_chunk_at(1, gama.clone()).unwrap();
+        assert_eq!(png.chunks()[1], gama);
+
+        assert!(matches!(
+            png.insert_chunk_at(4, gama.clone()),
+            Err(PngMeError::Png(PngError::Misplaced(_)))
+        ));
+        assert!(matches!(
+            png.insert_chunk_at(7, gama),
+            Err(PngMeError::Png(PngError::PositionOutOfRange { .. }))
+        ));
+        assert_eq!(png.chunks().len(), 6);
+
+        let removed = png.remove_chunk_at(1).unwrap();
+        assert_eq!(removed.chunk_type().to_string(), "gAMA");
+    }
+
+    #[test]
+    fn test_unknown_critical_policy() {
+        let mut png = png();
+        png.append_chunk(Chunk::new(ChunkType::new_unchecked(*b"RuSt"), Vec::new()));
+        let bytes = png.as_bytes();
+        let with =
+            |unknown_critical| ParseOptions::default().with_unknown_critical(unknown_critical);
+
+        let (_, report) =
+            Png::from_bytes_with_report(&bytes, &with(UnknownCriticalPolicy::Warn)).unwrap();
+        assert_eq!(
+            report.warnings,
+            [ParseWarning::UnknownCriticalChunk {
+                position: 4,
+                offset: Some(bytes.len() as u64 - 24),
+                chunk_type: "RuSt".to_string(),
+            }]
+        );
+        let (_, report) =
+            Png::from_reader_with_report(&bytes[..], with(UnknownCriticalPolicy::Keep)).unwrap();
+        assert!(report.is_clean());
+
+        let error = with(UnknownCriticalPolicy::Error);
+        for result in [
+            Png::from_bytes_with(&bytes, &error),
+            Png::from_reader_with(&bytes[..], error),
+        ] {
+            assert!(matches!(
+                result,
+                Err(PngMeError::Png(PngError::UnknownCriticalChunk { .. }))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_validate_reports_first_bad_crc() {
+        let mut bytes = testing_chunk().as_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        let bad = Chunk::from_bytes_with(&bytes, &ParseOptions::permissive()).unwrap();
+
+        let mut png = Png::from_chunks(vec![testing_chunk(), testing_chunk()]);
+        assert!(png.validate().is_ok());
+        png.append_chunk(bad.clone());
+        let bad_iend =
+            Chunk::from_parts(ChunkType::IEND, Vec::new(), 0, &ParseOptions::permissive());
+        png.append_chunk(bad_iend.unwrap());
+        let err = png.validate().unwrap_err();
+        assert!(matches!(
+            err,
+            PngMeError::CrcMismatch { ref chunk_type, found, .. }
+                if chunk_type == "RuSt" && found == bad.crc()
+        ));
+    }
+
+    #[test]
+    fn test_max_total_size() {
+        let bytes = Png::from_chunks(vec![testing_chunk(), testing_chunk()]).as_bytes();
+        // Room for the signature and the first chunk only.
+        let options = ParseOptions::default().with_max_total_size(8 + testing_chunk().total_size());
+        assert!(matches!(
+            Png::from_bytes_with(&bytes, &options),
+            Err(PngMeError::InputTooLarge { .. })
+        ));
+        assert!(matches!(
+            Png::from_reader_with(bytes.as_slice(), options),
+            Err(PngMeError::InputTooLarge { .. })
+        ));
+        let options = options.with_max_total_size(bytes.len() as u64);
+        assert!(Png::from_bytes_with(&bytes, &options).is_ok());
+    }
+
+    #[test]
+    fn test_corrupt_input_never_panics() {
+        let mut png = minimal_png();
+        png.append_chunk(testing_chunk());
+        let png = png.as_bytes();
+        let parse_all = |bytes: &[u8]| {
+            let _ = Chunk::try_from(bytes);
+            let _ = Chunk::try_from(bytes.get(8..).unwrap_or_default());
+            let _ = Png::try_from(bytes);
+            let _ = Png::from_bytes_with(bytes, &ParseOptions::permissive());
+            let _ = Png::from_reader(bytes);
+        };
+
+        for len in 0..png.len() {
+            parse_all(&png[..len]);
+        }
+        for i in 0..png.len() {
+            for byte in [0x00, 0x7f, 0x80, 0xff] {
+                let mut corrupt = png.clone();
+                corrupt[i] = byte;
+                parse_all(&corrupt);
+            }
+        }
+    }
+
+    #[test]
+    fn test_chunk_refs_borrow_input() {
+        let mut png = minimal_png();
+        png.append_chunk(testing_chunk());
+        let bytes = png.as_bytes();
+        let chunks = Png::chunk_refs(&bytes, &ParseOptions::default())
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+
+        let found = chunks[1];
+        assert_eq!(found.offset(), Some(33));
+        assert!(bytes.as_ptr_range().contains(&found.data().as_ptr()));
+        assert_eq!(Chunk::from(found), testing_chunk());
+        assert_eq!(
+            testing_chunk().as_chunk_ref(),
+            found.to_chunk().as_chunk_ref()
+        );
+    }
+}
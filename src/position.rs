@@ -0,0 +1,154 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::{Png, PngError};
+use crate::{PngMeError, Result};
+
+/// Where new chunks are inserted. Viewers and scanners treat unknown
+/// ancillary chunks differently depending on where they sit, so hidden
+/// chunks can be placed alongside the image data rather than at the end.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChunkPosition {
+    /// Just before the first IDAT chunk.
+    BeforeIdat,
+    /// Just after the last IDAT chunk.
+    AfterIdat,
+    /// Just before IEND, where [`Png::append_chunk`] puts chunks.
+    #[default]
+    BeforeIend,
+    /// At this index in the chunk list.
+    Index(usize),
+}
+
+impl ChunkPosition {
+    /// The index in `png`'s chunk list this position refers to.
+    pub fn resolve(self, png: &Png) -> Result<usize> {
+        let chunks = png.chunks();
+        let is = |chunk_type: ChunkType| move |chunk: &Chunk| *chunk.chunk_type() == chunk_type;
+        let idat = || PngMeError::ChunkNotFound(ChunkType::IDAT.to_string());
+        match self {
+            ChunkPosition::BeforeIdat => {
+                chunks.iter().position(is(ChunkType::IDAT)).ok_or_else(idat)
+            }
+            ChunkPosition::AfterIdat => chunks
+                .iter()
+                .rposition(is(ChunkType::IDAT))
+                .map(|last| last + 1)
+                .ok_or_else(idat),
+            ChunkPosition::BeforeIend => Ok(match chunks.last() {
+                Some(last) if *last.chunk_type() == ChunkType::IEND => chunks.len() - 1,
+                _ => chunks.len(),
+            }),
+            ChunkPosition::Index(index) => Ok(index),
+        }
+    }
+}
+
+impl FromStr for ChunkPosition {
+    type Err = PngMeError;
+
+    /// Parses `before-idat`, `after-idat`, `before-iend` or `index:N`.
+    fn from_str(s: &str) -> Result<ChunkPosition> {
+        match s {
+            "before-idat" => Ok(ChunkPosition::BeforeIdat),
+            "after-idat" => Ok(ChunkPosition::AfterIdat),
+            "before-iend" => Ok(ChunkPosition::BeforeIend),
+            _ => s
+                .strip_prefix("index:")
+                .and_then(|index| index.parse().ok())
+                .map(ChunkPosition::Index)
+                .ok_or_else(|| PngError::InvalidPosition(s.to_string()).into()),
+        }
+    }
+}
+
+impl fmt::Display for ChunkPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChunkPosition::BeforeIdat => write!(f, "before-idat"),
+            ChunkPosition::AfterIdat => write!(f, "after-idat"),
+            ChunkPosition::BeforeIend => write!(f, "before-iend"),
+            ChunkPosition::Index(index) => write!(f, "index:{index}"),
+        }
+    }
+}
+
+impl Png {
+    /// Inserts `chunks`, kept in order, at `position`. Anywhere but before
+    /// IEND the placement is checked like [`Png::insert_chunk_at`].
+    pub fn insert_chunks(&mut self, position: ChunkPosition, chunks: Vec<Chunk>) -> Result<()> {
+        if position == ChunkPosition::BeforeIend {
+            for chunk in chunks {
+                self.append_chunk(chunk);
+            }
+            return Ok(());
+        }
+        let start = position.resolve(self)?;
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            self.insert_chunk_at(start + i, chunk)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    use crate::png::minimal_png;
+
+    fn png() -> Png {
+        let mut png = minimal_png();
+        png.append_chunk(Chunk::new(ChunkType::IDAT, Vec::new()));
+        png.append_chunk(Chunk::new(ChunkType::IDAT, Vec::new()));
+        png
+    }
+
+    fn types(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        for position in ["before-idat", "after-idat", "before-iend", "index:3"] {
+            assert_eq!(
+                ChunkPosition::from_str(position).unwrap().to_string(),
+                position
+            );
+        }
+        assert!(ChunkPosition::from_str("index:x").is_err());
+        assert!(ChunkPosition::from_str("middle").is_err());
+    }
+
+    #[test]
+    fn test_insert_chunks() {
+        let hidden = || {
+            vec![Chunk::new(
+                ChunkType::try_from(*b"ruSt").unwrap(),
+                Vec::new(),
+            )]
+        };
+        let mut png = png();
+        png.insert_chunks(ChunkPosition::BeforeIdat, hidden())
+            .unwrap();
+        png.insert_chunks(ChunkPosition::AfterIdat, hidden())
+            .unwrap();
+        png.insert_chunks(ChunkPosition::BeforeIend, hidden())
+            .unwrap();
+        assert_eq!(
+            types(&png),
+            ["IHDR", "ruSt", "IDAT", "IDAT", "ruSt", "ruSt", "IEND"]
+        );
+
+        // Splitting the IDAT run breaks the ordering rules.
+        assert!(png
+            .insert_chunks(ChunkPosition::Index(3), hidden())
+            .is_err());
+    }
+}
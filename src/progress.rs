@@ -0,0 +1,58 @@
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+use congenial_chainsaw::batch;
+use congenial_chainsaw::Result;
+
+/// Whether progress is drawn at all, decided once by [`init`].
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Set while a batch bar is on screen, so the per-file spinners of the
+/// operations it runs stay hidden instead of fighting it for the line.
+static IN_BATCH: AtomicBool = AtomicBool::new(false);
+
+/// Turns progress on when standard output is a terminal, unless `quiet`.
+/// Progress is drawn on standard error, so it never mixes with output.
+pub fn init(quiet: bool) {
+    ENABLED.store(!quiet && io::stdout().is_terminal(), Ordering::Relaxed);
+}
+
+/// A spinner with `message` for a single slow operation, such as LSB
+/// embedding or recompressing image data. Finish it with
+/// [`ProgressBar::finish_and_clear`].
+pub fn spinner(message: &'static str) -> ProgressBar {
+    if !ENABLED.load(Ordering::Relaxed) || IN_BATCH.load(Ordering::Relaxed) {
+        return ProgressBar::hidden();
+    }
+    let spinner = ProgressBar::new_spinner().with_message(message);
+    spinner.enable_steady_tick(Duration::from_millis(100));
+    spinner
+}
+
+/// [`batch::run`] with a bar counting the files done.
+pub fn run_batch<T, F>(files: &[PathBuf], op: F) -> Vec<(PathBuf, std::result::Result<T, String>)>
+where
+    T: Send,
+    F: Fn(&Path) -> Result<T> + Sync,
+{
+    if !ENABLED.load(Ordering::Relaxed) {
+        return batch::run(files, op);
+    }
+    let bar = ProgressBar::new(files.len() as u64).with_style(
+        ProgressStyle::with_template("{bar:40} {pos}/{len} files, {eta} left")
+            .expect("valid template"),
+    );
+    IN_BATCH.store(true, Ordering::Relaxed);
+    let results = batch::run(files, |file| {
+        let result = op(file);
+        bar.inc(1);
+        result
+    });
+    IN_BATCH.store(false, Ordering::Relaxed);
+    bar.finish_and_clear();
+    results
+}
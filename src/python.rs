@@ -0,0 +1,157 @@
+//! Python bindings, built with the `python` feature into an extension module
+//! named `congenial_chainsaw` (see `pyproject.toml`).
+//!
+//! Functions that take image bytes accept anything supporting the buffer
+//! protocol, such as `bytes`, `bytearray`, `memoryview` or a numpy `uint8`
+//! array, and read contiguous buffers in place rather than copying them.
+
+use std::path::PathBuf;
+use std::slice;
+use std::str::FromStr;
+
+use pyo3::buffer::PyBuffer;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::png::ParseOptions;
+use crate::{message, Chunk, ChunkType, Png, PngMeError};
+
+impl From<PngMeError> for PyErr {
+    fn from(err: PngMeError) -> PyErr {
+        PyValueError::new_err(err.to_string())
+    }
+}
+
+/// Calls `f` with the bytes behind `buffer`, copying them only if they aren't
+/// contiguous in memory.
+fn with_bytes<R>(
+    py: Python<'_>,
+    buffer: &PyBuffer<u8>,
+    f: impl FnOnce(&[u8]) -> PyResult<R>,
+) -> PyResult<R> {
+    if !buffer.is_c_contiguous() {
+        return f(&buffer.to_vec(py)?);
+    }
+    // SAFETY: the buffer is contiguous and holds `len_bytes` bytes, and it
+    // stays alive and unreleased because we hold both it and the GIL until
+    // `f` returns.
+    let bytes = unsafe { slice::from_raw_parts(buffer.buf_ptr().cast::<u8>(), buffer.len_bytes()) };
+    f(bytes)
+}
+
+/// A single chunk: its type, data and CRC.
+#[pyclass(name = "Chunk", module = "congenial_chainsaw")]
+#[derive(Clone)]
+pub struct PyChunk(Chunk);
+
+#[pymethods]
+impl PyChunk {
+    #[new]
+    fn new(py: Python<'_>, chunk_type: &str, data: PyBuffer<u8>) -> PyResult<PyChunk> {
+        let chunk_type = ChunkType::from_str(chunk_type)?;
+        with_bytes(py, &data, |data| {
+            Ok(PyChunk(Chunk::try_new(chunk_type, data.to_vec())?))
+        })
+    }
+
+    #[getter]
+    fn chunk_type(&self) -> String {
+        self.0.chunk_type().to_string()
+    }
+
+    #[getter]
+    fn data<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new_bound(py, self.0.data())
+    }
+
+    #[getter]
+    fn crc(&self) -> u32 {
+        self.0.crc()
+    }
+
+    fn __len__(&self) -> usize {
+        self.0.data().len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Chunk({:?}, {} bytes)", self.chunk_type(), self.__len__())
+    }
+}
+
+/// A parsed PNG file.
+#[pyclass(name = "Png", module = "congenial_chainsaw")]
+pub struct PyPng(Png);
+
+#[pymethods]
+impl PyPng {
+    /// Parses a PNG from bytes or any other buffer.
+    #[new]
+    fn new(py: Python<'_>, data: PyBuffer<u8>) -> PyResult<PyPng> {
+        with_bytes(py, &data, |data| {
+            Ok(PyPng(Png::from_bytes_with(data, &ParseOptions::default())?))
+        })
+    }
+
+    #[staticmethod]
+    fn from_file(path: PathBuf) -> PyResult<PyPng> {
+        Ok(PyPng(Png::from_file(path)?))
+    }
+
+    fn chunks(&self) -> Vec<PyChunk> {
+        self.0.chunks().iter().cloned().map(PyChunk).collect()
+    }
+
+    fn chunk_by_type(&self, chunk_type: &str) -> Option<PyChunk> {
+        self.0.chunk_by_type(chunk_type).cloned().map(PyChunk)
+    }
+
+    /// Inserts `chunk` just before the IEND chunk.
+    fn append_chunk(&mut self, chunk: PyChunk) {
+        self.0.append_chunk(chunk.0);
+    }
+
+    fn remove_first_chunk(&mut self, chunk_type: &str) -> PyResult<PyChunk> {
+        Ok(PyChunk(self.0.remove_first_chunk(chunk_type)?))
+    }
+
+    fn to_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new_bound(py, &self.0.as_bytes())
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Png({} chunks)", self.0.chunks().len())
+    }
+}
+
+/// Hides `message` in the PNG `data` in chunks of type `chunk_type`,
+/// returning the new file.
+#[pyfunction]
+fn encode<'py>(
+    py: Python<'py>,
+    data: PyBuffer<u8>,
+    chunk_type: &str,
+    message: &str,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let encoded = with_bytes(py, &data, |data| {
+        Ok(message::embed(data, chunk_type, message.as_bytes())?)
+    })?;
+    Ok(PyBytes::new_bound(py, &encoded))
+}
+
+/// Reveals the message hidden in the PNG `data` in chunks of type
+/// `chunk_type`.
+#[pyfunction]
+fn decode(py: Python<'_>, data: PyBuffer<u8>, chunk_type: &str) -> PyResult<String> {
+    let message = with_bytes(py, &data, |data| Ok(message::extract(data, chunk_type)?))?;
+    Ok(String::from_utf8(message).map_err(PngMeError::from)?)
+}
+
+#[pymodule]
+fn congenial_chainsaw(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyPng>()?;
+    module.add_class::<PyChunk>()?;
+    module.add_function(wrap_pyfunction!(encode, module)?)?;
+    module.add_function(wrap_pyfunction!(decode, module)?)?;
+    Ok(())
+}
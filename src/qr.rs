@@ -0,0 +1,137 @@
+//! QR codes for moving payloads across an air gap, built with the `qr`
+//! feature: a decoded message can be shown as a QR code in the terminal,
+//! and the data of a QR code in a PNG, such as a photo of another screen,
+//! can be embedded as a message.
+
+use thiserror::Error;
+
+#[cfg(feature = "qr")]
+pub use codes::{render, scan};
+
+/// `bytes` as a QR code drawn with Unicode block characters, two rows of
+/// modules per line of text. Needs the `qr` feature.
+#[cfg(not(feature = "qr"))]
+pub fn render(_bytes: &[u8]) -> crate::Result<String> {
+    Err(QrError::Unsupported.into())
+}
+
+/// The data of the first QR code found in the image of `png`. Needs the
+/// `qr` feature.
+#[cfg(not(feature = "qr"))]
+pub fn scan(_png: &crate::png::Png) -> crate::Result<Vec<u8>> {
+    Err(QrError::Unsupported.into())
+}
+
+#[cfg(feature = "qr")]
+mod codes {
+    use qrcode::render::unicode::Dense1x2;
+    use qrcode::QrCode;
+
+    use super::QrError;
+    use crate::png::Png;
+    use crate::Result;
+
+    /// `bytes` as a QR code drawn with Unicode block characters, two rows
+    /// of modules per line of text. The code is drawn light on dark, which
+    /// scanners read as dark on light on the usual dark terminal.
+    pub fn render(bytes: &[u8]) -> Result<String> {
+        let code = QrCode::new(bytes).map_err(QrError::Encode)?;
+        Ok(code
+            .render::<Dense1x2>()
+            .dark_color(Dense1x2::Light)
+            .light_color(Dense1x2::Dark)
+            .build())
+    }
+
+    /// The data of the first QR code found in the image of `png`.
+    /// Transparent pixels count as white.
+    pub fn scan(png: &Png) -> Result<Vec<u8>> {
+        let image = png.decode_pixels()?;
+        let mut prepared = rqrr::PreparedImage::prepare_from_greyscale(
+            image.width() as usize,
+            image.height() as usize,
+            |x, y| luma(image.pixel(x as u32, y as u32)),
+        );
+        let grid = prepared
+            .detect_grids()
+            .into_iter()
+            .next()
+            .ok_or(QrError::NotFound)?;
+        let mut data = Vec::new();
+        grid.decode_to(&mut data).map_err(QrError::Decode)?;
+        Ok(data)
+    }
+
+    /// The brightness of an RGBA pixel laid over white.
+    fn luma([r, g, b, a]: [u8; 4]) -> u8 {
+        let luma = (299 * r as u32 + 587 * g as u32 + 114 * b as u32) / 1000;
+        ((luma * a as u32 + 255 * (255 - a as u32)) / 255) as u8
+    }
+}
+
+/// Errors raised while drawing or reading QR codes.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum QrError {
+    /// The data is too long to fit in a single QR code.
+    #[cfg(feature = "qr")]
+    #[error("couldn't make a QR code: {0}")]
+    Encode(qrcode::types::QrError),
+    /// A QR code was found but couldn't be read.
+    #[cfg(feature = "qr")]
+    #[error("couldn't read the QR code: {0}")]
+    Decode(rqrr::DeQRError),
+    /// The image has no QR code in it.
+    #[error("no QR code found in the image")]
+    NotFound,
+    /// QR codes were requested from a build without the `qr` feature.
+    #[error("QR codes need pngme to be built with the qr feature")]
+    Unsupported,
+    /// Several PNGs were decoded, but one QR code is shown at a time.
+    #[error("--qr takes a single PNG, not several")]
+    SeveralFiles,
+}
+
+#[cfg(all(test, feature = "qr"))]
+mod tests {
+    use super::*;
+    use crate::ihdr::ColorType;
+    use crate::png::Png;
+    use qrcode::{Color, QrCode};
+
+    /// How many pixels wide each module of a test code is drawn.
+    const SCALE: usize = 4;
+
+    #[test]
+    fn test_scan() {
+        let message = b"meet at the usual place";
+        let code = QrCode::new(message).unwrap();
+        // A quiet zone of four modules, as scanners expect.
+        let size = (code.width() + 8) * SCALE;
+        let mut pixels = vec![255; size * size];
+        for (index, color) in code.to_colors().into_iter().enumerate() {
+            if color == Color::Dark {
+                let (x, y) = (index % code.width() + 4, index / code.width() + 4);
+                for row in y * SCALE..(y + 1) * SCALE {
+                    pixels[row * size + x * SCALE..row * size + (x + 1) * SCALE].fill(0);
+                }
+            }
+        }
+        let png =
+            Png::from_pixels(size as u32, size as u32, ColorType::Grayscale, &pixels).unwrap();
+        assert_eq!(scan(&png).unwrap(), message);
+
+        let blank = Png::from_pixels(8, 8, ColorType::Grayscale, &[255; 64]).unwrap();
+        assert!(matches!(
+            scan(&blank),
+            Err(crate::PngMeError::Qr(QrError::NotFound))
+        ));
+    }
+
+    #[test]
+    fn test_render() {
+        let rendered = render(b"hi").unwrap();
+        assert!(rendered.lines().count() > 10);
+        assert!(render(&[0; 4096]).is_err());
+    }
+}
@@ -0,0 +1,165 @@
+use std::fmt;
+
+use crate::pixels::{ImageBuffer, PixelError};
+use crate::png::Png;
+use crate::Result;
+
+/// Side of the square windows SSIM is averaged over.
+const SSIM_WINDOW: usize = 8;
+
+/// Distance between neighboring SSIM windows.
+const SSIM_STEP: usize = 4;
+
+/// How close a modified image is to the original.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quality {
+    /// Peak signal-to-noise ratio of the color samples in decibels, infinite
+    /// for identical images. Above about 40 dB differences are hard to see.
+    pub psnr: f64,
+    /// Mean structural similarity of the luma, at most 1, which only
+    /// identical images reach.
+    pub ssim: f64,
+}
+
+impl fmt::Display for Quality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.psnr.is_finite() {
+            true => writeln!(f, "PSNR: {:.2} dB", self.psnr)?,
+            false => writeln!(f, "PSNR: identical")?,
+        }
+        writeln!(f, "SSIM: {:.5}", self.ssim)
+    }
+}
+
+/// Compares two decoded images of the same size. Alpha is left out, so
+/// transparent pixels count by their color alone.
+pub fn compare(original: &ImageBuffer, modified: &ImageBuffer) -> Result<Quality> {
+    if (original.width(), original.height()) != (modified.width(), modified.height()) {
+        return Err(PixelError::SizeMismatch {
+            first: (original.width(), original.height()),
+            second: (modified.width(), modified.height()),
+        }
+        .into());
+    }
+    Ok(Quality {
+        psnr: psnr(original.as_bytes(), modified.as_bytes()),
+        ssim: ssim(
+            &luma(original),
+            &luma(modified),
+            original.width() as usize,
+            original.height() as usize,
+        ),
+    })
+}
+
+impl Png {
+    /// Decodes both images and compares them with [`compare`].
+    pub fn compare(&self, modified: &Png) -> Result<Quality> {
+        compare(&self.decode_pixels()?, &modified.decode_pixels()?)
+    }
+}
+
+fn psnr(original: &[u8], modified: &[u8]) -> f64 {
+    let (mut squares, mut samples) = (0u64, 0u64);
+    for (a, b) in original.chunks_exact(4).zip(modified.chunks_exact(4)) {
+        for channel in 0..3 {
+            let error = i64::from(a[channel]) - i64::from(b[channel]);
+            squares += (error * error) as u64;
+            samples += 1;
+        }
+    }
+    if squares == 0 {
+        return f64::INFINITY;
+    }
+    let mse = squares as f64 / samples as f64;
+    10.0 * (255f64.powi(2) / mse).log10()
+}
+
+/// Rec. 601 luma of each pixel.
+fn luma(image: &ImageBuffer) -> Vec<f64> {
+    image
+        .as_bytes()
+        .chunks_exact(4)
+        .map(|pixel| {
+            0.299 * f64::from(pixel[0]) + 0.587 * f64::from(pixel[1]) + 0.114 * f64::from(pixel[2])
+        })
+        .collect()
+}
+
+/// SSIM from Wang et al. (2004), averaged over [`SSIM_WINDOW`]-pixel square
+/// windows, or the whole image if it is smaller than that.
+fn ssim(a: &[f64], b: &[f64], width: usize, height: usize) -> f64 {
+    let c1 = (0.01 * 255.0f64).powi(2);
+    let c2 = (0.03 * 255.0f64).powi(2);
+    let (window_width, window_height) = (SSIM_WINDOW.min(width), SSIM_WINDOW.min(height));
+    let starts = |length: usize, window: usize| (0..=length - window).step_by(SSIM_STEP);
+
+    let (mut total, mut windows) = (0.0, 0);
+    for top in starts(height, window_height) {
+        for left in starts(width, window_width) {
+            let pixels = (top..top + window_height)
+                .flat_map(|y| (left..left + window_width).map(move |x| y * width + x));
+            let n = (window_width * window_height) as f64;
+            let (mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) =
+                (0.0, 0.0, 0.0, 0.0, 0.0);
+            for i in pixels {
+                sum_a += a[i];
+                sum_b += b[i];
+                sum_aa += a[i] * a[i];
+                sum_bb += b[i] * b[i];
+                sum_ab += a[i] * b[i];
+            }
+            let (mean_a, mean_b) = (sum_a / n, sum_b / n);
+            let var_a = sum_aa / n - mean_a * mean_a;
+            let var_b = sum_bb / n - mean_b * mean_b;
+            let covariance = sum_ab / n - mean_a * mean_b;
+            total += ((2.0 * mean_a * mean_b + c1) * (2.0 * covariance + c2))
+                / ((mean_a * mean_a + mean_b * mean_b + c1) * (var_a + var_b + c2));
+            windows += 1;
+        }
+    }
+    total / f64::from(windows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: u32, height: u32, pixel: impl Fn(u32, u32) -> [u8; 4]) -> ImageBuffer {
+        let pixel = &pixel;
+        let data = (0..height)
+            .flat_map(|y| (0..width).flat_map(move |x| pixel(x, y)))
+            .collect();
+        ImageBuffer::new(width, height, data).unwrap()
+    }
+
+    #[test]
+    fn test_compare() {
+        let gradient = |x: u32, y: u32| [(x * 9) as u8, (y * 7) as u8, (x * y) as u8, 255];
+        let original = image(20, 12, gradient);
+
+        let same = compare(&original, &original).unwrap();
+        assert!(same.psnr.is_infinite());
+        assert!((same.ssim - 1.0).abs() < 1e-9);
+
+        // Flipping every red low bit gives an MSE of 1/3.
+        let flipped = image(20, 12, |x, y| {
+            let [r, g, b, a] = gradient(x, y);
+            [r ^ 1, g, b, a]
+        });
+        let quality = compare(&original, &flipped).unwrap();
+        assert!((quality.psnr - 10.0 * (3.0 * 255f64.powi(2)).log10()).abs() < 1e-9);
+        assert!(quality.ssim > 0.99 && quality.ssim < 1.0);
+
+        let inverted = image(20, 12, |x, y| gradient(x, y).map(|sample| 255 - sample));
+        assert!(compare(&original, &inverted).unwrap().ssim < 0.0);
+    }
+
+    #[test]
+    fn test_size_mismatch() {
+        let small = image(2, 2, |_, _| [0; 4]);
+        let large = image(3, 2, |_, _| [0; 4]);
+        assert!(compare(&small, &large).is_err());
+        assert!(compare(&small, &small).unwrap().ssim > 0.99);
+    }
+}
@@ -0,0 +1,248 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::{ParseOptions, Png, PngError};
+use crate::{PngMeError, Result};
+
+/// Reads chunks one at a time from any [`Read`] source.
+///
+/// The signature is checked when the reader is created and each chunk is
+/// checked against the [`ParseOptions`] as it is read, so only one chunk is
+/// held in memory at a time. Iteration stops at end of input or after the
+/// first error.
+#[derive(Debug)]
+pub struct PngReader<R: Read> {
+    inner: R,
+    options: ParseOptions,
+    offset: u64,
+    seen_iend: bool,
+    done: bool,
+}
+
+impl<R: Read> PngReader<R> {
+    /// Wraps `inner`, consuming and validating the PNG signature.
+    pub fn new(inner: R) -> Result<Self> {
+        PngReader::with_options(inner, ParseOptions::default())
+    }
+
+    /// Like [`PngReader::new`], checking chunks as `options` require.
+    pub fn with_options(mut inner: R, options: ParseOptions) -> Result<Self> {
+        let mut signature = [0; 8];
+        read_exact(&mut inner, &mut signature).map_err(|_| PngMeError::InvalidSignature)?;
+        if signature != Png::STANDARD_HEADER {
+            return Err(PngMeError::InvalidSignature);
+        }
+        Ok(PngReader {
+            inner,
+            options,
+            offset: Png::STANDARD_HEADER.len() as u64,
+            seen_iend: false,
+            done: false,
+        })
+    }
+
+    /// Reads the next chunk, or returns `None` at a clean end of input, or
+    /// after IEND if trailing garbage is allowed.
+    pub fn next_chunk(&mut self) -> Result<Option<Chunk>> {
+        if self.seen_iend && self.options.allow_trailing_garbage {
+            return Ok(None);
+        }
+        let _span = tracing::debug_span!("chunk", offset = self.offset).entered();
+        let mut header = [0; 8];
+        match self.inner.read(&mut header[..1])? {
+            0 => return Ok(None),
+            _ => read_exact(&mut self.inner, &mut header[1..])?,
+        }
+
+        let length = u32::from_be_bytes(header[..4].try_into()?);
+        self.options.check_length(length)?;
+        self.options.check_total(self.offset, length)?;
+        let chunk_type = self.options.chunk_type(header[4..].try_into()?)?;
+        self.options.check_critical(&chunk_type, self.offset)?;
+
+        // Read through `take` so a bogus length can't trigger a huge up-front allocation.
+        let mut data = Vec::new();
+        (&mut self.inner)
+            .take(length.into())
+            .read_to_end(&mut data)?;
+        if data.len() != length as usize {
+            return Err(PngError::Truncated.into());
+        }
+
+        let mut crc = [0; 4];
+        read_exact(&mut self.inner, &mut crc)?;
+        self.seen_iend = chunk_type == ChunkType::IEND;
+        let chunk = Chunk::from_parts(chunk_type, data, u32::from_be_bytes(crc), &self.options)?
+            .at_offset(self.offset);
+        tracing::trace!(%chunk_type, length, "read chunk");
+        self.offset += chunk.total_size();
+        Ok(Some(chunk))
+    }
+
+    /// Returns the underlying reader, positioned after the last chunk read.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Iterator for PngReader<R> {
+    type Item = Result<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let next = self.next_chunk().transpose();
+        if !matches!(next, Some(Ok(_))) {
+            self.done = true;
+        }
+        next
+    }
+}
+
+/// Reads the chunks of type `chunk_type` from a PNG, checked as `options`
+/// require, seeking past the data of every other chunk so that only chunk
+/// headers are read in between. Stops at IEND, so nothing after it is read.
+/// A skipped chunk that runs past the end of the input is reported as
+/// [`PngError::Truncated`], as a read one would be.
+pub fn read_chunks_of_type<R: Read + Seek>(
+    mut reader: R,
+    chunk_type: &ChunkType,
+    options: &ParseOptions,
+) -> Result<Vec<Chunk>> {
+    let mut signature = [0; 8];
+    read_exact(&mut reader, &mut signature).map_err(|_| PngMeError::InvalidSignature)?;
+    if signature != Png::STANDARD_HEADER {
+        return Err(PngMeError::InvalidSignature);
+    }
+    // Offsets count from the signature, as they do for `PngReader`.
+    let start = reader.stream_position()? - signature.len() as u64;
+    let len = reader.seek(SeekFrom::End(0))? - start;
+    let mut offset = reader.seek(SeekFrom::Start(start + signature.len() as u64))? - start;
+    let mut chunks = Vec::new();
+    loop {
+        let mut header = [0; 8];
+        match reader.read(&mut header[..1])? {
+            0 => return Ok(chunks),
+            _ => read_exact(&mut reader, &mut header[1..])?,
+        }
+        let length = u32::from_be_bytes(header[..4].try_into()?);
+        options.check_length(length)?;
+        options.check_total(offset, length)?;
+        let found = options.chunk_type(header[4..].try_into()?)?;
+        options.check_critical(&found, offset)?;
+        let next = offset + Chunk::OVERHEAD as u64 + u64::from(length);
+        if found == *chunk_type {
+            let mut data = Vec::new();
+            (&mut reader).take(length.into()).read_to_end(&mut data)?;
+            if data.len() != length as usize {
+                return Err(PngError::Truncated.into());
+            }
+            let mut crc = [0; 4];
+            read_exact(&mut reader, &mut crc)?;
+            let chunk = Chunk::from_parts(found, data, u32::from_be_bytes(crc), options)?;
+            chunks.push(chunk.at_offset(offset));
+        } else if found == ChunkType::IEND {
+            return Ok(chunks);
+        } else if next > len {
+            return Err(PngError::Truncated.into());
+        } else {
+            reader.seek(SeekFrom::Current(i64::from(length) + 4))?;
+        }
+        offset = next;
+    }
+}
+
+/// Like [`Read::read_exact`], but reports a short read as a truncated PNG.
+fn read_exact<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<()> {
+    reader.read_exact(buf).map_err(|err| match err.kind() {
+        io::ErrorKind::UnexpectedEof => PngError::Truncated.into(),
+        _ => err.into(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::{minimal_png, UnknownCriticalPolicy};
+    use std::io::Cursor;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_read_chunks_of_type() {
+        let rust = ChunkType::from_str("ruSt").unwrap();
+        let mut png = minimal_png();
+        png.append_chunk(Chunk::new(rust, b"one".to_vec()));
+        png.append_chunk(Chunk::new(
+            ChunkType::from_str("teSt").unwrap(),
+            vec![1; 100],
+        ));
+        png.append_chunk(Chunk::new(rust, b"two".to_vec()));
+        // Past IEND, so never read.
+        let mut bytes = png.as_bytes();
+        bytes.extend(Chunk::new(rust, b"three".to_vec()).as_bytes());
+
+        let chunks =
+            read_chunks_of_type(Cursor::new(bytes), &rust, &ParseOptions::default()).unwrap();
+        let data: Vec<&[u8]> = chunks.iter().map(Chunk::data).collect();
+        assert_eq!(data, [b"one".as_slice(), b"two"]);
+    }
+
+    fn sample() -> Vec<u8> {
+        let mut png = minimal_png();
+        png.append_chunk(Chunk::new(
+            ChunkType::from_str("teSt").unwrap(),
+            vec![1; 100],
+        ));
+        png.append_chunk(Chunk::new(
+            ChunkType::from_str("ruSt").unwrap(),
+            b"one".to_vec(),
+        ));
+        png.as_bytes()
+    }
+
+    #[test]
+    fn test_skipped_chunk_cut_short_is_truncated() {
+        let rust = ChunkType::from_str("ruSt").unwrap();
+        let mut bytes = sample();
+        // Cut inside the teSt chunk, which is skipped rather than read.
+        bytes.truncate(8 + 25 + 50);
+        assert!(matches!(
+            read_chunks_of_type(Cursor::new(bytes), &rust, &ParseOptions::default()),
+            Err(PngMeError::Png(PngError::Truncated))
+        ));
+    }
+
+    #[test]
+    fn test_read_chunks_of_type_applies_options() {
+        let rust = ChunkType::from_str("ruSt").unwrap();
+        let options = ParseOptions::default().with_max_total_size(100);
+        assert!(matches!(
+            read_chunks_of_type(Cursor::new(sample()), &rust, &options),
+            Err(PngMeError::InputTooLarge { max: 100 })
+        ));
+
+        let mut bytes = sample();
+        // Make teSt critical and unknown.
+        bytes[8 + 25 + 4] = b'T';
+        let options = ParseOptions::default().with_unknown_critical(UnknownCriticalPolicy::Error);
+        assert!(matches!(
+            read_chunks_of_type(Cursor::new(bytes), &rust, &options),
+            Err(PngMeError::Png(PngError::UnknownCriticalChunk {
+                offset: 33,
+                ..
+            }))
+        ));
+
+        let mut bytes = sample();
+        bytes[8 + 25 + 4] = b'1';
+        assert!(matches!(
+            read_chunks_of_type(Cursor::new(bytes.clone()), &rust, &ParseOptions::default()),
+            Err(PngMeError::InvalidChunkType(_))
+        ));
+        let options = ParseOptions::default().with_strict_chunk_types(false);
+        let chunks = read_chunks_of_type(Cursor::new(bytes), &rust, &options).unwrap();
+        assert_eq!(chunks[0].offset(), Some(8 + 25 + 112));
+    }
+}
@@ -0,0 +1,113 @@
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+
+use crate::chunk_type::ChunkType;
+
+/// A chunk type outside the PNG specification that is known to be written
+/// by some piece of software or by a registered extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisteredChunk {
+    pub chunk_type: ChunkType,
+    /// Who writes it.
+    pub owner: &'static str,
+    /// What it holds.
+    pub purpose: &'static str,
+}
+
+const fn entry(code: &[u8; 4], owner: &'static str, purpose: &'static str) -> RegisteredChunk {
+    RegisteredChunk {
+        chunk_type: ChunkType::new_unchecked(*code),
+        owner,
+        purpose,
+    }
+}
+
+/// Chunk types known to be in use outside the specification. Hiding a
+/// payload under one of these risks software that reads it misreading the
+/// payload, or the payload being mistaken for that software's data.
+pub const REGISTRY: [RegisteredChunk; 21] = [
+    entry(b"oFFs", "PNG extensions", "image offset"),
+    entry(b"pCAL", "PNG extensions", "pixel value calibration"),
+    entry(b"sCAL", "PNG extensions", "physical scale"),
+    entry(b"gIFg", "PNG extensions", "GIF graphic control extension"),
+    entry(b"gIFx", "PNG extensions", "GIF application extension"),
+    entry(b"gIFt", "PNG extensions", "GIF plain text extension"),
+    entry(b"sTER", "PNG extensions", "stereo image layout"),
+    entry(b"fRAc", "PNG extensions", "fractal image parameters"),
+    entry(b"dSIG", "PNG extensions", "digital signature"),
+    entry(b"CgBI", "Apple", "iOS-optimized image data"),
+    entry(b"iDOT", "Apple", "offsets for decoding in parallel"),
+    entry(b"prVW", "Macromedia Fireworks", "preview image"),
+    entry(b"mkBF", "Macromedia Fireworks", "editable document data"),
+    entry(b"mkBS", "Macromedia Fireworks", "editable document data"),
+    entry(b"mkBT", "Macromedia Fireworks", "editable document data"),
+    entry(b"mkTS", "Macromedia Fireworks", "editable document data"),
+    entry(b"npTc", "Android", "nine-patch stretch regions"),
+    entry(b"npLb", "Android", "nine-patch layout bounds"),
+    entry(b"npOl", "Android", "nine-patch outline"),
+    entry(b"vpAg", "ImageMagick", "virtual page size"),
+    entry(b"caNv", "ImageMagick", "canvas size and offset"),
+];
+
+impl ChunkType {
+    /// The [`REGISTRY`] entry for this type, if software is known to use it.
+    pub fn registered(&self) -> Option<&'static RegisteredChunk> {
+        REGISTRY.iter().find(|entry| entry.chunk_type == *self)
+    }
+
+    /// Who is known to write chunks of this type, such as "Apple" for iDOT.
+    /// Types defined by the PNG specification itself aren't in the registry.
+    pub fn registered_owner(&self) -> Option<&'static str> {
+        self.registered().map(|entry| entry.owner)
+    }
+
+    /// A random ancillary, private, safe-to-copy type that isn't in the
+    /// [`REGISTRY`], for payloads that don't need a particular type.
+    pub fn random_private() -> ChunkType {
+        loop {
+            let mut random = [0; 4];
+            OsRng.fill_bytes(&mut random);
+            let chunk_type = private_type(random);
+            if chunk_type.registered().is_none() {
+                return chunk_type;
+            }
+        }
+    }
+}
+
+/// Maps four random bytes to letters, all lowercase but the third, which
+/// makes the type ancillary, private and safe to copy with a valid reserved
+/// bit.
+fn private_type(random: [u8; 4]) -> ChunkType {
+    let mut bytes = random.map(|byte| b'a' + byte % 26);
+    bytes[2].make_ascii_uppercase();
+    ChunkType::new_unchecked(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_registered_owner() {
+        let owner = |code| ChunkType::from_str(code).unwrap().registered_owner();
+        assert_eq!(owner("iDOT"), Some("Apple"));
+        assert_eq!(owner("prVW"), Some("Macromedia Fireworks"));
+        assert_eq!(owner("ruSt"), None);
+        assert_eq!(owner("IDAT"), None);
+        assert!(REGISTRY.iter().all(|entry| entry.chunk_type.is_valid()));
+        assert!(REGISTRY.iter().all(|entry| !entry.chunk_type.is_standard()));
+    }
+
+    #[test]
+    fn test_random_private() {
+        assert_eq!(private_type([0, 25, 26, 255]).to_string(), "azAv");
+        let chunk_type = ChunkType::random_private();
+        assert_eq!(
+            chunk_type.describe().to_string(),
+            "ancillary, private, safe to copy"
+        );
+        assert!(chunk_type.is_valid() && chunk_type.registered().is_none());
+    }
+}
@@ -0,0 +1,220 @@
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::{Png, PngError};
+use crate::{PngMeError, Result};
+
+/// Which kinds of damage [`Png::repair`] is allowed to fix. Damage it may not
+/// fix is returned as an error instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RepairPolicy {
+    /// Replace stored CRCs that don't match the chunk contents.
+    pub fix_crcs: bool,
+    /// Drop a final chunk cut short, along with anything unreadable after it.
+    pub drop_truncated: bool,
+    /// Append an IEND chunk if the file has none.
+    pub add_iend: bool,
+}
+
+impl Default for RepairPolicy {
+    fn default() -> Self {
+        RepairPolicy {
+            fix_crcs: true,
+            drop_truncated: true,
+            add_iend: true,
+        }
+    }
+}
+
+/// One change made by [`Png::repair`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Fix {
+    /// The CRC of the chunk at `position` was recomputed.
+    RecomputedCrc {
+        position: usize,
+        chunk_type: String,
+        stored: u32,
+        computed: u32,
+    },
+    /// `len` unreadable bytes starting at byte `offset` were dropped.
+    DroppedTail {
+        offset: usize,
+        len: usize,
+    },
+    /// `len` bytes after IEND were dropped.
+    DroppedTrailingData {
+        len: usize,
+    },
+    AddedIend,
+}
+
+impl fmt::Display for Fix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fix::RecomputedCrc {
+                position,
+                chunk_type,
+                stored,
+                computed,
+            } => write!(
+                f,
+                "chunk {position} ({chunk_type}): replaced CRC {stored:#010x} with {computed:#010x}"
+            ),
+            Fix::DroppedTail { offset, len } => {
+                write!(f, "dropped {len} truncated bytes at offset {offset}")
+            }
+            Fix::DroppedTrailingData { len } => write!(f, "dropped {len} bytes after IEND"),
+            Fix::AddedIend => write!(f, "added a missing IEND chunk"),
+        }
+    }
+}
+
+/// Everything [`Png::repair`] changed, in file order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    pub fixes: Vec<Fix>,
+}
+
+impl RepairReport {
+    /// Returns true if the file needed no repairs.
+    pub fn is_clean(&self) -> bool {
+        self.fixes.is_empty()
+    }
+}
+
+impl Png {
+    /// Parses `bytes` as leniently as `policy` allows, returning the repaired
+    /// PNG and what was fixed. Only the signature must be intact.
+    pub fn repair(bytes: &[u8], policy: RepairPolicy) -> Result<(Png, RepairReport)> {
+        let mut rest = bytes
+            .strip_prefix(&Png::STANDARD_HEADER)
+            .ok_or(PngMeError::InvalidSignature)?;
+        let mut report = RepairReport::default();
+        let mut chunks = Vec::new();
+
+        while !rest.is_empty() {
+            let offset = bytes.len() - rest.len();
+            let Some((chunk_type, data, stored_crc, tail)) = split_raw_chunk(rest) else {
+                if !policy.drop_truncated {
+                    return Err(PngError::Truncated.into());
+                }
+                report.fixes.push(Fix::DroppedTail {
+                    offset,
+                    len: rest.len(),
+                });
+                break;
+            };
+
+            let chunk = Chunk::new(chunk_type, data.to_vec());
+            if chunk.crc() != stored_crc {
+                if !policy.fix_crcs {
+                    // Reports the mismatch with the same error a strict parse would.
+                    Chunk::with_stored_crc(*chunk.chunk_type(), data.to_vec(), stored_crc)?;
+                }
+                report.fixes.push(Fix::RecomputedCrc {
+                    position: chunks.len(),
+                    chunk_type: chunk.chunk_type().to_string(),
+                    stored: stored_crc,
+                    computed: chunk.crc(),
+                });
+            }
+            rest = tail;
+
+            let is_iend = *chunk.chunk_type() == ChunkType::IEND;
+            chunks.push(chunk);
+            if is_iend {
+                if !rest.is_empty() {
+                    report
+                        .fixes
+                        .push(Fix::DroppedTrailingData { len: rest.len() });
+                }
+                break;
+            }
+        }
+
+        let has_iend = chunks
+            .last()
+            .is_some_and(|chunk| *chunk.chunk_type() == ChunkType::IEND);
+        if !has_iend && policy.add_iend {
+            chunks.push(Chunk::new(ChunkType::IEND, Vec::new()));
+            report.fixes.push(Fix::AddedIend);
+        }
+        Ok((Png::from_chunks(chunks), report))
+    }
+}
+
+/// Splits the first chunk off `bytes` without checking its CRC, returning its
+/// type, data, stored CRC and the remaining input. Returns `None` if the chunk
+/// is cut short or its type is not made of ASCII letters, which means the
+/// length field was read from garbage.
+fn split_raw_chunk(bytes: &[u8]) -> Option<(ChunkType, &[u8], u32, &[u8])> {
+    let length = u32::from_be_bytes(bytes.get(..4)?.try_into().ok()?) as usize;
+    let type_bytes: [u8; 4] = bytes.get(4..8)?.try_into().ok()?;
+    if !type_bytes.iter().all(u8::is_ascii_alphabetic) {
+        return None;
+    }
+    let data = bytes.get(8..8usize.checked_add(length)?)?;
+    let crc_end = 8 + length + 4;
+    let stored_crc = u32::from_be_bytes(bytes.get(8 + length..crc_end)?.try_into().ok()?);
+    let chunk_type = ChunkType::try_from(type_bytes).ok()?;
+    Some((chunk_type, data, stored_crc, &bytes[crc_end..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::minimal_png;
+
+    fn chunk(chunk_type: &[u8; 4], data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::try_from(*chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn damaged_bytes() -> Vec<u8> {
+        let png = Png::from_chunks(vec![chunk(b"IHDR", &[0; 13]), chunk(b"RuSt", b"payload")]);
+        let mut bytes = png.as_bytes();
+        // Corrupt the RuSt CRC, then append half of a chunk.
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        bytes.extend(&chunk(b"tEXt", b"cut off").as_bytes()[..10]);
+        bytes
+    }
+
+    #[test]
+    fn test_repair_fixes_everything() {
+        let bytes = damaged_bytes();
+        assert!(Png::try_from(bytes.as_slice()).is_err());
+
+        let (png, report) = Png::repair(&bytes, RepairPolicy::default()).unwrap();
+        assert_eq!(report.fixes.len(), 3);
+        assert!(matches!(
+            report.fixes[0],
+            Fix::RecomputedCrc { position: 1, .. }
+        ));
+        assert!(matches!(report.fixes[1], Fix::DroppedTail { len: 10, .. }));
+        assert_eq!(report.fixes[2], Fix::AddedIend);
+
+        let repaired = Png::try_from(png.as_bytes().as_slice()).unwrap();
+        assert_eq!(repaired.chunks().len(), 3);
+        assert_eq!(repaired.chunk_by_type("RuSt").unwrap().data(), b"payload");
+    }
+
+    #[test]
+    fn test_repair_respects_policy() {
+        let bytes = damaged_bytes();
+        let strict = RepairPolicy {
+            fix_crcs: false,
+            ..RepairPolicy::default()
+        };
+        assert!(Png::repair(&bytes, strict).is_err());
+    }
+
+    #[test]
+    fn test_clean_file() {
+        let bytes = minimal_png().as_bytes();
+        let (_, report) = Png::repair(&bytes, RepairPolicy::default()).unwrap();
+        assert!(report.is_clean());
+    }
+}
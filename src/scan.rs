@@ -0,0 +1,232 @@
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::copy_policy::KNOWN_CHUNK_TYPES;
+use crate::ordering::SINGLE;
+use crate::png::{ParseOptions, Png};
+use crate::text::TextKind;
+use crate::trailer;
+use crate::Result;
+
+/// Text chunks longer than this are flagged. Ordinary metadata such as an
+/// author or a comment is far shorter.
+pub const LARGE_TEXT_LEN: u32 = 16 * 1024;
+
+/// Something in a PNG that is unusual enough to be hiding data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "kind", rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum Finding {
+    /// A chunk whose type is not defined by the PNG specification.
+    NonStandardChunk {
+        position: usize,
+        chunk_type: String,
+        len: u32,
+    },
+    /// An ancillary chunk type the specification allows only once appears
+    /// `count` times.
+    DuplicateAncillary { chunk_type: String, count: usize },
+    /// `len` bytes follow the IEND chunk, starting at byte `offset`.
+    DataAfterIend { offset: usize, len: usize },
+    /// A payload written by the trailer backend takes `len` bytes, footer
+    /// included, from byte `offset`.
+    PayloadTrailer { offset: usize, len: usize },
+    /// A text chunk holds more than [`LARGE_TEXT_LEN`] bytes.
+    LargeText {
+        position: usize,
+        chunk_type: String,
+        len: u32,
+    },
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Finding::NonStandardChunk {
+                position,
+                chunk_type,
+                len,
+            } => write!(
+                f,
+                "chunk {position} ({chunk_type}): non-standard chunk type, {len} bytes"
+            ),
+            Finding::DuplicateAncillary { chunk_type, count } => {
+                write!(
+                    f,
+                    "{chunk_type} appears {count} times but may only appear once"
+                )
+            }
+            Finding::DataAfterIend { offset, len } => {
+                write!(f, "{len} bytes after IEND at offset {offset}")
+            }
+            Finding::PayloadTrailer { offset, len } => {
+                write!(f, "{len}-byte pngme trailer at offset {offset}")
+            }
+            Finding::LargeText {
+                position,
+                chunk_type,
+                len,
+            } => write!(
+                f,
+                "chunk {position} ({chunk_type}): unusually large text, {len} bytes"
+            ),
+        }
+    }
+}
+
+/// Everything a scan found in one PNG.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ScanReport {
+    pub findings: Vec<Finding>,
+}
+
+impl ScanReport {
+    /// Scans a PNG held in memory. Bad CRCs are tolerated so damaged files can
+    /// still be triaged, but input that can't be split into chunks is an
+    /// error.
+    pub fn from_bytes(bytes: &[u8]) -> Result<ScanReport> {
+        let png = Png::from_bytes_with(bytes, &ParseOptions::permissive())?;
+        let chunks = png.chunks();
+        let mut findings = Vec::new();
+
+        for (position, chunk) in chunks.iter().enumerate() {
+            let chunk_type = chunk.chunk_type();
+            if !KNOWN_CHUNK_TYPES.contains(&&chunk_type.bytes()) {
+                findings.push(Finding::NonStandardChunk {
+                    position,
+                    chunk_type: chunk_type.to_string(),
+                    len: chunk.length(),
+                });
+            } else if TextKind::of(chunk).is_some() && chunk.length() > LARGE_TEXT_LEN {
+                findings.push(Finding::LargeText {
+                    position,
+                    chunk_type: chunk_type.to_string(),
+                    len: chunk.length(),
+                });
+            }
+        }
+
+        for chunk_type in SINGLE.iter().filter(|t| t[0].is_ascii_lowercase()) {
+            let count = chunks
+                .iter()
+                .filter(|chunk| chunk.chunk_type().bytes() == **chunk_type)
+                .count();
+            if count > 1 {
+                findings.push(Finding::DuplicateAncillary {
+                    chunk_type: String::from_utf8_lossy(*chunk_type).into_owned(),
+                    count,
+                });
+            }
+        }
+
+        let end = trailer::encoded_len(&png);
+        let trailer = trailer::find(bytes).filter(|trailer| trailer.start >= end);
+        let foreign_end = trailer
+            .as_ref()
+            .map_or(bytes.len(), |trailer| trailer.start);
+        if foreign_end > end {
+            findings.push(Finding::DataAfterIend {
+                offset: end,
+                len: foreign_end - end,
+            });
+        }
+        if let Some(trailer) = trailer {
+            findings.push(Finding::PayloadTrailer {
+                offset: trailer.start,
+                len: trailer.len(),
+            });
+        }
+
+        Ok(ScanReport { findings })
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+
+    fn chunk(chunk_type: &[u8; 4], data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::try_from(*chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn png(chunks: Vec<Chunk>) -> Vec<u8> {
+        Png::from_chunks(chunks).as_bytes()
+    }
+
+    #[test]
+    fn test_clean_file() {
+        let bytes = png(vec![
+            chunk(b"IHDR", &[0; 13]),
+            chunk(b"tEXt", b"Author\0me"),
+            chunk(b"IDAT", &[]),
+            chunk(b"IEND", &[]),
+        ]);
+        assert!(ScanReport::from_bytes(&bytes).unwrap().is_clean());
+    }
+
+    #[test]
+    fn test_findings() {
+        let mut bytes = png(vec![
+            chunk(b"IHDR", &[0; 13]),
+            chunk(b"gAMA", &[0; 4]),
+            chunk(b"gAMA", &[0; 4]),
+            chunk(b"ruSt", b"secret"),
+            chunk(b"tEXt", &[b'x'; LARGE_TEXT_LEN as usize + 1]),
+            chunk(b"IDAT", &[]),
+            chunk(b"IEND", &[]),
+        ]);
+        let end = bytes.len();
+        bytes.extend_from_slice(b"appended");
+
+        let report = ScanReport::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            report.findings,
+            [
+                Finding::NonStandardChunk {
+                    position: 3,
+                    chunk_type: "ruSt".to_string(),
+                    len: 6,
+                },
+                Finding::LargeText {
+                    position: 4,
+                    chunk_type: "tEXt".to_string(),
+                    len: LARGE_TEXT_LEN + 1,
+                },
+                Finding::DuplicateAncillary {
+                    chunk_type: "gAMA".to_string(),
+                    count: 2,
+                },
+                Finding::DataAfterIend {
+                    offset: end,
+                    len: 8,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_payload_trailer() {
+        let clean = png(vec![chunk(b"IHDR", &[0; 13]), chunk(b"IEND", &[])]);
+        let bytes = trailer::embed(&clean, b"hidden").unwrap();
+        let report = ScanReport::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            report.findings,
+            [Finding::PayloadTrailer {
+                offset: clean.len(),
+                len: 6 + trailer::FOOTER_LEN,
+            }]
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use std::io;
+
+use thiserror::Error;
+
+use crate::Result;
+
+/// Environment variable holding a password, so scripts can keep it out of
+/// their command lines, which other users can see in the process list.
+pub const PASSWORD_VAR: &str = "CHAINSAW_PASSWORD";
+
+/// Environment variable holding the password of a hidden message, read in
+/// place of an `encode --hidden-password` the way [`PASSWORD_VAR`] is.
+pub const HIDDEN_PASSWORD_VAR: &str = "CHAINSAW_HIDDEN_PASSWORD";
+
+/// Service that keyring entries read by [`keyring_password`] are stored
+/// under.
+pub const KEYRING_SERVICE: &str = "chainsaw";
+
+/// The password in [`PASSWORD_VAR`], if it is set and not empty.
+pub fn env_password() -> Option<String> {
+    env_secret(PASSWORD_VAR)
+}
+
+/// The password in [`HIDDEN_PASSWORD_VAR`], if it is set and not empty.
+pub fn env_hidden_password() -> Option<String> {
+    env_secret(HIDDEN_PASSWORD_VAR)
+}
+
+fn env_secret(name: &str) -> Option<String> {
+    std::env::var(name)
+        .ok()
+        .filter(|password| !password.is_empty())
+}
+
+/// The password stored in the OS keyring under [`KEYRING_SERVICE`] for the
+/// account `name`. Needs the `keyring` feature.
+#[cfg(feature = "keyring")]
+pub fn keyring_password(name: &str) -> Result<String> {
+    keyring::Entry::new(KEYRING_SERVICE, name)
+        .and_then(|entry| entry.get_password())
+        .map_err(|err| SecretError::Keyring(err).into())
+}
+
+/// The password stored in the OS keyring under [`KEYRING_SERVICE`] for the
+/// account `name`. Needs the `keyring` feature.
+#[cfg(not(feature = "keyring"))]
+pub fn keyring_password(_name: &str) -> Result<String> {
+    Err(SecretError::KeyringUnsupported.into())
+}
+
+/// Asks for a password on the terminal without echoing it. With `confirm`
+/// it is asked for twice, so a typo can't lock a payload away for good.
+/// Fails if there is no terminal to ask on.
+pub fn prompt_password(confirm: bool) -> Result<String> {
+    prompt_secret("password", confirm)
+}
+
+/// Like [`prompt_password`], asking for the password of a hidden message.
+pub fn prompt_hidden_password(confirm: bool) -> Result<String> {
+    prompt_secret("hidden password", confirm)
+}
+
+fn prompt_secret(name: &str, confirm: bool) -> Result<String> {
+    let (first, rest) = name.split_at(1);
+    let prompt = format!("{}{rest}: ", first.to_uppercase());
+    let password = rpassword::prompt_password(prompt).map_err(SecretError::NoTerminal)?;
+    if password.is_empty() {
+        return Err(SecretError::EmptyPassword.into());
+    }
+    if confirm {
+        let again = rpassword::prompt_password(format!("Confirm {name}: "))
+            .map_err(SecretError::NoTerminal)?;
+        if again != password {
+            return Err(SecretError::PasswordMismatch.into());
+        }
+    }
+    Ok(password)
+}
+
+/// Errors raised while looking up a password.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum SecretError {
+    /// The keyring has no such entry or couldn't be reached.
+    #[cfg(feature = "keyring")]
+    #[error("couldn't read the password from the keyring: {0}")]
+    Keyring(keyring::Error),
+    /// The keyring was requested from a build without the `keyring` feature.
+    #[error("the keyring needs pngme to be built with the keyring feature")]
+    KeyringUnsupported,
+    /// A password prompt couldn't be shown, usually because there is no
+    /// terminal.
+    #[error("couldn't prompt for a password: {0}")]
+    NoTerminal(io::Error),
+    /// A password had to be asked for, but prompting was turned off.
+    #[error("a password is needed but prompting is turned off")]
+    PromptDisabled,
+    #[error("the password is empty")]
+    EmptyPassword,
+    /// The password and its confirmation differ.
+    #[error("the passwords don't match")]
+    PasswordMismatch,
+}
@@ -0,0 +1,144 @@
+//! Serialize and Deserialize for [`Png`], [`Chunk`] and [`ChunkType`], so a
+//! PNG's structure can be written to JSON or any other serde format and
+//! read back.
+//!
+//! A chunk is `{ "type": "IHDR", "data": "<base64>", "crc": 1234 }`, and a
+//! PNG is `{ "chunks": [...] }`. The CRC is kept as stored, even if wrong,
+//! so damaged files round-trip unchanged; when deserializing it may be left
+//! out to have it computed.
+
+use std::str::FromStr;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::{ParseOptions, Png};
+
+/// Serializes as the four-letter code, such as `"IHDR"`.
+impl Serialize for ChunkType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for ChunkType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let code = String::deserialize(deserializer)?;
+        ChunkType::from_str(&code).map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for Chunk {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Chunk", 3)?;
+        state.serialize_field("type", self.chunk_type())?;
+        state.serialize_field("data", &BASE64.encode(self.data()))?;
+        state.serialize_field("crc", &self.crc())?;
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Chunk {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        struct Fields {
+            #[serde(rename = "type")]
+            chunk_type: ChunkType,
+            data: String,
+            crc: Option<u32>,
+        }
+
+        let fields = Fields::deserialize(deserializer)?;
+        let data = BASE64.decode(&fields.data).map_err(de::Error::custom)?;
+        match fields.crc {
+            Some(crc) => {
+                Chunk::from_parts(fields.chunk_type, data, crc, &ParseOptions::permissive())
+                    .map_err(de::Error::custom)
+            }
+            None => Chunk::try_new(fields.chunk_type, data).map_err(de::Error::custom),
+        }
+    }
+}
+
+impl Serialize for Png {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Png", 1)?;
+        state.serialize_field("chunks", self.chunks())?;
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Png {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        struct Fields {
+            chunks: Vec<Chunk>,
+        }
+
+        Ok(Png::from_chunks(Fields::deserialize(deserializer)?.chunks))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::minimal_png;
+
+    #[test]
+    fn test_chunk_type_round_trip() {
+        let json = serde_json::to_string(&ChunkType::IHDR).unwrap();
+        assert_eq!(json, r#""IHDR""#);
+        assert_eq!(
+            serde_json::from_str::<ChunkType>(&json).unwrap(),
+            ChunkType::IHDR
+        );
+        assert!(serde_json::from_str::<ChunkType>(r#""IH""#).is_err());
+    }
+
+    #[test]
+    fn test_chunk_round_trip() {
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"hello".to_vec());
+        let json = serde_json::to_string(&chunk).unwrap();
+        assert_eq!(serde_json::from_str::<Chunk>(&json).unwrap(), chunk);
+    }
+
+    #[test]
+    fn test_bad_crc_is_kept() {
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let chunk = Chunk::from_parts(
+            chunk_type,
+            b"hello".to_vec(),
+            1,
+            &ParseOptions::permissive(),
+        )
+        .unwrap();
+        let json = serde_json::to_string(&chunk).unwrap();
+        let read = serde_json::from_str::<Chunk>(&json).unwrap();
+        assert_eq!(read.crc(), 1);
+        assert!(!read.has_valid_crc());
+    }
+
+    #[test]
+    fn test_missing_crc_is_computed() {
+        let json = r#"{ "type": "ruSt", "data": "aGVsbG8=" }"#;
+        let chunk = serde_json::from_str::<Chunk>(json).unwrap();
+        assert_eq!(chunk.data(), b"hello");
+        assert!(chunk.has_valid_crc());
+    }
+
+    #[test]
+    fn test_png_round_trip() {
+        let mut png = minimal_png();
+        png.append_chunk(Chunk::new(
+            ChunkType::from_str("ruSt").unwrap(),
+            b"hello".to_vec(),
+        ));
+        let json = serde_json::to_string(&png).unwrap();
+        let read = serde_json::from_str::<Png>(&json).unwrap();
+        assert_eq!(read.as_bytes(), png.as_bytes());
+    }
+}
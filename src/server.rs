@@ -0,0 +1,663 @@
+//! An HTTP service running encode, decode and scan on uploaded PNGs, built
+//! with the `server` feature, so other programs can use pngme without
+//! shelling out to it.
+//!
+//! Every endpoint takes a `multipart/form-data` POST with the PNG in an
+//! `image` field:
+//!
+//! - `/encode` also takes `chunk_type` and `message` fields and answers with
+//!   the new PNG.
+//! - `/decode` also takes a `chunk_type` field and answers with the message.
+//! - `/scan` answers with the findings of [`ScanReport`], as JSON with the
+//!   `serde` feature.
+//!
+//! Payloads are handled as [`message`](crate::message) handles them, without
+//! encryption or labels. Failures are answered with a 4xx or 5xx status and
+//! the error message as plain text.
+//!
+//! Requests are answered by a fixed number of worker threads, one
+//! connection each, and every connection is closed after one reply. Bodies
+//! must give a Content-Length and arrive in full within
+//! [`ServerOptions::timeout`], however slowly they trickle in, and payloads
+//! are decoded with limits taken from [`ServerOptions::max_body`], so no
+//! request can hold a worker for longer than the timeout plus the time it
+//! takes to decode and answer a bounded upload.
+//!
+//! [`ScanReport`]: crate::scan::ScanReport
+
+use std::time::Duration;
+
+use thiserror::Error;
+
+/// Limits on the requests [`serve`] accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServerOptions {
+    /// The largest request body accepted, in bytes. Larger uploads are
+    /// answered with 413 Payload Too Large, and no payload may decompress to
+    /// more than this either.
+    pub max_body: u64,
+    /// How long an HTTP request may take to arrive in full before it is
+    /// answered with 408 Request Timeout and dropped, and how long a reply
+    /// may wait to be accepted. gRPC calls taking longer than this are
+    /// cancelled.
+    pub timeout: Duration,
+    /// How many HTTP requests are handled at once. Further connections wait
+    /// to be accepted.
+    pub workers: usize,
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        ServerOptions {
+            max_body: 16 * 1024 * 1024,
+            timeout: Duration::from_secs(30),
+            workers: 8,
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+pub use service::serve;
+
+/// Answers requests on `addr`, such as `127.0.0.1:8080`, until the process
+/// is stopped. Needs the `server` feature.
+#[cfg(not(feature = "server"))]
+pub fn serve(_addr: &str, _options: ServerOptions) -> crate::Result<()> {
+    Err(ServerError::Unsupported("server").into())
+}
+
+/// Answers gRPC calls on `addr` until the process is stopped, with the
+/// service in `proto/pngme.proto`. Needs the `grpc` feature.
+#[cfg(not(feature = "grpc"))]
+pub fn serve_grpc(_addr: &str, _options: ServerOptions) -> crate::Result<()> {
+    Err(ServerError::Unsupported("grpc").into())
+}
+
+/// Answers gRPC calls on `addr` until the process is stopped, with the
+/// service in `proto/pngme.proto`.
+#[cfg(feature = "grpc")]
+pub fn serve_grpc(addr: &str, options: ServerOptions) -> crate::Result<()> {
+    let addr: std::net::SocketAddr = addr
+        .parse()
+        .map_err(|err| ServerError::Bind(Box::new(err)))?;
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    runtime
+        .block_on(crate::grpc::serve(addr, options))
+        .map_err(|err| ServerError::Bind(Box::new(err)))?;
+    Ok(())
+}
+
+#[cfg(feature = "server")]
+mod service {
+    use std::io::{self, BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use std::time::Instant;
+
+    use super::{ServerError, ServerOptions};
+    use crate::exit_code::ExitCode;
+    use crate::message;
+    use crate::png::ParseOptions;
+    use crate::scan::ScanReport;
+    use crate::{PngMeError, Result};
+
+    /// The most the request line and headers of a request may take up.
+    const MAX_HEAD: u64 = 16 * 1024;
+
+    /// Answers requests on `addr`, such as `127.0.0.1:8080`, until the
+    /// process is stopped, on [`ServerOptions::workers`] threads.
+    pub fn serve(addr: &str, options: ServerOptions) -> Result<()> {
+        let listener = TcpListener::bind(addr).map_err(|err| ServerError::Bind(Box::new(err)))?;
+        let workers = (0..options.workers.max(1))
+            .map(|_| {
+                let listener = listener.try_clone()?;
+                Ok(thread::spawn(move || {
+                    for stream in listener.incoming() {
+                        match stream {
+                            Ok(stream) => handle(stream, options),
+                            Err(err) => tracing::debug!(%err, "couldn't accept a connection"),
+                        }
+                    }
+                }))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        for worker in workers {
+            let _ = worker.join();
+        }
+        Ok(())
+    }
+
+    /// A response before it is written to the connection.
+    #[derive(Debug, PartialEq, Eq)]
+    pub(super) struct Reply {
+        pub(super) status: u16,
+        pub(super) content_type: &'static str,
+        pub(super) body: Vec<u8>,
+    }
+
+    impl Reply {
+        fn ok(content_type: &'static str, body: Vec<u8>) -> Reply {
+            Reply {
+                status: 200,
+                content_type,
+                body,
+            }
+        }
+
+        fn error(status: u16, message: impl std::fmt::Display) -> Reply {
+            Reply {
+                status,
+                content_type: "text/plain; charset=utf-8",
+                body: format!("{message}\n").into_bytes(),
+            }
+        }
+    }
+
+    /// A field of a multipart form.
+    #[derive(Debug, PartialEq, Eq)]
+    pub(super) struct Part {
+        pub(super) name: String,
+        pub(super) data: Vec<u8>,
+    }
+
+    /// The request line and headers of a request.
+    #[derive(Debug, PartialEq, Eq)]
+    pub(super) struct Head {
+        pub(super) method: String,
+        /// The request target without its query string.
+        pub(super) path: String,
+        pub(super) headers: Vec<(String, String)>,
+    }
+
+    impl Head {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(field, _)| field.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+        }
+    }
+
+    /// Answers the one request read from `stream`, giving up on a client
+    /// whose request hasn't arrived by the end of the timeout.
+    pub(super) fn handle(stream: TcpStream, options: ServerOptions) {
+        let reply = match stream.set_write_timeout(Some(options.timeout)) {
+            Ok(()) => {
+                let request = Deadline {
+                    stream: &stream,
+                    end: Instant::now() + options.timeout,
+                };
+                respond(&mut BufReader::new(request), options)
+            }
+            Err(err) => Reply::error(500, err),
+        };
+        if let Err(err) = write_reply(&stream, &reply) {
+            tracing::debug!(%err, "couldn't send the response");
+        }
+    }
+
+    /// Reads from `stream` until `end`, shrinking the read timeout before
+    /// each read, so a client sending a byte at a time can't hold the
+    /// connection open for longer than one that stalls.
+    struct Deadline<'a> {
+        stream: &'a TcpStream,
+        end: Instant,
+    }
+
+    impl Read for Deadline<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let left = self.end.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            self.stream.set_read_timeout(Some(left))?;
+            self.stream.read(buf)
+        }
+    }
+
+    fn respond(reader: &mut impl BufRead, options: ServerOptions) -> Reply {
+        let head = match read_head(reader) {
+            Ok(head) => head,
+            Err(reply) => return reply,
+        };
+        tracing::debug!(method = head.method, path = head.path, "request");
+        match (head.method.as_str(), head.path.as_str()) {
+            ("POST", "/encode" | "/decode" | "/scan") => match read_form(reader, &head, options) {
+                Ok(form) => {
+                    endpoint(&head.path, &form, options).unwrap_or_else(|err| error_reply(&err))
+                }
+                Err(reply) => reply,
+            },
+            (_, "/encode" | "/decode" | "/scan") => Reply::error(405, "only POST is supported"),
+            _ => Reply::error(404, "no such endpoint"),
+        }
+    }
+
+    /// Reads the request line and headers, or the reply to refuse them
+    /// with.
+    pub(super) fn read_head(reader: &mut impl BufRead) -> std::result::Result<Head, Reply> {
+        let mut reader = reader.take(MAX_HEAD);
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            reader
+                .read_line(&mut line)
+                .map_err(|err| read_error(&err))?;
+            if !line.ends_with('\n') {
+                return Err(match reader.limit() {
+                    0 => Reply::error(
+                        431,
+                        format!("request headers are limited to {MAX_HEAD} bytes"),
+                    ),
+                    _ => Reply::error(400, "the request ended early"),
+                });
+            }
+            let line = line.trim_end_matches(['\r', '\n']).to_string();
+            if line.is_empty() {
+                break;
+            }
+            lines.push(line);
+        }
+        let malformed = || Reply::error(400, "malformed request");
+        let (request_line, header_lines) = lines.split_first().ok_or_else(malformed)?;
+        let mut fields = request_line.split(' ');
+        let (Some(method), Some(target), Some(_version)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            return Err(malformed());
+        };
+        let headers = header_lines
+            .iter()
+            .map(|line| {
+                line.split_once(':')
+                    .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            })
+            .collect::<Option<_>>()
+            .ok_or_else(malformed)?;
+        Ok(Head {
+            method: method.to_string(),
+            path: target.split('?').next().unwrap_or_default().to_string(),
+            headers,
+        })
+    }
+
+    /// Reads and parses the body of the request `head` begins, or the reply
+    /// to refuse it with.
+    fn read_form(
+        reader: &mut impl BufRead,
+        head: &Head,
+        options: ServerOptions,
+    ) -> std::result::Result<Vec<Part>, Reply> {
+        let length_required = || Reply::error(411, "uploads need a Content-Length");
+        if head.header("Transfer-Encoding").is_some() {
+            return Err(length_required());
+        }
+        let length: u64 = head
+            .header("Content-Length")
+            .ok_or_else(length_required)?
+            .parse()
+            .map_err(|_| Reply::error(400, "invalid Content-Length"))?;
+        if length > options.max_body {
+            return Err(Reply::error(
+                413,
+                format!("uploads are limited to {} bytes", options.max_body),
+            ));
+        }
+        let boundary = head
+            .header("Content-Type")
+            .and_then(boundary)
+            .ok_or_else(|| Reply::error(400, "expected a multipart/form-data body"))?;
+        let mut body = Vec::new();
+        reader
+            .take(length)
+            .read_to_end(&mut body)
+            .map_err(|err| read_error(&err))?;
+        if (body.len() as u64) < length {
+            return Err(Reply::error(400, "the request ended early"));
+        }
+        parse_multipart(&body, &boundary).map_err(|err| error_reply(&err))
+    }
+
+    /// The reply to a failed read, 408 if the client stalled.
+    fn read_error(err: &io::Error) -> Reply {
+        match err.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => {
+                Reply::error(408, "the request took too long to arrive")
+            }
+            _ => Reply::error(400, err),
+        }
+    }
+
+    fn write_reply(mut stream: impl Write, reply: &Reply) -> io::Result<()> {
+        write!(
+            stream,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            reply.status,
+            reason(reply.status),
+            reply.content_type,
+            reply.body.len()
+        )?;
+        stream.write_all(&reply.body)?;
+        stream.flush()
+    }
+
+    fn reason(status: u16) -> &'static str {
+        match status {
+            200 => "OK",
+            400 => "Bad Request",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            408 => "Request Timeout",
+            411 => "Length Required",
+            413 => "Payload Too Large",
+            422 => "Unprocessable Entity",
+            431 => "Request Header Fields Too Large",
+            _ => "Internal Server Error",
+        }
+    }
+
+    /// Parsing limits for uploads: nothing in them, compressed or not, may
+    /// be larger than the upload limit.
+    fn parse_options(options: ServerOptions) -> ParseOptions {
+        ParseOptions {
+            max_total_size: options.max_body,
+            max_payload_size: options.max_body,
+            ..ParseOptions::default()
+        }
+    }
+
+    pub(super) fn endpoint(path: &str, form: &[Part], options: ServerOptions) -> Result<Reply> {
+        let image = field(form, "image")?;
+        match path {
+            "/encode" => {
+                let chunk_type = text_field(form, "chunk_type")?;
+                let png = message::embed(image, chunk_type, field(form, "message")?)?;
+                Ok(Reply::ok("image/png", png))
+            }
+            "/decode" => {
+                let chunk_type = text_field(form, "chunk_type")?;
+                let message = message::extract_with(image, chunk_type, &parse_options(options))?;
+                Ok(match std::str::from_utf8(&message) {
+                    Ok(_) => Reply::ok("text/plain; charset=utf-8", message),
+                    Err(_) => Reply::ok("application/octet-stream", message),
+                })
+            }
+            _ => scan(&ScanReport::from_bytes(image)?),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    fn scan(report: &ScanReport) -> Result<Reply> {
+        Ok(Reply::ok("application/json", serde_json::to_vec(report)?))
+    }
+
+    #[cfg(not(feature = "serde"))]
+    fn scan(report: &ScanReport) -> Result<Reply> {
+        let lines: String = report
+            .findings
+            .iter()
+            .map(|finding| format!("{finding}\n"))
+            .collect();
+        Ok(Reply::ok("text/plain; charset=utf-8", lines.into_bytes()))
+    }
+
+    /// The reply to a failed request. Errors in what was uploaded get a 4xx
+    /// status and failures of the service itself 500.
+    pub(super) fn error_reply(err: &PngMeError) -> Reply {
+        let status = match err {
+            PngMeError::Server(ServerError::Multipart | ServerError::MissingField(_)) => 400,
+            _ => match err.exit_code() {
+                ExitCode::NotFound => 404,
+                ExitCode::AuthFailure => 403,
+                ExitCode::Io => 500,
+                _ => 422,
+            },
+        };
+        Reply::error(status, err)
+    }
+
+    fn field<'a>(form: &'a [Part], name: &str) -> Result<&'a [u8]> {
+        form.iter()
+            .find(|part| part.name == name)
+            .map(|part| part.data.as_slice())
+            .ok_or_else(|| ServerError::MissingField(name.to_string()).into())
+    }
+
+    fn text_field<'a>(form: &'a [Part], name: &str) -> Result<&'a str> {
+        Ok(std::str::from_utf8(field(form, name)?)?)
+    }
+
+    /// The boundary of a `multipart/form-data` content type.
+    pub(super) fn boundary(content_type: &str) -> Option<String> {
+        let (mime, params) = content_type.split_once(';')?;
+        if !mime.trim().eq_ignore_ascii_case("multipart/form-data") {
+            return None;
+        }
+        params
+            .split(';')
+            .find_map(|param| param.trim().strip_prefix("boundary="))
+            .map(|boundary| boundary.trim_matches('"').to_string())
+    }
+
+    /// Splits a `multipart/form-data` body into its named fields.
+    pub(super) fn parse_multipart(body: &[u8], boundary: &str) -> Result<Vec<Part>> {
+        let delimiter = format!("--{boundary}").into_bytes();
+        let next_delimiter = [b"\r\n".as_slice(), &delimiter].concat();
+        let start = find(body, &delimiter).ok_or(ServerError::Multipart)?;
+        let mut rest = &body[start + delimiter.len()..];
+        let mut parts = Vec::new();
+        while !rest.starts_with(b"--") {
+            let part = rest.strip_prefix(b"\r\n").ok_or(ServerError::Multipart)?;
+            let headers_end = find(part, b"\r\n\r\n").ok_or(ServerError::Multipart)?;
+            let headers = std::str::from_utf8(&part[..headers_end])?;
+            let data = &part[headers_end + 4..];
+            let data_end = find(data, &next_delimiter).ok_or(ServerError::Multipart)?;
+            if let Some(name) = field_name(headers) {
+                parts.push(Part {
+                    name,
+                    data: data[..data_end].to_vec(),
+                });
+            }
+            rest = &data[data_end + next_delimiter.len()..];
+        }
+        Ok(parts)
+    }
+
+    /// The `name` in a part's Content-Disposition header.
+    fn field_name(headers: &str) -> Option<String> {
+        let (_, disposition) = headers.lines().find_map(|line| {
+            line.split_once(':')
+                .filter(|(name, _)| name.trim().eq_ignore_ascii_case("content-disposition"))
+        })?;
+        disposition
+            .split(';')
+            .find_map(|param| param.trim().strip_prefix("name="))
+            .map(|name| name.trim_matches('"').to_string())
+    }
+
+    fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+        haystack
+            .windows(needle.len())
+            .position(|window| window == needle)
+    }
+}
+
+/// Errors raised by the HTTP service.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ServerError {
+    /// The service was started from a build without the feature named here.
+    #[error("serve needs pngme to be built with the {0} feature")]
+    Unsupported(&'static str),
+    /// The address couldn't be listened on.
+    #[error("couldn't listen: {0}")]
+    Bind(Box<dyn std::error::Error + Send + Sync>),
+    /// A request body isn't well-formed `multipart/form-data`.
+    #[error("malformed multipart/form-data body")]
+    Multipart,
+    /// A request lacks the form field named here.
+    #[error("missing form field {0}")]
+    MissingField(String),
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::service::*;
+    use super::ServerOptions;
+    use crate::png::minimal_png;
+
+    fn form(fields: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (name, data) in fields {
+            body.extend(
+                format!("--XyZ\r\nContent-Disposition: form-data; name=\"{name}\"\r\n\r\n").bytes(),
+            );
+            body.extend(*data);
+            body.extend(b"\r\n");
+        }
+        body.extend(b"--XyZ--\r\n");
+        body
+    }
+
+    #[test]
+    fn test_parse_multipart() {
+        let body = form(&[("chunk_type", b"ruSt"), ("message", b"a\r\nb")]);
+        let delimiter = boundary("multipart/form-data; boundary=\"XyZ\"").unwrap();
+        let parts = parse_multipart(&body, &delimiter).unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[1].name, "message");
+        assert_eq!(parts[1].data, b"a\r\nb");
+        assert!(parse_multipart(b"--XyZ\r\nno end", "XyZ").is_err());
+        assert_eq!(boundary("text/plain; boundary=XyZ"), None);
+    }
+
+    #[test]
+    fn test_endpoints() {
+        let png = minimal_png().as_bytes();
+        let parts = |fields: &[(&str, &[u8])]| parse_multipart(&form(fields), "XyZ").unwrap();
+        let options = ServerOptions::default();
+
+        let encoded = endpoint(
+            "/encode",
+            &parts(&[("image", &png), ("chunk_type", b"ruSt"), ("message", b"hi")]),
+            options,
+        )
+        .unwrap();
+        assert_eq!(encoded.content_type, "image/png");
+        let decoded = endpoint(
+            "/decode",
+            &parts(&[("image", &encoded.body), ("chunk_type", b"ruSt")]),
+            options,
+        )
+        .unwrap();
+        assert_eq!(decoded.body, b"hi");
+
+        let err = endpoint(
+            "/decode",
+            &parts(&[("image", &png), ("chunk_type", b"ruSt")]),
+            options,
+        )
+        .unwrap_err();
+        assert_eq!(error_reply(&err).status, 404);
+        let err = endpoint("/scan", &parts(&[("chunk_type", b"ruSt")]), options).unwrap_err();
+        assert_eq!(error_reply(&err).status, 400);
+
+        // A payload may not decompress to more than an upload may hold.
+        let big = vec![0; 4096];
+        let png = crate::message::embed(&png, "ruSt", &big).unwrap();
+        let small = ServerOptions {
+            max_body: 1024,
+            ..options
+        };
+        let err = endpoint(
+            "/decode",
+            &parts(&[("image", &png), ("chunk_type", b"ruSt")]),
+            small,
+        )
+        .unwrap_err();
+        assert_eq!(error_reply(&err).status, 422);
+    }
+
+    #[test]
+    fn test_read_head() {
+        let mut request = &b"GET /scan?x=1 HTTP/1.1\r\nHost: a\r\nContent-Length: 3\r\n\r\nabc"[..];
+        let head = read_head(&mut request).unwrap();
+        assert_eq!(head.method, "GET");
+        assert_eq!(head.path, "/scan");
+        assert_eq!(
+            head.headers[1],
+            ("Content-Length".to_string(), "3".to_string())
+        );
+        assert_eq!(request, b"abc");
+
+        let endless = [b"GET / HTTP/1.1\r\nX: ".as_slice(), &[b'a'; 20_000]].concat();
+        assert_eq!(read_head(&mut endless.as_slice()).unwrap_err().status, 431);
+        assert_eq!(
+            read_head(&mut &b"GET / HTTP/1.1\r\n"[..])
+                .unwrap_err()
+                .status,
+            400
+        );
+    }
+
+    #[test]
+    fn test_stalled_client_times_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let options = ServerOptions {
+            timeout: Duration::from_millis(100),
+            ..ServerOptions::default()
+        };
+        let server = thread::spawn(move || handle(stream, options));
+        // Headers that never finish, as a slow-loris client sends them.
+        client
+            .write_all(b"POST /decode HTTP/1.1\r\nContent-")
+            .unwrap();
+        let mut reply = String::new();
+        client.read_to_string(&mut reply).unwrap();
+        server.join().unwrap();
+        assert!(reply.starts_with("HTTP/1.1 408 "), "{reply}");
+    }
+
+    #[test]
+    fn test_trickling_client_times_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let options = ServerOptions {
+            timeout: Duration::from_millis(300),
+            ..ServerOptions::default()
+        };
+        let start = Instant::now();
+        let server = thread::spawn(move || handle(stream, options));
+        // A body sent a byte at a time, each well within the timeout.
+        let mut writer = client.try_clone().unwrap();
+        let trickle = thread::spawn(move || {
+            writer
+                .write_all(
+                    b"POST /decode HTTP/1.1\r\nContent-Length: 1000\r\n\
+                      Content-Type: multipart/form-data; boundary=XyZ\r\n\r\n",
+                )
+                .unwrap();
+            while start.elapsed() < Duration::from_secs(3) && writer.write_all(b"-").is_ok() {
+                thread::sleep(Duration::from_millis(50));
+            }
+        });
+        let mut reply = String::new();
+        client.read_to_string(&mut reply).unwrap();
+        server.join().unwrap();
+        assert!(reply.starts_with("HTTP/1.1 408 "), "{reply}");
+        assert!(start.elapsed() < Duration::from_secs(2));
+        drop(client);
+        trickle.join().unwrap();
+    }
+}
@@ -0,0 +1,294 @@
+//! Shamir secret sharing, for splitting a message across several PNGs so
+//! that any `threshold` of them give it back and fewer reveal nothing about
+//! it.
+//!
+//! Each byte of the secret is the constant term of its own random
+//! polynomial of degree `threshold - 1` over GF(256), and share `x` holds
+//! every polynomial evaluated at `x`. Shares are stored as messages, so
+//! each can be compressed, encrypted and hidden like any other.
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use thiserror::Error;
+
+use crate::Result;
+
+/// Marks a message holding a [`Share`]. Like the other payload headers, the
+/// first byte is not valid UTF-8.
+const SHARE_MAGIC: [u8; 2] = [0x8f, b'H'];
+/// Magic, threshold and index.
+const SHARE_HEADER_LEN: usize = SHARE_MAGIC.len() + 1 + 1;
+
+/// One share of a secret split by [`split`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Share {
+    /// Where the polynomials were evaluated, from 1.
+    pub index: u8,
+    /// How many distinct shares are needed to rebuild the secret.
+    pub threshold: u8,
+    pub data: Vec<u8>,
+}
+
+impl Share {
+    /// Whether `bytes` were written by [`Share::to_bytes`].
+    pub fn is_share(bytes: &[u8]) -> bool {
+        bytes.len() >= SHARE_HEADER_LEN && bytes.starts_with(&SHARE_MAGIC)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = SHARE_MAGIC.to_vec();
+        bytes.extend([self.threshold, self.index]);
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+
+    /// Parses bytes written by [`Share::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Share> {
+        if !Share::is_share(bytes) {
+            return Err(ShareError::NotAShare.into());
+        }
+        Ok(Share {
+            threshold: bytes[2],
+            index: bytes[3],
+            data: bytes[SHARE_HEADER_LEN..].to_vec(),
+        })
+    }
+}
+
+/// Splits `secret` into `shares` shares, any `threshold` of which rebuild
+/// it. The threshold must be at least 2 and at most `shares`.
+pub fn split(secret: &[u8], shares: u8, threshold: u8) -> Result<Vec<Share>> {
+    if threshold < 2 || threshold > shares {
+        return Err(ShareError::InvalidThreshold { shares, threshold }.into());
+    }
+    // The coefficients of each byte's polynomial above the constant term.
+    let mut coefficients = vec![0; secret.len() * (threshold as usize - 1)];
+    OsRng.fill_bytes(&mut coefficients);
+    let coefficients: Vec<&[u8]> = coefficients.chunks(threshold as usize - 1).collect();
+    Ok((1..=shares)
+        .map(|x| Share {
+            index: x,
+            threshold,
+            data: secret
+                .iter()
+                .zip(&coefficients)
+                .map(|(&byte, higher)| {
+                    // Horner's rule, from the highest coefficient down.
+                    let sum = higher
+                        .iter()
+                        .rev()
+                        .fold(0, |sum, &coefficient| mul(sum, x) ^ coefficient);
+                    mul(sum, x) ^ byte
+                })
+                .collect(),
+        })
+        .collect())
+}
+
+/// Rebuilds the secret from `shares`, which must be at least their
+/// threshold of distinct shares of the same secret. Shares of different
+/// secrets with the same threshold and length can't be told apart, and
+/// combine into garbage.
+pub fn combine(shares: &[Share]) -> Result<Vec<u8>> {
+    let first = shares.first().ok_or(ShareError::TooFew {
+        found: 0,
+        threshold: 2,
+    })?;
+    if first.threshold < 2 {
+        return Err(ShareError::Inconsistent.into());
+    }
+    let mut seen = [false; 256];
+    for share in shares {
+        if share.threshold != first.threshold || share.data.len() != first.data.len() {
+            return Err(ShareError::Inconsistent.into());
+        }
+        if share.index == 0 || seen[share.index as usize] {
+            return Err(ShareError::DuplicateShare(share.index).into());
+        }
+        seen[share.index as usize] = true;
+    }
+    if shares.len() < first.threshold as usize {
+        return Err(ShareError::TooFew {
+            found: shares.len(),
+            threshold: first.threshold,
+        }
+        .into());
+    }
+
+    // Lagrange interpolation at 0, where each polynomial holds its byte.
+    let shares = &shares[..first.threshold as usize];
+    let weights: Vec<u8> = shares
+        .iter()
+        .map(|share| {
+            shares
+                .iter()
+                .filter(|other| other.index != share.index)
+                .fold(1, |weight, other| {
+                    mul(weight, div(other.index, other.index ^ share.index))
+                })
+        })
+        .collect();
+    Ok((0..first.data.len())
+        .map(|at| {
+            shares
+                .iter()
+                .zip(&weights)
+                .fold(0, |byte, (share, &weight)| {
+                    byte ^ mul(share.data[at], weight)
+                })
+        })
+        .collect())
+}
+
+/// Multiplication in GF(256) with the AES polynomial, x^8 + x^4 + x^3 + x + 1.
+fn mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80 != 0;
+        a <<= 1;
+        if carry {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+/// Division in GF(256), multiplying by the inverse `b^254`. `b` must not be
+/// zero.
+fn div(a: u8, b: u8) -> u8 {
+    let mut inverse = 1;
+    for _ in 0..254 {
+        inverse = mul(inverse, b);
+    }
+    mul(a, inverse)
+}
+
+/// Errors raised while splitting or combining shares.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ShareError {
+    /// The threshold is below 2 or above the number of shares.
+    #[error("threshold {threshold} must be from 2 up to the number of shares, {shares}")]
+    InvalidThreshold { shares: u8, threshold: u8 },
+    /// The number of PNGs to split across isn't the number of shares.
+    #[error("{shares} shares need {shares} PNGs, found {files}")]
+    WrongFileCount { shares: u8, files: usize },
+    /// A message being combined isn't a share.
+    #[error("message is not a share of a split secret")]
+    NotAShare,
+    /// Fewer shares were found than their threshold.
+    #[error("found {found} share(s), {threshold} are needed to rebuild the secret")]
+    TooFew { found: usize, threshold: u8 },
+    /// The shares disagree on their threshold or length, so they are of
+    /// different secrets, or claim a threshold [`split`] never writes.
+    #[error("shares are of different secrets")]
+    Inconsistent,
+    /// The share with this index was given more than once.
+    #[error("share {0} appears more than once")]
+    DuplicateShare(u8),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use crate::envelope::{EnvelopeFlags, PayloadEnvelope};
+    use crate::payload::{self, Payload};
+    use crate::png::{minimal_png, Png};
+    use crate::position::ChunkPosition;
+    use crate::PngMeError;
+
+    #[test]
+    fn test_field() {
+        assert_eq!(mul(0x53, 0xca), 0x01);
+        assert_eq!(div(1, 0x53), 0xca);
+        for a in 1..=255 {
+            assert_eq!(mul(div(7, a), a), 7);
+        }
+    }
+
+    #[test]
+    fn test_split_and_combine() {
+        let secret = b"the vault code is 4921";
+        let shares = split(secret, 5, 3).unwrap();
+        assert_eq!(shares.len(), 5);
+        assert!(shares.iter().all(|share| share.data != secret));
+        for picked in [[0, 1, 2], [4, 2, 0], [1, 3, 4]] {
+            let picked: Vec<Share> = picked.iter().map(|&i| shares[i].clone()).collect();
+            assert_eq!(combine(&picked).unwrap(), secret);
+        }
+        assert_eq!(combine(&shares).unwrap(), secret);
+
+        let parsed = Share::from_bytes(&shares[3].to_bytes()).unwrap();
+        assert_eq!(parsed, shares[3]);
+        assert!(!Share::is_share(secret));
+
+        assert!(matches!(
+            combine(&shares[..2]),
+            Err(PngMeError::Share(ShareError::TooFew { found: 2, .. }))
+        ));
+        let repeated = [shares[0].clone(), shares[0].clone(), shares[1].clone()];
+        assert!(matches!(
+            combine(&repeated),
+            Err(PngMeError::Share(ShareError::DuplicateShare(1)))
+        ));
+        assert!(split(secret, 3, 1).is_err());
+        assert!(split(secret, 2, 3).is_err());
+    }
+
+    #[test]
+    fn test_low_threshold_is_inconsistent() {
+        let mut shares = split(b"secret", 3, 2).unwrap();
+        for share in &mut shares {
+            share.threshold = 1;
+        }
+        assert!(matches!(
+            combine(&shares),
+            Err(PngMeError::Share(ShareError::Inconsistent))
+        ));
+    }
+
+    #[test]
+    fn test_combine_shares_read_from_pngs() {
+        let secret = b"the vault code is 4921";
+        let chunk_type: ChunkType = "ruSt".parse().unwrap();
+        let pngs: Vec<Vec<u8>> = split(secret, 3, 2)
+            .unwrap()
+            .into_iter()
+            .map(|share| {
+                let payload = Payload::Share(share);
+                let flags = EnvelopeFlags::kind_of(&payload);
+                let data = PayloadEnvelope::new(flags, payload.to_bytes()).encode();
+                let mut png = minimal_png();
+                let chunks = payload::split(&chunk_type, &data, 16).unwrap();
+                png.insert_chunks(ChunkPosition::BeforeIend, chunks)
+                    .unwrap();
+                png.as_bytes()
+            })
+            .collect();
+
+        let shares: Vec<Share> = pngs[1..]
+            .iter()
+            .map(|bytes| {
+                let png = Png::try_from(bytes.as_slice()).unwrap();
+                let parts: Vec<&[u8]> = png
+                    .chunks()
+                    .iter()
+                    .filter(|chunk| *chunk.chunk_type() == chunk_type)
+                    .map(|chunk| chunk.data())
+                    .collect();
+                let envelope =
+                    PayloadEnvelope::decode(&payload::reassemble(&parts).unwrap()).unwrap();
+                match envelope.open(envelope.payload(), 1024).unwrap() {
+                    Payload::Share(share) => share,
+                    other => panic!("expected a share, found {other:?}"),
+                }
+            })
+            .collect();
+        assert_eq!(combine(&shares).unwrap(), secret);
+    }
+}
@@ -0,0 +1,133 @@
+use std::path::Path;
+
+use aes_gcm::aead::OsRng;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey, SIGNATURE_LENGTH};
+use thiserror::Error;
+
+use crate::chunk_type::ChunkType;
+use crate::crypto;
+use crate::Result;
+
+/// Generates a new random Ed25519 signing key.
+pub fn generate_key() -> SigningKey {
+    SigningKey::generate(&mut OsRng)
+}
+
+/// Loads a signing key from a key file written by [`key_file_contents`].
+pub fn read_signing_key<P: AsRef<Path>>(path: P) -> Result<SigningKey> {
+    Ok(SigningKey::from_bytes(&crypto::read_key_file(path)?))
+}
+
+/// Parses a hex-encoded Ed25519 public key.
+pub fn parse_verifying_key(s: &str) -> Result<VerifyingKey> {
+    VerifyingKey::from_bytes(&crypto::parse_hex_key(s)?)
+        .map_err(|_| SignatureError::InvalidKey.into())
+}
+
+/// Renders `key` as a key file, with its public half in a comment.
+pub fn key_file_contents(key: &SigningKey) -> String {
+    crypto::key_file_contents(
+        &hex::encode(key.verifying_key().as_bytes()),
+        &key.to_bytes(),
+    )
+}
+
+/// Appends an Ed25519 signature over the chunk type and `payload`.
+///
+/// The chunk type is signed too, so a signed payload can't be moved to a
+/// chunk of a different type without invalidating the signature.
+pub fn sign(key: &SigningKey, chunk_type: &ChunkType, payload: &[u8]) -> Vec<u8> {
+    let signature = key.sign(&signed_message(chunk_type, payload));
+    let mut data = Vec::with_capacity(payload.len() + SIGNATURE_LENGTH);
+    data.extend_from_slice(payload);
+    data.extend_from_slice(&signature.to_bytes());
+    data
+}
+
+/// Checks the trailing signature written by [`sign`] and returns the payload
+/// in front of it.
+pub fn verify<'a>(key: &VerifyingKey, chunk_type: &ChunkType, data: &'a [u8]) -> Result<&'a [u8]> {
+    let split = data
+        .len()
+        .checked_sub(SIGNATURE_LENGTH)
+        .ok_or(SignatureError::Missing)?;
+    let (payload, signature) = data.split_at(split);
+    let signature = Signature::from_bytes(signature.try_into()?);
+
+    key.verify_strict(&signed_message(chunk_type, payload), &signature)
+        .map_err(|_| SignatureError::Invalid)?;
+    Ok(payload)
+}
+
+fn signed_message(chunk_type: &ChunkType, payload: &[u8]) -> Vec<u8> {
+    chunk_type.bytes().iter().chain(payload).copied().collect()
+}
+
+/// Errors raised while verifying a signed payload.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum SignatureError {
+    /// The chunk is too short to hold a signature.
+    #[error("chunk does not carry a signature")]
+    Missing,
+    /// The signature doesn't match the payload and public key.
+    #[error("signature is not valid for this payload and public key")]
+    Invalid,
+    /// The public key is not a valid Ed25519 point.
+    #[error("invalid Ed25519 public key")]
+    InvalidKey,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PngMeError;
+
+    fn chunk_type() -> ChunkType {
+        "ruSt".parse().unwrap()
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = generate_key();
+        let data = sign(&key, &chunk_type(), b"signed, sealed");
+        assert_eq!(data.len(), b"signed, sealed".len() + SIGNATURE_LENGTH);
+        let payload = verify(&key.verifying_key(), &chunk_type(), &data).unwrap();
+        assert_eq!(payload, b"signed, sealed");
+
+        let public = hex::encode(key.verifying_key().as_bytes());
+        assert_eq!(parse_verifying_key(&public).unwrap(), key.verifying_key());
+    }
+
+    #[test]
+    fn test_tampered_payload_is_invalid() {
+        let key = generate_key();
+        let mut data = sign(&key, &chunk_type(), b"signed, sealed");
+        data[0] ^= 1;
+        assert!(matches!(
+            verify(&key.verifying_key(), &chunk_type(), &data),
+            Err(PngMeError::Signature(SignatureError::Invalid))
+        ));
+
+        // Moving the payload to another chunk type breaks it too.
+        let data = sign(&key, &chunk_type(), b"signed, sealed");
+        let other = "ruSu".parse().unwrap();
+        assert!(matches!(
+            verify(&key.verifying_key(), &other, &data),
+            Err(PngMeError::Signature(SignatureError::Invalid))
+        ));
+        assert!(matches!(
+            verify(&generate_key().verifying_key(), &chunk_type(), &data),
+            Err(PngMeError::Signature(SignatureError::Invalid))
+        ));
+    }
+
+    #[test]
+    fn test_missing_signature() {
+        let key = generate_key();
+        assert!(matches!(
+            verify(&key.verifying_key(), &chunk_type(), b"too short"),
+            Err(PngMeError::Signature(SignatureError::Missing))
+        ));
+    }
+}
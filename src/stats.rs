@@ -0,0 +1,186 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+
+/// How many of the largest chunks [`Stats`] keeps.
+pub const LARGEST_CHUNKS: usize = 10;
+
+/// Chunk statistics for one PNG or, [merged](Stats::merge), for a set of
+/// them, to see what takes up the space.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Stats {
+    pub files: usize,
+    /// Count and data bytes of each chunk type.
+    pub chunk_types: BTreeMap<String, TypeStats>,
+    /// Data bytes of every ancillary chunk, the ones a viewer can do without.
+    pub ancillary_bytes: u64,
+    /// The [`LARGEST_CHUNKS`] largest chunks, largest first.
+    pub largest: Vec<LargeChunk>,
+    /// Bytes of IDAT data, compressed and decompressed, over the images whose
+    /// image data could be decompressed.
+    pub idat_compressed: u64,
+    pub idat_decompressed: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct TypeStats {
+    pub count: usize,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct LargeChunk {
+    /// The file the chunk is in, as given to [`Stats::of`].
+    pub source: String,
+    pub position: usize,
+    pub chunk_type: String,
+    pub length: u32,
+}
+
+impl Stats {
+    /// Statistics for `png`, read from `source`.
+    pub fn of(png: &Png, source: &str) -> Stats {
+        let mut stats = Stats {
+            files: 1,
+            ..Stats::default()
+        };
+        for (position, chunk) in png.chunks().iter().enumerate() {
+            let chunk_type = chunk.chunk_type();
+            let bytes = u64::from(chunk.length());
+            let entry = stats.chunk_types.entry(chunk_type.to_string()).or_default();
+            entry.count += 1;
+            entry.bytes += bytes;
+            if !chunk_type.is_critical() {
+                stats.ancillary_bytes += bytes;
+            }
+            stats.largest.push(LargeChunk {
+                source: source.to_string(),
+                position,
+                chunk_type: chunk_type.to_string(),
+                length: chunk.length(),
+            });
+        }
+        stats.keep_largest();
+        if let Ok(data) = png.image_data() {
+            stats.idat_compressed = stats
+                .chunk_types
+                .get(&ChunkType::IDAT.to_string())
+                .map_or(0, |idat| idat.bytes);
+            stats.idat_decompressed = data.len() as u64;
+        }
+        stats
+    }
+
+    /// Adds the statistics of other files.
+    pub fn merge(&mut self, other: Stats) {
+        self.files += other.files;
+        for (chunk_type, counts) in other.chunk_types {
+            let entry = self.chunk_types.entry(chunk_type).or_default();
+            entry.count += counts.count;
+            entry.bytes += counts.bytes;
+        }
+        self.ancillary_bytes += other.ancillary_bytes;
+        self.largest.extend(other.largest);
+        self.keep_largest();
+        self.idat_compressed += other.idat_compressed;
+        self.idat_decompressed += other.idat_decompressed;
+    }
+
+    /// Decompressed image data bytes per compressed byte, or `None` without
+    /// any image data.
+    pub fn compression_ratio(&self) -> Option<f64> {
+        (self.idat_compressed > 0)
+            .then(|| self.idat_decompressed as f64 / self.idat_compressed as f64)
+    }
+
+    fn keep_largest(&mut self) {
+        // Stable, so equal chunks keep file order.
+        self.largest.sort_by_key(|chunk| Reverse(chunk.length));
+        self.largest.truncate(LARGEST_CHUNKS);
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut types: Vec<_> = self.chunk_types.iter().collect();
+        types.sort_by_key(|(_, counts)| Reverse(counts.bytes));
+        writeln!(f, "{:<6} {:>8} {:>12}", "TYPE", "COUNT", "BYTES")?;
+        for (chunk_type, counts) in types {
+            writeln!(
+                f,
+                "{chunk_type:<6} {:>8} {:>12}",
+                counts.count, counts.bytes
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "Files: {}", self.files)?;
+        writeln!(f, "Ancillary bytes: {}", self.ancillary_bytes)?;
+        match self.compression_ratio() {
+            Some(ratio) => writeln!(
+                f,
+                "IDAT compression: {} bytes to {}, {ratio:.2}:1",
+                self.idat_decompressed, self.idat_compressed
+            )?,
+            None => writeln!(f, "IDAT compression: no image data")?,
+        }
+        if !self.largest.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "Largest chunks:")?;
+        }
+        for chunk in &self.largest {
+            writeln!(
+                f,
+                "  {} chunk {} ({}): {} bytes",
+                chunk.source, chunk.position, chunk.chunk_type, chunk.length
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::ihdr::ColorType;
+
+    #[test]
+    fn test_stats() {
+        let mut png = Png::from_pixels(4, 4, ColorType::Grayscale, &[0; 16]).unwrap();
+        let text = ChunkType::new_unchecked(*b"tEXt");
+        let comment = [b"Comment\0".as_slice(), &[b'x'; 100]].concat();
+        png.append_chunk(Chunk::new(text, comment));
+        png.append_chunk(Chunk::new(text, b"Author\0me".to_vec()));
+
+        let mut stats = Stats::of(&png, "a.png");
+        assert_eq!(stats.files, 1);
+        assert_eq!(
+            stats.chunk_types["tEXt"],
+            TypeStats {
+                count: 2,
+                bytes: 117
+            }
+        );
+        assert_eq!(stats.ancillary_bytes, 117);
+        assert_eq!(stats.idat_decompressed, 4 * 5);
+        assert!(stats.compression_ratio().is_some());
+        assert_eq!(stats.largest[0].chunk_type, "tEXt");
+        assert_eq!(stats.largest[0].position, 2);
+
+        stats.merge(Stats::of(&png, "b.png"));
+        assert_eq!(stats.files, 2);
+        assert_eq!(stats.chunk_types["tEXt"].count, 4);
+        assert_eq!(stats.ancillary_bytes, 234);
+        assert_eq!(stats.largest.len(), LARGEST_CHUNKS);
+        assert_eq!(stats.largest[1].source, "b.png");
+    }
+}
@@ -0,0 +1,231 @@
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::cloud;
+use crate::http::{self, HttpError};
+use crate::png::Png;
+use crate::Result;
+
+/// The path that stands for standard input when reading and standard output
+/// when writing.
+pub const STDIO_PATH: &str = "-";
+
+pub fn is_stdio(path: &Path) -> bool {
+    path == Path::new(STDIO_PATH)
+}
+
+/// How [`write`] and [`write_png`] replace a file that already exists.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WriteOptions {
+    /// Keep the replaced file at its path with this suffix appended, such as
+    /// `image.png.bak`. An empty suffix, which would name the file itself,
+    /// keeps nothing.
+    pub backup_suffix: Option<OsString>,
+}
+
+/// Parses the PNG at `path`, from standard input, or from an
+/// [http](http::is_url) or [object storage](cloud::is_object_url) URL.
+pub fn read_png(path: &Path) -> Result<Png> {
+    match is_stdio(path) || http::is_url(path) || cloud::is_object_url(path) {
+        true => Png::from_reader(open(path)?),
+        false => Png::from_file(path),
+    }
+}
+
+/// Reads all of `path`, of standard input, or of an [http](http::is_url) or
+/// [object storage](cloud::is_object_url) URL.
+pub fn read(path: &Path) -> Result<Vec<u8>> {
+    if cloud::is_object_url(path) {
+        return cloud::read(&path.to_string_lossy());
+    }
+    if !is_stdio(path) && !http::is_url(path) {
+        return Ok(fs::read(path)?);
+    }
+    let mut bytes = Vec::new();
+    open(path)?.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Opens `path`, standard input, or an [http](http::is_url) or
+/// [object storage](cloud::is_object_url) URL for buffered reading.
+pub fn open(path: &Path) -> Result<Box<dyn Read>> {
+    if cloud::is_object_url(path) {
+        return Ok(Box::new(io::Cursor::new(read(path)?)));
+    }
+    if http::is_url(path) {
+        return http::open(&path.to_string_lossy());
+    }
+    Ok(match is_stdio(path) {
+        true => Box::new(io::stdin().lock()),
+        false => Box::new(BufReader::new(File::open(path)?)),
+    })
+}
+
+/// Fails for a URL, which can be read from but not written to.
+fn check_writable(path: &Path) -> Result<()> {
+    match http::is_url(path) {
+        true => Err(HttpError::ReadOnly(path.display().to_string()).into()),
+        false => Ok(()),
+    }
+}
+
+/// Writes `png` to `path`, to an [object storage](cloud::is_object_url) URL,
+/// or streams it to standard output.
+pub fn write_png(path: &Path, png: &Png, options: &WriteOptions) -> Result<()> {
+    check_writable(path)?;
+    if cloud::is_object_url(path) {
+        return cloud::write(&path.to_string_lossy(), &png.as_bytes());
+    }
+    match is_stdio(path) {
+        true => png.write_to(io::stdout().lock()).map(drop),
+        false => replace(path, &png.as_bytes(), options.backup_suffix.as_deref()),
+    }
+}
+
+/// Writes `bytes` to `path`, to an [object storage](cloud::is_object_url)
+/// URL, or to standard output.
+pub fn write(path: &Path, bytes: &[u8], options: &WriteOptions) -> Result<()> {
+    check_writable(path)?;
+    if cloud::is_object_url(path) {
+        return cloud::write(&path.to_string_lossy(), bytes);
+    }
+    match is_stdio(path) {
+        true => {
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(bytes)?;
+            stdout.flush()?;
+        }
+        false => replace(path, bytes, options.backup_suffix.as_deref())?,
+    }
+    Ok(())
+}
+
+/// Writes `bytes` to a new file at `path` that only its owner can read, for
+/// secrets such as keys. Fails if `path` already exists rather than
+/// replacing it.
+pub fn write_private(path: &Path, bytes: &[u8]) -> Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path).map_err(|err| match err.kind() {
+        io::ErrorKind::AlreadyExists => {
+            io::Error::new(err.kind(), format!("{} already exists", path.display()))
+        }
+        _ => err,
+    })?;
+    file.write_all(bytes)?;
+    Ok(file.sync_all()?)
+}
+
+/// Writes `bytes` to a temporary file next to `path` and renames it over
+/// `path`, so a crash leaves either the old file or the new one, never a
+/// mix. With a non-empty `backup` suffix the old file is kept under that suffix.
+fn replace(path: &Path, bytes: &[u8], backup: Option<&OsStr>) -> Result<()> {
+    let temp = sibling(path, ".tmp", true);
+    let written = write_synced(&temp, bytes, path);
+    let renamed = written.and_then(|()| {
+        if let Some(suffix) = backup.filter(|suffix| !suffix.is_empty()) {
+            keep_backup(path, &sibling(path, suffix, false))?;
+        }
+        fs::rename(&temp, path)
+    });
+    if renamed.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    Ok(renamed?)
+}
+
+/// `path` with `suffix` appended to its file name, and a leading dot and the
+/// process ID before it if `hidden`, so concurrent runs don't collide.
+fn sibling(path: &Path, suffix: impl AsRef<OsStr>, hidden: bool) -> PathBuf {
+    let mut name = OsString::new();
+    if hidden {
+        name.push(".");
+    }
+    name.push(path.file_name().unwrap_or(path.as_os_str()));
+    if hidden {
+        name.push(format!(".{}", std::process::id()));
+    }
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Writes `bytes` to a new file at `path` and flushes it to disk, copying the
+/// permissions of `original` if it exists.
+fn write_synced(path: &Path, bytes: &[u8], original: &Path) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(bytes)?;
+    if let Ok(metadata) = fs::metadata(original) {
+        file.set_permissions(metadata.permissions())?;
+    }
+    file.sync_all()
+}
+
+/// Makes `backup` a copy of `path`, replacing any earlier backup. A hard
+/// link is tried first, which costs nothing and survives the rename over
+/// `path`. Nothing is kept if `path` doesn't exist yet.
+fn keep_backup(path: &Path, backup: &Path) -> io::Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    match fs::remove_file(backup) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+    if fs::hard_link(path, backup).is_err() {
+        fs::copy(path, backup)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace() {
+        let dir = std::env::temp_dir().join(format!("pngme-stdio-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("image.png");
+
+        replace(&path, b"first", None).unwrap();
+        replace(&path, b"second", Some(OsStr::new(".bak"))).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        assert_eq!(fs::read(dir.join("image.png.bak")).unwrap(), b"first");
+
+        replace(&path, b"third", Some(OsStr::new(".bak"))).unwrap();
+        assert_eq!(fs::read(dir.join("image.png.bak")).unwrap(), b"second");
+        let mut names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["image.png", "image.png.bak"]);
+
+        replace(&path, b"fourth", Some(OsStr::new(""))).unwrap();
+        assert_eq!(fs::read(dir.join("image.png.bak")).unwrap(), b"second");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_private() {
+        let dir = std::env::temp_dir().join(format!("pngme-private-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("identity.txt");
+
+        write_private(&path, b"secret").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let err = write_private(&path, b"other").unwrap_err();
+        assert!(err.to_string().contains("already exists"));
+        assert_eq!(fs::read(&path).unwrap(), b"secret");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
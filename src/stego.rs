@@ -0,0 +1,366 @@
+use std::fmt;
+
+use thiserror::Error;
+
+use crate::ihdr::{ColorType, Ihdr, Interlace};
+use crate::lsb;
+use crate::png::Png;
+use crate::Result;
+
+/// Adjacent samples of one channel that RS analysis treats as a group.
+const GROUP_LEN: usize = 4;
+
+/// Which samples of a group the RS flipping functions act on.
+const MASK: [bool; GROUP_LEN] = [false, true, true, false];
+
+/// How conspicuous LSB embedding in an image is, by two classic attacks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Analysis {
+    /// Westfeld and Pfitzmann's test of whether each pair of values that
+    /// differ only in the low bit occurs equally often, as it does once the
+    /// low bits are replaced by random data. `None` if the image has too few
+    /// distinct values to test.
+    pub chi_square: Option<ChiSquare>,
+    /// Fridrich's estimate of the fraction of samples whose low bit carries
+    /// a payload, from 0 to 1. `None` if the image is too small or too
+    /// regular for the estimate to exist.
+    pub rs_rate: Option<f64>,
+}
+
+impl Analysis {
+    /// A score from 0, nothing detected, to 1, embedding almost certain: the
+    /// stronger of the two attacks' results.
+    pub fn detectability(&self) -> f64 {
+        let chi = self.chi_square.map_or(0.0, |chi| chi.p_value);
+        let rs = self.rs_rate.unwrap_or(0.0);
+        chi.max(rs)
+    }
+}
+
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.chi_square {
+            Some(chi) => writeln!(
+                f,
+                "Chi-square: {:.2} with {} degrees of freedom, embedding probability {:.3}",
+                chi.statistic, chi.degrees_of_freedom, chi.p_value
+            )?,
+            None => writeln!(f, "Chi-square: too few distinct sample values")?,
+        }
+        match self.rs_rate {
+            Some(rate) => writeln!(f, "RS analysis: {:.1}% of samples embedded", rate * 100.0)?,
+            None => writeln!(f, "RS analysis: no estimate")?,
+        }
+        let score = self.detectability();
+        let verdict = match score {
+            s if s < 0.3 => "low",
+            s if s < 0.7 => "moderate",
+            _ => "high",
+        };
+        writeln!(f, "Detectability: {score:.2} ({verdict})")
+    }
+}
+
+/// The result of the chi-square attack.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChiSquare {
+    pub statistic: f64,
+    pub degrees_of_freedom: u32,
+    /// The probability that the value pairs are as even as LSB embedding
+    /// leaves them.
+    pub p_value: f64,
+}
+
+/// Runs chi-square and RS steganalysis over the color samples of `png`.
+/// Alpha samples are skipped, as the LSB backend never writes to them.
+pub fn analyze(png: &Png) -> Result<Analysis> {
+    let ihdr = png.ihdr()?;
+    check_supported(&ihdr)?;
+    let data = png.scanlines()?.data;
+    Ok(Analysis {
+        chi_square: chi_square(&ihdr, &data),
+        rs_rate: rs_rate(&ihdr, &data),
+    })
+}
+
+fn chi_square(ihdr: &Ihdr, data: &[u8]) -> Option<ChiSquare> {
+    let mut histogram = [0u64; 256];
+    for sample in lsb::sample_indices(ihdr) {
+        histogram[data[sample] as usize] += 1;
+    }
+
+    let mut statistic = 0.0;
+    let mut pairs = 0;
+    for pair in histogram.chunks_exact(2) {
+        let expected = (pair[0] + pair[1]) as f64 / 2.0;
+        if expected > 0.0 {
+            statistic += (pair[0] as f64 - expected).powi(2) / expected;
+            pairs += 1;
+        }
+    }
+    if pairs < 2 {
+        return None;
+    }
+    let degrees_of_freedom = pairs - 1;
+    Some(ChiSquare {
+        statistic,
+        degrees_of_freedom,
+        p_value: 1.0
+            - lower_regularized_gamma(f64::from(degrees_of_freedom) / 2.0, statistic / 2.0),
+    })
+}
+
+/// Groups of [`GROUP_LEN`] horizontally adjacent samples of one channel.
+fn groups(ihdr: &Ihdr, data: &[u8]) -> Vec<[i32; GROUP_LEN]> {
+    let channels = ihdr.color_type.channels() as usize;
+    let color_channels = match ihdr.color_type {
+        ColorType::Rgba | ColorType::GrayscaleAlpha => channels - 1,
+        _ => channels,
+    };
+    let stride = ihdr.width as usize * channels;
+    let mut groups = Vec::new();
+    for row in data.chunks_exact(stride) {
+        for channel in 0..color_channels {
+            let samples: Vec<i32> = row
+                .iter()
+                .skip(channel)
+                .step_by(channels)
+                .map(|&sample| i32::from(sample))
+                .collect();
+            groups.extend(
+                samples
+                    .chunks_exact(GROUP_LEN)
+                    .map(|group| <[i32; GROUP_LEN]>::try_from(group).expect("exact chunk")),
+            );
+        }
+    }
+    groups
+}
+
+/// Estimates the embedding rate from how flipping low bits changes the
+/// smoothness of sample groups, as in Fridrich, Goljan and Du's "Reliable
+/// Detection of LSB Steganography in Color and Grayscale Images".
+fn rs_rate(ihdr: &Ihdr, data: &[u8]) -> Option<f64> {
+    let groups = groups(ihdr, data);
+    if groups.is_empty() {
+        return None;
+    }
+    let flipped: Vec<[i32; GROUP_LEN]> = groups
+        .iter()
+        .map(|group| group.map(|sample| sample ^ 1))
+        .collect();
+
+    let (d0, dn0) = regular_minus_singular(&groups);
+    let (d1, dn1) = regular_minus_singular(&flipped);
+    let a = 2.0 * (d1 + d0);
+    let b = dn0 - dn1 - d1 - 3.0 * d0;
+    let c = d0 - dn0;
+    let z = if a.abs() < f64::EPSILON {
+        -c / b
+    } else {
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let roots = [
+            (-b + discriminant.sqrt()) / (2.0 * a),
+            (-b - discriminant.sqrt()) / (2.0 * a),
+        ];
+        match roots[0].abs() <= roots[1].abs() {
+            true => roots[0],
+            false => roots[1],
+        }
+    };
+    let rate = z / (z - 0.5);
+    rate.is_finite().then(|| rate.clamp(0.0, 1.0))
+}
+
+/// The fraction of regular minus singular groups under the mask, then under
+/// the negated mask.
+fn regular_minus_singular(groups: &[[i32; GROUP_LEN]]) -> (f64, f64) {
+    let (mut positive, mut negative) = (0i64, 0i64);
+    for group in groups {
+        let smoothness = variation(group);
+        positive += (variation(&flip(group, |x| x ^ 1)) - smoothness).signum() as i64;
+        negative += (variation(&flip(group, |x| ((x + 1) ^ 1) - 1)) - smoothness).signum() as i64;
+    }
+    let total = groups.len() as f64;
+    (positive as f64 / total, negative as f64 / total)
+}
+
+fn flip(group: &[i32; GROUP_LEN], f: impl Fn(i32) -> i32) -> [i32; GROUP_LEN] {
+    let mut flipped = *group;
+    for (sample, masked) in flipped.iter_mut().zip(MASK) {
+        if masked {
+            *sample = f(*sample);
+        }
+    }
+    flipped
+}
+
+/// How far the group is from smooth: the sum of differences between
+/// neighbors.
+fn variation(group: &[i32; GROUP_LEN]) -> i32 {
+    group.windows(2).map(|pair| (pair[1] - pair[0]).abs()).sum()
+}
+
+/// Both attacks need one byte per sample in simple row order, and palette
+/// indices say nothing about how smooth the image is.
+fn check_supported(ihdr: &Ihdr) -> Result<()> {
+    let supported = ihdr.bit_depth == 8
+        && ihdr.interlace == Interlace::None
+        && ihdr.color_type != ColorType::Indexed;
+    if !supported {
+        return Err(StegoError::UnsupportedImage(*ihdr).into());
+    }
+    Ok(())
+}
+
+/// The regularized lower incomplete gamma function P(a, x), which gives the
+/// chi-square distribution function as P(k / 2, x / 2).
+fn lower_regularized_gamma(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    let prefix = (a * x.ln() - x - ln_gamma(a)).exp();
+    if x < a + 1.0 {
+        // Series expansion, which converges quickly below the mean.
+        let (mut term, mut sum, mut n) = (1.0 / a, 1.0 / a, a);
+        while term.abs() > sum.abs() * 1e-14 {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+        }
+        prefix * sum
+    } else {
+        // Lentz's continued fraction for the upper function, 1 - P.
+        let tiny = 1e-300;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut fraction = d;
+        for i in 1..1000 {
+            let an = -f64::from(i) * (f64::from(i) - a);
+            b += 2.0;
+            d = an * d + b;
+            d = if d.abs() < tiny { tiny } else { d };
+            c = b + an / c;
+            c = if c.abs() < tiny { tiny } else { c };
+            d = 1.0 / d;
+            let delta = d * c;
+            fraction *= delta;
+            if (delta - 1.0).abs() < 1e-14 {
+                break;
+            }
+        }
+        1.0 - prefix * fraction
+    }
+}
+
+/// ln Γ(x) for x > 0, by the Lanczos approximation.
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    let x = x - 1.0;
+    let t = x + 7.5;
+    let series = COEFFICIENTS[1..]
+        .iter()
+        .enumerate()
+        .fold(COEFFICIENTS[0], |sum, (i, c)| {
+            sum + c / (x + i as f64 + 1.0)
+        });
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + series.ln()
+}
+
+/// Errors raised by steganalysis.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum StegoError {
+    /// The image format is not one the attacks can read.
+    #[error("steganalysis needs an 8-bit, non-interlaced, non-indexed image, not {0}")]
+    UnsupportedImage(Ihdr),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::lsb::LsbOptions;
+
+    /// A smooth RGB gradient with every sample even, like an image whose
+    /// low bits were never touched.
+    fn gradient(width: u32, height: u32) -> Png {
+        let ihdr = Ihdr::new(width, height, 8, ColorType::Rgb).unwrap();
+        let mut png = Png::from_chunks(vec![
+            ihdr.to_chunk(),
+            Chunk::new(ChunkType::try_from(*b"IDAT").unwrap(), Vec::new()),
+            Chunk::new(ChunkType::try_from(*b"IEND").unwrap(), Vec::new()),
+        ]);
+        let stride = width as usize * 3;
+        let filtered: Vec<u8> = (0..height as usize)
+            .flat_map(|y| {
+                std::iter::once(0)
+                    .chain((0..stride).map(move |i| ((y + i / 3) * 2 + i % 3) as u8 & !1))
+            })
+            .collect();
+        png.set_image_data(&filtered).unwrap();
+        png
+    }
+
+    #[test]
+    fn test_gamma_matches_known_values() {
+        assert!((ln_gamma(5.0) - 24f64.ln()).abs() < 1e-10);
+        // The chi-square distribution function with 2 degrees of freedom is 1 - e^(-x/2).
+        let p = lower_regularized_gamma(1.0, 1.5);
+        assert!((p - (1.0 - (-1.5f64).exp())).abs() < 1e-10);
+        let p = lower_regularized_gamma(1.0, 20.0);
+        assert!((p - (1.0 - (-20f64).exp())).abs() < 1e-10);
+    }
+
+    #[test]
+    fn test_clean_image_scores_low() {
+        let analysis = analyze(&gradient(64, 64)).unwrap();
+        assert!(analysis.chi_square.unwrap().p_value < 0.01);
+        assert!(analysis.detectability() < 0.3, "{analysis}");
+    }
+
+    #[test]
+    fn test_full_embedding_scores_high() {
+        let mut png = gradient(64, 64);
+        let ihdr = png.ihdr().unwrap();
+        // Bytes from a xorshift generator stand in for an encrypted payload.
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let payload: Vec<u8> = (0..lsb::payload_capacity(&ihdr).unwrap())
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        lsb::embed(&mut png, &payload, &LsbOptions::default()).unwrap();
+
+        let analysis = analyze(&png).unwrap();
+        assert!(analysis.chi_square.unwrap().p_value > 0.5, "{analysis}");
+        assert!(analysis.detectability() > 0.7, "{analysis}");
+    }
+
+    #[test]
+    fn test_indexed_is_unsupported() {
+        let ihdr = Ihdr::new(4, 4, 8, ColorType::Indexed).unwrap();
+        let png = Png::from_chunks(vec![ihdr.to_chunk()]);
+        assert!(analyze(&png).is_err());
+    }
+}
@@ -0,0 +1,133 @@
+//! Traits for where chunks are read from and written to, so code that works
+//! chunk by chunk, such as [`message::embed_chunks`], doesn't care whether
+//! the PNG framing around them comes from a file, a buffer or an adapter for
+//! some other store.
+//!
+//! [`message::embed_chunks`]: crate::message::embed_chunks
+
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+
+use crate::chunk::Chunk;
+use crate::png::{ChunkRefs, Png};
+use crate::reader::PngReader;
+use crate::writer::PngWriter;
+use crate::Result;
+
+/// Yields chunks one at a time, in file order.
+pub trait ChunkSource {
+    /// The next chunk, or `None` once there are no more.
+    fn next_chunk(&mut self) -> Result<Option<Chunk>>;
+}
+
+/// Takes chunks one at a time, in file order.
+pub trait ChunkSink {
+    fn write_chunk(&mut self, chunk: &Chunk) -> Result<()>;
+
+    /// Called once after the last chunk, to push out anything buffered.
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<S: ChunkSource + ?Sized> ChunkSource for &mut S {
+    fn next_chunk(&mut self) -> Result<Option<Chunk>> {
+        (**self).next_chunk()
+    }
+}
+
+impl<S: ChunkSink + ?Sized> ChunkSink for &mut S {
+    fn write_chunk(&mut self, chunk: &Chunk) -> Result<()> {
+        (**self).write_chunk(chunk)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        (**self).flush()
+    }
+}
+
+impl<R: Read> ChunkSource for PngReader<R> {
+    fn next_chunk(&mut self) -> Result<Option<Chunk>> {
+        PngReader::next_chunk(self)
+    }
+}
+
+impl ChunkSource for ChunkRefs<'_> {
+    fn next_chunk(&mut self) -> Result<Option<Chunk>> {
+        self.next().map(|chunk| chunk.map(Chunk::from)).transpose()
+    }
+}
+
+/// Chunks held in memory, taken from the front.
+impl ChunkSource for VecDeque<Chunk> {
+    fn next_chunk(&mut self) -> Result<Option<Chunk>> {
+        Ok(self.pop_front())
+    }
+}
+
+impl<W: Write> ChunkSink for PngWriter<W> {
+    fn write_chunk(&mut self, chunk: &Chunk) -> Result<()> {
+        PngWriter::write_chunk(self, chunk)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        PngWriter::flush(self)
+    }
+}
+
+/// Collects the chunks in memory.
+impl ChunkSink for Vec<Chunk> {
+    fn write_chunk(&mut self, chunk: &Chunk) -> Result<()> {
+        self.push(chunk.clone());
+        Ok(())
+    }
+}
+
+impl Png {
+    /// Reads every chunk from `source`.
+    pub fn from_source<S: ChunkSource>(mut source: S) -> Result<Png> {
+        let mut chunks = Vec::new();
+        while let Some(chunk) = source.next_chunk()? {
+            chunks.push(chunk);
+        }
+        Ok(Png::from_chunks(chunks))
+    }
+
+    /// Writes every chunk to `sink` and flushes it.
+    pub fn write_to_sink<S: ChunkSink>(&self, mut sink: S) -> Result<()> {
+        for chunk in self.chunks() {
+            sink.write_chunk(chunk)?;
+        }
+        sink.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::ColorType;
+    use crate::png::ParseOptions;
+
+    #[test]
+    fn test_sources_and_sinks() {
+        let png = Png::from_pixels(2, 2, ColorType::Grayscale, &[0; 4]).unwrap();
+        let bytes = png.as_bytes();
+
+        let mut chunks = Vec::new();
+        Png::from_source(Png::chunk_refs(&bytes, &ParseOptions::default()).unwrap())
+            .unwrap()
+            .write_to_sink(&mut chunks)
+            .unwrap();
+        assert_eq!(chunks, png.chunks());
+
+        let mut writer = PngWriter::new(Vec::new()).unwrap();
+        Png::from_source(VecDeque::from(chunks))
+            .unwrap()
+            .write_to_sink(&mut writer)
+            .unwrap();
+        let written = writer.finish().unwrap();
+        assert_eq!(written, bytes);
+        let read = Png::from_source(PngReader::new(written.as_slice()).unwrap()).unwrap();
+        assert_eq!(read.as_bytes(), bytes);
+    }
+}
@@ -0,0 +1,114 @@
+#[cfg(feature = "serde")]
+use std::io::{self, Write};
+
+use clap::ValueEnum;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::png::Png;
+use crate::Result;
+
+/// How a command prints its results.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable text.
+    #[default]
+    Text,
+    /// Pretty-printed JSON, for scripts.
+    Json,
+}
+
+/// Where a chunk sits in a PNG, and what it holds, without its data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ChunkSummary {
+    /// Index of the chunk among all chunks, from 0.
+    pub position: usize,
+    /// Byte offset of the chunk's length field from the start of the file.
+    pub offset: u64,
+    pub chunk_type: String,
+    /// Number of bytes in the data field.
+    pub length: u32,
+    pub crc: u32,
+    pub crc_valid: bool,
+}
+
+impl Png {
+    /// Summarizes every chunk, in file order.
+    pub fn chunk_summaries(&self) -> Vec<ChunkSummary> {
+        self.chunks()
+            .iter()
+            .zip(self.chunk_spans())
+            .enumerate()
+            .map(|(position, (chunk, span))| ChunkSummary {
+                position,
+                offset: span.start,
+                chunk_type: chunk.chunk_type().to_string(),
+                length: chunk.length(),
+                crc: chunk.crc(),
+                crc_valid: chunk.has_valid_crc(),
+            })
+            .collect()
+    }
+}
+
+/// Prints `value` to standard output as pretty-printed JSON.
+#[cfg(feature = "serde")]
+pub fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    serde_json::to_writer_pretty(&mut stdout, value)?;
+    stdout.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Without the `serde` feature there is no JSON output.
+#[cfg(not(feature = "serde"))]
+pub fn print_json<T: ?Sized>(_value: &T) -> Result<()> {
+    Err(crate::PngMeError::JsonUnsupported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::png::minimal_png;
+
+    #[test]
+    fn test_chunk_offsets() {
+        let mut png = minimal_png();
+        png.append_chunk(Chunk::new(
+            ChunkType::try_from(*b"ruSt").unwrap(),
+            b"hi".to_vec(),
+        ));
+        let summaries = png.chunk_summaries();
+        let offsets: Vec<u64> = summaries.iter().map(|summary| summary.offset).collect();
+        assert_eq!(offsets, [8, 8 + 25, 8 + 25 + 14]);
+        assert_eq!(summaries[1].chunk_type, "ruSt");
+        assert_eq!(summaries[1].length, 2);
+        assert!(summaries.iter().all(|summary| summary.crc_valid));
+    }
+
+    #[test]
+    fn test_parsed_offsets_match_spans() {
+        let mut png = minimal_png();
+        png.append_chunk(Chunk::new(
+            ChunkType::try_from(*b"ruSt").unwrap(),
+            b"hi".to_vec(),
+        ));
+        let bytes = png.as_bytes();
+        let parsed = Png::try_from(&bytes[..]).unwrap();
+        let streamed = Png::from_reader(&bytes[..]).unwrap();
+        let spans = parsed.chunk_spans();
+        assert_eq!(spans.last().unwrap().end, bytes.len() as u64);
+        for png in [parsed, streamed] {
+            for (chunk, span) in png.chunks().iter().zip(&spans) {
+                assert_eq!(chunk.offset(), Some(span.start));
+                assert_eq!(chunk.total_size(), span.end - span.start);
+            }
+        }
+        assert_eq!(spans[1].start, 8 + 25);
+    }
+}
@@ -0,0 +1,379 @@
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use thiserror::Error;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::{ParseOptions, Png};
+use crate::{PngMeError, Result};
+
+/// Longest keyword the specification allows, in bytes.
+pub const MAX_KEYWORD_LEN: usize = 79;
+
+/// Which of the three textual chunk types a [`TextChunk`] came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextKind {
+    /// `tEXt`: uncompressed Latin-1 text.
+    Text,
+    /// `zTXt`: zlib-compressed Latin-1 text.
+    Compressed,
+    /// `iTXt`: UTF-8 text, optionally compressed, with a language tag.
+    International,
+}
+
+/// A keyword/value pair from a `tEXt`, `zTXt` or `iTXt` chunk, with any
+/// compression undone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextChunk {
+    pub kind: TextKind,
+    pub keyword: String,
+    pub text: String,
+    /// RFC 3066 language tag, `iTXt` only. Empty means unspecified.
+    pub language_tag: Option<String>,
+    /// The keyword translated into the language above, `iTXt` only.
+    pub translated_keyword: Option<String>,
+}
+
+impl Png {
+    /// Returns every textual chunk that can be decoded, in file order.
+    ///
+    /// Chunks that are malformed, or compressed with an unknown method, are
+    /// skipped; use `TextChunk::try_from` on a single chunk to see why.
+    pub fn text_chunks(&self) -> Vec<TextChunk> {
+        self.chunks()
+            .iter()
+            .filter(|chunk| TextKind::of(chunk).is_some())
+            .filter_map(|chunk| TextChunk::try_from(chunk).ok())
+            .collect()
+    }
+
+    /// Returns the first textual chunk with the given keyword.
+    pub fn text(&self, keyword: &str) -> Option<TextChunk> {
+        self.text_chunks()
+            .into_iter()
+            .find(|text| text.keyword == keyword)
+    }
+
+    /// Stores `text` under `keyword` in a `tEXt` chunk, replacing any textual
+    /// chunk with the same keyword. Both must be representable in Latin-1;
+    /// use [`Png::set_itxt`] for other text.
+    pub fn set_text(&mut self, keyword: &str, text: &str) -> Result<()> {
+        validate_keyword(keyword)?;
+        let mut data = to_latin1(keyword)?;
+        data.push(0);
+        let text = to_latin1(text)?;
+        if text.contains(&0) {
+            return Err(TextError::NulInText.into());
+        }
+        data.extend(text);
+        self.replace_text(keyword, Chunk::new(ChunkType::TEXT, data));
+        Ok(())
+    }
+
+    /// Stores UTF-8 `text` under `keyword` in an uncompressed `iTXt` chunk,
+    /// replacing any textual chunk with the same keyword.
+    pub fn set_itxt(
+        &mut self,
+        keyword: &str,
+        language_tag: &str,
+        translated_keyword: &str,
+        text: &str,
+    ) -> Result<()> {
+        validate_keyword(keyword)?;
+        if language_tag.contains('\0') || translated_keyword.contains('\0') {
+            return Err(TextError::NulInText.into());
+        }
+        let mut data = to_latin1(keyword)?;
+        // Keyword separator, then the compression flag and method.
+        data.extend([0, 0, 0]);
+        data.extend(language_tag.as_bytes());
+        data.push(0);
+        data.extend(translated_keyword.as_bytes());
+        data.push(0);
+        data.extend(text.as_bytes());
+        self.replace_text(keyword, Chunk::new(ChunkType::ITXT, data));
+        Ok(())
+    }
+
+    /// Removes every textual chunk with the given keyword, returning how many
+    /// were removed.
+    pub fn remove_text(&mut self, keyword: &str) -> usize {
+        let before = self.chunks().len();
+        self.retain_chunks(|chunk| !has_keyword(chunk, keyword));
+        before - self.chunks().len()
+    }
+
+    fn replace_text(&mut self, keyword: &str, chunk: Chunk) {
+        self.remove_text(keyword);
+        self.append_chunk(chunk);
+    }
+}
+
+/// Returns true if `chunk` is a textual chunk with the given keyword. Only the
+/// keyword field is parsed, so chunks with corrupt text still match.
+fn has_keyword(chunk: &Chunk, keyword: &str) -> bool {
+    TextKind::of(chunk).is_some()
+        && split_nul(chunk.data()).is_ok_and(|(found, _)| latin1(found) == keyword)
+}
+
+/// Checks the keyword rules from section 11.3.4.2 of the PNG specification:
+/// 1 to 79 printable Latin-1 characters, with no leading, trailing or
+/// consecutive spaces.
+pub fn validate_keyword(keyword: &str) -> Result<()> {
+    let bytes = to_latin1(keyword)?;
+    if bytes.is_empty() || bytes.len() > MAX_KEYWORD_LEN {
+        return Err(TextError::KeywordLength(bytes.len()).into());
+    }
+    if let Some(&byte) = bytes.iter().find(|&&b| !(32..=126).contains(&b) && b < 161) {
+        return Err(TextError::KeywordByte(byte).into());
+    }
+    if keyword.starts_with(' ') || keyword.ends_with(' ') || keyword.contains("  ") {
+        return Err(TextError::KeywordSpaces.into());
+    }
+    Ok(())
+}
+
+impl TextKind {
+    /// Returns the kind of textual chunk `chunk` is, if it is one.
+    pub fn of(chunk: &Chunk) -> Option<TextKind> {
+        match &chunk.chunk_type().bytes() {
+            b"tEXt" => Some(TextKind::Text),
+            b"zTXt" => Some(TextKind::Compressed),
+            b"iTXt" => Some(TextKind::International),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<&Chunk> for TextChunk {
+    type Error = PngMeError;
+
+    fn try_from(chunk: &Chunk) -> Result<TextChunk> {
+        let kind = TextKind::of(chunk)
+            .ok_or_else(|| TextError::NotText(chunk.chunk_type().to_string()))?;
+        let (keyword, rest) = split_nul(chunk.data())?;
+        let keyword = latin1(keyword);
+
+        let text_chunk = match kind {
+            TextKind::Text => TextChunk {
+                kind,
+                keyword,
+                text: latin1(rest),
+                language_tag: None,
+                translated_keyword: None,
+            },
+            TextKind::Compressed => {
+                let (&method, compressed) = rest.split_first().ok_or(TextError::Truncated)?;
+                TextChunk {
+                    kind,
+                    keyword,
+                    text: latin1(&inflate(method, compressed)?),
+                    language_tag: None,
+                    translated_keyword: None,
+                }
+            }
+            TextKind::International => {
+                let [flag, method, rest @ ..] = rest else {
+                    return Err(TextError::Truncated.into());
+                };
+                let (language_tag, rest) = split_nul(rest)?;
+                let (translated_keyword, text) = split_nul(rest)?;
+                let text = match flag {
+                    0 => text.to_vec(),
+                    _ => inflate(*method, text)?,
+                };
+                TextChunk {
+                    kind,
+                    keyword,
+                    text: String::from_utf8(text)?,
+                    language_tag: Some(String::from_utf8(language_tag.to_vec())?),
+                    translated_keyword: Some(String::from_utf8(translated_keyword.to_vec())?),
+                }
+            }
+        };
+        Ok(text_chunk)
+    }
+}
+
+/// Splits `data` at the first NUL byte, dropping the separator.
+pub(crate) fn split_nul(data: &[u8]) -> Result<(&[u8], &[u8])> {
+    let nul = data
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or(TextError::MissingSeparator)?;
+    Ok((&data[..nul], &data[nul + 1..]))
+}
+
+/// Encodes `text` as ISO 8859-1, failing if it has characters outside it.
+pub(crate) fn to_latin1(text: &str) -> Result<Vec<u8>> {
+    text.chars()
+        .map(|c| u8::try_from(c).map_err(|_| TextError::NotLatin1(c).into()))
+        .collect()
+}
+
+/// Decodes ISO 8859-1 text, whose bytes map one-to-one onto the first 256
+/// Unicode code points.
+pub(crate) fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| char::from(b)).collect()
+}
+
+/// Decompresses a zlib stream. Method 0 (deflate) is the only one defined.
+/// The text may be no larger than
+/// [`ParseOptions::DEFAULT_MAX_PAYLOAD_SIZE`].
+pub(crate) fn inflate(method: u8, data: &[u8]) -> Result<Vec<u8>> {
+    inflate_within(method, data, ParseOptions::DEFAULT_MAX_PAYLOAD_SIZE)
+}
+
+/// Like [`inflate`], failing once the text runs past `max` bytes.
+fn inflate_within(method: u8, data: &[u8], max: u64) -> Result<Vec<u8>> {
+    if method != 0 {
+        return Err(TextError::UnknownCompression(method).into());
+    }
+    // One byte past the limit is enough to tell that it was passed.
+    let mut text = Vec::new();
+    ZlibDecoder::new(data)
+        .take(max.saturating_add(1))
+        .read_to_end(&mut text)?;
+    match text.len() as u64 > max {
+        true => Err(TextError::TooLarge { max }.into()),
+        false => Ok(text),
+    }
+}
+
+/// Compresses `data` with compression method 0, the inverse of [`inflate`].
+pub(crate) fn deflate(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+/// Errors raised while decoding a textual chunk.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum TextError {
+    /// The chunk is not `tEXt`, `zTXt` or `iTXt`.
+    #[error("{0} is not a text chunk")]
+    NotText(String),
+    /// A NUL separator between fields is missing.
+    #[error("text chunk is missing a NUL separator")]
+    MissingSeparator,
+    /// The chunk ends before its compression fields.
+    #[error("text chunk is truncated")]
+    Truncated,
+    #[error("unknown text compression method {0}")]
+    UnknownCompression(u8),
+    /// Compressed text inflates to more than `max` bytes.
+    #[error("compressed text is larger than the limit of {max} bytes")]
+    TooLarge { max: u64 },
+    /// Keywords must be between 1 and 79 bytes long.
+    #[error(
+        "keyword must be 1 to {MAX_KEYWORD_LEN} bytes long, got {0}",
+        MAX_KEYWORD_LEN = MAX_KEYWORD_LEN
+    )]
+    KeywordLength(usize),
+    /// Keywords may only contain printable Latin-1 characters.
+    #[error("keyword contains unprintable byte {0:#04x}")]
+    KeywordByte(u8),
+    /// Keywords may not have leading, trailing or consecutive spaces.
+    #[error("keyword may not have leading, trailing or consecutive spaces")]
+    KeywordSpaces,
+    /// `tEXt` keywords and text must be Latin-1.
+    #[error("{0:?} is not Latin-1, store the text as iTXt instead")]
+    NotLatin1(char),
+    /// Text fields may not contain NUL, which separates them.
+    #[error("text may not contain NUL characters")]
+    NulInText,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    #[test]
+    fn test_text_is_latin1() {
+        let text = TextChunk::try_from(&chunk("tEXt", b"Author\0Ren\xe9")).unwrap();
+        assert_eq!(text.kind, TextKind::Text);
+        assert_eq!(text.keyword, "Author");
+        assert_eq!(text.text, "René");
+        assert_eq!(text.language_tag, None);
+    }
+
+    #[test]
+    fn test_ztxt_is_decompressed() {
+        let mut data = b"Comment\0\0".to_vec();
+        data.extend(deflate(b"a long comment").unwrap());
+        let text = TextChunk::try_from(&chunk("zTXt", &data)).unwrap();
+        assert_eq!(text.kind, TextKind::Compressed);
+        assert_eq!(text.text, "a long comment");
+    }
+
+    #[test]
+    fn test_itxt_fields() {
+        let mut data = "Title\0\x01\0fr\0Titre\0".as_bytes().to_vec();
+        data.extend(deflate("Été".as_bytes()).unwrap());
+        let text = TextChunk::try_from(&chunk("iTXt", &data)).unwrap();
+        assert_eq!(text.kind, TextKind::International);
+        assert_eq!(text.keyword, "Title");
+        assert_eq!(text.text, "Été");
+        assert_eq!(text.language_tag.as_deref(), Some("fr"));
+        assert_eq!(text.translated_keyword.as_deref(), Some("Titre"));
+    }
+
+    #[test]
+    fn test_set_text_replaces_keyword() {
+        let mut png = Png::from_chunks(vec![chunk("IEND", b"")]);
+        png.set_text("Author", "someone").unwrap();
+        png.set_itxt("Author", "de", "Autor", "jemand 🦀").unwrap();
+
+        assert_eq!(png.chunks().len(), 2);
+        assert_eq!(png.chunks()[1].chunk_type().to_string(), "IEND");
+        let text = png.text("Author").unwrap();
+        assert_eq!(text.kind, TextKind::International);
+        assert_eq!(text.text, "jemand 🦀");
+        assert_eq!(text.translated_keyword.as_deref(), Some("Autor"));
+
+        assert_eq!(png.remove_text("Author"), 1);
+        assert!(png.text_chunks().is_empty());
+    }
+
+    #[test]
+    fn test_keyword_rules() {
+        assert!(validate_keyword("Creation Time").is_ok());
+        assert!(validate_keyword("").is_err());
+        assert!(validate_keyword(&"k".repeat(80)).is_err());
+        assert!(validate_keyword(" Title").is_err());
+        assert!(validate_keyword("Two  spaces").is_err());
+        assert!(validate_keyword("Tab\there").is_err());
+        assert!(Png::from_chunks(Vec::new()).set_text("Crab", "🦀").is_err());
+    }
+
+    #[test]
+    fn test_text_chunks_skips_other_and_malformed_chunks() {
+        let png = Png::from_chunks(vec![
+            chunk("tEXt", b"Software\0pngme"),
+            chunk("RuSt", b"not text"),
+            chunk("tEXt", b"no separator"),
+        ]);
+        let texts = png.text_chunks();
+        assert_eq!(texts.len(), 1);
+        assert_eq!(texts[0].keyword, "Software");
+    }
+
+    #[test]
+    fn test_inflate_limit() {
+        let compressed = deflate(&[b'a'; 1000]).unwrap();
+        assert_eq!(inflate_within(0, &compressed, 1000).unwrap().len(), 1000);
+        assert!(matches!(
+            inflate_within(0, &compressed, 999),
+            Err(PngMeError::Text(TextError::TooLarge { max: 999 }))
+        ));
+    }
+}
@@ -0,0 +1,139 @@
+use std::ops::Range;
+
+use thiserror::Error;
+
+use crate::chunk::{self, Chunk};
+use crate::png::{ParseOptions, Png};
+use crate::Result;
+
+/// Ends every trailer, so it can be found by reading back from the end of
+/// the file.
+pub const MAGIC: [u8; 8] = *b"pngmeTRL";
+
+/// Bytes the trailer adds after the payload: its length, its CRC and
+/// [`MAGIC`].
+pub const FOOTER_LEN: usize = 4 + 4 + MAGIC.len();
+
+/// Appends `payload` after the IEND chunk of the PNG in `bytes`, replacing
+/// any trailer written earlier. Other data after IEND is kept ahead of the
+/// new trailer.
+pub fn embed(bytes: &[u8], payload: &[u8]) -> Result<Vec<u8>> {
+    let end = png_len(bytes)?;
+    let length = u32::try_from(payload.len()).map_err(|_| TrailerError::TooLarge(payload.len()))?;
+    let keep = find(bytes)
+        .filter(|trailer| trailer.start >= end)
+        .map_or(bytes.len(), |trailer| trailer.start);
+
+    let mut out = Vec::with_capacity(keep + payload.len() + FOOTER_LEN);
+    out.extend_from_slice(&bytes[..keep]);
+    out.extend_from_slice(payload);
+    out.extend_from_slice(&length.to_be_bytes());
+    out.extend_from_slice(&chunk::crc32(payload).to_be_bytes());
+    out.extend_from_slice(&MAGIC);
+    Ok(out)
+}
+
+/// Reads the payload written by [`embed`], or returns `None` if the file
+/// doesn't end in a trailer.
+pub fn extract(bytes: &[u8]) -> Result<Option<Vec<u8>>> {
+    let Some(trailer) = find(bytes) else {
+        return Ok(None);
+    };
+    let footer = trailer.end - FOOTER_LEN;
+    let payload = &bytes[trailer.start..footer];
+    let stored = u32::from_be_bytes(bytes[footer + 4..footer + 8].try_into()?);
+    if chunk::crc32(payload) != stored {
+        return Err(TrailerError::CrcMismatch.into());
+    }
+    Ok(Some(payload.to_vec()))
+}
+
+/// The byte range of the trailer at the end of `bytes`, footer included, if
+/// there is one. Neither the CRC nor whether the range starts after IEND is
+/// checked.
+pub fn find(bytes: &[u8]) -> Option<Range<usize>> {
+    let footer = bytes.len().checked_sub(FOOTER_LEN)?;
+    if bytes[footer + 8..] != MAGIC {
+        return None;
+    }
+    let length = u32::from_be_bytes(bytes[footer..footer + 4].try_into().ok()?) as usize;
+    let start = footer.checked_sub(length)?;
+    Some(start..bytes.len())
+}
+
+/// Bytes taken by the PNG itself, from the signature through IEND, ignoring
+/// anything after it. Bad CRCs are tolerated, since the bytes are kept as
+/// they are.
+pub fn png_len(bytes: &[u8]) -> Result<usize> {
+    Ok(encoded_len(&Png::from_bytes_with(
+        bytes,
+        &ParseOptions::permissive(),
+    )?))
+}
+
+/// Bytes `png` takes when serialized.
+pub(crate) fn encoded_len(png: &Png) -> usize {
+    let chunks: usize = png
+        .chunks()
+        .iter()
+        .map(|chunk| Chunk::OVERHEAD + chunk.data().len())
+        .sum();
+    Png::STANDARD_HEADER.len() + chunks
+}
+
+/// Errors raised by the trailer backend.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum TrailerError {
+    /// The length field is 32 bits.
+    #[error("trailer payloads are limited to 4 GiB, got {0} bytes")]
+    TooLarge(usize),
+    /// The trailer's contents don't match its CRC.
+    #[error("trailer is corrupt: CRC does not match")]
+    CrcMismatch,
+    /// Labels only apply to chunk payloads; a file holds one trailer.
+    #[error("labels cannot be used with trailer embedding")]
+    Labeled,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::minimal_png;
+
+    use crate::PngMeError;
+
+    #[test]
+    fn test_round_trip() {
+        let bytes = embed(&minimal_png().as_bytes(), b"after the end").unwrap();
+        assert_eq!(png_len(&bytes).unwrap(), minimal_png().as_bytes().len());
+        assert_eq!(extract(&bytes).unwrap().unwrap(), b"after the end");
+        assert!(extract(&minimal_png().as_bytes()).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_replaces_trailer_and_keeps_other_data() {
+        let mut bytes = minimal_png().as_bytes();
+        bytes.extend_from_slice(b"someone else's");
+        let bytes = embed(&bytes, b"first").unwrap();
+        let bytes = embed(&bytes, b"second").unwrap();
+        assert_eq!(extract(&bytes).unwrap().unwrap(), b"second");
+
+        let trailer = find(&bytes).unwrap();
+        assert_eq!(
+            &bytes[minimal_png().as_bytes().len()..trailer.start],
+            b"someone else's"
+        );
+    }
+
+    #[test]
+    fn test_corrupt_trailer() {
+        let mut bytes = embed(&minimal_png().as_bytes(), b"payload").unwrap();
+        bytes[minimal_png().as_bytes().len()] ^= 1;
+        let err = extract(&bytes).unwrap_err();
+        assert!(matches!(
+            err,
+            PngMeError::Trailer(TrailerError::CrcMismatch)
+        ));
+    }
+}
@@ -0,0 +1,24 @@
+//! JavaScript bindings, built with the `wasm` feature, so a web page can hide
+//! and reveal messages without the image ever leaving the browser.
+
+use wasm_bindgen::prelude::*;
+
+use crate::message;
+
+/// Hides `message` in the PNG `bytes` in chunks of type `chunk_type`,
+/// returning the new file as a `Uint8Array`.
+#[wasm_bindgen]
+pub fn encode(
+    bytes: &[u8],
+    chunk_type: &str,
+    message: &str,
+) -> std::result::Result<Vec<u8>, JsError> {
+    Ok(message::embed(bytes, chunk_type, message.as_bytes())?)
+}
+
+/// Reveals the message hidden in the PNG `bytes` in chunks of type
+/// `chunk_type`.
+#[wasm_bindgen]
+pub fn decode(bytes: &[u8], chunk_type: &str) -> std::result::Result<String, JsError> {
+    Ok(String::from_utf8(message::extract(bytes, chunk_type)?)?)
+}
@@ -0,0 +1,126 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+
+/// How long a file must go without changes before it is handled, so files
+/// still being copied in aren't read half-written.
+pub const SETTLE_TIME: Duration = Duration::from_millis(500);
+
+/// Watches `dir` and its subdirectories, calling `handle` once for each PNG
+/// file that appears, after it has settled. Runs until the watcher fails.
+pub fn run(dir: &Path, mut handle: impl FnMut(&Path)) -> crate::Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(dir, RecursiveMode::Recursive)?;
+
+    let mut arrivals = Arrivals::default();
+    loop {
+        match rx.recv_timeout(SETTLE_TIME / 4) {
+            Ok(event) => arrivals.record(&event?, Instant::now()),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+        for path in arrivals.settled(Instant::now()) {
+            handle(&path);
+        }
+    }
+}
+
+/// Tracks PNG files that have appeared and not yet been handled.
+///
+/// Handling a file usually rewrites it, so a handled file is ignored until it
+/// is removed; otherwise every embed would trigger another.
+#[derive(Debug, Default)]
+struct Arrivals {
+    pending: HashMap<PathBuf, Instant>,
+    handled: HashSet<PathBuf>,
+}
+
+impl Arrivals {
+    fn record(&mut self, event: &Event, now: Instant) {
+        for path in event.paths.iter().filter(|path| is_png(path)) {
+            match event.kind {
+                EventKind::Create(_) | EventKind::Modify(_) if !self.handled.contains(path) => {
+                    self.pending.insert(path.clone(), now);
+                }
+                EventKind::Remove(_) => {
+                    self.pending.remove(path);
+                    self.handled.remove(path);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Files that haven't changed for [`SETTLE_TIME`], now marked handled.
+    fn settled(&mut self, now: Instant) -> Vec<PathBuf> {
+        let mut ready: Vec<PathBuf> = self
+            .pending
+            .iter()
+            .filter(|(_, &changed)| now.duration_since(changed) >= SETTLE_TIME)
+            .map(|(path, _)| path.clone())
+            .collect();
+        ready.sort();
+        for path in &ready {
+            self.pending.remove(path);
+            self.handled.insert(path.clone());
+        }
+        ready
+    }
+}
+
+fn is_png(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, ModifyKind, RemoveKind};
+
+    fn event(kind: EventKind, path: &str) -> Event {
+        Event::new(kind).add_path(PathBuf::from(path))
+    }
+
+    #[test]
+    fn test_files_settle_once() {
+        let start = Instant::now();
+        let mut arrivals = Arrivals::default();
+        arrivals.record(
+            &event(EventKind::Create(CreateKind::File), "in/a.png"),
+            start,
+        );
+        arrivals.record(
+            &event(EventKind::Create(CreateKind::File), "in/notes.txt"),
+            start,
+        );
+        assert!(arrivals.settled(start + SETTLE_TIME / 2).is_empty());
+
+        // Still being written: the clock restarts.
+        let later = start + SETTLE_TIME / 2;
+        arrivals.record(
+            &event(EventKind::Modify(ModifyKind::Any), "in/a.png"),
+            later,
+        );
+        assert!(arrivals.settled(start + SETTLE_TIME).is_empty());
+        assert_eq!(
+            arrivals.settled(later + SETTLE_TIME),
+            [PathBuf::from("in/a.png")]
+        );
+
+        // Our own rewrite is ignored, but a new file of the same name is not.
+        let end = later + SETTLE_TIME;
+        arrivals.record(&event(EventKind::Modify(ModifyKind::Any), "in/a.png"), end);
+        assert!(arrivals.settled(end + SETTLE_TIME).is_empty());
+        arrivals.record(&event(EventKind::Remove(RemoveKind::File), "in/a.png"), end);
+        arrivals.record(&event(EventKind::Create(CreateKind::File), "in/a.png"), end);
+        assert_eq!(
+            arrivals.settled(end + SETTLE_TIME),
+            [PathBuf::from("in/a.png")]
+        );
+    }
+}
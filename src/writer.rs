@@ -0,0 +1,222 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::chunk::Chunk;
+use crate::png::{Png, PngError};
+use crate::{PngMeError, Result};
+
+/// Writes a PNG to any [`Write`] sink one chunk at a time.
+#[derive(Debug)]
+pub struct PngWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> PngWriter<W> {
+    /// Wraps `inner` and writes the PNG signature to it.
+    pub fn new(mut inner: W) -> Result<Self> {
+        inner.write_all(&Png::STANDARD_HEADER)?;
+        Ok(PngWriter { inner })
+    }
+
+    pub fn write_chunk(&mut self, chunk: &Chunk) -> Result<()> {
+        self.inner.write_all(&chunk.as_bytes())?;
+        Ok(())
+    }
+
+    /// The underlying writer, holding everything written so far.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Flushes the underlying writer, leaving it open for more chunks.
+    pub fn flush(&mut self) -> Result<()> {
+        self.inner.flush()?;
+        Ok(())
+    }
+
+    /// Flushes and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl Png {
+    /// Streams the signature and every chunk to `writer`, returning it once
+    /// flushed.
+    pub fn write_to<W: Write>(&self, writer: W) -> Result<W> {
+        let mut writer = PngWriter::new(writer)?;
+        for chunk in self.chunks() {
+            writer.write_chunk(chunk)?;
+        }
+        writer.finish()
+    }
+}
+
+/// Inserts `chunks` before the IEND chunk of the PNG in `file`.
+///
+/// Only chunk headers are read while looking for IEND, and only the bytes
+/// from IEND onwards are rewritten, so the cost doesn't depend on the size
+/// of the image data. Without an IEND chunk the new chunks are appended.
+pub(crate) fn insert_before_iend<F: Read + Write + Seek>(
+    file: &mut F,
+    chunks: &[Chunk],
+) -> Result<()> {
+    let offset = find_iend(file)?;
+
+    file.seek(SeekFrom::Start(offset))?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+
+    file.seek(SeekFrom::Start(offset))?;
+    for chunk in chunks {
+        file.write_all(&chunk.as_bytes())?;
+    }
+    file.write_all(&tail)?;
+    file.flush()?;
+    Ok(())
+}
+
+/// Returns the offset of the IEND chunk, or of the end of the last chunk if
+/// there is no IEND.
+fn find_iend<F: Read + Seek>(file: &mut F) -> Result<u64> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut signature = [0; 8];
+    file.read_exact(&mut signature)
+        .map_err(|_| PngMeError::InvalidSignature)?;
+    if signature != Png::STANDARD_HEADER {
+        return Err(PngMeError::InvalidSignature);
+    }
+
+    let end = file.seek(SeekFrom::End(0))?;
+    let mut offset = Png::STANDARD_HEADER.len() as u64;
+    while offset < end {
+        file.seek(SeekFrom::Start(offset))?;
+        let mut header = [0; 8];
+        file.read_exact(&mut header)
+            .map_err(|_| PngError::Truncated)?;
+        if &header[4..] == b"IEND" {
+            return Ok(offset);
+        }
+
+        let length = u32::from_be_bytes(header[..4].try_into()?);
+        offset += Chunk::OVERHEAD as u64 + u64::from(length);
+    }
+
+    if offset > end {
+        return Err(PngError::Truncated.into());
+    }
+    Ok(offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Cursor;
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use crate::png::minimal_png;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn file(chunks: &[Chunk]) -> Vec<u8> {
+        let mut writer = PngWriter::new(Vec::new()).unwrap();
+        for chunk in chunks {
+            writer.write_chunk(chunk).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn test_writer_matches_as_bytes() {
+        let mut png = minimal_png();
+        png.append_chunk(chunk("ruSt", b"hello"));
+        assert_eq!(file(png.chunks()), png.as_bytes());
+        assert_eq!(png.write_to(Vec::new()).unwrap(), png.as_bytes());
+    }
+
+    #[test]
+    fn test_insert_before_iend_keeps_trailing_bytes() {
+        let mut bytes = minimal_png().as_bytes();
+        bytes.extend(b"trailer");
+        let mut cursor = Cursor::new(bytes);
+        insert_before_iend(&mut cursor, &[chunk("ruSt", b"hello")]).unwrap();
+
+        let mut expected = file(&[
+            chunk("IHDR", &[0; 13]),
+            chunk("ruSt", b"hello"),
+            chunk("IEND", b""),
+        ]);
+        expected.extend(b"trailer");
+        assert_eq!(cursor.into_inner(), expected);
+    }
+
+    #[test]
+    fn test_insert_without_iend_appends() {
+        let mut cursor = Cursor::new(file(&[chunk("IHDR", &[0; 13])]));
+        insert_before_iend(&mut cursor, &[chunk("ruSt", b"a"), chunk("ruSt", b"b")]).unwrap();
+        assert_eq!(
+            cursor.into_inner(),
+            file(&[
+                chunk("IHDR", &[0; 13]),
+                chunk("ruSt", b"a"),
+                chunk("ruSt", b"b"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_truncated_last_chunk() {
+        let mut bytes = file(&[chunk("IHDR", &[0; 13]), chunk("ruSt", b"hello")]);
+        bytes.truncate(bytes.len() - 3);
+        let mut cursor = Cursor::new(bytes);
+        assert!(matches!(
+            insert_before_iend(&mut cursor, &[chunk("ruSt", b"x")]),
+            Err(PngMeError::Png(PngError::Truncated))
+        ));
+
+        // A chunk header cut short is just as truncated.
+        let mut bytes = file(&[chunk("IHDR", &[0; 13])]);
+        bytes.extend([0, 0, 0]);
+        assert!(matches!(
+            find_iend(&mut Cursor::new(bytes)),
+            Err(PngMeError::Png(PngError::Truncated))
+        ));
+    }
+
+    #[test]
+    fn test_bad_signature() {
+        let mut bytes = file(&[chunk("IEND", b"")]);
+        bytes[1] = b'J';
+        assert!(matches!(
+            insert_before_iend(&mut Cursor::new(bytes), &[]),
+            Err(PngMeError::InvalidSignature)
+        ));
+        assert!(matches!(
+            find_iend(&mut Cursor::new(b"\x89PN".to_vec())),
+            Err(PngMeError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn test_append_chunks_in_place() {
+        let path = std::env::temp_dir().join(format!("pngme-writer-{}.png", std::process::id()));
+        fs::write(&path, minimal_png().as_bytes()).unwrap();
+
+        Png::append_chunk_in_place(&path, chunk("ruSt", b"a")).unwrap();
+        Png::append_chunks_in_place(&path, &[chunk("ruSt", b"b"), chunk("ruSt", b"c")]).unwrap();
+        let png = Png::from_file(&path).unwrap();
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "ruSt", "ruSt", "ruSt", "IEND"]);
+        let data: Vec<&[u8]> = png.chunks()[1..4].iter().map(Chunk::data).collect();
+        assert_eq!(data, [b"a", b"b", b"c"]);
+        fs::remove_file(&path).unwrap();
+    }
+}